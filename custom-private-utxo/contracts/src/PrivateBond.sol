@@ -13,6 +13,9 @@ contract PrivateBond is Ownable {
     mapping(bytes32 => bool) public knownRoots;
     mapping(bytes32 => bool) public nullifiers;
 
+    // One per nullifier marked spent, so the spent set can be rebuilt from logs
+    event NullifierSpent(bytes32 indexed nullifier);
+
     constructor(address _verifier, address initialOwner) Ownable(initialOwner) {
         verifier = HonkVerifier(_verifier);
     }
@@ -40,6 +43,8 @@ contract PrivateBond is Ownable {
 
         nullifiers[nullifiersIn[0]] = true;
         nullifiers[nullifiersIn[1]] = true;
+        emit NullifierSpent(nullifiersIn[0]);
+        emit NullifierSpent(nullifiersIn[1]);
 
         commitments.push(commitmentsOut[0]);
         commitments.push(commitmentsOut[1]);
@@ -123,6 +128,8 @@ contract PrivateBond is Ownable {
 
         nullifiers[nullifiersIn[0]] = true;
         nullifiers[nullifiersIn[1]] = true;
+        emit NullifierSpent(nullifiersIn[0]);
+        emit NullifierSpent(nullifiersIn[1]);
 
         // For burn, we still add commitments (value=0 notes) to maintain tree structure
         commitments.push(commitmentsOut[0]);
@@ -158,6 +165,8 @@ contract PrivateBond is Ownable {
 
         nullifiers[nullifierA] = true;
         nullifiers[nullifierB] = true;
+        emit NullifierSpent(nullifierA);
+        emit NullifierSpent(nullifierB);
 
         commitments.push(publicInputsA[2]);
         commitments.push(publicInputsB[2]);
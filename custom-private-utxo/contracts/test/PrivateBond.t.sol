@@ -60,6 +60,10 @@ contract PrivateBondTest is Test {
         inputsB[2] = COMM_OUT_B;
         inputsB[3] = MATURITY;
 
+        vm.expectEmit(true, false, false, false);
+        emit PrivateBond.NullifierSpent(NULL_A);
+        vm.expectEmit(true, false, false, false);
+        emit PrivateBond.NullifierSpent(NULL_B);
         privateBond.atomicSwap("", inputsA, "", inputsB);
         assertTrue(privateBond.nullifiers(NULL_A));
     }
@@ -0,0 +1,130 @@
+//! Asset metadata registry (`data/assets.json`)
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::utils::{assets_path, ensure_data_dir, write_atomic};
+
+/// Display metadata attached to an asset id
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AssetMeta {
+    pub id: u64,
+    pub name: String,
+    pub ticker: String,
+    /// Face value paid at maturity, in smallest units
+    pub face_value: u64,
+    /// Issue price for zero-coupon bonds, in smallest units
+    pub issue_price: u64,
+    pub decimals: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AssetRegistry {
+    pub assets: Vec<AssetMeta>,
+}
+
+impl AssetRegistry {
+    /// The saved registry, or an empty one if no asset was registered yet. A file
+    /// that does not parse is an error, so `register-asset` never overwrites it.
+    pub fn load() -> Result<Self, String> {
+        Self::load_from(&assets_path())
+    }
+
+    fn load_from(path: &str) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AssetRegistry::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path, e)),
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        ensure_data_dir()?;
+        write_atomic(&assets_path(), serde_json::to_string_pretty(self)?)
+    }
+
+    /// Add an asset, rejecting duplicate ids and tickers (tickers compare case-insensitively)
+    pub fn register(&mut self, meta: AssetMeta) -> Result<(), String> {
+        if meta.ticker.trim().is_empty() {
+            return Err("Ticker must not be empty".to_string());
+        }
+        if self.get(meta.id).is_some() {
+            return Err(format!("Asset id {} is already registered", meta.id));
+        }
+        if self
+            .assets
+            .iter()
+            .any(|a| a.ticker.eq_ignore_ascii_case(&meta.ticker))
+        {
+            return Err(format!("Ticker '{}' is already registered", meta.ticker));
+        }
+        self.assets.push(meta);
+        Ok(())
+    }
+
+    pub fn get(&self, id: u64) -> Option<&AssetMeta> {
+        self.assets.iter().find(|a| a.id == id)
+    }
+
+    /// Human-readable label for an asset id, falling back to the bare id
+    pub fn display_name(&self, id: u64) -> String {
+        match self.get(id) {
+            Some(meta) => format!("{} ({}, id {})", meta.name, meta.ticker, id),
+            None => id.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(id: u64, ticker: &str) -> AssetMeta {
+        AssetMeta {
+            id,
+            name: format!("Bond {}", ticker),
+            ticker: ticker.to_string(),
+            face_value: 1_000,
+            issue_price: 950,
+            decimals: 2,
+        }
+    }
+
+    #[test]
+    fn test_register_and_resolve() {
+        let mut registry = AssetRegistry::default();
+        registry.register(meta(1, "ZCB30")).unwrap();
+        registry.register(meta(2, "ZCB35")).unwrap();
+
+        assert_eq!(registry.get(2).unwrap().ticker, "ZCB35");
+        assert_eq!(registry.display_name(1), "Bond ZCB30 (ZCB30, id 1)");
+        assert_eq!(registry.display_name(7), "7");
+    }
+
+    #[test]
+    fn test_register_rejects_duplicates() {
+        let mut registry = AssetRegistry::default();
+        registry.register(meta(1, "ZCB30")).unwrap();
+
+        assert!(registry.register(meta(1, "OTHER")).is_err());
+        assert!(registry.register(meta(2, "zcb30")).is_err());
+        assert!(registry.register(meta(3, "  ")).is_err());
+        assert_eq!(registry.assets.len(), 1);
+    }
+
+    #[test]
+    fn test_corrupt_registry_is_an_error() {
+        let path = std::env::temp_dir().join(format!("assets_corrupt_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(AssetRegistry::load_from(path).unwrap().assets.is_empty());
+
+        let mut registry = AssetRegistry::default();
+        registry.register(meta(1, "ZCB30")).unwrap();
+        fs::write(path, serde_json::to_string_pretty(&registry).unwrap()).unwrap();
+        assert_eq!(AssetRegistry::load_from(path).unwrap().assets, registry.assets);
+
+        fs::write(path, "{ truncated").unwrap();
+        assert!(AssetRegistry::load_from(path).unwrap_err().contains("Invalid"));
+        let _ = fs::remove_file(path);
+    }
+}
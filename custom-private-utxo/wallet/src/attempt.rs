@@ -0,0 +1,71 @@
+//! Spends recorded before they are submitted, so a rerun of a command whose
+//! transaction went out but never confirmed (e.g. the connection dropped) picks
+//! up the earlier attempt instead of building a second spend of the same note.
+//! Each attempt is keyed by the `witness_id` of the witness it proves.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::utils::{attempts_dir, ensure_data_dir, write_atomic};
+
+/// One submission, from just before it is sent until its outputs are saved
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attempt {
+    /// `witness_id` of the proven witness: the idempotency key
+    pub key: String,
+    /// Command that made it: `buy` for one spend (a plain buy or one fan-out
+    /// hop), `fan-out` for the record of a whole fan-out chain
+    pub command: String,
+    /// Nullifier of the note being spent (bytes32 hex); a rerun finds the attempt by it
+    pub spends: String,
+    /// Output salts, so a rerun rebuilds the same output notes
+    pub salts: (u64, u64),
+    /// Output commitments as submitted (bytes32 hex)
+    pub commitments: [String; 2],
+    pub proof: String,
+    /// Set once the transaction was sent, even if it never confirmed
+    pub tx_hash: Option<String>,
+    /// Reruns that picked this attempt up; capped by `config::RETRY_BUDGET`
+    pub retries: u32,
+    /// For a `fan-out` record: the (recipient, change, pad) salts of every hop, so
+    /// a rerun rebuilds the same hops and pads
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chain: Vec<(u64, u64, u64)>,
+}
+
+impl Attempt {
+    pub fn path(&self) -> String {
+        format!("{}/{}.json", attempts_dir(), self.key)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        ensure_data_dir().map_err(|e| e.to_string())?;
+        fs::create_dir_all(attempts_dir()).map_err(|e| format!("Failed to create {}: {}", attempts_dir(), e))?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize attempt: {}", e))?;
+        write_atomic(&self.path(), json).map_err(|e| format!("Failed to write {}: {}", self.path(), e))
+    }
+
+    /// Drop the record once the attempt's outputs are saved (or it was never sent)
+    pub fn clear(&self) {
+        let _ = fs::remove_file(self.path());
+    }
+
+    /// The unfinished `command` attempt spending `nullifier`, if any
+    pub fn find(command: &str, nullifier: &str) -> Result<Option<Attempt>, String> {
+        let entries = match fs::read_dir(attempts_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read {}: {}", attempts_dir(), e)),
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let json = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let attempt: Attempt =
+                serde_json::from_str(&json).map_err(|e| format!("Invalid attempt {}: {}", path.display(), e))?;
+            if attempt.command == command && attempt.spends.eq_ignore_ascii_case(nullifier) {
+                return Ok(Some(attempt));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,286 @@
+//! Baby Jubjub, the twisted Edwards curve whose base field is `Fr` (BN254's
+//! scalar field), so a circuit can check points on it natively. Parameters and
+//! `base8` follow circomlib and Noir's `std::eddsa`.
+
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use num_bigint::BigUint;
+use subtle::{Choice, ConditionallySelectable};
+
+use crate::field::{Fr, FrRepr};
+
+/// Curve coefficient `a` of a·x² + y² = 1 + d·x²·y²
+const A: u64 = 168700;
+/// Curve coefficient `d`
+const D: u64 = 168696;
+
+/// Order of the prime subgroup `base8` generates (the curve has cofactor 8)
+pub const SUBORDER: &str = "2736030358979909402780800718157159386076813972158567259200215660948447373041";
+
+const BASE8_X: &str = "5299619240641551281634865583518297030282874472190772894086521144482721001553";
+const BASE8_Y: &str = "16950150798460657717958625567821834550301663161624707787222815936182638968203";
+
+/// An affine point; the identity is (0, 1)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Point {
+    pub x: Fr,
+    pub y: Fr,
+}
+
+pub fn suborder() -> BigUint {
+    BigUint::parse_bytes(SUBORDER.as_bytes(), 10).unwrap()
+}
+
+fn constant(n: u64) -> Fr {
+    Fr::from_str(&n.to_string()).unwrap()
+}
+
+impl Point {
+    pub fn identity() -> Self {
+        Point {
+            x: Fr::zero(),
+            y: Fr::one(),
+        }
+    }
+
+    /// Generator of the prime-order subgroup
+    pub fn base8() -> Self {
+        Point {
+            x: Fr::from_str(BASE8_X).unwrap(),
+            y: Fr::from_str(BASE8_Y).unwrap(),
+        }
+    }
+
+    pub fn is_on_curve(&self) -> bool {
+        let (mut x2, mut y2) = (self.x, self.y);
+        x2.square();
+        y2.square();
+        let mut lhs = constant(A);
+        lhs.mul_assign(&x2);
+        lhs.add_assign(&y2);
+        let mut rhs = constant(D);
+        rhs.mul_assign(&x2);
+        rhs.mul_assign(&y2);
+        rhs.add_assign(&Fr::one());
+        lhs == rhs
+    }
+
+    /// Complete addition: d is not a square, so this holds for every pair of points
+    pub fn add(&self, other: &Point) -> Point {
+        let mut x1y2 = self.x;
+        x1y2.mul_assign(&other.y);
+        let mut y1x2 = self.y;
+        y1x2.mul_assign(&other.x);
+        let mut x1x2 = self.x;
+        x1x2.mul_assign(&other.x);
+        let mut y1y2 = self.y;
+        y1y2.mul_assign(&other.y);
+        let mut dxy = constant(D);
+        dxy.mul_assign(&x1x2);
+        dxy.mul_assign(&y1y2);
+
+        // x3 = (x1·y2 + y1·x2) / (1 + d·x1·x2·y1·y2)
+        let mut x3 = x1y2;
+        x3.add_assign(&y1x2);
+        let mut x_den = Fr::one();
+        x_den.add_assign(&dxy);
+        x3.mul_assign(&x_den.inverse().expect("1 + d·x1·x2·y1·y2 is never zero"));
+
+        // y3 = (y1·y2 - a·x1·x2) / (1 - d·x1·x2·y1·y2)
+        let mut y3 = y1y2;
+        let mut ax = constant(A);
+        ax.mul_assign(&x1x2);
+        y3.sub_assign(&ax);
+        let mut y_den = Fr::one();
+        y_den.sub_assign(&dxy);
+        y3.mul_assign(&y_den.inverse().expect("1 - d·x1·x2·y1·y2 is never zero"));
+
+        Point { x: x3, y: y3 }
+    }
+
+    /// In the subgroup of order 8 (the identity among them): `8·self` is the
+    /// identity, so such a key or nonce point drops out of a signature check
+    pub fn is_small_order(&self) -> bool {
+        self.mul(&BigUint::from(8u32)) == Point::identity()
+    }
+
+    /// `scalar`·self by a Montgomery ladder: two additions and a masked swap per
+    /// bit over a fixed width, so the sequence of curve operations does not depend
+    /// on the scalar. This is not a constant-time guarantee: `ff_ce`'s field
+    /// arithmetic reduces with a data-dependent branch.
+    pub fn mul(&self, scalar: &BigUint) -> Point {
+        // Bits come from fixed-width limbs, not from the scalar's own length
+        let width = (scalar.bits().div_ceil(64) as usize).max(4);
+        let mut limbs = scalar.to_u64_digits();
+        limbs.resize(width, 0);
+        let mut r0 = Projective::from_affine(&Point::identity());
+        let mut r1 = Projective::from_affine(self);
+        for i in (0..64 * width).rev() {
+            // Invariant: r1 = r0 + self
+            let bit = Choice::from(((limbs[i / 64] >> (i % 64)) & 1) as u8);
+            Projective::conditional_swap(&mut r0, &mut r1, bit);
+            r1 = r0.add(&r1);
+            r0 = r0.add(&r0);
+            Projective::conditional_swap(&mut r0, &mut r1, bit);
+        }
+        r0.to_affine()
+    }
+}
+
+/// Projective coordinates (X : Y : Z) of the point (X/Z, Y/Z), so adding takes
+/// no field inversion
+#[derive(Clone, Copy, Debug)]
+struct Projective {
+    x: Fr,
+    y: Fr,
+    z: Fr,
+}
+
+impl Projective {
+    fn from_affine(point: &Point) -> Self {
+        Projective {
+            x: point.x,
+            y: point.y,
+            z: Fr::one(),
+        }
+    }
+
+    /// Complete addition (add-2008-bbjlp); doubles too
+    fn add(&self, other: &Projective) -> Projective {
+        let mut a = self.z;
+        a.mul_assign(&other.z);
+        let mut b = a;
+        b.square();
+        let mut c = self.x;
+        c.mul_assign(&other.x);
+        let mut d = self.y;
+        d.mul_assign(&other.y);
+        let mut e = constant(D);
+        e.mul_assign(&c);
+        e.mul_assign(&d);
+        let mut f = b;
+        f.sub_assign(&e);
+        let mut g = b;
+        g.add_assign(&e);
+
+        // X3 = A·F·((X1 + Y1)·(X2 + Y2) - C - D)
+        let mut x3 = self.x;
+        x3.add_assign(&self.y);
+        let mut sum2 = other.x;
+        sum2.add_assign(&other.y);
+        x3.mul_assign(&sum2);
+        x3.sub_assign(&c);
+        x3.sub_assign(&d);
+        x3.mul_assign(&a);
+        x3.mul_assign(&f);
+
+        // Y3 = A·G·(D - a·C)
+        let mut y3 = d;
+        let mut ac = constant(A);
+        ac.mul_assign(&c);
+        y3.sub_assign(&ac);
+        y3.mul_assign(&a);
+        y3.mul_assign(&g);
+
+        // Z3 = F·G
+        let mut z3 = f;
+        z3.mul_assign(&g);
+        Projective { x: x3, y: y3, z: z3 }
+    }
+
+    /// Back to affine, inverting Z as Z^(p-2) so the inversion takes the same
+    /// steps for every Z
+    fn to_affine(self) -> Point {
+        let mut exponent = Fr::char();
+        exponent.sub_noborrow(&FrRepr::from(2));
+        let z_inverse = self.z.pow(exponent);
+        let (mut x, mut y) = (self.x, self.y);
+        x.mul_assign(&z_inverse);
+        y.mul_assign(&z_inverse);
+        Point { x, y }
+    }
+
+    /// Swap `a` and `b` if `swap` is set, without branching on it
+    fn conditional_swap(a: &mut Projective, b: &mut Projective, swap: Choice) {
+        for (u, v) in [(&mut a.x, &mut b.x), (&mut a.y, &mut b.y), (&mut a.z, &mut b.z)] {
+            let (mut ru, mut rv) = (u.into_raw_repr(), v.into_raw_repr());
+            for (lu, lv) in ru.as_mut().iter_mut().zip(rv.as_mut().iter_mut()) {
+                u64::conditional_swap(lu, lv, swap);
+            }
+            *u = Fr::from_raw_repr(ru).expect("a swapped coordinate is still a field element");
+            *v = Fr::from_raw_repr(rv).expect("a swapped coordinate is still a field element");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base8_generates_the_prime_subgroup() {
+        let base8 = Point::base8();
+        assert!(base8.is_on_curve());
+        assert_ne!(base8, Point::identity());
+        assert_eq!(base8.mul(&suborder()), Point::identity());
+
+        // circomlib's generator, times the cofactor
+        let generator = Point {
+            x: Fr::from_str("995203441582195749578291179787384436505546430278305826713579947235728471134").unwrap(),
+            y: Fr::from_str("5472060717959818805561601436314318772137091100104008585924551046643952123905").unwrap(),
+        };
+        assert!(generator.is_on_curve());
+        assert_eq!(generator.mul(&BigUint::from(8u32)), base8);
+
+        // circomlib's key derivation test vector (test/eddsa.js): the private key
+        // 0x000102..0001 prunes to this scalar, whose public key is pinned there
+        let scalar = BigUint::parse_bytes(
+            b"6466070937662820620902051049739362987537906109895538826186780010858059362905",
+            10,
+        )
+        .unwrap();
+        let public = base8.mul(&scalar);
+        assert_eq!(
+            public.x,
+            Fr::from_str("13277427435165878497778222415993513565335242147425444199013288855685581939618").unwrap()
+        );
+        assert_eq!(
+            public.y,
+            Fr::from_str("13622229784656158136036771217484571176836296686641868549125388198837476602820").unwrap()
+        );
+
+        // Scalar multiplication distributes over addition of scalars
+        let (a, b) = (BigUint::from(12345u32), BigUint::from(67890u32));
+        assert_eq!(base8.mul(&a).add(&base8.mul(&b)), base8.mul(&(a + b)));
+    }
+
+    /// `scalar`·point by plain double-and-add over affine additions
+    fn double_and_add(point: &Point, scalar: &BigUint) -> Point {
+        let mut acc = Point::identity();
+        for i in (0..scalar.bits()).rev() {
+            acc = acc.add(&acc);
+            if scalar.bit(i) {
+                acc = acc.add(point);
+            }
+        }
+        acc
+    }
+
+    #[test]
+    fn test_ladder_matches_double_and_add() {
+        let base8 = Point::base8();
+        let generator = base8.mul(&BigUint::from(5u32));
+        let scalars = [
+            BigUint::from(0u32),
+            BigUint::from(1u32),
+            BigUint::from(8u32),
+            suborder() - 1u32,
+            suborder(),
+            BigUint::parse_bytes(b"30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000000", 16).unwrap(),
+        ];
+        for scalar in &scalars {
+            assert_eq!(base8.mul(scalar), double_and_add(&base8, scalar), "{}", scalar);
+            assert_eq!(generator.mul(scalar), double_and_add(&generator, scalar), "{}", scalar);
+        }
+        assert_eq!(base8.mul(&BigUint::from(0u32)), Point::identity());
+    }
+}
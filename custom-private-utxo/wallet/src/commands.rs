@@ -0,0 +1,6390 @@
+//! Wallet commands, with the proving backend and contract injected by the caller
+
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use alloy::primitives::{keccak256, Bytes, FixedBytes, TxHash};
+
+use crate::assets::{AssetMeta, AssetRegistry};
+use crate::attempt::Attempt;
+use crate::calldata::{CalldataBundle, Encoding, PreparedMint};
+use crate::config::{
+    CIRCUIT_DIR, CIRCUIT_INPUTS, RETRY_BUDGET, ROOT_HISTORY_SIZE, TREE_BATCH_FLUSH,
+};
+use crate::contract::{
+    bind_proof_network, bundle_calldata, check_proof_network, decode_mint_batch, mint_batch_calldata,
+    BondContract, NetworkBinding, TxError,
+};
+use crate::disclosure::{Disclosure, DisclosurePackage};
+use crate::error::WalletError;
+use crate::events::{EventSink, PendingAction};
+use crate::field::Fr;
+use crate::history::{self, HistoryRecord};
+use crate::keys::{
+    auth_key_hex, parse_auth_key, parse_public_spending_key, ChallengeSignature, ShieldedKeys, TransferReceipt,
+    CURRENT_KDF_VERSION,
+};
+use crate::merkle::{BuildProgress, MAX_LEAVES, TREE_HEIGHT};
+use crate::notes::{self, MemoEnvelope, Note};
+use crate::prover::{
+    self, witness_id, CircuitNote, JoinSplitInputs, ProofMeta, Prover, WitnessBuilder, DUMMY_SALT,
+    TRANSFER_PUBLIC_INPUTS,
+};
+use crate::scheme::{self, CommitmentScheme};
+use crate::spent::SpentNullifiers;
+use crate::utils::{
+    archive_dir, bonds_in, data_dir, ensure_data_dir, format_date, fr_from_bytes32, fr_from_hex,
+    fr_to_bytes32, fr_to_hex, global_note_path, history_path, index_bond, load_bond, load_wallet, maturity_status,
+    canonical_order, parse_commitment, resolve_bond_path, save_wallet, short, spent_nullifiers_path, unix_now, wallet_exists, wallet_path, witness_path,
+    write_atomic, Bond, Leaf, MaturityStatus, RedemptionReceipt, Salt, TreeState, Wallet, BOND_SCHEMA_VERSION,
+    WALLET_SCHEMA_VERSION,
+};
+
+/// Print a command's failure and hand it back, typed, for the exit code
+fn fail<T>(e: impl Into<WalletError>) -> Result<T, WalletError> {
+    let e = e.into();
+    println!("❌ {}", e);
+    Err(e)
+}
+
+/// Load `name`, or fail with `WalletNotFound`
+fn require_wallet(name: &str) -> Result<Wallet, WalletError> {
+    load_wallet(name).ok_or_else(|| WalletError::wallet_not_found(name))
+}
+
+/// Generate a proof and record the network it is meant for next to it
+async fn prove_bound(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    witness: &WitnessBuilder,
+) -> Result<String, WalletError> {
+    prove_bound_leg(prover, contract, events, witness, None).await
+}
+
+/// `prove_bound`, as one named leg of several proven concurrently
+async fn prove_bound_leg(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    witness: &WitnessBuilder,
+    leg: Option<&str>,
+) -> Result<String, WalletError> {
+    events.on_witness_built(witness);
+    let network = contract.network().await.map_err(|e| e.to_string())?;
+    events.on_proof_started();
+    let path = match leg {
+        Some(leg) => prover.prove_leg(witness, leg).await?,
+        None => prover.prove(witness).await?,
+    };
+    bind_proof_network(&path, &network)?;
+    events.on_proof_done(&path);
+    Ok(path)
+}
+
+/// Prove one leg of a trade and read the proof back for submission
+async fn prove_trade_leg(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    witness: &WitnessBuilder,
+    leg: &str,
+) -> Result<Vec<u8>, WalletError> {
+    let name = leg.to_uppercase();
+    let path = prove_bound_leg(prover, contract, events, witness, Some(leg))
+        .await
+        .map_err(|e| e.map_message(|m| format!("Proof {} generation failed: {}", name, m)))?;
+    Ok(load_bound_proof(contract, &path)
+        .await
+        .map_err(|e| format!("Cannot use proof {}: {}", name, e))?)
+}
+
+/// Read a proof for submission, refusing one generated for a different network
+async fn load_bound_proof(
+    contract: &impl BondContract,
+    proof_path: &str,
+) -> Result<Vec<u8>, String> {
+    let network = contract.network().await.map_err(|e| e.to_string())?;
+    check_proof_network(proof_path, &network)?;
+    if let Some(meta) = ProofMeta::load(proof_path) {
+        meta?.check_public_inputs(TRANSFER_PUBLIC_INPUTS.len())?;
+    }
+    fs::read(proof_path).map_err(|e| format!("Failed to read proof file: {}", e))
+}
+
+/// Persist the structured witness next to the proof, keyed by the transaction hash
+pub fn save_witness(witness: &WitnessBuilder, key: &str) {
+    let path = witness_path(key);
+    match witness.save_json(&path) {
+        Ok(_) => println!("   📝 Witness saved to: {}", path),
+        Err(e) => println!("   ⚠️  {}", e),
+    }
+}
+
+/// Every commitment the contract holds, read leaf by leaf until the getter fails
+/// (there is no count getter), with the error that stopped the read. `None` when
+/// only exporting: nothing on-chain to read.
+async fn read_commitments(contract: &impl BondContract) -> Option<(Vec<FixedBytes<32>>, TxError)> {
+    let mut stored = Vec::new();
+    loop {
+        match contract.commitment_at(stored.len() as u64).await {
+            Ok(Some(commitment)) => stored.push(commitment),
+            Ok(None) => return None,
+            Err(e) => return Some((stored, e)),
+        }
+    }
+}
+
+/// A confirmed `mintBatch` is not proof the contract stored the commitments. Read
+/// the list back and require it to end with what was minted (only the owner can
+/// mint, so nothing else should have landed after them).
+async fn check_minted(contract: &impl BondContract, minted: &[FixedBytes<32>]) -> Result<(), String> {
+    let Some((stored, stopped)) = read_commitments(contract).await else {
+        return Ok(());
+    };
+    if stored.ends_with(minted) {
+        Ok(())
+    } else {
+        Err(format!(
+            "mintBatch confirmed but the contract does not hold the {} minted commitment(s) ({} read back, {})",
+            minted.len(),
+            stored.len(),
+            stopped
+        ))
+    }
+}
+
+/// Log a confirmed call to `data/history.jsonl`; a failed write only warns, as the
+/// call itself already went through. A redemption logs its settlement details, and
+/// no value when its receipt was made `--private`.
+async fn log_history(
+    contract: &impl BondContract,
+    command: &str,
+    nullifiers: &[FixedBytes<32>],
+    commitments: &[FixedBytes<32>],
+    value: u64,
+    tx_hash: TxHash,
+    redemption: Option<&RedemptionReceipt>,
+) {
+    let record = HistoryRecord {
+        timestamp: Utc::now().to_rfc3339(),
+        command: command.to_string(),
+        nullifiers: nullifiers.iter().map(|n| format!("{}", n)).collect(),
+        commitments: commitments.iter().map(|c| format!("{}", c)).collect(),
+        value: redemption.map_or(Some(value), |r| r.value),
+        tx_hash: format!("{}", tx_hash),
+        network: contract.network().await.map(|n| n.to_string()).unwrap_or_default(),
+        settlement_ref: redemption.and_then(|r| r.settlement_ref.clone()),
+        settlement_account: redemption.and_then(|r| r.settlement_account.clone()),
+    };
+    if let Err(e) = history::append_history(&record) {
+        println!("   ⚠️  Transaction not logged to history: {}", e);
+    }
+}
+
+/// If `commitment` is missing from the local tree but the contract holds it, offer
+/// (through `events`) to append every on-chain commitment the tree lacks, in chain
+/// order. Returns the commitment's index once it is in the tree, `None` if it is
+/// still missing or the contract holds a leaf the tree cannot (`chain_leaves`).
+async fn repair_tree_from_chain(
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    tree_state: &mut TreeState,
+    commitment: &Fr,
+) -> Option<usize> {
+    if let Some(index) = tree_state.find_commitment(commitment) {
+        return Some(index);
+    }
+    let (stored, _) = read_commitments(contract).await?;
+    let leaves = match chain_leaves(&stored) {
+        Ok(leaves) => leaves,
+        Err(e) => {
+            println!("⚠️  Local tree not repaired: {}", e);
+            return None;
+        }
+    };
+    let missing: Vec<Fr> = canonical_order(leaves)
+        .into_iter()
+        .map(|l| l.commitment)
+        .filter(|c| tree_state.find_commitment(c).is_none())
+        .collect();
+    if !missing.contains(commitment) {
+        return None;
+    }
+    println!("⚠️  Source note is on-chain but not in the local merkle tree");
+    if !events.confirm_repair(missing.len()) {
+        println!("   ℹ️  Local tree left as is");
+        return None;
+    }
+    tree_state.begin_batch(TREE_BATCH_FLUSH);
+    for c in missing {
+        let index = tree_state.add_commitment(c);
+        println!("   🔧 Restored {} at tree index {}", short(&fr_to_hex(&c), 18), index);
+    }
+    tree_state.commit();
+    tree_state.find_commitment(commitment)
+}
+
+/// The contract's leaves with their indices. A leaf that is not a field element
+/// has no place in the circuit's tree, and skipping it would shift every later
+/// leaf off its on-chain index, so it is an error.
+fn chain_leaves(stored: &[FixedBytes<32>]) -> Result<Vec<Leaf>, String> {
+    stored
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let commitment = fr_from_bytes32(c)
+                .ok_or_else(|| format!("Contract leaf {} ({}) is not a field element", i, c))?;
+            Ok(Leaf {
+                index: Some(i as u64),
+                commitment,
+            })
+        })
+        .collect()
+}
+
+/// Create an issuer wallet and mint its tranche note. An existing wallet of that
+/// name is only replaced with `force`, since the notes its keys own are lost with it;
+/// even then it is copied aside first, and only replaced once the mint is confirmed.
+pub async fn onboard(contract: &impl BondContract, wallet_name: &str, label: Option<&str>, force: bool) -> Result<(), WalletError> {
+    println!("\n🔐 Issuer Onboarding: Creating initial bond tranche...");
+
+    // Ensure data directory exists
+    if let Err(e) = ensure_data_dir() {
+        return fail(e.to_string());
+    }
+
+    // Check if wallet already exists; whatever is replaced is kept under this stamp
+    let filename = wallet_path(wallet_name);
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S%.6fZ").to_string();
+    if wallet_exists(wallet_name) {
+        if !force {
+            println!("⚠️  Wallet '{}' already exists", wallet_name);
+            println!("   Onboarding again replaces its keys and orphans every note they own; pass --force to do it anyway");
+            return Err(WalletError::Other(format!("Wallet '{}' already exists", wallet_name)));
+        }
+        let backup = format!("{}.bak-{}", filename, stamp);
+        if Path::new(&backup).exists() {
+            return fail(format!("Backup {} already exists; not replacing it", backup));
+        }
+        if let Err(e) = fs::copy(&filename, &backup) {
+            return fail(format!("Failed to back up {} before replacing it: {}", filename, e));
+        }
+        println!("⚠️  Replacing wallet '{}' (--force) once the new tranche is minted", wallet_name);
+        println!("   Its old keys are kept at {}", backup);
+    }
+
+    // Generate keys for issuer; the tranche note's salt is their first, so it is
+    // saved with the wallet
+    let mut keys = ShieldedKeys::generate();
+    let salt = keys.next_salt();
+
+    let wallet = Wallet {
+        schema_version: WALLET_SCHEMA_VERSION,
+        keys: keys.clone(),
+        created_at: Utc::now().to_rfc3339(),
+    };
+
+    // Create initial Global Note commitment for the bond tranche
+    // Example: $100M bond tranche maturing 2030-01-01
+    let global_value = 100_000_000u64; // $100M in smallest units
+    let maturity_date = 1893456000u64; // 2030-01-01
+
+
+    // Get owner as Fr (proper field element)
+    let owner_fr = keys.public_spending_key();
+
+    // Create CircuitNote for commitment computation (matches circuit exactly)
+    let global_note = match CircuitNote::for_mint(global_value, salt, owner_fr, 1, maturity_date, unix_now()) {
+        Ok(note) => note,
+        Err(e) => return fail(e.to_string()),
+    };
+
+    // Compute commitment using CircuitNote.commitment() - matches circuit's note_commit
+    let commitment = global_note.commitment();
+    println!("\n📊 Global Note (Bond Tranche):");
+    println!("   Value:     {} (units)", global_value);
+    println!(
+        "   Maturity:  {} ({})",
+        maturity_date,
+        format_date(maturity_date)
+    );
+    println!("   Commitment: {}", fr_to_hex(&commitment));
+
+    // Mint the note and its padding dummy, in the order the local tree holds them
+    let dummy_commitment = global_note.padding().commitment();
+    let minted = vec![fr_to_bytes32(&commitment), fr_to_bytes32(&dummy_commitment)];
+    match contract.mint_batch(minted.clone()).await {
+        Ok(tx_hash) => {
+            println!("   Mint transaction confirmed: {:?}", tx_hash);
+            log_history(contract, "onboard", &[], &minted, global_value, tx_hash, None).await;
+        }
+        Err(e) => return fail(WalletError::from(e).map_message(|m| format!("Failed to mint note batch: {}", m))),
+    }
+    if let Err(e) = check_minted(contract, &minted).await {
+        return fail(e);
+    }
+
+    // Save wallet, now that the tranche its keys own is on-chain
+    match save_wallet(wallet_name, &wallet) {
+        Ok(_) => {
+            println!("✅ Issuer wallet created!");
+            println!("   Saved to: {}", filename);
+        }
+        Err(e) => return fail(format!("Error: {}", e)),
+    }
+
+    // Add commitment to the global tree state
+    let mut tree_state = TreeState::load();
+    let leaf_index = tree_state.add_commitment(commitment);
+    println!("   Added real note to merkle tree at index: {}", leaf_index);
+
+    // Also add the dummy note (value=0, salt=0, same owner) to the tree
+    // This is required because the circuit verifies merkle proofs for both inputs
+    let dummy_index = tree_state.add_commitment(dummy_commitment);
+    println!(
+        "   Added dummy note to merkle tree at index: {}",
+        dummy_index
+    );
+
+    // Save the global note as initial bond
+    let bond = Bond {
+        schema_version: BOND_SCHEMA_VERSION,
+        commitment: fr_to_hex(&commitment),
+        nullifier: "N/A (Global Note)".to_string(),
+        value: global_value,
+        salt,
+        owner: keys.public_spending_key_hex,
+        asset_id: 1,
+        maturity_date,
+        created_at: Utc::now().to_rfc3339(),
+        label: label.map(str::to_string),
+        memo_hash: None,
+    };
+
+    // The tranche file there (an earlier onboard's, with its value, salt and label)
+    // is moved aside, not overwritten
+    let filename = global_note_path();
+    if Path::new(&filename).exists() {
+        let backup = format!("{}.bak-{}", filename, stamp);
+        if let Err(e) = fs::rename(&filename, &backup) {
+            return fail(format!("Failed to move {} aside before replacing it: {}", filename, e));
+        }
+        println!("   Earlier tranche note moved to {}", backup);
+    }
+    match write_atomic(&filename, serde_json::to_string_pretty(&bond).unwrap()) {
+        Ok(_) => {
+            println!("\n✅ Global note saved to: {}", filename);
+            index_bond(&mut tree_state, &filename, &bond);
+            Ok(())
+        }
+        Err(e) => fail(format!("Error saving: {}", e)),
+    }
+}
+
+/// Prepare a tranche mint for an existing issuer wallet without the network: the
+/// note, plus its padding dummy, and `mintBatch` calldata minting both in the order
+/// the local tree will hold them. Nothing is written to the tree until
+/// `submit_prepared_file` sees the mint confirmed.
+pub fn prepare_mint(
+    wallet_name: &str,
+    value: u64,
+    asset_id: u64,
+    maturity_date: u64,
+    network: NetworkBinding,
+    encoding: Encoding,
+) -> Result<PreparedMint, WalletError> {
+    let mut wallet = require_wallet(wallet_name)?;
+    let salt = draw_salts(wallet_name, &mut wallet, 1)?[0];
+    let note = CircuitNote::for_mint(
+        value,
+        salt,
+        wallet.keys.public_spending_key(),
+        asset_id,
+        maturity_date,
+        unix_now(),
+    )
+    .map_err(|e| e.to_string())?;
+    let leaves = [note.commitment(), note.padding().commitment()];
+    Ok(PreparedMint {
+        network,
+        encoding,
+        calldata: encoding.encode(&mint_batch_calldata(leaves.iter().map(fr_to_bytes32).collect())),
+        commitments: leaves.iter().map(fr_to_hex).collect(),
+        wallet: wallet_name.to_string(),
+        bond: Bond {
+            schema_version: BOND_SCHEMA_VERSION,
+            commitment: fr_to_hex(&leaves[0]),
+            nullifier: fr_to_hex(&wallet.keys.sign_nullifier(salt)),
+            value,
+            salt,
+            owner: wallet.keys.public_spending_key_hex.clone(),
+            asset_id,
+            maturity_date,
+            created_at: Utc::now().to_rfc3339(),
+            label: None,
+            memo_hash: None,
+        },
+    })
+}
+
+pub fn prepare_mint_to_file(
+    wallet_name: &str,
+    value: u64,
+    asset_id: u64,
+    maturity_date: u64,
+    network: NetworkBinding,
+    encoding: Encoding,
+    out: &str,
+) -> Result<(), WalletError> {
+    println!("\n📝 Preparing mintBatch for wallet '{}'...", wallet_name);
+    match prepare_mint(wallet_name, value, asset_id, maturity_date, network, encoding)
+        .and_then(|prepared| Ok(prepared.save(out).map(|_| prepared)?))
+    {
+        Ok(prepared) => {
+            println!("   Value:      {} (asset {})", value, asset_id);
+            println!("   Maturity:   {} ({})", maturity_date, format_date(maturity_date));
+            println!("   Commitment: {}", prepared.bond.commitment);
+            println!("✅ Written to {}; submit it with `submit-prepared` on an online machine", out);
+            Ok(())
+        }
+        Err(e) => fail(e),
+    }
+}
+
+/// Send a `PreparedMint` and, once the contract holds its leaves, append them to the
+/// local tree and save the note. Returns the saved bond file.
+pub async fn submit_prepared_file(contract: &impl BondContract, file: &str) -> Result<String, WalletError> {
+    let prepared = PreparedMint::load(file)?;
+    let network = contract.network().await.map_err(|e| e.to_string())?;
+    if prepared.network != network {
+        return Err(WalletError::Other(format!("{} was prepared for {}, not {}", file, prepared.network, network)));
+    }
+
+    // The calldata is what gets sent; it must mint exactly the leaves recorded locally
+    let minted = decode_mint_batch(&prepared.encoding.decode(&prepared.calldata)?)?;
+    let leaves: Vec<Fr> = prepared
+        .commitments
+        .iter()
+        .map(|c| fr_from_hex(c).ok_or_else(|| format!("Invalid commitment {}", c)))
+        .collect::<Result<_, _>>()?;
+    if minted != leaves.iter().map(fr_to_bytes32).collect::<Vec<_>>() {
+        return Err(WalletError::Other(format!("{}: calldata does not mint the listed commitments", file)));
+    }
+    if parse_commitment(&prepared.bond.commitment) != leaves.first().copied() {
+        return Err(WalletError::Other(format!("{}: the note is not the first minted commitment", file)));
+    }
+    let mut tree_state = TreeState::load();
+    if leaves.iter().any(|c| tree_state.find_commitment(c).is_some()) {
+        return Err(WalletError::Other(format!("{}: already in the local tree; was it submitted before?", file)));
+    }
+
+    let tx_hash = contract.mint_batch(minted.clone()).await?;
+    println!("   Mint transaction confirmed: {:?}", tx_hash);
+    check_minted(contract, &minted).await?;
+    log_history(contract, "submit-prepared", &[], &minted, prepared.bond.value, tx_hash, None).await;
+
+    tree_state.begin_batch(TREE_BATCH_FLUSH);
+    for leaf in leaves {
+        let index = tree_state.add_commitment(leaf);
+        println!("   Added {} to merkle tree at index: {}", short(&fr_to_hex(&leaf), 18), index);
+    }
+    tree_state.commit();
+    ensure_data_dir().map_err(|e| e.to_string())?;
+    let bond = prepared.bond;
+    let path = format!(
+        "{}/bond_{}_{}.json",
+        data_dir(),
+        prepared.wallet,
+        &format!("{:016x}", bond.salt)[..8]
+    );
+    bond.save(&path)?;
+    index_bond(&mut tree_state, &path, &bond);
+    Ok(path)
+}
+
+pub async fn submit_prepared(contract: &impl BondContract, file: &str) -> Result<(), WalletError> {
+    println!("\n📡 Submitting prepared mint {}...", file);
+    match submit_prepared_file(contract, file).await {
+        Ok(path) => {
+            println!("✅ Minted; note saved to {}", path);
+            Ok(())
+        }
+        Err(e) => fail(e),
+    }
+}
+
+pub fn register(wallet_name: &str) -> Result<(), WalletError> {
+    println!("\n📋 Registering new wallet...");
+
+    if wallet_name.contains('@') {
+        return fail(format!("'{}' names a derived account; only base wallets are created", wallet_name));
+    }
+
+    // Ensure data directory exists
+    if let Err(e) = ensure_data_dir() {
+        return fail(e.to_string());
+    }
+
+    // Check if wallet already exists
+    if wallet_exists(wallet_name) {
+        println!("⚠️  Wallet '{}' already exists", wallet_name);
+        return Err(WalletError::Other(format!("Wallet '{}' already exists", wallet_name)));
+    }
+
+    // Generate keys
+    let keys = ShieldedKeys::generate();
+
+    let wallet = Wallet {
+        schema_version: WALLET_SCHEMA_VERSION,
+        keys: keys.clone(),
+        created_at: Utc::now().to_rfc3339(),
+    };
+
+    // Save wallet
+    let filename = wallet_path(wallet_name);
+    match save_wallet(wallet_name, &wallet) {
+        Ok(_) => {
+            println!("✅ Wallet created!");
+            println!("   Saved to: {}", filename);
+            println!("   Public key: {}", keys.public_spending_key_hex);
+            println!("   Receipt key: {}", hex::encode(keys.receipt_verifying_key()));
+            println!("   Auth key:    {}", auth_key_hex(&keys.auth_public_key()));
+            Ok(())
+        }
+        Err(e) => fail(format!("Error: {}", e)),
+    }
+}
+
+/// Import a wallet from its seed; `kdf_version` must match how the original derived its keys
+pub fn import(wallet_name: &str, seed_hex: &str, kdf_version: u8, salt_counter: u64) -> Result<(), WalletError> {
+    println!("\n📥 Importing wallet from seed...");
+
+    if wallet_name.contains('@') {
+        return fail(format!("'{}' names a derived account; only base wallets are created", wallet_name));
+    }
+
+    // Ensure data directory exists
+    if let Err(e) = ensure_data_dir() {
+        return fail(e.to_string());
+    }
+
+    if wallet_exists(wallet_name) {
+        println!("⚠️  Wallet '{}' already exists", wallet_name);
+        return Err(WalletError::Other(format!("Wallet '{}' already exists", wallet_name)));
+    }
+
+    let mut keys = match ShieldedKeys::from_seed_hex(seed_hex, kdf_version) {
+        Ok(k) => k,
+        Err(e) => return fail(e),
+    };
+    keys.set_salt_counter(salt_counter);
+
+    let wallet = Wallet {
+        schema_version: WALLET_SCHEMA_VERSION,
+        keys: keys.clone(),
+        created_at: Utc::now().to_rfc3339(),
+    };
+
+    let filename = wallet_path(wallet_name);
+    match save_wallet(wallet_name, &wallet) {
+        Ok(_) => {
+            println!("✅ Wallet imported!");
+            println!("   Saved to: {}", filename);
+            println!("   Public key: {}", keys.public_spending_key_hex);
+            println!("   Receipt key: {}", hex::encode(keys.receipt_verifying_key()));
+            println!("   Auth key:    {}", auth_key_hex(&keys.auth_public_key()));
+            if salt_counter == 0 {
+                println!("   ⚠️  Salts restart at the beginning of the seed's sequence; if the original wallet");
+                println!("      drew any, pass its salt_counter with --salt-counter so none is drawn twice");
+            }
+            Ok(())
+        }
+        Err(e) => fail(format!("Error: {}", e)),
+    }
+}
+
+/// Re-derive a wallet's spending key with the current key derivation.
+/// Notes are owned by the old public key, so this refuses while any are unspent;
+/// the old wallet file is kept next to the new one.
+pub async fn migrate_keys(contract: &impl BondContract, wallet_name: &str) -> Result<String, WalletError> {
+    let wallet = require_wallet(wallet_name)?;
+    let from = wallet.keys.kdf_version();
+    if from == CURRENT_KDF_VERSION {
+        return Err(WalletError::Other(format!(
+            "Wallet '{}' already uses key derivation v{}",
+            wallet_name, from
+        )));
+    }
+
+    let unspent = wallet_balance(contract, wallet_name).await?;
+    if !unspent.notes.is_empty() {
+        return Err(WalletError::Other(format!(
+            "Wallet '{}' holds {} unspent notes owned by its v{} key ({}); redeem or trade them first",
+            wallet_name,
+            unspent.notes.len(),
+            from,
+            unspent.notes.iter().map(|n| n.path.as_str()).collect::<Vec<_>>().join(", ")
+        )));
+    }
+
+    let path = wallet_path(wallet_name);
+    let backup = format!("{}.kdf{}.json", path.trim_end_matches(".json"), from);
+    fs::copy(&path, &backup).map_err(|e| format!("Failed to back up {}: {}", path, e))?;
+
+    // The salt sequence depends on the seed alone, so it carries on where it was
+    let mut keys = ShieldedKeys::from_seed_versioned(*wallet.keys.seed(), CURRENT_KDF_VERSION)?;
+    keys.set_salt_counter(wallet.keys.salt_counter());
+    let migrated = Wallet {
+        schema_version: WALLET_SCHEMA_VERSION,
+        keys,
+        created_at: wallet.created_at,
+    };
+    save_wallet(wallet_name, &migrated).map_err(|e| format!("Failed to save {}: {}", path, e))?;
+    Ok(backup)
+}
+
+pub async fn migrate(contract: &impl BondContract, wallet_name: &str) -> Result<(), WalletError> {
+    println!("\n🔑 Migrating keys of '{}'...", wallet_name);
+    match migrate_keys(contract, wallet_name).await {
+        Ok(backup) => {
+            let wallet = load_wallet(wallet_name).expect("just saved");
+            println!("✅ Keys re-derived with v{}", CURRENT_KDF_VERSION);
+            println!("   Public key: {}", wallet.keys.public_spending_key_hex);
+            println!("   Old wallet kept at: {}", backup);
+            Ok(())
+        }
+        Err(e) => fail(e),
+    }
+}
+
+/// How `buy` picks the issuer note it splits
+pub enum SourceNote {
+    /// Bond file at this path
+    Path(String),
+    /// The owned bond file with this commitment
+    Commitment(String),
+    /// The largest unspent owned note of this asset
+    Auto { asset_id: u64 },
+    /// The tranche `onboard` wrote (`global_note_tranche.json` in the data directory)
+    Tranche,
+}
+
+impl SourceNote {
+    /// `buy`'s source flags: an explicit path wins, then a commitment, then `--auto`;
+    /// with none of them, the tranche
+    pub fn from_args(path: Option<String>, commitment: Option<String>, auto: bool, asset_id: u64) -> Self {
+        match (path, commitment) {
+            (Some(path), _) => SourceNote::Path(path),
+            (_, Some(commitment)) => SourceNote::Commitment(commitment),
+            _ if auto => SourceNote::Auto { asset_id },
+            _ => SourceNote::Tranche,
+        }
+    }
+}
+
+/// Draw `count` salts from `wallet_name`'s sequence, saving the advanced counter
+/// before any of them is used. Only the counter is written back, onto the wallet
+/// file as it is now: a sub-account (`name@N`) is never saved itself, so its
+/// counter lives in the base wallet's file, which another account may have
+/// advanced since `wallet` was loaded (a trade between two accounts of one seed).
+fn draw_salts(wallet_name: &str, wallet: &mut Wallet, count: usize) -> Result<Vec<u64>, String> {
+    let salts = (0..count).map(|_| wallet.keys.next_salt()).collect();
+    let (base, account) = match wallet_name.rsplit_once('@') {
+        Some((base, account)) => {
+            let account: u32 = account.parse().map_err(|_| format!("Invalid account in '{}'", wallet_name))?;
+            (base, account)
+        }
+        None => (wallet_name, 0),
+    };
+    let mut saved = load_wallet(base).ok_or_else(|| format!("Wallet '{}' not found", base))?;
+    match account {
+        0 => saved.keys.set_salt_counter(wallet.keys.salt_counter()),
+        n => saved.keys.set_account_salt_counter(n, wallet.keys.salt_counter()),
+    }
+    save_wallet(base, &saved).map_err(|e| format!("Failed to save {}: {}", wallet_path(base), e))?;
+    Ok(salts)
+}
+
+/// Resolve a source selector to a bond file owned by `owner`.
+/// Lookups by commitment or `Auto` skip notes whose nullifier is already spent.
+pub async fn select_source_note(
+    contract: &impl BondContract,
+    owner: &Wallet,
+    source: &SourceNote,
+) -> Result<(String, Bond), String> {
+    if let SourceNote::Path(path) = source {
+        return load_bond(path)
+            .map(|bond| (path.clone(), bond))
+            .ok_or_else(|| format!("Source note '{}' not found.", path));
+    }
+    if let SourceNote::Tranche = source {
+        let path = global_note_path();
+        let bond = load_bond(&path)
+            .ok_or_else(|| format!("No tranche note at {}; run onboard or pass --source-note.", path))?;
+        if bond.owner != owner.keys.public_spending_key_hex {
+            return Err(format!(
+                "Tranche note {} is not owned by this issuer wallet; pass --source-note.",
+                path
+            ));
+        }
+        return Ok((path, bond));
+    }
+
+    let wanted = match source {
+        SourceNote::Commitment(hex) => {
+            Some(parse_commitment(hex).ok_or_else(|| format!("Invalid commitment '{}'.", hex))?)
+        }
+        _ => None,
+    };
+
+    // Auto never picks a matured note: it can only be redeemed
+    let policy = MaturityPolicy::now();
+    let mut candidates = Vec::new();
+    // Filled on the first failed on-chain check (offline, or the node is down)
+    let mut local_spent: Option<SpentNullifiers> = None;
+    for (path, bond) in owned_bonds(owner) {
+        let keep = match (source, wanted) {
+            (SourceNote::Auto { asset_id }, _) => {
+                bond.asset_id == *asset_id && bond.value > 0 && policy.check_transfer(&bond).is_ok()
+            }
+            (_, wanted) => parse_commitment(&bond.commitment) == wanted,
+        };
+        if !keep {
+            continue;
+        }
+        let nullifier = fr_to_bytes32(&owner.keys.sign_nullifier(bond.salt));
+        let spent = match contract.nullifier_spent(nullifier).await {
+            Ok(spent) => spent,
+            Err(e) => {
+                // As in `check_source_unspent`: warn and fall back to the local spent set
+                if local_spent.is_none() {
+                    println!("   ⚠️  Could not check source notes on-chain: {}", e);
+                    println!("      Using the local spent set ({})", spent_nullifiers_path());
+                    local_spent = Some(SpentNullifiers::load()?);
+                }
+                local_spent.as_ref().is_some_and(|local| local.contains(&nullifier))
+            }
+        };
+        if spent {
+            continue;
+        }
+        candidates.push((path, bond));
+    }
+
+    match source {
+        SourceNote::Commitment(hex) => match candidates.len() {
+            0 => Err(format!("No unspent owned note with commitment {}.", hex)),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(format!(
+                "Several owned notes match commitment {}: {}; pass --source-note.",
+                hex,
+                candidates.iter().map(|(p, _)| p.as_str()).collect::<Vec<_>>().join(", ")
+            )),
+        },
+        SourceNote::Auto { asset_id } => {
+            let largest = candidates.iter().map(|(_, b)| b.value).max().ok_or_else(|| {
+                format!("No unspent owned note of asset {} to buy from.", asset_id)
+            })?;
+            candidates.retain(|(_, b)| b.value == largest);
+            if candidates.len() > 1 {
+                return Err(format!(
+                    "Several owned notes of asset {} share the largest value {}: {}; pass --source-note.",
+                    asset_id,
+                    largest,
+                    candidates.iter().map(|(p, _)| p.as_str()).collect::<Vec<_>>().join(", ")
+                ));
+            }
+            Ok(candidates.remove(0))
+        }
+        SourceNote::Path(_) | SourceNote::Tranche => unreachable!(),
+    }
+}
+
+/// Refuse a source note whose nullifier is already spent: in this wallet's history,
+/// in the set `sync-nullifiers` rebuilt, or on-chain. Without a node (`--offline`)
+/// only the local records are checked.
+pub async fn check_source_unspent(
+    contract: &impl BondContract,
+    owner: &ShieldedKeys,
+    bond: &Bond,
+) -> Result<(), String> {
+    let nullifier = fr_to_bytes32(&owner.sign_nullifier(bond.salt));
+    if history::spent_nullifiers()?.contains(&format!("{}", nullifier)) {
+        return Err(format!(
+            "Source note already spent: nullifier {} is in {}",
+            nullifier,
+            history_path()
+        ));
+    }
+    if SpentNullifiers::load()?.contains(&nullifier) {
+        return Err(format!(
+            "Source note already spent: nullifier {} is in {}",
+            nullifier,
+            spent_nullifiers_path()
+        ));
+    }
+    match contract.nullifier_spent(nullifier).await {
+        Ok(true) => Err(format!("Source note already spent on-chain: nullifier {}", nullifier)),
+        Ok(false) => Ok(()),
+        Err(e) => {
+            println!("   ⚠️  Could not check the source note on-chain: {}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Everything `buy` submits and saves, computed from already-loaded inputs
+pub struct BuyPlan {
+    pub change_value: u64,
+    pub buyer_salt: u64,
+    pub change_salt: u64,
+    pub input_nullifier: Fr,
+    pub dummy_nullifier: Fr,
+    pub buyer_commitment: Fr,
+    pub change_commitment: Fr,
+    /// The two outputs as committed; the witness holds them in canonical order
+    pub buyer_note: CircuitNote,
+    pub change_note: CircuitNote,
+    pub real_note_index: usize,
+    pub dummy_note_index: usize,
+    pub witness: WitnessBuilder,
+}
+
+/// Split `source` (owned by `issuer`) into a `buy_value` note for `buyer` and
+/// change back to the issuer. No IO: salts for the two outputs are passed in.
+pub fn plan_buy(
+    buyer: &ShieldedKeys,
+    issuer: &ShieldedKeys,
+    source: &Bond,
+    buy_value: u64,
+    tree_state: &TreeState,
+    salts: (u64, u64),
+) -> Result<BuyPlan, WalletError> {
+    plan_buy_to(buyer, issuer, source, buy_value, tree_state, salts, None)
+}
+
+/// `plan_buy` with the change going to `change_to` instead of the issuer. A
+/// whole-note buy has zero change, which stays the issuer's: a zero-value note
+/// for anyone else is refused by the witness.
+pub fn plan_buy_to(
+    buyer: &ShieldedKeys,
+    issuer: &ShieldedKeys,
+    source: &Bond,
+    buy_value: u64,
+    tree_state: &TreeState,
+    (buyer_salt, change_salt): (u64, u64),
+    change_to: Option<&FanOutRecipient>,
+) -> Result<BuyPlan, WalletError> {
+    // Value conservation: buyer + change == input. Buying the whole note is a
+    // plain transfer; the change output is then a zero-value note.
+    if buy_value == 0 {
+        return Err(WalletError::Other("Buy value must be greater than zero: a zero-value buyer note would spend the source and a tree leaf for nothing.".to_string()));
+    }
+    let change_value = source.value.checked_sub(buy_value).ok_or_else(|| {
+        WalletError::InsufficientValue(format!(
+            "Buy value ({}) must not exceed source note value ({}).",
+            buy_value, source.value
+        ))
+    })?;
+
+    // Input note (issuer's note being consumed) and nullifiers (issuer signs)
+    let issuer_owner_fr = issuer.public_spending_key();
+    let note = |value, salt, owner| {
+        CircuitNote::new(value, salt, owner, source.asset_id, source.maturity_date)
+            .map_err(|e| e.to_string())
+    };
+    let input_note = note(source.value, source.salt, issuer_owner_fr)?.with_memo_hash(source.memo_hash);
+    let input_nullifier = issuer.sign_nullifier(source.salt);
+    let dummy_nullifier = issuer.sign_nullifier(DUMMY_SALT);
+
+    // Output 1: buyer's note; output 2: change, the issuer's unless redirected.
+    // Notes handed over with a memo are bound to it under a memo-bound scheme.
+    let buyer_note = note(buy_value, buyer_salt, buyer.public_spending_key())?
+        .bound_to_memo(issuer, buyer.public_viewing_key())?;
+    let change_note = match change_to {
+        Some(recipient) if change_value > 0 => note(change_value, change_salt, recipient.spending_key)?
+            .bound_to_memo(issuer, &recipient.viewing_key)?,
+        _ => note(change_value, change_salt, issuer_owner_fr)?,
+    };
+    let buyer_commitment = buyer_note.commitment();
+    let change_commitment = change_note.commitment();
+
+    // Locate the source note and the issuer's dummy note (value=0, salt=0) in the tree
+    let real_note_index = parse_commitment(&source.commitment)
+        .and_then(|c| tree_state.find_commitment(&c))
+        .ok_or_else(|| {
+            format!(
+                "Source note commitment not found in tree state: {}\n   ℹ️  Make sure the issuer ran 'onboard' to register the initial note.",
+                source.commitment
+            )
+        })?;
+    let dummy_note = input_note.padding();
+    let dummy_commitment = dummy_note.commitment();
+    let dummy_note_index = tree_state.find_commitment(&dummy_commitment).ok_or_else(|| {
+        format!(
+            "Dummy note commitment not found in tree state: {}\n   ℹ️  The issuer's onboard should have added both real and dummy notes.",
+            fr_to_hex(&dummy_commitment)
+        )
+    })?;
+
+    // Merkle proofs for BOTH inputs, then the JoinSplit witness: 2 inputs -> 2 outputs
+    let tree = tree_state.proof_tree()?;
+    let witness = JoinSplitInputs {
+        root: tree.root(),
+        input_notes: vec![input_note, dummy_note],
+        merkle_paths: vec![
+            tree.generate_proof(real_note_index)?,
+            tree.generate_proof(dummy_note_index)?,
+        ],
+        nullifiers: vec![input_nullifier, dummy_nullifier],
+        output_notes: [buyer_note.clone(), change_note.clone()],
+        commitments_out: [buyer_commitment, change_commitment],
+        private_key: issuer.get_private_spending_key(),
+        burn: false,
+    }
+    .build()?;
+
+    Ok(BuyPlan {
+        change_value,
+        buyer_salt,
+        change_salt,
+        input_nullifier,
+        dummy_nullifier,
+        buyer_commitment,
+        change_commitment,
+        buyer_note,
+        change_note,
+        real_note_index,
+        dummy_note_index,
+        witness,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn buy(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    buyer_wallet_name: &str,
+    buy_value: u64,
+    source: &SourceNote,
+    issuer_wallet_path: &str,
+    change_to: Option<&str>,
+    label: Option<&str>,
+) -> Result<(String, Bond), WalletError> {
+    println!("\n💳 Buying bond from issuer...");
+    println!("   Buy amount: {}", buy_value);
+
+    // 1. Load buyer's wallet (to get buyer's public key)
+    let buyer_wallet = match load_wallet(buyer_wallet_name) {
+        Some(w) => w,
+        None => {
+            return fail(WalletError::WalletNotFound(format!(
+                "Buyer wallet '{}' not found. Run 'onboard' first.",
+                buyer_wallet_name
+            )))
+        }
+    };
+
+    // 2. Load issuer's wallet (for private key to sign nullifier)
+    let mut issuer_wallet = match load_wallet(issuer_wallet_path) {
+        Some(w) => w,
+        None => {
+            return fail(WalletError::WalletNotFound(format!(
+                "Issuer wallet '{}' not found.",
+                issuer_wallet_path
+            )))
+        }
+    };
+
+    // 3. Load source note (issuer's note being split); a note sold before would
+    // only be caught by the contract's revert, after proving
+    let (source_note_path, source_bond) =
+        match select_source_note(contract, &issuer_wallet, source).await {
+            Ok(found) => found,
+            Err(e) => return fail(e),
+        };
+    // A rerun after a transfer that went out unconfirmed resumes that attempt
+    // rather than spending the note into new outputs
+    let spends = format!("{}", fr_to_bytes32(&issuer_wallet.keys.sign_nullifier(source_bond.salt)));
+    let prior = match resume_attempt(contract, "buy", &spends).await {
+        Ok(prior) => prior,
+        Err(e) => return fail(e),
+    };
+    let landed = prior.as_ref().is_some_and(|(_, landed)| *landed);
+    if !landed {
+        if let Err(e) = check_source_unspent(contract, &issuer_wallet.keys, &source_bond).await {
+            return fail(e);
+        }
+        if let Err(e) = MaturityPolicy::now().check_transfer(&source_bond) {
+            let e = format!("Source note {} {}", source_note_path, e);
+            return fail(e);
+        }
+    }
+
+    // Change may go to another key the issuer controls (e.g. a treasury); naming
+    // the issuer itself is the same as leaving it out
+    let change_recipient = match change_to.map(|who| parse_recipient(who, 0)).transpose() {
+        Ok(recipient) => {
+            recipient.filter(|r| r.spending_key != issuer_wallet.keys.public_spending_key())
+        }
+        Err(e) => return fail(e.map_message(|m| format!("Invalid --change-to: {}", m))),
+    };
+
+    println!(
+        "   Source note: {} (value={})",
+        source_note_path, source_bond.value
+    );
+    match &change_recipient {
+        Some(r) => println!("   Change to {}: {}", r.name, source_bond.value.saturating_sub(buy_value)),
+        None => println!("   Change to issuer: {}", source_bond.value.saturating_sub(buy_value)),
+    }
+    println!(
+        "   Maturity: {} ({})",
+        source_bond.maturity_date,
+        format_date(source_bond.maturity_date)
+    );
+
+    // 4-8. Build notes, nullifiers, merkle proofs and the witness
+    let salts = match &prior {
+        Some((attempt, _)) => attempt.salts,
+        None => match draw_salts(issuer_wallet_path, &mut issuer_wallet, 2) {
+            Ok(salts) => (salts[0], salts[1]),
+            Err(e) => return fail(e),
+        },
+    };
+    let mut tree_state = TreeState::load();
+    // Repairs below are undone if the transfer is not sent
+    let checkpoint = tree_state.snapshot();
+    if let Some(commitment) = parse_commitment(&source_bond.commitment) {
+        repair_tree_from_chain(contract, events, &mut tree_state, &commitment).await;
+    }
+    let plan = match plan_buy_to(
+        &buyer_wallet.keys,
+        &issuer_wallet.keys,
+        &source_bond,
+        buy_value,
+        &tree_state,
+        salts,
+        change_recipient.as_ref(),
+    ) {
+        Ok(p) => p,
+        Err(e) => return fail(e),
+    };
+    let BuyPlan {
+        change_value,
+        buyer_salt,
+        change_salt,
+        input_nullifier: input_nullifier_fr,
+        dummy_nullifier: dummy_nullifier_fr,
+        buyer_commitment: buyer_commitment_fr,
+        change_commitment: change_commitment_fr,
+        buyer_note,
+        change_note,
+        real_note_index,
+        dummy_note_index,
+        witness,
+    } = plan;
+    let merkle_root = witness.root;
+    let commitments_hex = witness.commitments_out.map(|c| format!("{}", fr_to_bytes32(&c)));
+    if let Some((attempt, _)) = &prior {
+        if attempt.commitments != commitments_hex {
+            let e = format!(
+                "Attempt {} spends this note into other outputs; rerun it with the same value and --change-to",
+                attempt.key
+            );
+            return fail(e);
+        }
+    }
+
+    // Each value also shown as the bytes32 submitted on-chain, to match calldata
+    println!("\n📊 JoinSplit Summary:");
+    println!(
+        "   INPUT:  value={}, nullifier={}",
+        source_bond.value,
+        fr_to_hex(&input_nullifier_fr)
+    );
+    println!("           bytes32={}", fr_to_bytes32(&input_nullifier_fr));
+    println!("   DUMMY:  value=0, nullifier={}", fr_to_hex(&dummy_nullifier_fr));
+    println!("           bytes32={}", fr_to_bytes32(&dummy_nullifier_fr));
+    println!(
+        "   OUTPUT1 (buyer):  value={}, commitment={}",
+        buy_value,
+        fr_to_hex(&buyer_commitment_fr)
+    );
+    println!("                     bytes32={}", fr_to_bytes32(&buyer_commitment_fr));
+    println!(
+        "   OUTPUT2 (change): value={}, commitment={}",
+        change_value,
+        fr_to_hex(&change_commitment_fr)
+    );
+    println!("                     bytes32={}", fr_to_bytes32(&change_commitment_fr));
+
+    println!("   Real note at tree index: {}", real_note_index);
+    println!("   Dummy note at tree index: {}", dummy_note_index);
+    println!("   Merkle root: {}", fr_to_hex(&merkle_root));
+    println!("   Real note path_indices: {:?}", witness.merkle_paths[0].indices);
+    println!("   Dummy note path_indices: {:?}", witness.merkle_paths[1].indices);
+
+    // 9. Nothing is spent yet; last chance to back out
+    let pending = PendingAction {
+        action: "buy".to_string(),
+        value: buy_value,
+        nullifier: format!("{}", fr_to_bytes32(&input_nullifier_fr)),
+        recipient: Some(buyer_wallet.keys.public_spending_key_hex.clone()),
+    };
+    if !landed && !events.confirm(&pending) {
+        println!("🚫 Buy cancelled; nothing was submitted");
+        return Err(WalletError::Other("Not confirmed".to_string()));
+    }
+
+    // Convert Fr values to bytes32
+    let root_bytes = fr_to_bytes32(&merkle_root);
+    let nullifier0_bytes = fr_to_bytes32(&input_nullifier_fr);
+    let nullifier1_bytes = fr_to_bytes32(&dummy_nullifier_fr);
+    // Outputs in the witness's canonical order (same order the circuit proved)
+    let commitment0_bytes = fr_to_bytes32(&witness.commitments_out[0]);
+    let commitment1_bytes = fr_to_bytes32(&witness.commitments_out[1]);
+
+    let (attempt, tx_hash) = match prior {
+        // The earlier transfer was mined: only its outputs are left to save
+        Some((attempt, true)) => {
+            let tx_hash = attempt.tx_hash.as_deref().and_then(|h| h.parse().ok()).unwrap_or_default();
+            (attempt, tx_hash)
+        }
+        prior => {
+            // 10. Write the witness and generate proof; an attempt that never landed
+            // is resubmitted with its proof when the witness is unchanged
+            let key = witness_id(&witness);
+            let reusable = prior
+                .as_ref()
+                .map(|(attempt, _)| attempt)
+                .filter(|attempt| attempt.key == key && Path::new(&attempt.proof).exists());
+            let proof_file = match reusable {
+                Some(attempt) => {
+                    println!("   ♻️  Reusing proof {}", attempt.proof);
+                    attempt.proof.clone()
+                }
+                None => match prove_bound(prover, contract, events, &witness).await {
+                    Ok(path) => path,
+                    Err(e) => {
+                        println!("   ⚠️  Proof generation failed: {}", e);
+                        println!("   ℹ️  You can run manually:");
+                        println!("      cd {} && nargo execute circuits && bb prove -b ./target/circuits.json -w ./target/circuits -o ./target", CIRCUIT_DIR);
+                        return Err(e.map_message(|m| format!("Proof generation failed: {}", m)));
+                    }
+                },
+            };
+
+            // 11. Call contract transfer() with proof
+            // Read proof bytes (only if it was generated for this network)
+            let proof_bytes = match load_bound_proof(contract, &proof_file).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("   ❌ {}", e);
+                    return Err(e.into());
+                }
+            };
+
+            // The contract accepts every root it has built, so if someone else's insert moved
+            // it on while we proved, the proof still verifies against our (now historical) root
+            if let Ok(onchain) = contract.current_root().await {
+                if onchain != root_bytes && tree_state.is_recent_root(&merkle_root) {
+                    println!("   ℹ️  Contract root moved on to {}; submitting against recent root {}", onchain, root_bytes);
+                }
+            }
+
+            // Recorded before sending, so a rerun knows this spend may be out there
+            let mut attempt = Attempt {
+                key,
+                command: "buy".to_string(),
+                spends: spends.clone(),
+                salts: (buyer_salt, change_salt),
+                commitments: commitments_hex.clone(),
+                proof: proof_file,
+                tx_hash: None,
+                retries: prior.as_ref().map_or(0, |(attempt, _)| attempt.retries),
+                chain: Vec::new(),
+            };
+            if let Err(e) = attempt.save() {
+                println!("   ❌ {}", e);
+                return Err(e.into());
+            }
+            if let Some((earlier, _)) = prior.filter(|(earlier, _)| earlier.key != attempt.key) {
+                earlier.clear();
+            }
+
+            // Call transfer(); notes and tree state are only written once it is mined
+            events.on_tx_sent("transfer");
+            match contract
+                .transfer(
+                    Bytes::from(proof_bytes),
+                    root_bytes,
+                    [nullifier0_bytes, nullifier1_bytes],
+                    [commitment0_bytes, commitment1_bytes],
+                )
+                .await
+            {
+                Ok(tx_hash) => (attempt, tx_hash),
+                Err(TxError::Unconfirmed { tx_hash, reason }) => {
+                    tree_state.restore(checkpoint);
+                    attempt.tx_hash = Some(format!("{}", tx_hash));
+                    let _ = attempt.save();
+                    println!("   ⚠️  Transaction pending but watch failed: {}", reason);
+                    println!("   ℹ️  No local state written; rerun this buy to resume once {:?} is mined", tx_hash);
+                    return Err(WalletError::Other(reason));
+                }
+                Err(e @ TxError::Pending { tx_hash, .. }) => {
+                    tree_state.restore(checkpoint);
+                    attempt.tx_hash = Some(format!("{}", tx_hash));
+                    let _ = attempt.save();
+                    println!("   ⏳ {}", e);
+                    println!("   ℹ️  No local state written; rerun this buy to resume once {:?} is mined", tx_hash);
+                    return Err(e.into());
+                }
+                Err(e) => {
+                    tree_state.restore(checkpoint);
+                    attempt.clear();
+                    println!("   ❌ Contract call failed: {}", e);
+                    println!("   ℹ️  Make sure anvil is running and contract is deployed");
+                    return Err(e.into());
+                }
+            }
+        }
+    };
+    events.on_tx_confirmed("transfer", tx_hash);
+    save_witness(&witness, &format!("{}", tx_hash));
+    log_history(
+        contract,
+        "buy",
+        &[nullifier0_bytes, nullifier1_bytes],
+        &[commitment0_bytes, commitment1_bytes],
+        buy_value,
+        tx_hash,
+        None,
+    )
+    .await;
+
+    // 12. Save buyer's bond
+    let buyer_bond = Bond {
+        schema_version: BOND_SCHEMA_VERSION,
+        commitment: fr_to_hex(&buyer_commitment_fr),
+        nullifier: fr_to_hex(&buyer_wallet.keys.sign_nullifier(buyer_salt)),
+        value: buy_value,
+        salt: buyer_salt,
+        owner: buyer_wallet.keys.public_spending_key_hex.clone(),
+        asset_id: source_bond.asset_id,
+        maturity_date: source_bond.maturity_date,
+        created_at: Utc::now().to_rfc3339(),
+        label: label.map(str::to_string),
+        memo_hash: buyer_note.memo_hash,
+    };
+
+    let buyer_filename = format!(
+        "{}/bond_{}_{}.json",
+        data_dir(),
+        buyer_wallet_name,
+        &format!("{:016x}", buyer_salt)[..8]
+    );
+    match write_atomic(
+        &buyer_filename,
+        serde_json::to_string_pretty(&buyer_bond).unwrap(),
+    ) {
+        Ok(_) => {
+            println!("\n✅ Buyer bond saved to: {}", buyer_filename);
+            index_bond(&mut tree_state, &buyer_filename, &buyer_bond);
+        }
+        Err(e) => println!("❌ Error saving buyer bond: {}", e),
+    }
+
+    // 13. Encrypt the buyer's note to their viewing key, so `scan` recovers it
+    let buyer_memo_note = Note::describing(&buyer_note);
+
+    let memo_stem = format!("memo_{}_{}", buyer_wallet_name, &format!("{:016x}", buyer_salt)[..8]);
+    match MemoEnvelope::seal(&issuer_wallet.keys, buyer_wallet.keys.public_viewing_key(), &buyer_memo_note)
+        .and_then(|envelope| save_memo(&memo_stem, &envelope))
+    {
+        Ok(memo_filename) => println!("🔒 Encrypted memo saved to: {}", memo_filename),
+        Err(e) => println!("⚠️  Failed to save memo: {}", e),
+    }
+
+    // 14. Save issuer's change note (update source); a full-note buy leaves none worth keeping
+    if change_value == 0 {
+        println!("ℹ️  Whole note bought: no change note saved");
+    } else if let Some(recipient) = &change_recipient {
+        save_redirected_change(&mut tree_state, &issuer_wallet.keys, recipient, &change_note);
+    } else {
+        let change_bond = Bond {
+            schema_version: BOND_SCHEMA_VERSION,
+            commitment: fr_to_hex(&change_commitment_fr),
+            nullifier: fr_to_hex(&issuer_wallet.keys.sign_nullifier(change_salt)),
+            value: change_value,
+            salt: change_salt,
+            owner: issuer_wallet.keys.public_spending_key_hex.clone(),
+            asset_id: source_bond.asset_id,
+            maturity_date: source_bond.maturity_date,
+            created_at: Utc::now().to_rfc3339(),
+            label: None,
+            memo_hash: None,
+        };
+        let change_filename = format!(
+            "{}/issuer_change_{}.json",
+            data_dir(),
+            &format!("{:016x}", change_salt)[..8]
+        );
+        match write_atomic(
+            &change_filename,
+            serde_json::to_string_pretty(&change_bond).unwrap(),
+        ) {
+            Ok(_) => {
+                println!("✅ Issuer change note saved to: {}", change_filename);
+                index_bond(&mut tree_state, &change_filename, &change_bond);
+            }
+            Err(e) => println!("❌ Error saving change note: {}", e),
+        }
+    }
+
+    // 14. Add new commitments to tree state (for future transactions)
+    // Same order as submitted, so the local tree matches the contract's leaf order.
+    // A resumed attempt's may already be there, restored from the chain.
+    for commitment in witness.commitments_out {
+        if tree_state.find_commitment(&commitment).is_none() {
+            tree_state.add_commitment(commitment);
+        }
+    }
+    println!("   📝 Added 2 new commitments to merkle tree");
+    attempt.clear();
+    Ok((buyer_filename, buyer_bond))
+}
+
+/// The `command` attempt spending `spends` that an earlier run left unfinished,
+/// and whether it landed: its nullifier is spent on-chain
+async fn resume_attempt(
+    contract: &impl BondContract,
+    command: &str,
+    spends: &str,
+) -> Result<Option<(Attempt, bool)>, String> {
+    let Some(mut attempt) = Attempt::find(command, spends)? else {
+        return Ok(None);
+    };
+    let tx = attempt.tx_hash.clone().unwrap_or_else(|| "(not sent)".to_string());
+    if attempt.retries >= RETRY_BUDGET {
+        return Err(format!(
+            "Attempt {} was already retried {} times; check transaction {} by hand, then remove {}",
+            attempt.key,
+            attempt.retries,
+            tx,
+            attempt.path()
+        ));
+    }
+    let nullifier: FixedBytes<32> = spends.parse().map_err(|e| format!("Invalid nullifier {}: {}", spends, e))?;
+    let landed = contract
+        .nullifier_spent(nullifier)
+        .await
+        .map_err(|e| format!("Cannot tell whether attempt {} (tx {}) landed: {}", attempt.key, tx, e))?;
+    attempt.retries += 1;
+    attempt.save()?;
+    if landed {
+        println!("   🔁 Attempt {} (tx {}) was mined; saving its outputs", attempt.key, tx);
+    } else {
+        println!("   🔁 Attempt {} (tx {}) never landed; resubmitting it", attempt.key, tx);
+    }
+    Ok(Some((attempt, landed)))
+}
+
+/// `reconstruct` command that saves `note` into `wallet_name`'s data directory
+fn reconstruct_command(wallet_name: &str, note: &CircuitNote) -> String {
+    format!(
+        "wallet --wallet {} reconstruct --value {} --salt {} --owner {} --asset {} --maturity {} --out {}/bond_{}_{}.json",
+        wallet_name,
+        note.value,
+        note.salt,
+        fr_to_hex(&note.owner),
+        note.asset_id,
+        note.maturity_date,
+        data_dir(),
+        wallet_name,
+        &format!("{:016x}", note.salt)[..8]
+    )
+}
+
+/// How to finish a `command` whose transaction went out but was not seen mined.
+/// Only `buy` records an `Attempt` to resume, so the other spends list the
+/// `reconstruct` commands that save their `notes` (wallet name, note) by hand.
+fn print_unfinished(command: &str, tx_hash: TxHash, notes: &[(&str, &CircuitNote)]) {
+    println!("   ℹ️  No local state written, and a rerun does not resume a {}.", command);
+    println!("      Once {:?} is mined, run `sync`, then:", tx_hash);
+    for (wallet_name, note) in notes {
+        match note.memo_hash {
+            // `reconstruct` rebuilds notes without a memo, so it cannot hash to this one
+            Some(_) => println!(
+                "      (note {} for {} is bound to its memo and cannot be rebuilt from its fields)",
+                short(&fr_to_hex(&note.commitment()), 18),
+                wallet_name
+            ),
+            None => println!("      {}", reconstruct_command(wallet_name, note)),
+        }
+    }
+    if notes.is_empty() {
+        println!("      wallet prune   (archives the spent bond file)");
+    }
+    println!("      If it is never mined, rerun the {}: the notes it spends are still unspent.", command);
+}
+
+/// Change `buy` sent to a key other than the issuer's: a bond file when that key is
+/// a wallet here, and always a memo sealed to its viewing key so `scan` recovers it
+fn save_redirected_change(
+    tree_state: &mut TreeState,
+    issuer: &ShieldedKeys,
+    recipient: &FanOutRecipient,
+    change: &CircuitNote,
+) {
+    let salt8 = format!("{:016x}", change.salt)[..8].to_string();
+    if let Some(keys) = &recipient.keys {
+        let bond = Bond {
+            schema_version: BOND_SCHEMA_VERSION,
+            commitment: fr_to_hex(&change.commitment()),
+            nullifier: fr_to_hex(&keys.sign_nullifier(change.salt)),
+            value: change.value,
+            salt: change.salt,
+            owner: fr_to_hex(&recipient.spending_key),
+            asset_id: change.asset_id,
+            maturity_date: change.maturity_date,
+            created_at: Utc::now().to_rfc3339(),
+            label: None,
+            memo_hash: change.memo_hash,
+        };
+        let path = format!("{}/bond_{}_{}.json", data_dir(), recipient.name, salt8);
+        match write_atomic(&path, serde_json::to_string_pretty(&bond).unwrap()) {
+            Ok(_) => {
+                println!("✅ Change note for {} saved to: {}", recipient.name, path);
+                index_bond(tree_state, &path, &bond);
+            }
+            Err(e) => println!("❌ Error saving change note for {}: {}", recipient.name, e),
+        }
+    }
+    match MemoEnvelope::seal(issuer, &recipient.viewing_key, &Note::describing(change))
+        .and_then(|envelope| save_memo(&format!("memo_{}_{}", recipient.name, salt8), &envelope))
+    {
+        Ok(path) => println!("🔒 Change memo for {} saved to: {}", recipient.name, path),
+        Err(e) => println!("⚠️  Failed to save change memo: {}", e),
+    }
+}
+
+/// One recipient of a fan-out sale
+#[derive(Clone, Debug)]
+pub struct FanOutRecipient {
+    /// Wallet name, or the shortened public key for recipients without a local wallet
+    pub name: String,
+    pub spending_key: Fr,
+    /// X25519 key their memo is sealed to
+    pub viewing_key: [u8; 32],
+    pub value: u64,
+    /// Keys of a wallet in this data directory, whose bond file is then saved here
+    pub keys: Option<ShieldedKeys>,
+}
+
+/// A local wallet name, or a `<public spending key>:<viewing key hex>` pair for
+/// someone without a wallet here
+pub fn parse_recipient(who: &str, value: u64) -> Result<FanOutRecipient, WalletError> {
+    match who.split_once(':') {
+        Some((spending, viewing)) => {
+            let spending_key = parse_public_spending_key(spending).map_err(|e| e.to_string())?;
+            let viewing_key: [u8; 32] = hex::decode(viewing.trim_start_matches("0x"))
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| "viewing key must be 64 hex characters".to_string())?;
+            Ok(FanOutRecipient {
+                name: short(spending.trim_start_matches("0x"), 8).to_string(),
+                spending_key,
+                viewing_key,
+                value,
+                keys: None,
+            })
+        }
+        None => {
+            let wallet = require_wallet(who)?;
+            Ok(FanOutRecipient {
+                name: who.to_string(),
+                spending_key: wallet.keys.public_spending_key(),
+                viewing_key: *wallet.keys.public_viewing_key(),
+                value,
+                keys: Some(wallet.keys),
+            })
+        }
+    }
+}
+
+/// Parse `recipient,value` lines. A recipient is a local wallet name, or a
+/// `<public spending key>:<viewing key hex>` pair for someone without a wallet here.
+pub fn parse_recipients(csv: &str) -> Result<Vec<FanOutRecipient>, WalletError> {
+    let mut recipients = Vec::new();
+    for (number, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (who, value) = line
+            .rsplit_once(',')
+            .ok_or_else(|| format!("Line {}: expected `recipient,value`", number + 1))?;
+        let value: u64 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("Line {}: invalid value '{}'", number + 1, value.trim()))?;
+        let recipient = parse_recipient(who.trim(), value)
+            .map_err(|e| e.map_message(|m| format!("Line {}: {}", number + 1, m)))?;
+        recipients.push(recipient);
+    }
+    if recipients.is_empty() {
+        return Err(WalletError::Other("No recipients given".to_string()));
+    }
+    Ok(recipients)
+}
+
+/// One JoinSplit of a fan-out chain: the issuer's `input` and zero-value `pad`
+/// notes go to one recipient's `output` and the issuer's `change`
+#[derive(Clone, Debug)]
+pub struct FanOutHop {
+    pub recipient: usize,
+    pub input: CircuitNote,
+    pub pad: CircuitNote,
+    pub output: CircuitNote,
+    pub change: CircuitNote,
+}
+
+/// Split `source` (owned by `issuer`) across `recipients` as a chain of 2-in/2-out
+/// JoinSplits, each spending the previous hop's change. The first hop pads with the
+/// issuer's onboard dummy; every other hop needs its own zero-value pad (the
+/// dummy's nullifier is spent after one use), which the caller mints first.
+/// `salts` holds (recipient, change, pad) salts per hop; hop 0's pad salt is unused.
+pub fn plan_fan_out(
+    issuer: &ShieldedKeys,
+    source: &Bond,
+    recipients: &[FanOutRecipient],
+    salts: &[(u64, u64, u64)],
+) -> Result<Vec<FanOutHop>, WalletError> {
+    if recipients.is_empty() || salts.len() != recipients.len() {
+        return Err(WalletError::Other("Need one set of salts per recipient".to_string()));
+    }
+    if let Some(r) = recipients.iter().find(|r| r.value == 0) {
+        return Err(WalletError::Other(format!("Value for {} must be greater than zero.", r.name)));
+    }
+    recipients
+        .iter()
+        .try_fold(0u64, |sum, r| sum.checked_add(r.value))
+        .filter(|total| *total <= source.value)
+        .ok_or_else(|| {
+            WalletError::InsufficientValue(format!(
+                "Recipient values must not exceed source note value ({}).",
+                source.value
+            ))
+        })?;
+
+    let owner = issuer.public_spending_key();
+    let note = |value, salt, owner| {
+        CircuitNote::new(value, salt, owner, source.asset_id, source.maturity_date)
+            .map_err(|e| e.to_string())
+    };
+
+    let mut input = note(source.value, source.salt, owner)?.with_memo_hash(source.memo_hash);
+    let mut hops = Vec::with_capacity(recipients.len());
+    for (index, (recipient, &(recipient_salt, change_salt, pad_salt))) in
+        recipients.iter().zip(salts).enumerate()
+    {
+        let pad_salt = if index == 0 { 0 } else { pad_salt };
+        let change = note(input.value - recipient.value, change_salt, owner)?;
+        hops.push(FanOutHop {
+            recipient: index,
+            input: input.clone(),
+            pad: note(0, pad_salt, owner)?,
+            output: note(recipient.value, recipient_salt, recipient.spending_key)
+                .and_then(|output| output.bound_to_memo(issuer, &recipient.viewing_key))
+                .map_err(|e| format!("{}: {}", recipient.name, e))?,
+            change: change.clone(),
+        });
+        input = change;
+    }
+    Ok(hops)
+}
+
+/// Witness for one fan-out hop against the current tree
+fn fan_out_hop_witness(
+    issuer: &ShieldedKeys,
+    hop: &FanOutHop,
+    tree_state: &TreeState,
+) -> Result<WitnessBuilder, String> {
+    let locate = |note: &CircuitNote, what: &str| {
+        tree_state
+            .find_commitment(&note.commitment())
+            .ok_or_else(|| format!("{} commitment not found in tree state: {}", what, fr_to_hex(&note.commitment())))
+    };
+    let input_index = locate(&hop.input, "Input note")?;
+    let pad_index = locate(&hop.pad, "Pad note")?;
+
+    let tree = tree_state.proof_tree()?;
+    JoinSplitInputs {
+        root: tree.root(),
+        input_notes: vec![hop.input.clone(), hop.pad.clone()],
+        merkle_paths: vec![tree.generate_proof(input_index)?, tree.generate_proof(pad_index)?],
+        nullifiers: vec![
+            issuer.sign_nullifier(hop.input.salt),
+            issuer.sign_nullifier(hop.pad.salt),
+        ],
+        output_notes: [hop.output.clone(), hop.change.clone()],
+        commitments_out: [hop.output.commitment(), hop.change.commitment()],
+        private_key: issuer.get_private_spending_key(),
+        burn: false,
+    }
+    .build()
+}
+
+/// Sell parts of one issuer note to several recipients, one JoinSplit per recipient.
+/// Returns the bond files saved for local recipients and the final change, if any.
+///
+/// The chain's salts are recorded (a `fan-out` `Attempt`) before anything is sent,
+/// and each hop records its own `buy` attempt, as `buy` does. A rerun with the same
+/// source and recipients after a failed hop rebuilds the same pads and hops, skips
+/// the hops that went through and resumes from the one that did not.
+#[allow(clippy::too_many_arguments)]
+pub async fn buy_fan_out(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    recipients: &[FanOutRecipient],
+    source: &SourceNote,
+    issuer_wallet_path: &str,
+    label: Option<&str>,
+) -> Result<Vec<String>, WalletError> {
+    println!("\n💳 Fan-out sale to {} recipients...", recipients.len());
+
+    let mut issuer_wallet = load_wallet(issuer_wallet_path)
+        .ok_or_else(|| WalletError::WalletNotFound(format!("Issuer wallet '{}' not found.", issuer_wallet_path)))
+        .or_else(fail)?;
+    let (source_note_path, source_bond) =
+        select_source_note(contract, &issuer_wallet, source).await.or_else(fail)?;
+    let spends = format!("{}", fr_to_bytes32(&issuer_wallet.keys.sign_nullifier(source_bond.salt)));
+
+    // A rerun after a failed hop picks up the chain an earlier run recorded
+    let prior_chain = Attempt::find("fan-out", &spends).or_else(fail)?;
+    let resuming = prior_chain.is_some();
+    let salts: Vec<(u64, u64, u64)> = match &prior_chain {
+        Some(chain) => {
+            if chain.chain.len() != recipients.len() {
+                let e = format!(
+                    "Fan-out {} from this note went to {} recipients; rerun it with the same --recipients",
+                    chain.key,
+                    chain.chain.len()
+                );
+                return fail(e);
+            }
+            println!("   🔁 Resuming fan-out {} from this note", chain.key);
+            chain.chain.clone()
+        }
+        None => {
+            check_source_unspent(contract, &issuer_wallet.keys, &source_bond)
+                .await
+                .or_else(fail)?;
+            MaturityPolicy::now()
+                .check_transfer(&source_bond)
+                .map_err(|e| format!("Source note {} {}", source_note_path, e))
+                .or_else(fail)?;
+            let drawn = draw_salts(issuer_wallet_path, &mut issuer_wallet, 3 * recipients.len()).or_else(fail)?;
+            drawn.chunks(3).map(|s| (s[0], s[1], s[2])).collect()
+        }
+    };
+    println!("   Source note: {} (value={})", source_note_path, source_bond.value);
+    let hops = plan_fan_out(&issuer_wallet.keys, &source_bond, recipients, &salts).or_else(fail)?;
+    // A hop's outputs in the order its witness submits them
+    let outputs_of = |hop: &FanOutHop| {
+        let mut outputs = [hop.output.commitment(), hop.change.commitment()];
+        outputs.sort_by_key(fr_to_bytes32);
+        outputs
+    };
+    let outputs_hex = |hop: &FanOutHop| outputs_of(hop).map(|c| format!("{}", fr_to_bytes32(&c)));
+    if let Some(chain) = &prior_chain {
+        if chain.commitments != outputs_hex(&hops[0]) {
+            let e = format!(
+                "Fan-out {} sends this note to other outputs; rerun it with the same --recipients",
+                chain.key
+            );
+            return fail(e);
+        }
+    }
+
+    // Pads for every hop after the first, then two outputs per hop; an earlier
+    // run's are already in the tree
+    let mut tree_state = TreeState::load();
+    let pads: Vec<Fr> = hops[1..]
+        .iter()
+        .map(|hop| hop.pad.commitment())
+        .filter(|pad| tree_state.find_commitment(pad).is_none())
+        .collect();
+    let outputs = hops
+        .iter()
+        .flat_map(|hop| [hop.output.commitment(), hop.change.commitment()])
+        .filter(|output| tree_state.find_commitment(output).is_none())
+        .count();
+    let needed = pads.len() + outputs;
+    let room = MAX_LEAVES.saturating_sub(tree_state.commitments().len());
+    if needed > room {
+        let e = format!(
+            "Fan-out to {} recipients needs {} tree leaves; only {} left",
+            hops.len(),
+            needed,
+            room
+        );
+        return fail(e);
+    }
+
+    let total: u64 = recipients.iter().map(|r| r.value).sum();
+    println!("   Selling {} of {} in {} JoinSplits", total, source_bond.value, hops.len());
+    let pending = PendingAction {
+        action: "buy".to_string(),
+        value: total,
+        nullifier: spends.clone(),
+        recipient: Some(format!("{} recipients", recipients.len())),
+    };
+    if !resuming && !events.confirm(&pending) {
+        println!("🚫 Buy cancelled; nothing was submitted");
+        return Err(WalletError::Other("Not confirmed".to_string()));
+    }
+
+    // Recorded before the pads are minted, so a rerun rebuilds the same ones
+    let chain = match prior_chain {
+        Some(chain) => chain,
+        None => {
+            let chain = Attempt {
+                key: format!("fan-out-{}", short(spends.trim_start_matches("0x"), 16)),
+                command: "fan-out".to_string(),
+                spends: spends.clone(),
+                salts: (hops[0].output.salt, hops[0].change.salt),
+                commitments: outputs_hex(&hops[0]),
+                proof: String::new(),
+                tx_hash: None,
+                retries: 0,
+                chain: salts.clone(),
+            };
+            chain.save().or_else(fail)?;
+            chain
+        }
+    };
+
+    // Zero-value pads carry no value, so the issuer mints them without a proof
+    if !pads.is_empty() {
+        events.on_tx_sent("mintBatch");
+        let minted: Vec<FixedBytes<32>> = pads.iter().map(fr_to_bytes32).collect();
+        let tx_hash = contract
+            .mint_batch(minted.clone())
+            .await
+            .map_err(WalletError::from)
+            .inspect_err(|e| println!("   ❌ Minting pad notes failed: {}", e))?;
+        events.on_tx_confirmed("mintBatch", tx_hash);
+        check_minted(contract, &minted)
+            .await
+            .inspect_err(|e| println!("   ❌ {}", e))?;
+        log_history(contract, "buy", &[], &minted, 0, tx_hash, None).await;
+        tree_state.begin_batch(TREE_BATCH_FLUSH);
+        for pad in &pads {
+            tree_state.add_commitment(*pad);
+        }
+        tree_state.commit();
+        println!("   📝 Minted {} zero-value pad notes", pads.len());
+    }
+
+    let mut saved = Vec::new();
+    for hop in &hops {
+        let recipient = &recipients[hop.recipient];
+        println!(
+            "\n   ➡️  Hop {}/{}: {} to {}, change {}",
+            hop.recipient + 1,
+            hops.len(),
+            hop.output.value,
+            recipient.name,
+            hop.change.value
+        );
+
+        // Earlier hops stay mined; a rerun resumes from this one
+        let stopped = |e: WalletError| {
+            let e = e.map_message(|m| format!("Hop {} of {}: {}", hop.recipient + 1, hops.len(), m));
+            println!("   ❌ {}", e);
+            println!(
+                "   ℹ️  Rerun this buy with --source-note {} and the same --recipients to resume from this hop",
+                source_note_path
+            );
+            e
+        };
+        let hop_spends = format!("{}", fr_to_bytes32(&issuer_wallet.keys.sign_nullifier(hop.input.salt)));
+        let prior = resume_attempt(contract, "buy", &hop_spends)
+            .await
+            .map_err(|e| stopped(e.into()))?;
+        let landed = prior.as_ref().is_some_and(|(_, landed)| *landed);
+        if resuming && prior.is_none() {
+            // Without an attempt of its own, a hop whose input is spent was finished
+            // by an earlier run (its attempt is cleared once its outputs are saved)
+            let nullifier = fr_to_bytes32(&issuer_wallet.keys.sign_nullifier(hop.input.salt));
+            if contract.nullifier_spent(nullifier).await.map_err(|e| stopped(e.into()))? {
+                println!("   ✔️  Sold by an earlier run");
+                continue;
+            }
+        }
+        if let Some((attempt, _)) = &prior {
+            if attempt.commitments != outputs_hex(hop) {
+                let e = format!("Attempt {} spends this hop's note into other outputs", attempt.key);
+                return Err(stopped(e.into()));
+            }
+        }
+
+        // Repairs below are undone if the transfer is not sent
+        let checkpoint = tree_state.snapshot();
+        let witness = match landed {
+            true => None,
+            false => {
+                repair_tree_from_chain(contract, events, &mut tree_state, &hop.input.commitment()).await;
+                match fan_out_hop_witness(&issuer_wallet.keys, hop, &tree_state) {
+                    Ok(witness) => Some(witness),
+                    Err(e) => {
+                        tree_state.restore(checkpoint);
+                        return Err(stopped(e.into()));
+                    }
+                }
+            }
+        };
+
+        let (attempt, tx_hash, witness) = match (prior, witness) {
+            // The earlier transfer was mined: only its outputs are left to save
+            (Some((attempt, true)), _) => {
+                let tx_hash = attempt.tx_hash.as_deref().and_then(|h| h.parse().ok()).unwrap_or_default();
+                (attempt, tx_hash, None)
+            }
+            (prior, Some(witness)) => {
+                // A hop that never landed is resubmitted with its proof when the witness is unchanged
+                let key = witness_id(&witness);
+                let reusable = prior
+                    .as_ref()
+                    .map(|(attempt, _)| attempt)
+                    .filter(|attempt| attempt.key == key && Path::new(&attempt.proof).exists());
+                let proof_file = match reusable {
+                    Some(attempt) => {
+                        println!("   ♻️  Reusing proof {}", attempt.proof);
+                        attempt.proof.clone()
+                    }
+                    None => match prove_bound(prover, contract, events, &witness).await {
+                        Ok(path) => path,
+                        Err(e) => {
+                            tree_state.restore(checkpoint);
+                            return Err(stopped(e));
+                        }
+                    },
+                };
+                let proof_bytes = match load_bound_proof(contract, &proof_file).await {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        tree_state.restore(checkpoint);
+                        return Err(stopped(e.into()));
+                    }
+                };
+
+                // Recorded before sending, so a rerun knows this spend may be out there
+                let mut attempt = Attempt {
+                    key,
+                    command: "buy".to_string(),
+                    spends: hop_spends.clone(),
+                    salts: (hop.output.salt, hop.change.salt),
+                    commitments: outputs_hex(hop),
+                    proof: proof_file,
+                    tx_hash: None,
+                    retries: prior.as_ref().map_or(0, |(attempt, _)| attempt.retries),
+                    chain: Vec::new(),
+                };
+                if let Err(e) = attempt.save() {
+                    tree_state.restore(checkpoint);
+                    return Err(stopped(e.into()));
+                }
+                if let Some((earlier, _)) = prior.filter(|(earlier, _)| earlier.key != attempt.key) {
+                    earlier.clear();
+                }
+
+                events.on_tx_sent("transfer");
+                let sent = contract
+                    .transfer(
+                        Bytes::from(proof_bytes),
+                        fr_to_bytes32(&witness.root),
+                        [fr_to_bytes32(&witness.nullifiers[0]), fr_to_bytes32(&witness.nullifiers[1])],
+                        [
+                            fr_to_bytes32(&witness.commitments_out[0]),
+                            fr_to_bytes32(&witness.commitments_out[1]),
+                        ],
+                    )
+                    .await;
+                match sent {
+                    Ok(tx_hash) => (attempt, tx_hash, Some(witness)),
+                    Err(e) => {
+                        tree_state.restore(checkpoint);
+                        match &e {
+                            TxError::Unconfirmed { tx_hash, .. } | TxError::Pending { tx_hash, .. } => {
+                                attempt.tx_hash = Some(format!("{}", tx_hash));
+                                let _ = attempt.save();
+                                println!("   ℹ️  No local state written for this hop; it resumes once {:?} is mined", tx_hash);
+                            }
+                            _ => attempt.clear(),
+                        }
+                        return Err(stopped(e.into()));
+                    }
+                }
+            }
+            (_, None) => unreachable!("a hop that did not land has a witness"),
+        };
+        events.on_tx_confirmed("transfer", tx_hash);
+        if let Some(witness) = &witness {
+            save_witness(witness, &format!("{}", tx_hash));
+        }
+        let commitments_out = outputs_of(hop);
+        log_history(
+            contract,
+            "buy",
+            &[hop.input.salt, hop.pad.salt].map(|salt| fr_to_bytes32(&issuer_wallet.keys.sign_nullifier(salt))),
+            &commitments_out.map(|c| fr_to_bytes32(&c)),
+            hop.output.value,
+            tx_hash,
+            None,
+        )
+        .await;
+
+        let salt8 = format!("{:016x}", hop.output.salt)[..8].to_string();
+        if let Some(keys) = &recipient.keys {
+            let bond = Bond {
+                schema_version: BOND_SCHEMA_VERSION,
+                commitment: fr_to_hex(&hop.output.commitment()),
+                nullifier: fr_to_hex(&keys.sign_nullifier(hop.output.salt)),
+                value: hop.output.value,
+                salt: hop.output.salt,
+                owner: fr_to_hex(&recipient.spending_key),
+                asset_id: source_bond.asset_id,
+                maturity_date: source_bond.maturity_date,
+                created_at: Utc::now().to_rfc3339(),
+                label: label.map(str::to_string),
+                memo_hash: hop.output.memo_hash,
+            };
+            let path = format!("{}/bond_{}_{}.json", data_dir(), recipient.name, salt8);
+            match write_atomic(&path, serde_json::to_string_pretty(&bond).unwrap()) {
+                Ok(_) => {
+                    println!("   ✅ Bond for {} saved to: {}", recipient.name, path);
+                    index_bond(&mut tree_state, &path, &bond);
+                    saved.push(path);
+                }
+                Err(e) => println!("   ❌ Error saving bond for {}: {}", recipient.name, e),
+            }
+        }
+
+        match MemoEnvelope::seal(&issuer_wallet.keys, &recipient.viewing_key, &Note::describing(&hop.output))
+            .and_then(|envelope| save_memo(&format!("memo_{}_{}", recipient.name, salt8), &envelope))
+        {
+            Ok(path) => println!("   🔒 Encrypted memo saved to: {}", path),
+            Err(e) => println!("   ⚠️  Failed to save memo: {}", e),
+        }
+
+        // Each change is spent by the next hop; `prune` archives the spent ones
+        if hop.change.value > 0 {
+            let change_bond = Bond {
+                schema_version: BOND_SCHEMA_VERSION,
+                commitment: fr_to_hex(&hop.change.commitment()),
+                nullifier: fr_to_hex(&issuer_wallet.keys.sign_nullifier(hop.change.salt)),
+                value: hop.change.value,
+                salt: hop.change.salt,
+                owner: issuer_wallet.keys.public_spending_key_hex.clone(),
+                asset_id: source_bond.asset_id,
+                maturity_date: source_bond.maturity_date,
+                created_at: Utc::now().to_rfc3339(),
+                label: None,
+                memo_hash: None,
+            };
+            let path = format!(
+                "{}/issuer_change_{}.json",
+                data_dir(),
+                &format!("{:016x}", hop.change.salt)[..8]
+            );
+            if write_atomic(&path, serde_json::to_string_pretty(&change_bond).unwrap()).is_ok() {
+                index_bond(&mut tree_state, &path, &change_bond);
+            }
+        }
+
+        // Same order as submitted; a resumed hop's may already be there, restored from the chain
+        for commitment in commitments_out {
+            if tree_state.find_commitment(&commitment).is_none() {
+                tree_state.add_commitment(commitment);
+            }
+        }
+        attempt.clear();
+    }
+
+    chain.clear();
+    let change = hops.last().map(|hop| hop.change.value).unwrap_or(0);
+    println!("\n✅ Sold {} to {} recipients; issuer keeps {}", total, hops.len(), change);
+    Ok(saved)
+}
+
+pub async fn trade(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    wallet_a_name: &str, bond_a_path: &str, wallet_b_name: &str, bond_b_path: &str) -> Result<(), WalletError> {
+    println!("\n🔄 Atomic trade between {} and {}...", wallet_a_name, wallet_b_name);
+
+    // 1. Load both wallets
+    let mut wallet_a = match load_wallet(wallet_a_name) {
+        Some(w) => w,
+        None => return fail(WalletError::WalletNotFound(format!("Wallet A '{}' not found", wallet_a_name))),
+    };
+    let mut wallet_b = match load_wallet(wallet_b_name) {
+        Some(w) => w,
+        None => return fail(WalletError::WalletNotFound(format!("Wallet B '{}' not found", wallet_b_name))),
+    };
+
+    // 2. Load both bonds
+    // load_bond reports why a file could not be read
+    let bond_a = match load_bond(bond_a_path) {
+        Some(b) => b,
+        None => return Err(WalletError::Other(format!("Bond '{}' not found", bond_a_path))),
+    };
+    let bond_b = match load_bond(bond_b_path) {
+        Some(b) => b,
+        None => return Err(WalletError::Other(format!("Bond '{}' not found", bond_b_path))),
+    };
+
+    println!(
+        "   Party A ({}) gives: {} (value: {})",
+        wallet_a_name,
+        short(&bond_a.commitment, 12),
+        bond_a.value
+    );
+    println!(
+        "   Party B ({}) gives: {} (value: {})",
+        wallet_b_name,
+        short(&bond_b.commitment, 12),
+        bond_b.value
+    );
+
+    // 3. Check maturity for both bonds
+    let policy = MaturityPolicy::now();
+    for (name, bond) in [("A", &bond_a), ("B", &bond_b)] {
+        if let Err(e) = policy.check_transfer(bond) {
+            return fail(format!("Bond {} {}", name, e));
+        }
+    }
+
+    // 4. Check different nullifiers
+    if bond_a.nullifier == bond_b.nullifier {
+        return fail("Cannot trade: identical nullifiers!".to_string());
+    }
+
+    // 5. Verify ownership
+    if bond_a.owner != wallet_a.keys.public_spending_key_hex {
+        return fail("Wallet A doesn't own bond A".to_string());
+    }
+    if bond_b.owner != wallet_b.keys.public_spending_key_hex {
+        return fail("Wallet B doesn't own bond B".to_string());
+    }
+
+    println!("\n✅ Trade validation passed");
+
+    // Both notes are spent by one swap; a wrong bond path must not trade it away
+    let pending = PendingAction {
+        action: "trade".to_string(),
+        value: bond_a.value,
+        nullifier: parse_commitment(&bond_a.nullifier)
+            .map(|n| format!("{}", fr_to_bytes32(&n)))
+            .unwrap_or_else(|| bond_a.nullifier.clone()),
+        recipient: Some(wallet_b.keys.public_spending_key_hex.clone()),
+    };
+    if !events.confirm(&pending) {
+        println!("🚫 Trade cancelled; nothing was submitted");
+        return Err(WalletError::Other("Not confirmed".to_string()));
+    }
+
+    // 6. Load merkle tree
+    let mut tree_state = TreeState::load();
+    let tree = match tree_state.proof_tree() {
+        Ok(tree) => tree,
+        Err(e) => return fail(e),
+    };
+    let merkle_root = tree.root();
+
+    // Find both notes in tree
+    let index_a = match parse_commitment(&bond_a.commitment)
+        .and_then(|c| tree_state.find_commitment(&c))
+    {
+        Some(idx) => idx,
+        None => return fail("Bond A commitment not found in merkle tree".to_string()),
+    };
+    let index_b = match parse_commitment(&bond_b.commitment)
+        .and_then(|c| tree_state.find_commitment(&c))
+    {
+        Some(idx) => idx,
+        None => return fail("Bond B commitment not found in merkle tree".to_string()),
+    };
+
+    println!("   Bond A at tree index: {}", index_a);
+    println!("   Bond B at tree index: {}", index_b);
+
+    // 7. Prepare new output notes (A's bond → B, B's bond → A)
+    // Each side draws the salt of the note it hands over
+    let (new_salt_a_to_b, new_salt_b_to_a) = match (
+        draw_salts(wallet_a_name, &mut wallet_a, 1),
+        draw_salts(wallet_b_name, &mut wallet_b, 1),
+    ) {
+        (Ok(a), Ok(b)) => (a[0], b[0]),
+        (Err(e), _) | (_, Err(e)) => {
+            return fail(e);
+        }
+    };
+
+    // Derive owner field from public keys
+    let (owner_a_fr, owner_b_fr) = match (
+        parse_public_spending_key(&wallet_a.keys.public_spending_key_hex),
+        parse_public_spending_key(&wallet_b.keys.public_spending_key_hex),
+    ) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => {
+            return fail(e.to_string());
+        }
+    };
+
+    // Input notes, and the outputs each moving one bond to its counterparty
+    // (same value/maturity as the bond it comes from)
+    let note = |bond: &Bond, salt, owner| {
+        CircuitNote::new(bond.value, salt, owner, bond.asset_id, bond.maturity_date)
+    };
+    let (input_a, input_b, output_to_b, output_to_a) = match (
+        note(&bond_a, bond_a.salt, owner_a_fr),
+        note(&bond_b, bond_b.salt, owner_b_fr),
+        note(&bond_a, new_salt_a_to_b, owner_b_fr),
+        note(&bond_b, new_salt_b_to_a, owner_a_fr),
+    ) {
+        (Ok(input_a), Ok(input_b), Ok(to_b), Ok(to_a)) => (input_a, input_b, to_b, to_a),
+        (Err(e), ..) | (_, Err(e), ..) | (_, _, Err(e), _) | (.., Err(e)) => {
+            return fail(e.to_string());
+        }
+    };
+    let input_a = input_a.with_memo_hash(bond_a.memo_hash);
+    let input_b = input_b.with_memo_hash(bond_b.memo_hash);
+    // Each output is bound to the memo its sender seals for the counterparty
+    let (output_to_b, output_to_a) = match (
+        output_to_b.bound_to_memo(&wallet_a.keys, wallet_b.keys.public_viewing_key()),
+        output_to_a.bound_to_memo(&wallet_b.keys, wallet_a.keys.public_viewing_key()),
+    ) {
+        (Ok(to_b), Ok(to_a)) => (to_b, to_a),
+        (Err(e), _) | (_, Err(e)) => return fail(e),
+    };
+    let commitment_to_b = output_to_b.commitment();
+    let commitment_to_a = output_to_a.commitment();
+
+    println!("\n📝 Trade outputs:");
+    println!("   A→B: value={}, commitment={}", bond_a.value, fr_to_hex(&commitment_to_b));
+    println!("   B→A: value={}, commitment={}", bond_b.value, fr_to_hex(&commitment_to_a));
+
+    // 8. Build proofs for both transfers
+    // Proof A: A spends their note, creates output for B (+ dummy for change slot)
+    // Proof B: B spends their note, creates output for A (+ dummy for change slot)
+
+    let (path_a, path_b) = match (tree.generate_proof(index_a), tree.generate_proof(index_b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => return fail(e),
+    };
+
+    // Nullifiers
+    let nullifier_a = wallet_a.keys.sign_nullifier(bond_a.salt);
+    let nullifier_b = wallet_b.keys.sign_nullifier(bond_b.salt);
+
+    // The swap moves ownership only: each output keeps its input's value and terms
+    for (leg, input, output) in [("A→B", &input_a, &output_to_b), ("B→A", &input_b, &output_to_a)] {
+        if let Err(e) = check_swap_terms(input, output) {
+            return fail(format!("Output {} {}", leg, e));
+        }
+    }
+
+    // Dummy notes for the second input/output slot (value=0). The circuit proves one
+    // owner, asset and maturity per leg, so each leg pads with its spender's own dummy.
+    let mut dummies = Vec::new();
+    for (leg, input) in [("A", &input_a), ("B", &input_b)] {
+        let dummy = input.padding();
+        let commitment = dummy.commitment();
+        let Some(index) = tree_state.find_commitment(&commitment) else {
+            return fail(format!(
+                "No dummy note for leg {} (its owner, asset {}, maturity {}) in merkle tree; the circuit cannot prove that leg",
+                leg,
+                input.asset_id,
+                format_date(input.maturity_date)
+            ));
+        };
+        let path = match tree.generate_proof(index) {
+            Ok(path) => path,
+            Err(e) => return fail(e),
+        };
+        dummies.push((dummy, commitment, path));
+    }
+    let [(dummy_a, dummy_commitment_a, dummy_path_a), (dummy_b, dummy_commitment_b, dummy_path_b)] =
+        <[_; 2]>::try_from(dummies).unwrap();
+
+    // 9. Build both witnesses: A spends → B receives, B spends → A receives
+    let witness_a = match (JoinSplitInputs {
+        root: merkle_root,
+        input_notes: vec![input_a.clone(), dummy_a.clone()],
+        merkle_paths: vec![path_a, dummy_path_a],
+        nullifiers: vec![nullifier_a, wallet_a.keys.sign_nullifier(DUMMY_SALT)],
+        output_notes: [output_to_b.clone(), dummy_a],
+        commitments_out: [commitment_to_b, dummy_commitment_a],
+        private_key: wallet_a.keys.get_private_spending_key(),
+        burn: false,
+    })
+    .build()
+    {
+        Ok(w) => w,
+        Err(e) => return fail(format!("Invalid witness A: {}", e)),
+    };
+
+    let witness_b = match (JoinSplitInputs {
+        root: merkle_root,
+        input_notes: vec![input_b.clone(), dummy_b.clone()],
+        merkle_paths: vec![path_b, dummy_path_b],
+        nullifiers: vec![nullifier_b, wallet_b.keys.sign_nullifier(DUMMY_SALT)],
+        output_notes: [output_to_a.clone(), dummy_b],
+        commitments_out: [commitment_to_a, dummy_commitment_b],
+        private_key: wallet_b.keys.get_private_spending_key(),
+        burn: false,
+    })
+    .build()
+    {
+        Ok(w) => w,
+        Err(e) => return fail(format!("Invalid witness B: {}", e)),
+    };
+
+    // 10. Prove both legs concurrently, each in its own working directory. If either
+    //     fails the other is dropped (killing its prover) and nothing is submitted.
+    println!("\n⚙️  Proving {}→{} and {}→{} concurrently", wallet_a_name, wallet_b_name, wallet_b_name, wallet_a_name);
+    let (proof_a_bytes, proof_b_bytes) = match tokio::try_join!(
+        prove_trade_leg(prover, contract, events, &witness_a, "a"),
+        prove_trade_leg(prover, contract, events, &witness_b, "b"),
+    ) {
+        Ok(proofs) => proofs,
+        Err(e) => return fail(e),
+    };
+
+    // 11. Call atomicSwap on contract
+
+    // Build public inputs for proof A
+    let root_a = fr_to_bytes32(&merkle_root);
+    let null_a = fr_to_bytes32(&nullifier_a);
+    let comm_a = fr_to_bytes32(&commitment_to_b);
+    let maturity_a = FixedBytes::<32>::from_slice(&{
+        let mut bytes = [0u8; 32];
+        bytes[24..32].copy_from_slice(&bond_a.maturity_date.to_be_bytes());
+        bytes
+    });
+
+    // Build public inputs for proof B
+    let root_b = fr_to_bytes32(&merkle_root);
+    let null_b = fr_to_bytes32(&nullifier_b);
+    let comm_b = fr_to_bytes32(&commitment_to_a);
+    let maturity_b = FixedBytes::<32>::from_slice(&{
+        let mut bytes = [0u8; 32];
+        bytes[24..32].copy_from_slice(&bond_b.maturity_date.to_be_bytes());
+        bytes
+    });
+
+    events.on_tx_sent("atomicSwap");
+    match contract
+        .atomic_swap(
+            Bytes::from(proof_a_bytes),
+            vec![root_a, null_a, comm_a, maturity_a],
+            Bytes::from(proof_b_bytes),
+            vec![root_b, null_b, comm_b, maturity_b],
+        )
+        .await
+    {
+        Ok(tx_hash) => {
+            events.on_tx_confirmed("atomicSwap", tx_hash);
+            save_witness(&witness_a, &format!("{}_a", tx_hash));
+            save_witness(&witness_b, &format!("{}_b", tx_hash));
+            log_history(
+                contract,
+                "trade",
+                &[null_a, null_b],
+                &[comm_a, comm_b],
+                bond_a.value + bond_b.value,
+                tx_hash,
+                None,
+            )
+            .await;
+        }
+        Err(TxError::Unconfirmed { tx_hash, reason }) => {
+            println!("   ⚠️  Transaction pending but watch failed: {}", reason);
+            print_unfinished("trade", tx_hash, &[(wallet_b_name, &output_to_b), (wallet_a_name, &output_to_a)]);
+            return Err(WalletError::Other(reason));
+        }
+        Err(e @ TxError::Pending { tx_hash, .. }) => {
+            println!("   ⏳ {}", e);
+            print_unfinished("trade", tx_hash, &[(wallet_b_name, &output_to_b), (wallet_a_name, &output_to_a)]);
+            return Err(e.into());
+        }
+        Err(e) => {
+            println!("   ❌ atomicSwap failed: {}", e);
+            return Err(WalletError::from(e).map_message(|m| format!("atomicSwap failed: {}", m)));
+        }
+    }
+
+    // 12. Save new bonds
+    // Bond for B (received from A)
+    let bond_for_b = Bond {
+        schema_version: BOND_SCHEMA_VERSION,
+        commitment: fr_to_hex(&commitment_to_b),
+        nullifier: fr_to_hex(&wallet_b.keys.sign_nullifier(new_salt_a_to_b)),
+        value: bond_a.value,
+        salt: new_salt_a_to_b,
+        owner: wallet_b.keys.public_spending_key_hex.clone(),
+        asset_id: bond_a.asset_id,
+        maturity_date: bond_a.maturity_date,
+        created_at: Utc::now().to_rfc3339(),
+        label: None,
+        memo_hash: output_to_b.memo_hash,
+    };
+    let file_b = format!("{}/bond_{}_{}.json", data_dir(), wallet_b_name, &format!("{:016x}", new_salt_a_to_b)[..8]);
+    if let Err(e) = write_atomic(&file_b, serde_json::to_string_pretty(&bond_for_b).unwrap()) {
+        println!("⚠️  Failed to save bond for B: {}", e);
+    } else {
+        println!("\n✅ Bond for {} saved: {}", wallet_b_name, file_b);
+        index_bond(&mut tree_state, &file_b, &bond_for_b);
+    }
+
+    // Bond for A (received from B)
+    let bond_for_a = Bond {
+        schema_version: BOND_SCHEMA_VERSION,
+        commitment: fr_to_hex(&commitment_to_a),
+        nullifier: fr_to_hex(&wallet_a.keys.sign_nullifier(new_salt_b_to_a)),
+        value: bond_b.value,
+        salt: new_salt_b_to_a,
+        owner: wallet_a.keys.public_spending_key_hex.clone(),
+        asset_id: bond_b.asset_id,
+        maturity_date: bond_b.maturity_date,
+        created_at: Utc::now().to_rfc3339(),
+        label: None,
+        memo_hash: output_to_a.memo_hash,
+    };
+    let file_a = format!("{}/bond_{}_{}.json", data_dir(), wallet_a_name, &format!("{:016x}", new_salt_b_to_a)[..8]);
+    if let Err(e) = write_atomic(&file_a, serde_json::to_string_pretty(&bond_for_a).unwrap()) {
+        println!("⚠️  Failed to save bond for A: {}", e);
+    } else {
+        println!("✅ Bond for {} saved: {}", wallet_a_name, file_a);
+        index_bond(&mut tree_state, &file_a, &bond_for_a);
+    }
+
+    // 13. Encrypt memos for each party
+    let note_for_b = Note::describing(&output_to_b);
+    let memo_stem = format!("memo_trade_{}_{}", wallet_b_name, &format!("{:016x}", new_salt_a_to_b)[..8]);
+    match MemoEnvelope::seal(&wallet_a.keys, wallet_b.keys.public_viewing_key(), &note_for_b)
+        .and_then(|envelope| save_memo(&memo_stem, &envelope))
+    {
+        Ok(_) => println!("🔒 Encrypted memo for {} saved", wallet_b_name),
+        Err(e) => println!("⚠️  Failed to save memo for {}: {}", wallet_b_name, e),
+    }
+
+    let note_for_a = Note::describing(&output_to_a);
+    let memo_stem = format!("memo_trade_{}_{}", wallet_a_name, &format!("{:016x}", new_salt_b_to_a)[..8]);
+    match MemoEnvelope::seal(&wallet_b.keys, wallet_a.keys.public_viewing_key(), &note_for_a)
+        .and_then(|envelope| save_memo(&memo_stem, &envelope))
+    {
+        Ok(_) => println!("🔒 Encrypted memo for {} saved", wallet_a_name),
+        Err(e) => println!("⚠️  Failed to save memo for {}: {}", wallet_a_name, e),
+    }
+
+    // 14. Sign handover receipts (each recipient stores the sender's signed acknowledgment)
+    let receipt_for_b = wallet_a.keys.sign_receipt(
+        &bond_for_b.commitment,
+        bond_for_b.value,
+        bond_for_b.asset_id,
+        bond_for_b.maturity_date,
+        &bond_for_b.owner,
+    );
+    let receipt_file_b = format!("{}/receipt_{}_{}.json", data_dir(), wallet_b_name, &format!("{:016x}", new_salt_a_to_b)[..8]);
+    if write_atomic(&receipt_file_b, serde_json::to_string_pretty(&receipt_for_b).unwrap()).is_ok() {
+        println!("🧾 Receipt for {} saved: {}", wallet_b_name, receipt_file_b);
+    }
+
+    let receipt_for_a = wallet_b.keys.sign_receipt(
+        &bond_for_a.commitment,
+        bond_for_a.value,
+        bond_for_a.asset_id,
+        bond_for_a.maturity_date,
+        &bond_for_a.owner,
+    );
+    let receipt_file_a = format!("{}/receipt_{}_{}.json", data_dir(), wallet_a_name, &format!("{:016x}", new_salt_b_to_a)[..8]);
+    if write_atomic(&receipt_file_a, serde_json::to_string_pretty(&receipt_for_a).unwrap()).is_ok() {
+        println!("🧾 Receipt for {} saved: {}", wallet_a_name, receipt_file_a);
+    }
+
+    // 15. Update tree state
+    tree_state.add_commitment(commitment_to_b);
+    tree_state.add_commitment(commitment_to_a);
+    println!("   📝 Added 2 new commitments to merkle tree");
+
+    println!("\n🎉 Trade complete!");
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn redeem(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    wallet_name: &str,
+    bond_path: &str,
+    settlement_ref: Option<&str>,
+    settlement_account: Option<&str>,
+    private: bool,
+) -> Result<(), WalletError> {
+    println!("\n💰 Redeeming bond...");
+    prover::check_submittable(CIRCUIT_INPUTS).or_else(fail)?;
+
+    // 1. Load wallet and bond
+    let mut wallet = match load_wallet(wallet_name) {
+        Some(w) => w,
+        None => return fail(WalletError::wallet_not_found(wallet_name)),
+    };
+
+    let bond = match load_bond(bond_path) {
+        Some(b) => b,
+        None => return Err(WalletError::Other(format!("Bond '{}' not found", bond_path))),
+    };
+
+    println!(
+        "   Bond: {} (value: {})",
+        short(&bond.commitment, 12),
+        bond.value
+    );
+
+    // 2. Check maturity
+    if let Err(e) = MaturityPolicy::now().check_redeem(&bond) {
+        println!("❌ Cannot redeem: {}", e);
+        println!("   Maturity date: {}", format_date(bond.maturity_date));
+        return Err(e.into());
+    }
+
+    println!("✅ Bond at maturity - proceeding with redemption");
+
+    // 3. Verify ownership
+    if bond.owner != wallet.keys.public_spending_key_hex {
+        println!("❌ Wallet '{}' doesn't own this bond", wallet_name);
+        return Err(WalletError::Other(format!("Wallet '{}' doesn't own this bond", wallet_name)));
+    }
+
+    // 4. Load merkle tree and find bond
+    let mut tree_state = TreeState::load();
+    let tree = match tree_state.proof_tree() {
+        Ok(tree) => tree,
+        Err(e) => return fail(e),
+    };
+    let merkle_root = tree.root();
+
+    let bond_index = match parse_commitment(&bond.commitment)
+        .and_then(|c| tree_state.find_commitment(&c))
+    {
+        Some(idx) => idx,
+        None => {
+            println!("❌ Bond commitment not found in merkle tree");
+            return Err(WalletError::Other("Bond commitment not found in merkle tree".to_string()));
+        }
+    };
+
+    // 5. Derive owner Fr from public key
+    let owner_fr = match parse_public_spending_key(&wallet.keys.public_spending_key_hex) {
+        Ok(owner) => owner,
+        Err(e) => return fail(e.to_string()),
+    };
+
+    // 6. Create input note
+    let note = |value, salt| {
+        CircuitNote::new(value, salt, owner_fr, bond.asset_id, bond.maturity_date)
+            .map_err(|e| e.to_string())
+            .inspect_err(|e| println!("❌ {}", e))
+    };
+    let input_note = note(bond.value, bond.salt)?.with_memo_hash(bond.memo_hash);
+
+    // 7. Create dummy input note (second input slot) - only a 2-input circuit needs it
+    let dummy_input = if CIRCUIT_INPUTS == 2 {
+        let dummy_note = input_note.padding();
+        let dummy_commitment = dummy_note.commitment();
+
+        let dummy_index = match tree_state.find_commitment(&dummy_commitment) {
+            Some(idx) => idx,
+            None => {
+                println!("❌ Dummy note not found in merkle tree");
+                println!("   ℹ️  Ensure issuer ran 'onboard' which creates dummy notes");
+                return Err(WalletError::Other("Dummy note not found in merkle tree".to_string()));
+            }
+        };
+        println!("   Dummy at tree index: {}", dummy_index);
+        match tree.generate_proof(dummy_index) {
+            Ok(path) => Some((dummy_note, path)),
+            Err(e) => return fail(e),
+        }
+    } else {
+        None
+    };
+
+    println!("   Bond at tree index: {}", bond_index);
+
+    // 8. Generate merkle proof
+    let bond_path_proof = match tree.generate_proof(bond_index) {
+        Ok(path) => path,
+        Err(e) => return fail(e),
+    };
+
+    // 9. Compute nullifier (the dummy's nullifier is derived by the witness builder)
+    let nullifier = wallet.keys.sign_nullifier(bond.salt);
+
+    // 10. Create output notes with value = 0 (burn)
+    let (output_salt_0, output_salt_1) = match draw_salts(wallet_name, &mut wallet, 2) {
+        Ok(salts) => (salts[0], salts[1]),
+        Err(e) => return fail(e),
+    };
+
+    let output_note_0 = note(0, output_salt_0)?;
+    let output_note_1 = note(0, output_salt_1)?;
+
+    let commitment_out_0 = output_note_0.commitment();
+    let commitment_out_1 = output_note_1.commitment();
+
+    println!("\n📝 Burn transaction:");
+    println!("   Input value:  {} (will be burned)", bond.value);
+    println!("   Output value: 0 + 0 = 0");
+
+    // 11. Build witness for JoinSplit (redemption = outputs sum to 0)
+    let mut input_notes = vec![input_note];
+    let mut merkle_paths = vec![bond_path_proof];
+    let mut nullifiers = vec![nullifier];
+    if let Some((dummy_note, dummy_path)) = dummy_input {
+        input_notes.push(dummy_note);
+        merkle_paths.push(dummy_path);
+        nullifiers.push(wallet.keys.sign_nullifier(DUMMY_SALT));
+    }
+    let witness = match (JoinSplitInputs {
+        root: merkle_root,
+        input_notes,
+        merkle_paths,
+        nullifiers,
+        output_notes: [output_note_0, output_note_1],
+        commitments_out: [commitment_out_0, commitment_out_1],
+        private_key: wallet.keys.get_private_spending_key(),
+        burn: true,
+    })
+    .build()
+    {
+        Ok(w) => w,
+        Err(e) => {
+            println!("❌ Failed to build witness: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    // Burning is final: a mistyped bond path must not redeem the wrong note
+    let pending = PendingAction {
+        action: "redeem".to_string(),
+        value: bond.value,
+        nullifier: format!("{}", fr_to_bytes32(&nullifier)),
+        recipient: None,
+    };
+    if !events.confirm(&pending) {
+        println!("🚫 Redemption cancelled; nothing was submitted");
+        return Err(WalletError::Other("Not confirmed".to_string()));
+    }
+
+    // 12-13. Write the witness and generate proof
+    let proof_result = prove_bound(prover, contract, events, &witness).await;
+    let proof_bytes = match proof_result {
+        Ok(path) => {
+            match load_bound_proof(contract, &path).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("❌ Cannot use proof: {}", e);
+                    return Err(e.into());
+                }
+            }
+        }
+        Err(e) => return fail(e.map_message(|m| format!("Proof generation failed: {}", m))),
+    };
+
+    // 14. Call contract burn()
+
+    // Convert to bytes32
+    let root_bytes = fr_to_bytes32(&merkle_root);
+    let null_0 = fr_to_bytes32(&nullifier);
+    let null_1 = fr_to_bytes32(&witness.nullifiers[1]);
+    let comm_0 = fr_to_bytes32(&witness.commitments_out[0]);
+    let comm_1 = fr_to_bytes32(&witness.commitments_out[1]);
+    let maturity_bytes = FixedBytes::<32>::from_slice(&{
+        let mut bytes = [0u8; 32];
+        bytes[24..32].copy_from_slice(&bond.maturity_date.to_be_bytes());
+        bytes
+    });
+    let is_redeem = FixedBytes::<32>::from_slice(&{
+        let mut bytes = [0u8; 32];
+        bytes[31] = 1; // isRedeem = true
+        bytes
+    });
+
+    let mut receipt = RedemptionReceipt::new(
+        &bond,
+        &nullifier,
+        settlement_ref.map(str::to_string),
+        settlement_account.map(str::to_string),
+        private,
+    );
+
+    events.on_tx_sent("burn");
+    match contract
+        .burn(
+            Bytes::from(proof_bytes),
+            root_bytes,
+            [null_0, null_1],
+            [comm_0, comm_1],
+            maturity_bytes,
+            is_redeem,
+        )
+        .await
+    {
+        Ok(tx_hash) => {
+            events.on_tx_confirmed("burn", tx_hash);
+            save_witness(&witness, &format!("{}", tx_hash));
+            receipt.tx_hash = Some(format!("{}", tx_hash));
+            log_history(contract, "redeem", &[null_0, null_1], &[comm_0, comm_1], bond.value, tx_hash, Some(&receipt)).await;
+        }
+        Err(TxError::Unconfirmed { tx_hash, reason }) => {
+            println!("   ⚠️  Transaction pending but watch failed: {}", reason);
+            print_unfinished("redeem", tx_hash, &[]);
+            return Err(WalletError::Other(reason));
+        }
+        Err(e @ TxError::Pending { tx_hash, .. }) => {
+            println!("   ⏳ {}", e);
+            print_unfinished("redeem", tx_hash, &[]);
+            return Err(e.into());
+        }
+        Err(e) => {
+            println!("   ❌ Burn call failed: {}", e);
+            return Err(e.into());
+        }
+    }
+
+    // 15. Mark bond as redeemed (rename file)
+    let redeemed_path = bond_path.replace(".json", "_REDEEMED.json");
+    if let Err(e) = fs::rename(bond_path, &redeemed_path) {
+        println!("   ⚠️  Failed to mark bond as redeemed: {}", e);
+    } else {
+        println!("   📝 Bond marked as redeemed: {}", redeemed_path);
+    }
+
+    // 16. Update tree state (in submitted order)
+    for commitment in witness.commitments_out {
+        tree_state.add_commitment(commitment);
+    }
+
+    // 17. Save redemption receipt linking the burned nullifier to the settlement
+    match receipt.save() {
+        Ok(path) => println!("   🧾 Redemption receipt saved: {}", path),
+        Err(e) => println!("   ⚠️  Failed to save redemption receipt: {}", e),
+    }
+
+    println!("\n🎉 Redemption complete!");
+    println!("   Value burned: {}", bond.value);
+    match settlement_ref {
+        Some(reference) => println!("   Settlement ref: {}", reference),
+        None => println!("   ℹ️  Contact issuer for off-chain cash settlement"),
+    }
+    Ok(())
+}
+
+/// Bond files in the data directory owned by this wallet and not yet marked redeemed
+pub fn owned_bonds(wallet: &Wallet) -> Vec<(String, Bond)> {
+    TreeState::load()
+        .indexed_bonds()
+        .into_iter()
+        .filter(|(_, bond)| bond.owner == wallet.keys.public_spending_key_hex)
+        .collect()
+}
+
+/// Public view of a bond file: no salt or nullifier
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NoteSummary {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub commitment: String,
+    pub value: u64,
+    pub asset_id: u64,
+    pub maturity_date: u64,
+}
+
+impl NoteSummary {
+    pub fn new(path: &str, bond: &Bond) -> Self {
+        NoteSummary {
+            path: path.to_string(),
+            label: bond.label.clone(),
+            commitment: bond.commitment.clone(),
+            value: bond.value,
+            asset_id: bond.asset_id,
+            maturity_date: bond.maturity_date,
+        }
+    }
+}
+
+/// Unspent notes held by a wallet
+#[derive(Serialize, Debug, Clone)]
+pub struct Balance {
+    pub wallet: String,
+    pub total: u64,
+    pub notes: Vec<NoteSummary>,
+}
+
+/// Owned notes whose nullifier the contract has not seen
+pub async fn wallet_balance(
+    contract: &impl BondContract,
+    wallet_name: &str,
+) -> Result<Balance, WalletError> {
+    let wallet = require_wallet(wallet_name)?;
+
+    let owned = owned_bonds(&wallet);
+    let nullifiers = owned
+        .iter()
+        .map(|(_, bond)| fr_to_bytes32(&wallet.keys.sign_nullifier(bond.salt)))
+        .collect();
+    let spent = contract.nullifiers_spent(nullifiers).await?;
+    let notes: Vec<NoteSummary> = owned
+        .iter()
+        .zip(spent)
+        .filter(|(_, spent)| !spent)
+        .map(|((path, bond), _)| NoteSummary::new(path, bond))
+        .collect();
+    Ok(Balance {
+        wallet: wallet_name.to_string(),
+        total: notes.iter().map(|n| n.value).sum(),
+        notes,
+    })
+}
+
+/// Archived notes a wallet owned; all spent, so never part of its balance
+pub fn archived_notes(wallet_name: &str) -> Result<Vec<NoteSummary>, WalletError> {
+    let wallet = require_wallet(wallet_name)?;
+    Ok(bonds_in(&archive_dir())
+        .into_iter()
+        .filter(|(_, bond)| bond.owner == wallet.keys.public_spending_key_hex)
+        .map(|(path, bond)| NoteSummary::new(&path, &bond))
+        .collect())
+}
+
+fn print_note(note: &NoteSummary, assets: &AssetRegistry) {
+    println!(
+        "   {}{} value={} asset={} maturity={}",
+        note.path,
+        note.label.as_ref().map(|l| format!(" [{}]", l)).unwrap_or_default(),
+        note.value,
+        assets.display_name(note.asset_id),
+        format_date(note.maturity_date)
+    );
+}
+
+pub async fn balance(
+    contract: &impl BondContract,
+    wallet_name: &str,
+    include_archived: bool,
+) -> Result<(), WalletError> {
+    println!("\n💼 Balance of '{}'...", wallet_name);
+    let assets = AssetRegistry::load().or_else(fail)?;
+    match wallet_balance(contract, wallet_name).await {
+        Ok(balance) => {
+            balance.notes.iter().for_each(|note| print_note(note, &assets));
+            println!("   Total: {} ({} notes)", balance.total, balance.notes.len());
+        }
+        Err(e) => return fail(e),
+    }
+    if include_archived {
+        match archived_notes(wallet_name) {
+            Ok(archived) => {
+                println!("\n🗄️  Archived (spent): {} notes", archived.len());
+                archived.iter().for_each(|note| print_note(note, &assets));
+            }
+            Err(e) => return fail(e),
+        }
+    }
+    Ok(())
+}
+
+/// Bond files moved (or, on a dry run, to be moved) into the archive
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    /// (from, to) paths
+    pub archived: Vec<(String, String)>,
+    /// Bond files left in place: unspent, or whose nullifier could not be read
+    pub kept: usize,
+}
+
+/// Move every bond file whose nullifier the contract has seen into `archive/`.
+/// Files are only ever moved, so the note history is kept.
+pub async fn prune_spent(contract: &impl BondContract, dry_run: bool) -> Result<PruneReport, String> {
+    let mut report = PruneReport::default();
+    let mut tree_state = TreeState::load();
+    let archive = archive_dir();
+
+    let mut checked = Vec::new();
+    for (path, bond) in bonds_in(&data_dir()) {
+        match parse_commitment(&bond.nullifier) {
+            Some(nullifier) => checked.push((path, fr_to_bytes32(&nullifier))),
+            None => report.kept += 1,
+        }
+    }
+    let spent = contract
+        .nullifiers_spent(checked.iter().map(|(_, n)| *n).collect())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for ((path, _), spent) in checked.into_iter().zip(spent) {
+        if !spent {
+            report.kept += 1;
+            continue;
+        }
+
+        let name = Path::new(&path).file_name().unwrap().to_string_lossy().to_string();
+        let target = format!("{}/{}", archive, name);
+        if !dry_run {
+            if Path::new(&target).exists() {
+                return Err(format!("{} is already archived; not overwriting it", target));
+            }
+            fs::create_dir_all(&archive).map_err(|e| e.to_string())?;
+            fs::rename(&path, &target).map_err(|e| format!("Failed to archive {}: {}", path, e))?;
+            tree_state.forget_bond(&path);
+        }
+        report.archived.push((path, target));
+    }
+    Ok(report)
+}
+
+pub async fn prune(contract: &impl BondContract, dry_run: bool) -> Result<(), WalletError> {
+    println!("\n🧹 Pruning spent notes...");
+    match prune_spent(contract, dry_run).await {
+        Ok(report) => {
+            for (from, to) in &report.archived {
+                println!("   {} -> {}", from, to);
+            }
+            let verb = if dry_run { "Would archive" } else { "Archived" };
+            println!("✅ {} {} spent notes, kept {}", verb, report.archived.len(), report.kept);
+            Ok(())
+        }
+        Err(e) => fail(e),
+    }
+}
+
+/// Polls before retrying a note after `failures` consecutive failures (1, 2, 4, ... 32)
+fn backoff_polls(failures: u32) -> u64 {
+    1 << failures.saturating_sub(1).min(5)
+}
+
+/// Outcome of one watch poll
+#[derive(Debug, Default)]
+pub struct WatchReport {
+    /// Matured, unspent notes found (redeemed unless dry-run)
+    pub due: Vec<String>,
+    pub redeemed: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+/// Watch state carried across polls: per-note failure counts for backoff
+#[derive(Debug, Default)]
+pub struct Watcher {
+    poll: u64,
+    /// bond path -> (consecutive failures, poll at which to retry)
+    failures: std::collections::HashMap<String, (u32, u64)>,
+}
+
+impl Watcher {
+    /// Scan owned notes once and redeem each matured, unspent one
+    pub async fn poll(
+        &mut self,
+        prover: &impl Prover,
+        contract: &impl BondContract,
+        events: &impl EventSink,
+        wallet_name: &str,
+        dry_run: bool,
+    ) -> WatchReport {
+        self.poll += 1;
+        let mut report = WatchReport::default();
+
+        let wallet = match load_wallet(wallet_name) {
+            Some(w) => w,
+            None => {
+                let error = format!("Wallet '{}' not found", wallet_name);
+                report.failed.push((wallet_name.to_string(), error));
+                return report;
+            }
+        };
+
+        let policy = MaturityPolicy::now();
+        for (path, bond) in owned_bonds(&wallet) {
+            if policy.check_redeem(&bond).is_err() {
+                continue;
+            }
+            if let Some(&(_, retry_at)) = self.failures.get(&path) {
+                if self.poll < retry_at {
+                    continue;
+                }
+            }
+
+            let nullifier = fr_to_bytes32(&wallet.keys.sign_nullifier(bond.salt));
+            let result = match contract.nullifier_spent(nullifier).await {
+                Ok(true) => continue,
+                Ok(false) if dry_run => {
+                    println!("   ⏰ Would redeem {} (value: {})", path, bond.value);
+                    report.due.push(path);
+                    continue;
+                }
+                Ok(false) => {
+                    report.due.push(path.clone());
+                    redeem(prover, contract, events, wallet_name, &path, None, None, false).await
+                }
+                Err(e) => Err(e.into()),
+            };
+
+            match result {
+                Ok(()) => {
+                    self.failures.remove(&path);
+                    report.redeemed.push(path);
+                }
+                Err(e) => {
+                    let failures = self.failures.get(&path).map_or(0, |f| f.0) + 1;
+                    let retry_at = self.poll + backoff_polls(failures);
+                    println!(
+                        "   ⚠️  {} failed ({}), retrying in {} poll(s)",
+                        path,
+                        e,
+                        retry_at - self.poll
+                    );
+                    self.failures.insert(path.clone(), (failures, retry_at));
+                    report.failed.push((path, e.to_string()));
+                }
+            }
+        }
+        report
+    }
+}
+
+pub async fn watch(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    wallet_name: &str,
+    poll_interval: u64,
+    dry_run: bool,
+) -> Result<(), WalletError> {
+    println!(
+        "\n👀 Watching '{}' for matured notes every {}s{}...",
+        wallet_name,
+        poll_interval,
+        if dry_run { " (dry run)" } else { "" }
+    );
+    // Later polls report failures and keep going; a wallet that is not there never appears
+    require_wallet(wallet_name).or_else(fail)?;
+
+    let mut watcher = Watcher::default();
+    loop {
+        let report = watcher.poll(prover, contract, events, wallet_name, dry_run).await;
+        if !report.due.is_empty() || !report.failed.is_empty() {
+            println!(
+                "   [{}] due: {}, redeemed: {}, failed: {}",
+                Utc::now().to_rfc3339(),
+                report.due.len(),
+                report.redeemed.len(),
+                report.failed.len()
+            );
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(poll_interval)).await;
+    }
+}
+
+pub async fn export_vk(circuit_dir: &str, out_dir: &str) -> Result<(), WalletError> {
+    println!("\n🔑 Exporting verification key...");
+
+    match prover::export_vk(circuit_dir, out_dir).await {
+        Ok((vk_path, layout_path)) => {
+            println!("✅ Verification key: {}", vk_path);
+            println!("✅ Public input layout: {}", layout_path);
+            for (index, name) in TRANSFER_PUBLIC_INPUTS.iter().enumerate() {
+                println!("   [{}] {}", index, name);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            println!("❌ {}", e);
+            println!("   ℹ️  Compile the circuit first: cd {} && nargo compile", circuit_dir);
+            Err(e)
+        }
+    }
+}
+
+/// On-chain root next to the root of the local tree state
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootStatus {
+    pub onchain: FixedBytes<32>,
+    pub local: FixedBytes<32>,
+    pub leaves: usize,
+}
+
+impl RootStatus {
+    pub fn matches(&self) -> bool {
+        self.onchain == self.local
+    }
+}
+
+pub async fn root_status(contract: &impl BondContract) -> Result<RootStatus, String> {
+    root_status_with_progress(contract, |_| {}).await
+}
+
+/// `root_status`, reporting each level of the local rebuild to `on_level`
+pub async fn root_status_with_progress(
+    contract: &impl BondContract,
+    on_level: impl FnMut(BuildProgress),
+) -> Result<RootStatus, String> {
+    let onchain = contract.current_root().await.map_err(|e| e.to_string())?;
+    let tree_state = TreeState::load();
+    Ok(RootStatus {
+        onchain,
+        local: fr_to_bytes32(&tree_state.build_tree_with_progress(on_level).root()),
+        leaves: tree_state.commitments().len(),
+    })
+}
+
+/// Progress line for an interactive tree rebuild; silent when stdout is not a terminal
+fn print_build_progress(p: BuildProgress) {
+    if std::io::stdout().is_terminal() {
+        println!("   🔨 Building level {} of {}, {} nodes", p.level, p.levels, p.nodes);
+    }
+}
+
+/// Rebuild the local tree from the contract's leaves in `canonical_order`, so it
+/// holds what every other machine syncing from the same chain holds. Leaves only
+/// known here are kept, ordered after the chain's. Keeps the bond index. Fails,
+/// leaving the tree as it was, if a contract leaf is not a field element.
+pub async fn sync_tree(contract: &impl BondContract) -> Result<(RootStatus, usize), String> {
+    let (stored, _) = read_commitments(contract)
+        .await
+        .ok_or("Offline: there is no chain to sync the tree from")?;
+    let mut tree_state = TreeState::load();
+    let mut leaves = chain_leaves(&stored)?;
+    let local_only: Vec<Leaf> = tree_state
+        .commitments()
+        .iter()
+        .filter_map(|c| parse_commitment(c))
+        .filter(|c| !leaves.iter().any(|l| l.commitment == *c))
+        .map(|commitment| Leaf { index: None, commitment })
+        .collect();
+    let kept = local_only.len();
+    leaves.extend(local_only);
+    tree_state.replace_leaves(leaves);
+    Ok((root_status(contract).await?, kept))
+}
+
+pub async fn sync(contract: &impl BondContract) -> Result<(), WalletError> {
+    println!("\n🔄 Syncing the local merkle tree from the contract...");
+    match sync_tree(contract).await {
+        Ok((status, kept)) => {
+            println!("   Leaves:   {}", status.leaves);
+            println!("   Local:    {}", status.local);
+            if kept > 0 {
+                println!("   ⚠️  {} leaves are not on-chain; kept after the contract's", kept);
+            }
+            if status.matches() {
+                println!("✅ Local tree matches the on-chain root");
+            } else {
+                return fail(format!(
+                    "On-chain root is {}; transfers built on the local tree will revert",
+                    status.onchain
+                ));
+            }
+        }
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+/// Rebuild the local spent set from the contract's `NullifierSpent` events. From
+/// block 0 the set is replaced; a later `from_block` adds to the saved one.
+pub async fn sync_nullifier_set(contract: &impl BondContract, from_block: u64) -> Result<(SpentNullifiers, usize), String> {
+    let events = contract.spend_events(from_block).await.map_err(|e| e.to_string())?;
+    let mut set = if from_block == 0 {
+        SpentNullifiers::default()
+    } else {
+        SpentNullifiers::load()?
+    };
+    if set.nullifiers.is_empty() {
+        set.from_block = from_block;
+    }
+    let added = set.extend(&events);
+    set.save()?;
+    Ok((set, added))
+}
+
+pub async fn sync_nullifiers(contract: &impl BondContract, from_block: u64) -> Result<(), WalletError> {
+    println!("\n🔥 Rebuilding the spent set from NullifierSpent events since block {}...", from_block);
+    match sync_nullifier_set(contract, from_block).await {
+        Ok((set, added)) => {
+            println!("   Spent:      {} ({} new)", set.nullifiers.len(), added);
+            println!("   Blocks:     {}..={}", set.from_block, set.last_block);
+            println!("✅ Saved to {}", spent_nullifiers_path());
+        }
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+pub async fn root(contract: &impl BondContract) -> Result<(), WalletError> {
+    println!("\n🌳 Merkle root:");
+
+    match root_status_with_progress(contract, print_build_progress).await {
+        Ok(status) => {
+            println!("   On-chain: {}", status.onchain);
+            println!("   Local:    {} ({} leaves)", status.local, status.leaves);
+            if status.matches() {
+                println!("   ✅ Local tree is in sync");
+            } else {
+                println!("   ❌ Mismatch: transfers built on the local tree will revert");
+                return Err(WalletError::Other("Local tree does not match the on-chain root".to_string()));
+            }
+        }
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+pub fn info(bond_path: &str) -> Result<(), WalletError> {
+    println!("\n📊 Bond Information:");
+
+    let bond = match load_bond(bond_path) {
+        Some(b) => b,
+        None => return Err(WalletError::Other(format!("Bond '{}' not found", bond_path))),
+    };
+
+    if let Some(label) = &bond.label {
+        println!("   Label:      {}", label);
+    }
+    println!("   Commitment: {}", bond.commitment);
+    println!("   Nullifier:  {}", bond.nullifier);
+    println!("   Value:      {}", bond.value);
+    println!("   Salt:       {}", bond.salt);
+    match AssetRegistry::load() {
+        Ok(assets) => println!("   Asset:      {}", assets.display_name(bond.asset_id)),
+        Err(e) => return fail(e),
+    }
+    println!("   Created:    {}", bond.created_at);
+    println!("   Maturity:   {}", format_date(bond.maturity_date));
+    if let Some(leaf) = parse_commitment(&bond.commitment)
+        .and_then(|c| TreeState::load().find_commitment(&c))
+    {
+        println!("   Leaf:       {}", leaf);
+    }
+
+    println!("   Status:     {}", maturity_label(bond.maturity_date, unix_now()));
+    Ok(())
+}
+
+/// A swap output must carry its input's value, asset id and maturity unchanged
+fn check_swap_terms(input: &CircuitNote, output: &CircuitNote) -> Result<(), String> {
+    if output.asset_id != input.asset_id {
+        return Err(format!("changes asset id {} to {}", input.asset_id, output.asset_id));
+    }
+    if output.maturity_date != input.maturity_date {
+        return Err(format!(
+            "changes maturity {} to {}",
+            format_date(input.maturity_date),
+            format_date(output.maturity_date)
+        ));
+    }
+    if output.value != input.value {
+        return Err(format!("changes value {} to {}", input.value, output.value));
+    }
+    Ok(())
+}
+
+/// The maturity rules every spend goes through: a note moves to another owner
+/// (`buy`, `trade`) strictly before its maturity date, and from that date on it can
+/// only be burned (`redeem`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaturityPolicy {
+    /// Unix seconds the rules are applied at
+    pub now: u64,
+}
+
+impl MaturityPolicy {
+    pub fn at(now: u64) -> Self {
+        MaturityPolicy { now }
+    }
+
+    pub fn now() -> Self {
+        Self::at(unix_now())
+    }
+
+    /// A matured note is only redeemable; transferring it is refused
+    pub fn check_transfer(&self, bond: &Bond) -> Result<(), String> {
+        match maturity_status(bond.maturity_date, self.now) {
+            MaturityStatus::Matured => Err(format!(
+                "matured on {}; it can only be redeemed (`redeem --bond <note>`), not transferred",
+                format_date(bond.maturity_date)
+            )),
+            MaturityStatus::Pending { .. } => Ok(()),
+        }
+    }
+
+    /// Redemption opens at the maturity date itself
+    pub fn check_redeem(&self, bond: &Bond) -> Result<(), String> {
+        match maturity_status(bond.maturity_date, self.now) {
+            MaturityStatus::Matured => Ok(()),
+            MaturityStatus::Pending { days_left } => {
+                Err(format!("{} days until maturity", days_left))
+            }
+        }
+    }
+}
+
+/// `info` status line
+fn maturity_label(maturity_date: u64, now: u64) -> String {
+    match maturity_status(maturity_date, now) {
+        MaturityStatus::Matured => "🔴 Matured".to_string(),
+        MaturityStatus::Pending { days_left } => format!("🟢 {} days remaining", days_left),
+    }
+}
+
+/// Lines for `tree`: the root and leaf count, then each node as short trimmed hex for
+/// the one `level` requested, or for every level with `full`. Unfilled leaf slots show
+/// as `-`, so an empty tree is all dashes under the empty root.
+pub fn tree_lines(
+    tree_state: &TreeState,
+    level: Option<usize>,
+    full: bool,
+) -> Result<Vec<String>, String> {
+    let tree = tree_state.build_tree();
+    let mut lines = vec![
+        format!("Root:   {}", fr_to_hex(&tree.root())),
+        format!("Leaves: {} of {}", tree.len(), MAX_LEAVES),
+    ];
+    let levels: Vec<usize> = match (level, full) {
+        (Some(level), _) if level > TREE_HEIGHT => {
+            return Err(format!("Level {} does not exist (0 = leaves, {} = root)", level, TREE_HEIGHT))
+        }
+        (Some(level), _) => vec![level],
+        (None, true) => (0..=TREE_HEIGHT).rev().collect(),
+        (None, false) => Vec::new(),
+    };
+    for level in levels {
+        let nodes = tree.level(level).unwrap_or_default();
+        let shown: Vec<String> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                if level == 0 && i >= tree.len() {
+                    "-".to_string()
+                } else {
+                    short(&fr_to_hex(node), 10).to_string()
+                }
+            })
+            .collect();
+        lines.push(format!("Level {}: {}", level, shown.join(" ")));
+    }
+    Ok(lines)
+}
+
+pub fn tree(level: Option<usize>, full: bool) -> Result<(), WalletError> {
+    println!("\n🌳 Local merkle tree:");
+    match tree_lines(&TreeState::load(), level, full) {
+        Ok(lines) => {
+            for line in lines {
+                println!("   {}", line);
+            }
+        }
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+pub fn reindex() -> Result<(), WalletError> {
+    let count = TreeState::load().reindex_bonds();
+    println!("🗂️  Indexed {} bond files in {}", count, data_dir());
+    Ok(())
+}
+
+/// List logged transactions from `since` (YYYY-MM-DD or RFC 3339) on, and with
+/// `csv` also write them there for reconciliation
+pub fn history(since: Option<&str>, csv: Option<&str>) -> Result<(), WalletError> {
+    let since = match since.map(history::parse_since).transpose() {
+        Ok(since) => since,
+        Err(e) => return fail(e),
+    };
+    let records = match history::load_history(since) {
+        Ok(records) => records,
+        Err(e) => return fail(e),
+    };
+    println!("\n📜 {} logged transaction(s)", records.len());
+    for r in &records {
+        println!(
+            "   {}  {:<15} value {:>10}  tx {}",
+            r.timestamp,
+            r.command,
+            r.value.map_or("private".to_string(), |v| v.to_string()),
+            short(&r.tx_hash, 18)
+        );
+    }
+    if let Some(path) = csv {
+        match write_atomic(path, history::to_csv(&records)) {
+            Ok(()) => println!("✅ Exported to {}", path),
+            Err(e) => return fail(format!("Failed to write {}: {}", path, e)),
+        }
+    }
+    Ok(())
+}
+
+/// Notes and spends one data directory knows about
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NoteSet {
+    /// Bond files, live and archived, by commitment
+    pub notes: BTreeMap<String, NoteSummary>,
+    /// Nullifiers logged in its history or held by redeemed and archived bond files
+    pub spent: BTreeSet<String>,
+}
+
+/// Read `dir`'s bond files and history. Nothing is written and the node is not asked.
+pub fn note_set(dir: &str) -> Result<NoteSet, String> {
+    if !Path::new(dir).is_dir() {
+        return Err(format!("{} is not a directory", dir));
+    }
+    let mut set = NoteSet::default();
+    let archive = format!("{}/archive", dir);
+    for (path, bond) in bonds_in(dir).into_iter().chain(bonds_in(&archive)) {
+        if path.ends_with("_REDEEMED.json") || path.starts_with(&archive) {
+            set.spent.insert(bond.nullifier.to_lowercase());
+        }
+        set.notes.insert(bond.commitment.to_lowercase(), NoteSummary::new(&path, &bond));
+    }
+    let log = format!("{}/history.jsonl", dir);
+    for record in history::load_history_at(&log, None)? {
+        set.spent.extend(record.nullifiers.iter().map(|n| n.to_lowercase()));
+    }
+    Ok(set)
+}
+
+/// What one data directory has that the other lacks
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NoteDiff {
+    pub only_here: Vec<NoteSummary>,
+    pub only_there: Vec<NoteSummary>,
+    pub spent_only_here: Vec<String>,
+    pub spent_only_there: Vec<String>,
+}
+
+impl NoteDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_here.is_empty()
+            && self.only_there.is_empty()
+            && self.spent_only_here.is_empty()
+            && self.spent_only_there.is_empty()
+    }
+}
+
+/// Compare the note sets of the data directory (`here`) and `there`, e.g. a live
+/// wallet and its backup
+pub fn diff_note_sets(here: &str, there: &str) -> Result<NoteDiff, String> {
+    let (a, b) = (note_set(here)?, note_set(there)?);
+    let missing = |from: &NoteSet, other: &NoteSet| -> Vec<NoteSummary> {
+        from.notes
+            .iter()
+            .filter(|(commitment, _)| !other.notes.contains_key(*commitment))
+            .map(|(_, note)| note.clone())
+            .collect()
+    };
+    Ok(NoteDiff {
+        only_here: missing(&a, &b),
+        only_there: missing(&b, &a),
+        spent_only_here: a.spent.difference(&b.spent).cloned().collect(),
+        spent_only_there: b.spent.difference(&a.spent).cloned().collect(),
+    })
+}
+
+pub fn diff(other_data_dir: &str) -> Result<(), WalletError> {
+    let here = data_dir();
+    println!("\n🔍 Comparing {} with {}...", here, other_data_dir);
+    let diff = match diff_note_sets(&here, other_data_dir) {
+        Ok(diff) => diff,
+        Err(e) => return fail(e),
+    };
+    if diff.is_empty() {
+        println!("✅ Same notes and spends in both");
+        return Ok(());
+    }
+    let assets = AssetRegistry::load().or_else(fail)?;
+    for (dir, notes, spent) in [
+        (&here, &diff.only_here, &diff.spent_only_here),
+        (&other_data_dir.to_string(), &diff.only_there, &diff.spent_only_there),
+    ] {
+        if !notes.is_empty() {
+            println!("📄 {} note(s) only in {}:", notes.len(), dir);
+            notes.iter().for_each(|note| print_note(note, &assets));
+        }
+        if !spent.is_empty() {
+            println!("🔥 {} spend(s) only in {}:", spent.len(), dir);
+            for nullifier in spent {
+                println!("   {}", nullifier);
+            }
+        }
+    }
+    println!("ℹ️  Notes on one side only point to a stale backup or a missed `scan`");
+    Ok(())
+}
+
+/// Set (or with an empty label, clear) a bond's local label
+pub fn rename(bond_path: &str, label: &str) -> Result<(), WalletError> {
+    let path = match resolve_bond_path(bond_path) {
+        Some(p) => p,
+        None => return fail(format!("Bond file not found: {}", bond_path)),
+    };
+    let mut bond = match load_bond(&path) {
+        Some(b) => b,
+        None => return Err(WalletError::Other(format!("Bond '{}' not found", path))),
+    };
+
+    bond.label = (!label.is_empty()).then(|| label.to_string());
+    match bond.save(&path) {
+        Ok(_) => match &bond.label {
+            Some(label) => println!("🏷️  {} labeled '{}'", path, label),
+            None => println!("🏷️  Label cleared on {}", path),
+        },
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+pub fn register_asset(meta: AssetMeta) -> Result<(), WalletError> {
+    println!("\n🏷️  Registering asset {}...", meta.id);
+
+    let mut registry = AssetRegistry::load().or_else(fail)?;
+    let summary = format!("{} ({}) id={}", meta.name, meta.ticker, meta.id);
+    if let Err(e) = registry.register(meta) {
+        return fail(e);
+    }
+
+    match registry.save() {
+        Ok(_) => println!("✅ Asset registered: {}", summary),
+        Err(e) => return fail(format!("Error saving asset registry: {}", e)),
+    }
+    Ok(())
+}
+
+/// A memo addressed to this wallet that decrypted
+#[derive(Serialize, Debug, Clone)]
+pub struct ScannedMemo {
+    pub file: String,
+    pub sender: String,
+    pub value: u64,
+    /// Note secret; shown locally but never serialized
+    #[serde(skip)]
+    pub salt: u64,
+    pub asset_id: u64,
+    pub maturity_date: u64,
+    /// Under a scheme that binds memos, whether a commitment in the local tree
+    /// covers this memo; a memo that misstates its note matches none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches_commitment: Option<bool>,
+}
+
+/// Whether `memo`, which decrypted to `note` for the holder of `owner`, is the one
+/// a commitment in `tree_state` binds under `scheme`
+pub fn memo_matches_commitment(
+    scheme: &dyn CommitmentScheme,
+    note: &Note,
+    owner: Fr,
+    memo: &notes::Memo,
+    tree_state: &TreeState,
+) -> bool {
+    let bound = CircuitNote::new_unchecked(note.value, note.salt, owner, note.asset_id, note.maturity_date)
+        .with_memo_hash(Some(memo.hash()));
+    tree_state.find_commitment(&scheme.commit(&bound)).is_some()
+}
+
+/// Write a memo envelope to `{data}/{stem}.json`, returning the path
+pub fn save_memo(stem: &str, envelope: &MemoEnvelope) -> Result<String, String> {
+    let path = format!("{}/{}.json", data_dir(), stem);
+    let json = serde_json::to_string_pretty(envelope).map_err(|e| e.to_string())?;
+    write_atomic(&path, json).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Decrypt the memos addressed to `wallet_name`. Envelopes (`memo_*.json`) carry
+/// their sender's viewing key; raw `.bin` memos are tried against `sender_name` or
+/// every known wallet. Returns the number of memo files found and those decrypted.
+pub fn scan_memos(
+    wallet_name: &str,
+    sender_name: Option<&str>,
+) -> Result<(usize, Vec<ScannedMemo>), String> {
+    // Load recipient wallet
+    let recipient_wallet =
+        load_wallet(wallet_name).ok_or_else(|| format!("Wallet '{}' not found", wallet_name))?;
+
+    // Find memo files for this wallet
+    let entries = fs::read_dir(data_dir()).map_err(|_| "Cannot read data directory".to_string())?;
+
+    // Candidate senders: the one specified or all known wallets
+    let sender_names: Vec<String> = if let Some(name) = sender_name {
+        vec![name.to_string()]
+    } else {
+        // Find all wallet files
+        fs::read_dir(data_dir())
+            .ok()
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|e| {
+                        let name = e.file_name().to_string_lossy().to_string();
+                        if name.starts_with("wallet_") && name.ends_with(".json") {
+                            Some(name.trim_start_matches("wallet_").trim_end_matches(".json").to_string())
+                        } else {
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let sender_wallets: Vec<(String, [u8; 32])> = sender_names
+        .into_iter()
+        .filter_map(|name| load_wallet(&name).map(|w| (name, *w.keys.public_viewing_key())))
+        .collect();
+
+    let scheme = scheme::active();
+    let tree_state = scheme.binds_memo().then(TreeState::load);
+    let mut memos_found = 0;
+    let mut decrypted = Vec::new();
+
+    for entry in entries.flatten() {
+        let filename = entry.file_name().to_string_lossy().to_string();
+        let is_envelope = filename.starts_with("memo_") && filename.ends_with(".json");
+        if !is_envelope && !filename.ends_with(".bin") {
+            continue;
+        }
+        if !filename.contains(&format!("_{}_", wallet_name)) && !filename.contains(&format!("_{}", wallet_name)) {
+            continue;
+        }
+
+        memos_found += 1;
+        let memo_path = entry.path();
+
+        let (memo, candidates) = if is_envelope {
+            let envelope: MemoEnvelope = match fs::read_to_string(&memo_path)
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok())
+            {
+                Some(envelope) => envelope,
+                None => continue,
+            };
+            let (Ok(memo), Ok(sender_pubkey)) = (envelope.memo(), envelope.sender()) else {
+                continue;
+            };
+            // The tag settles most memos for someone else without decrypting them
+            if !envelope.tag_matches(&recipient_wallet.keys, &sender_pubkey) {
+                continue;
+            }
+            // Name the sender if it's a known wallet, otherwise show its viewing key
+            let known = sender_wallets.iter().find(|(_, key)| *key == sender_pubkey);
+            if sender_name.is_some() && known.is_none() {
+                continue;
+            }
+            let sender = known.map_or(envelope.sender_pubkey.clone(), |(name, _)| name.clone());
+            (memo, vec![(sender, sender_pubkey)])
+        } else {
+            // Read memo ciphertext
+            let ciphertext = match fs::read(&memo_path) {
+                Ok(c) => c,
+                Err(_) => continue,
+            };
+            (notes::Memo { ciphertext }, sender_wallets.clone())
+        };
+
+        for (sender, sender_pubkey) in &candidates {
+            if let Ok(note) = Note::decrypt(&recipient_wallet.keys, sender_pubkey, &memo) {
+                decrypted.push(ScannedMemo {
+                    file: filename.clone(),
+                    sender: sender.clone(),
+                    value: note.value,
+                    salt: note.salt,
+                    asset_id: note.asset_id,
+                    maturity_date: note.maturity_date,
+                    matches_commitment: tree_state.as_ref().map(|tree_state| {
+                        let owner = recipient_wallet.keys.public_spending_key();
+                        memo_matches_commitment(scheme, &note, owner, &memo, tree_state)
+                    }),
+                });
+                break; // Found the right sender
+            }
+        }
+    }
+
+    Ok((memos_found, decrypted))
+}
+
+pub fn scan(wallet_name: &str, sender_name: Option<&str>) -> Result<(), WalletError> {
+    println!("\n🔍 Scanning for encrypted memos...");
+
+    require_wallet(wallet_name).or_else(fail)?;
+    let (memos_found, decrypted) = match scan_memos(wallet_name, sender_name) {
+        Ok(found) => found,
+        Err(e) => return fail(e),
+    };
+
+    for memo in &decrypted {
+        println!("\n   📬 Memo from '{}': {}", memo.sender, memo.file);
+        println!("      Value:    {}", memo.value);
+        println!("      Salt:     {}", Salt(memo.salt));
+        println!("      Asset ID: {}", memo.asset_id);
+        println!("      Maturity: {}", format_date(memo.maturity_date));
+        if memo.matches_commitment == Some(false) {
+            println!("      ⚠️  No commitment in the local tree matches this memo (run `sync`); do not rely on it");
+        }
+    }
+
+    if memos_found == 0 {
+        println!("   No memos found for wallet '{}'", wallet_name);
+    } else {
+        println!("\n✅ Found {} memos, decrypted {}", memos_found, decrypted.len());
+        if decrypted.len() < memos_found {
+            println!("   ℹ️  Some memos could not be decrypted (sender unknown)");
+        }
+    }
+    Ok(())
+}
+
+/// Take in a note file a sender handed over directly: it must be this wallet's, its
+/// fields must hash to its commitment and the commitment must be in the local tree.
+/// The copy gets this wallet's nullifier and is indexed; returns its new path.
+pub fn import_note_file(wallet_name: &str, file: &str) -> Result<String, String> {
+    let wallet =
+        load_wallet(wallet_name).ok_or_else(|| format!("Wallet '{}' not found", wallet_name))?;
+    let json = fs::read_to_string(file).map_err(|e| format!("Cannot read {}: {}", file, e))?;
+    let mut bond: Bond =
+        serde_json::from_str(&json).map_err(|e| format!("Invalid note file {}: {}", file, e))?;
+
+    if bond.owner != wallet.keys.public_spending_key_hex {
+        return Err(format!(
+            "Note is owned by {}, not wallet '{}'",
+            short(&bond.owner, 18),
+            wallet_name
+        ));
+    }
+    // Recomputing under our own key is what actually ties the note to this wallet
+    let note = CircuitNote::new(
+        bond.value,
+        bond.salt,
+        wallet.keys.public_spending_key(),
+        bond.asset_id,
+        bond.maturity_date,
+    )
+    .map_err(|e| format!("Invalid note: {}", e))?
+    .with_memo_hash(bond.memo_hash);
+    let commitment = note.commitment();
+    if parse_commitment(&bond.commitment) != Some(commitment) {
+        return Err(format!(
+            "Note fields hash to {}, not the file's commitment {}",
+            fr_to_hex(&commitment),
+            bond.commitment
+        ));
+    }
+
+    let mut tree_state = TreeState::load();
+    if tree_state.find_commitment(&commitment).is_none() {
+        return Err(format!(
+            "Commitment {} is not in the local tree (check `root`, or wait for the sender's transaction)",
+            fr_to_hex(&commitment)
+        ));
+    }
+    if let Some(existing) = tree_state
+        .bond_files(&commitment)
+        .into_iter()
+        .find(|f| Path::new(f).is_file())
+    {
+        return Err(format!("Note already imported: {}", existing));
+    }
+
+    // The sender cannot know our nullifier, so whatever the file says is replaced
+    bond.commitment = fr_to_hex(&commitment);
+    bond.nullifier = fr_to_hex(&wallet.keys.sign_nullifier(bond.salt));
+    ensure_data_dir().map_err(|e| e.to_string())?;
+    let path = format!(
+        "{}/bond_{}_{}.json",
+        data_dir(),
+        wallet_name,
+        &format!("{:016x}", bond.salt)[..8]
+    );
+    bond.save(&path)?;
+    index_bond(&mut tree_state, &path, &bond);
+    Ok(path)
+}
+
+pub fn import_note(wallet_name: &str, file: &str) -> Result<(), WalletError> {
+    println!("\n📥 Importing note from {}...", file);
+    require_wallet(wallet_name).or_else(fail)?;
+    match import_note_file(wallet_name, file) {
+        Ok(path) => println!("✅ Note imported to {}", path),
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+/// Rebuild a lost bond file from its raw fields (read off a disclosure, or shared
+/// out-of-band). The fields must hash to a commitment in the local tree. The
+/// nullifier needs the owner's private key, so it is only filled in when
+/// `wallet_name` owns the note and is left empty otherwise.
+pub fn reconstruct_bond(
+    wallet_name: &str,
+    value: u64,
+    salt: u64,
+    owner: &str,
+    asset_id: u64,
+    maturity_date: u64,
+) -> Result<Bond, String> {
+    let owner = parse_public_spending_key(owner).map_err(|e| e.to_string())?;
+    let note = CircuitNote::new(value, salt, owner, asset_id, maturity_date)
+        .map_err(|e| format!("Invalid note: {}", e))?;
+    let commitment = note.commitment();
+    if TreeState::load().find_commitment(&commitment).is_none() {
+        return Err(format!(
+            "Fields hash to {}, which is not in the local tree (check the fields, or `root`)",
+            fr_to_hex(&commitment)
+        ));
+    }
+    let nullifier = match load_wallet(wallet_name) {
+        Some(wallet) if wallet.keys.public_spending_key() == owner => {
+            fr_to_hex(&wallet.keys.sign_nullifier(salt))
+        }
+        _ => String::new(),
+    };
+    Ok(Bond {
+        schema_version: BOND_SCHEMA_VERSION,
+        commitment: fr_to_hex(&commitment),
+        nullifier,
+        value,
+        salt,
+        owner: fr_to_hex(&owner),
+        asset_id,
+        maturity_date,
+        created_at: Utc::now().to_rfc3339(),
+        label: None,
+        memo_hash: None,
+    })
+}
+
+pub fn reconstruct(
+    wallet_name: &str,
+    value: u64,
+    salt: u64,
+    owner: &str,
+    asset_id: u64,
+    maturity_date: u64,
+    out: &str,
+) -> Result<(), WalletError> {
+    println!("\n🧩 Reconstructing bond from its fields...");
+    if Path::new(out).exists() {
+        return fail(format!("{} already exists; pick another --out", out));
+    }
+    let bond = match reconstruct_bond(wallet_name, value, salt, owner, asset_id, maturity_date) {
+        Ok(bond) => bond,
+        Err(e) => return fail(e),
+    };
+    if let Err(e) = bond.save(out) {
+        return fail(e);
+    }
+    index_bond(&mut TreeState::load(), out, &bond);
+    println!("✅ Bond written to {} (commitment {})", out, short(&bond.commitment, 18));
+    if bond.nullifier.is_empty() {
+        println!("   ℹ️  Wallet '{}' does not own the note, so no nullifier was recorded", wallet_name);
+    }
+    Ok(())
+}
+
+/// A 32-byte X25519 viewing key given as hex, or the viewing key of a local wallet
+fn resolve_viewing_key(key_or_wallet: &str, role: &str) -> Result<[u8; 32], String> {
+    let digits = key_or_wallet.trim().trim_start_matches("0x");
+    match hex::decode(digits) {
+        Ok(bytes) if digits.len() == 64 => Ok(bytes.try_into().unwrap()),
+        _ => match load_wallet(key_or_wallet) {
+            Some(wallet) => Ok(*wallet.keys.public_viewing_key()),
+            None => Err(format!(
+                "{} '{}' is neither a 32-byte hex viewing key nor a known wallet",
+                role, key_or_wallet
+            )),
+        },
+    }
+}
+
+/// Seal the note in `bond_path` and its inclusion proof in the local tree to the
+/// authority's viewing key (`to`: hex key or local wallet name). The owner's keys are
+/// not needed: whoever holds the note file (the holder, or the issuer who made it)
+/// can disclose it.
+pub fn disclose_note(bond_path: &str, to: &str) -> Result<DisclosurePackage, String> {
+    let recipient = resolve_viewing_key(to, "Authority")?;
+    let path = resolve_bond_path(bond_path).ok_or_else(|| format!("Bond file not found: {}", bond_path))?;
+    let bond = load_bond(&path).ok_or_else(|| format!("Cannot read bond file {}", path))?;
+    let disclosure = Disclosure::for_bond(&bond, &TreeState::load())?;
+    DisclosurePackage::seal(&disclosure, &recipient)
+}
+
+pub fn disclose(bond_path: &str, to: &str, out: &str) -> Result<(), WalletError> {
+    println!("\n🔏 Disclosing {}...", bond_path);
+    match disclose_note(bond_path, to).and_then(|package| package.save(out)) {
+        Ok(()) => println!("✅ Disclosure package written to {}; only the authority's wallet can open it", out),
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+/// Open a disclosure package with the authority's wallet and check the note against
+/// the contract: its fields must hash to the commitment, the path must lead to its
+/// root, and the contract must hold the commitment at that leaf or have built the
+/// root. A root from before later mints is fine; one the contract never saw is not.
+pub async fn open_disclosure(contract: &impl BondContract, wallet_name: &str, file: &str) -> Result<(), WalletError> {
+    println!("\n🔓 Opening disclosure {}...", file);
+    let wallet = require_wallet(wallet_name).or_else(fail)?;
+    let opened = DisclosurePackage::load(file)
+        .and_then(|package| package.open(&wallet.keys))
+        .and_then(|disclosure| disclosure.verify().map(|_| disclosure));
+    let disclosure = match opened {
+        Ok(d) => d,
+        Err(e) => return fail(e),
+    };
+    println!("   Value:      {}", disclosure.value);
+    println!("   Owner:      {}", fr_to_hex(&disclosure.owner));
+    println!("   Salt:       {}", disclosure.salt);
+    println!("   Asset:      {}", disclosure.asset_id);
+    println!("   Maturity:   {} ({})", disclosure.maturity_date, format_date(disclosure.maturity_date));
+    println!("   Commitment: {} (leaf {})", fr_to_hex(&disclosure.commitment), disclosure.leaf_index);
+    println!("   ✅ Fields match the commitment and the path leads to {}", fr_to_hex(&disclosure.root));
+    check_disclosure_onchain(contract, &disclosure).await.or_else(fail)
+}
+
+/// A self-consistent package proves nothing until the contract vouches for it: its
+/// root must be the contract's current root, its commitment must be on-chain at its
+/// leaf, or the contract must have built its root before later mints
+async fn check_disclosure_onchain(contract: &impl BondContract, disclosure: &Disclosure) -> Result<(), String> {
+    if contract
+        .current_root()
+        .await
+        .is_ok_and(|onchain| onchain == fr_to_bytes32(&disclosure.root))
+    {
+        println!("   ✅ That is the contract's current root");
+        return Ok(());
+    }
+    let leaf = contract.commitment_at(disclosure.leaf_index as u64).await;
+    if leaf.is_ok_and(|onchain| onchain == Some(fr_to_bytes32(&disclosure.commitment))) {
+        println!("   ✅ The contract holds this commitment at leaf {}", disclosure.leaf_index);
+        return Ok(());
+    }
+    match contract.root_known(fr_to_bytes32(&disclosure.root)).await {
+        Ok(true) => {
+            println!("   ✅ The contract built this root; the tree has changed since");
+            Ok(())
+        }
+        Ok(false) => Err(format!(
+            "The contract holds neither this commitment at leaf {} nor this root; do not rely on the package",
+            disclosure.leaf_index
+        )),
+        Err(e) => Err(format!("Could not check the package against the contract: {}", e)),
+    }
+}
+
+/// The `transfer` or `burn` call a spend makes with `witness` and `proof`, argument
+/// for argument as the flows submit it. A witness whose outputs are all zero-valued
+/// is a redemption: `burn` with the input's maturity and isRedeem set.
+pub fn witness_call(witness: &WitnessBuilder, proof: Vec<u8>) -> CalldataBundle {
+    let nullifier = |i: usize| witness.nullifiers.get(i).map(fr_to_bytes32).unwrap_or_default();
+    let redeem = witness.output_notes.iter().all(|n| n.value == 0);
+    let maturity_date = witness.input_notes.first().map(|n| n.maturity_date).unwrap_or_default();
+    CalldataBundle {
+        method: if redeem { "burn" } else { "transfer" }.to_string(),
+        proof: Bytes::from(proof),
+        root: fr_to_bytes32(&witness.root),
+        nullifiers: [nullifier(0), nullifier(1)],
+        commitments: [
+            fr_to_bytes32(&witness.commitments_out[0]),
+            fr_to_bytes32(&witness.commitments_out[1]),
+        ],
+        maturity_date: redeem.then(|| FixedBytes::left_padding_from(&maturity_date.to_be_bytes())),
+        is_redeem: redeem.then(|| FixedBytes::left_padding_from(&[1])),
+    }
+}
+
+/// The witness saved for the transaction that spent `bond`, found by its nullifier
+fn find_spending_witness(bond: &Bond) -> Result<String, String> {
+    let nullifier = parse_commitment(&bond.nullifier)
+        .ok_or_else(|| format!("Bond nullifier {} is not a field element", bond.nullifier))?;
+    let entries = fs::read_dir(data_dir()).map_err(|e| format!("Failed to read {}: {}", data_dir(), e))?;
+    let mut candidates: Vec<String> = entries
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with("witness_") && name.ends_with(".json"))
+        .collect();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .map(|name| format!("{}/{}", data_dir(), name))
+        .find(|path| {
+            WitnessBuilder::load_json(path).is_ok_and(|w| w.nullifiers.contains(&nullifier))
+        })
+        .ok_or_else(|| format!("No saved witness spends this bond (nullifier {})", bond.nullifier))
+}
+
+/// ABI-encode the call a spend submitted (or would submit), from its saved witness
+/// or the bond it spent, together with the proof that went with it
+pub fn spend_calldata(
+    bond_path: Option<&str>,
+    witness_path: Option<&str>,
+    proof_path: &str,
+) -> Result<(CalldataBundle, Vec<u8>), String> {
+    let witness_path = match (bond_path, witness_path) {
+        (Some(bond_path), None) => {
+            let bond = load_bond(bond_path).ok_or_else(|| format!("Cannot read bond file {}", bond_path))?;
+            find_spending_witness(&bond)?
+        }
+        (None, Some(path)) => path.to_string(),
+        _ => return Err("Give exactly one of --bond or --from-witness".to_string()),
+    };
+    let witness = WitnessBuilder::load_json(&witness_path)?;
+    let proof = fs::read(proof_path).map_err(|e| format!("Failed to read proof {}: {}", proof_path, e))?;
+    let bundle = witness_call(&witness, proof);
+    let calldata = bundle_calldata(&bundle)?;
+    Ok((bundle, calldata))
+}
+
+/// Print the calldata hash and bytes of a spend, for comparing with the input of the
+/// transaction that was actually mined (e.g. one a relayer sent)
+pub fn calldata(bond_path: Option<&str>, witness_path: Option<&str>, proof_path: &str) -> Result<(), WalletError> {
+    println!("\n🧾 Computing expected calldata...");
+    match spend_calldata(bond_path, witness_path, proof_path) {
+        Ok((bundle, calldata)) => {
+            println!("   Method:    {}()", bundle.method);
+            println!("   Root:      {}", bundle.root);
+            println!("   Keccak256: {}", keccak256(&calldata));
+            println!("   Calldata:  0x{}", hex::encode(&calldata));
+            println!("   ℹ️  Compare with the transaction's input data; any difference means it was altered");
+        }
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+/// What a saved proof commits to, and whether the root it was built against still verifies
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProofInfo {
+    pub root: FixedBytes<32>,
+    pub nullifiers: Vec<FixedBytes<32>>,
+    pub commitments: Vec<FixedBytes<32>>,
+    /// The contract's current root
+    pub current: bool,
+    /// Among the local tree's `recent_roots`
+    pub recent: bool,
+    /// In the contract's `knownRoots`; `None` if the node could not be asked
+    pub known_onchain: Option<bool>,
+}
+
+impl ProofInfo {
+    /// Built against a root that is neither current nor still in the local root history
+    pub fn stale(&self) -> bool {
+        !self.current && !self.recent
+    }
+}
+
+/// Public inputs of the proof at `proof_path` (its recorded root, and the
+/// `public_inputs` file bb wrote beside it) checked against the contract and tree
+pub async fn inspect_proof(contract: &impl BondContract, proof_path: &str) -> Result<ProofInfo, String> {
+    let inputs = prover::read_public_inputs(proof_path)?;
+    if inputs.len() != TRANSFER_PUBLIC_INPUTS.len() {
+        return Err(format!(
+            "Proof has {} public inputs, expected {} ({})",
+            inputs.len(),
+            TRANSFER_PUBLIC_INPUTS.len(),
+            TRANSFER_PUBLIC_INPUTS.join(", ")
+        ));
+    }
+    let named = |prefix: &str| -> Vec<FixedBytes<32>> {
+        TRANSFER_PUBLIC_INPUTS
+            .iter()
+            .zip(&inputs)
+            .filter(|(name, _)| name.starts_with(prefix))
+            .map(|(_, input)| *input)
+            .collect()
+    };
+    let recorded = match ProofMeta::load(proof_path) {
+        Some(meta) => meta?.root,
+        None => None,
+    };
+    let root = match recorded {
+        Some(root) => root.parse().map_err(|e| format!("Invalid recorded root {}: {}", root, e))?,
+        None => named("root")[0],
+    };
+    Ok(ProofInfo {
+        root,
+        nullifiers: named("nullifiers"),
+        commitments: named("commitments_out"),
+        current: contract.current_root().await.is_ok_and(|onchain| onchain == root),
+        recent: fr_from_bytes32(&root).is_some_and(|r| TreeState::load().is_recent_root(&r)),
+        known_onchain: contract.root_known(root).await.ok(),
+    })
+}
+
+/// Print a proof's root, nullifiers and commitments, and whether its root is still
+/// accepted: the first thing to check when a transfer reverts
+pub async fn proof_info(contract: &impl BondContract, proof_path: &str) -> Result<(), WalletError> {
+    println!("\n🔎 Proof {}:", proof_path);
+    match inspect_proof(contract, proof_path).await {
+        Ok(info) => {
+            println!("   Root:        {}", info.root);
+            for (i, n) in info.nullifiers.iter().enumerate() {
+                println!("   Nullifier {}: {}", i, n);
+            }
+            for (i, c) in info.commitments.iter().enumerate() {
+                println!("   Output {}:    {}", i, c);
+            }
+            match info.known_onchain {
+                Some(false) => println!("   ❌ The contract never built this root: the transfer reverts with \"Invalid Merkle Root\""),
+                Some(true) => println!("   ✅ The contract knows this root"),
+                None => println!("   ⚠️  Could not ask the contract about this root"),
+            }
+            if info.current {
+                println!("   ✅ Root is current");
+            } else if info.stale() {
+                println!("   ⚠️  Stale: root is not in the last {} local roots; rebuild the proof", ROOT_HISTORY_SIZE);
+            } else {
+                println!("   ℹ️  Root is recent but no longer current");
+            }
+        }
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+/// Decrypt a hex-encoded memo sent by `from_pubkey` (a 32-byte X25519 viewing key in
+/// hex, or the name of a local wallet). `Ok(None)` if it wasn't encrypted to `keys`.
+pub fn open_memo(
+    keys: &ShieldedKeys,
+    memo_hex: &str,
+    from_pubkey: &str,
+) -> Result<Option<Note>, String> {
+    let ciphertext = hex::decode(memo_hex.trim().trim_start_matches("0x"))
+        .map_err(|e| format!("Memo is not valid hex: {}", e))?;
+
+    let sender_pubkey = resolve_viewing_key(from_pubkey, "Sender")?;
+
+    // AEAD authentication fails unless the memo was encrypted to this wallet
+    Ok(Note::decrypt(keys, &sender_pubkey, &notes::Memo { ciphertext }).ok())
+}
+
+pub fn inspect_memo(wallet_name: &str, memo_hex: &str, from_pubkey: &str) -> Result<(), WalletError> {
+    println!("\n🔍 Inspecting memo...");
+
+    let wallet = require_wallet(wallet_name).or_else(fail)?;
+
+    match open_memo(&wallet.keys, memo_hex, from_pubkey) {
+        Ok(Some(note)) => {
+            println!("   📬 Memo decrypted for '{}':", wallet_name);
+            println!("      Value:    {}", note.value);
+            println!("      Salt:     {}", Salt(note.salt));
+            println!("      Owner:    {}", note.owner);
+            println!("      Asset ID: {}", note.asset_id);
+            println!("      Maturity: {}", format_date(note.maturity_date));
+        }
+        Ok(None) => println!("   📭 Memo is not for this wallet (authentication failed)"),
+        Err(e) => return fail(e),
+    }
+    Ok(())
+}
+
+/// Answer a counterparty's challenge by signing their nonce under this wallet's auth key
+pub fn sign_challenge(wallet_name: &str, nonce: &str) -> Result<(), WalletError> {
+    let wallet = require_wallet(wallet_name).or_else(fail)?;
+
+    let challenge = wallet.keys.sign_challenge(nonce);
+    println!("\n🔏 Challenge signature for '{}':", wallet_name);
+    println!("   Public key: {}", wallet.keys.public_spending_key_hex);
+    println!("   Nonce:      {}", nonce);
+    println!("   Auth key:   {}", auth_key_hex(&challenge.signer_key));
+    println!("   Signature:  {}", challenge.to_hex());
+    Ok(())
+}
+
+/// Check that `auth_key`, the counterparty's auth key as printed by their
+/// `register` and known from before the challenge, signed our nonce for `pubkey`.
+/// This vouches for the public key but does not prove control of the spending
+/// key behind it: the auth key cannot be tied to the Poseidon public key outside
+/// a circuit.
+pub fn verify_challenge(pubkey: &str, nonce: &str, sig: &str, auth_key: &str) -> Result<(), WalletError> {
+    println!("\n🔍 Verifying challenge signature for {}...", pubkey);
+
+    let pubkey_fr = match parse_public_spending_key(pubkey) {
+        Ok(fr) => fr,
+        Err(e) => return fail(e.to_string()),
+    };
+    let challenge = match ChallengeSignature::from_hex(sig) {
+        Ok(challenge) => challenge,
+        Err(e) => return fail(e.to_string()),
+    };
+    let auth_key = match parse_auth_key(auth_key) {
+        Ok(key) => key,
+        Err(e) => return fail(e.to_string()),
+    };
+
+    if challenge.signer_key != auth_key {
+        return fail(format!(
+            "Signed by {}, not the expected signer {}",
+            auth_key_hex(&challenge.signer_key),
+            auth_key_hex(&auth_key)
+        ));
+    }
+    if !ShieldedKeys::verify_challenge(&pubkey_fr, nonce, &challenge, &auth_key) {
+        return fail("Signature does not match this public key and nonce".to_string());
+    }
+    println!("✅ Challenge signed by the expected auth key (not a proof of spending-key control)");
+    Ok(())
+}
+
+/// Check a handover receipt (`receipt_*.json`, saved by `trade`) against the
+/// sender's receipt key as printed by their `register`, known from before the
+/// trade; the key inside the receipt proves nothing alone
+pub fn verify_receipt(file: &str, sender_key: &str) -> Result<(), WalletError> {
+    println!("\n🔍 Verifying receipt {}...", file);
+
+    let receipt: TransferReceipt = match fs::read_to_string(file)
+        .map_err(|e| format!("Cannot read {}: {}", file, e))
+        .and_then(|json| serde_json::from_str(&json).map_err(|e| format!("Invalid receipt file {}: {}", file, e)))
+    {
+        Ok(receipt) => receipt,
+        Err(e) => return fail(e),
+    };
+    let sender_key: [u8; 32] = match hex::decode(sender_key.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+    {
+        Some(key) => key,
+        None => return fail(format!("Receipt key '{}' is not 64 hex characters", sender_key)),
+    };
+
+    if receipt.sender_key != hex::encode(sender_key) {
+        return fail(format!(
+            "Signed by {}, not the expected sender {}",
+            receipt.sender_key,
+            hex::encode(sender_key)
+        ));
+    }
+    if !ShieldedKeys::verify_receipt(&receipt, &sender_key) {
+        return fail("Signature does not match the receipt".to_string());
+    }
+    println!("✅ Receipt signed by the expected sender");
+    println!("   Commitment: {}", receipt.output_commitment);
+    println!("   Value:      {}", receipt.value);
+    println!("   Recipient:  {}", short(&receipt.recipient_pubkey, 18));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::PrimeField;
+    use crate::config::{ANVIL_DEV_KEY, PRIVATE_BOND_ADDRESS};
+    use crate::contract::{proof_network_path, resolve_signer, MockBondContract, NetworkBinding, SignerSource};
+    use crate::events::{PrintSink, RecordingSink};
+    use crate::prover::MockProver;
+    use crate::utils::{global_note_path, set_data_dir, signer_key_path, temp_data_dir, u64_to_fr};
+
+    /// Mock prover whose `fail` leg errors, as a crashed bb would
+    struct FailingLegProver {
+        inner: MockProver,
+        fail: &'static str,
+    }
+
+    impl Prover for FailingLegProver {
+        async fn prove(&self, witness: &WitnessBuilder) -> Result<String, WalletError> {
+            self.inner.prove(witness).await
+        }
+
+        async fn prove_leg(&self, witness: &WitnessBuilder, leg: &str) -> Result<String, WalletError> {
+            if leg == self.fail {
+                return Err(WalletError::ProofFailed("bb crashed".to_string()));
+            }
+            self.inner.prove_leg(witness, leg).await
+        }
+    }
+
+    /// Registers each wallet with one note on the given terms (asset 1), plus the
+    /// wallet's own dummy trade pads its leg with. Returns the bond paths and bonds.
+    fn trade_fixture(dir: &str, notes: [(&str, u64, u64, u64); 2]) -> Vec<(String, Bond)> {
+        let mut tree_state = TreeState::from_commitments(Vec::new());
+        let mut bonds = Vec::new();
+        for (wallet, value, salt, maturity_date) in notes {
+            register(wallet).unwrap();
+            let keys = load_wallet(wallet).unwrap().keys;
+            let note = CircuitNote {
+                value,
+                salt,
+                owner: keys.public_spending_key(),
+                asset_id: 1,
+                maturity_date,
+                memo_hash: None,
+            };
+            tree_state.add_commitment(note.commitment());
+            tree_state.add_commitment(note.padding().commitment());
+            let bond = Bond {
+                schema_version: BOND_SCHEMA_VERSION,
+                commitment: fr_to_hex(&note.commitment()),
+                nullifier: fr_to_hex(&keys.sign_nullifier(salt)),
+                value,
+                salt,
+                owner: keys.public_spending_key_hex.clone(),
+                asset_id: 1,
+                maturity_date,
+                created_at: String::new(),
+                label: None,
+                memo_hash: None,
+            };
+            save_bond(&format!("bond_{}", wallet), &bond);
+            bonds.push((format!("{}/bond_{}.json", dir, wallet), bond));
+        }
+        tree_state.save();
+        bonds
+    }
+
+    #[test]
+    fn test_swap_terms_check() {
+        let (_, _, source, _) = buy_fixture();
+        let input = CircuitNote {
+            value: source.value,
+            salt: source.salt,
+            owner: Fr::from_str("1").unwrap(),
+            asset_id: source.asset_id,
+            maturity_date: source.maturity_date,
+            memo_hash: None,
+        };
+        // New owner and salt only
+        let output = CircuitNote {
+            salt: 99,
+            owner: Fr::from_str("2").unwrap(),
+            ..input.clone()
+        };
+        assert!(check_swap_terms(&input, &output).is_ok());
+
+        let other_asset = CircuitNote { asset_id: 2, ..output.clone() };
+        assert!(check_swap_terms(&input, &other_asset).unwrap_err().contains("asset id"));
+        let later = CircuitNote { maturity_date: source.maturity_date + 86_400, ..output.clone() };
+        assert!(check_swap_terms(&input, &later).unwrap_err().contains("maturity"));
+        let inflated = CircuitNote { value: source.value + 1, ..output };
+        assert!(check_swap_terms(&input, &inflated).unwrap_err().contains("value"));
+    }
+
+    #[test]
+    fn test_trade_across_maturities_keeps_each_legs_terms() {
+        let (_guard, dir) = temp_data_dir("trade_terms");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let later = 1893456000 + 365 * 86_400;
+        let bonds = trade_fixture(&dir, [("alice", 1_000, 11, 1893456000), ("bob", 2_000, 22, later)]);
+        let swaps = || contract.calls.lock().unwrap().iter().filter(|c| *c == "atomicSwap").count();
+
+        // Without bob's own dummy on his note's terms his leg cannot be proven
+        let bob_owner = load_wallet("bob").unwrap().keys.public_spending_key();
+        let bob_dummy = fr_to_hex(&CircuitNote::dummy(bob_owner, 1, later).commitment());
+        let leaves = TreeState::load().commitments().to_vec();
+        TreeState::from_commitments(leaves.iter().filter(|c| **c != bob_dummy).cloned().collect()).save();
+        assert!(rt.block_on(trade(&prover, &contract, &RecordingSink::new(), "alice", &bonds[0].0, "bob", &bonds[1].0)).is_err());
+        assert_eq!(swaps(), 0);
+
+        // A zero-owner dummy on his terms does not do either: the circuit has one input owner
+        let mut tree_state = TreeState::load();
+        tree_state.add_commitment(CircuitNote::dummy(Fr::from_str("0").unwrap(), 1, later).commitment());
+        tree_state.save();
+        assert!(rt.block_on(trade(&prover, &contract, &RecordingSink::new(), "alice", &bonds[0].0, "bob", &bonds[1].0)).is_err());
+        assert_eq!(swaps(), 0);
+
+        let mut tree_state = TreeState::load();
+        tree_state.add_commitment(parse_commitment(&bob_dummy).unwrap());
+        tree_state.save();
+        rt.block_on(trade(&prover, &contract, &RecordingSink::new(), "alice", &bonds[0].0, "bob", &bonds[1].0)).unwrap();
+        assert_eq!(swaps(), 1);
+
+        // Each party now holds the other's note on its original terms
+        let received: Vec<Bond> = TreeState::load()
+            .indexed_bonds()
+            .into_iter()
+            .map(|(_, bond)| bond)
+            .filter(|bond| bond.commitment != bonds[0].1.commitment && bond.commitment != bonds[1].1.commitment)
+            .collect();
+        let alice = load_wallet("alice").unwrap().keys.public_spending_key_hex;
+        let to_alice = received.iter().find(|b| b.owner == alice).unwrap();
+        assert_eq!((to_alice.value, to_alice.maturity_date), (2_000, later));
+        let to_bob = received.iter().find(|b| b.owner != alice).unwrap();
+        assert_eq!((to_bob.value, to_bob.maturity_date), (1_000, 1893456000));
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_trade_proves_both_legs_before_swapping() {
+        let (_guard, dir) = temp_data_dir("trade_legs");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let bought = trade_fixture(&dir, [("alice", 1_000, 11, 1893456000), ("bob", 2_000, 22, 1893456000)]);
+
+        let spent = |wallet: &str, bond: &Bond| {
+            let keys = load_wallet(wallet).unwrap().keys;
+            contract.is_spent(&fr_to_bytes32(&keys.sign_nullifier(bond.salt)))
+        };
+        let swaps = || contract.calls.lock().unwrap().iter().filter(|c| *c == "atomicSwap").count();
+
+        // One leg failing aborts the trade before anything is submitted
+        let failing = FailingLegProver {
+            inner: MockProver::new(&format!("{}/failing", dir)),
+            fail: "b",
+        };
+        assert!(rt.block_on(trade(&failing, &contract, &RecordingSink::new(), "alice", &bought[0].0, "bob", &bought[1].0)).is_err());
+        assert_eq!(swaps(), 0);
+        assert!(!spent("alice", &bought[0].1));
+
+        let events = RecordingSink::new();
+        rt.block_on(trade(&prover, &contract, &events, "alice", &bought[0].0, "bob", &bought[1].0)).unwrap();
+        assert_eq!(swaps(), 1);
+        assert!(Path::new(&format!("{}/proof/a/proof", dir)).exists());
+        assert!(Path::new(&format!("{}/proof/b/proof", dir)).exists());
+        assert!(spent("alice", &bought[0].1));
+        assert!(spent("bob", &bought[1].1));
+        let events = events.events.lock().unwrap();
+        assert_eq!(events.iter().filter(|e| *e == "proof_done").count(), 2);
+        assert_eq!(events.last().unwrap(), "tx_confirmed:atomicSwap");
+
+        // Bob's receipt verifies under Alice's receipt key only, also once re-signed by Bob
+        let receipt_key = |wallet: &str| hex::encode(load_wallet(wallet).unwrap().keys.receipt_verifying_key());
+        let receipt_file = fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.path().to_string_lossy().to_string())
+            .find(|p| p.contains("/receipt_bob_"))
+            .unwrap();
+        assert!(verify_receipt(&receipt_file, &receipt_key("alice")).is_ok());
+        assert!(verify_receipt(&receipt_file, &receipt_key("bob")).is_err());
+        let receipt: TransferReceipt = serde_json::from_str(&fs::read_to_string(&receipt_file).unwrap()).unwrap();
+        let resigned = load_wallet("bob").unwrap().keys.sign_receipt(
+            &receipt.output_commitment,
+            receipt.value,
+            receipt.asset_id,
+            receipt.maturity_date,
+            &receipt.recipient_pubkey,
+        );
+        fs::write(&receipt_file, serde_json::to_string(&resigned).unwrap()).unwrap();
+        assert!(verify_receipt(&receipt_file, &receipt_key("alice")).is_err());
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_buy_asks_before_spending_unless_yes() {
+        use crate::events::{PromptSink, Reversibility};
+
+        let (_guard, dir) = temp_data_dir("buy_confirm");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        let issuer = load_wallet("issuer").unwrap();
+        let source = load_bond(&global_note_path()).unwrap();
+        let source_note = SourceNote::Path(global_note_path());
+        // Declining at the prompt submits nothing
+        let asked = std::sync::Mutex::new(Vec::new());
+        let decline = PromptSink {
+            policy: Reversibility::Irreversible,
+            yes: false,
+            assume_safe: false,
+            prompt: |action: &PendingAction| {
+                asked.lock().unwrap().push(action.clone());
+                false
+            },
+        };
+        let bought = rt.block_on(buy(&prover, &contract, &decline, "buyer", 1_000, &source_note, "issuer", None, None));
+        assert!(bought.is_err());
+        let asked = asked.into_inner().unwrap();
+        assert_eq!(asked.len(), 1);
+        assert_eq!(asked[0].action, "buy");
+        assert_eq!(asked[0].value, 1_000);
+        assert_eq!(
+            asked[0].nullifier,
+            format!("{}", fr_to_bytes32(&issuer.keys.sign_nullifier(source.salt)))
+        );
+        assert_eq!(
+            asked[0].recipient.as_deref(),
+            Some(load_wallet("buyer").unwrap().keys.public_spending_key_hex.as_str())
+        );
+        assert_eq!(contract.calls.lock().unwrap().as_slice(), ["mintBatch"]);
+
+        // --yes goes ahead without prompting
+        let yes = PromptSink {
+            policy: Reversibility::Irreversible,
+            yes: true,
+            assume_safe: false,
+            prompt: |_: &PendingAction| -> bool { panic!("--yes must not prompt") },
+        };
+        let bought = rt.block_on(buy(&prover, &contract, &yes, "buyer", 1_000, &source_note, "issuer", None, None));
+        assert!(bought.is_ok());
+        assert_eq!(contract.calls.lock().unwrap().as_slice(), ["mintBatch", "transfer"]);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_buyer_scans_memo_without_issuer_wallet() {
+        let (_guard, _dir) = temp_data_dir("buy_memo");
+
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", data_dir()));
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        let issuer = load_wallet("issuer").unwrap();
+        let (_, buyer_bond) = rt
+            .block_on(buy(
+                &prover,
+                &contract,
+                &RecordingSink::new(),
+                "buyer",
+                1_000,
+                &SourceNote::Path(global_note_path()),
+                "issuer",
+                None,
+                None,
+            ))
+            .unwrap();
+
+        // The envelope names its sender, so the buyer needs nothing from the issuer
+        fs::remove_file(wallet_path("issuer")).unwrap();
+        let (found, memos) = scan_memos("buyer", None).unwrap();
+        assert_eq!(found, 1);
+        assert_eq!(memos.len(), 1);
+        let memo = &memos[0];
+        assert_eq!(memo.value, buyer_bond.value);
+        assert_eq!(memo.salt, buyer_bond.salt);
+        assert_eq!(memo.asset_id, buyer_bond.asset_id);
+        assert_eq!(memo.maturity_date, buyer_bond.maturity_date);
+        assert_eq!(memo.sender, hex::encode(issuer.keys.public_viewing_key()));
+
+        // Nobody else can open it, even filed under their name
+        register("eve").unwrap();
+        let memo_path = format!("{}/{}", data_dir(), memo.file);
+        fs::copy(&memo_path, format!("{}/memo_eve_copy.json", data_dir())).unwrap();
+        let (found, memos) = scan_memos("eve", None).unwrap();
+        assert_eq!((found, memos.len()), (1, 0));
+    }
+
+    #[test]
+    fn test_buy_sends_change_to_another_key() {
+        let (_guard, dir) = temp_data_dir("buy_change_to");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        register("treasury").unwrap();
+        let tranche = load_bond(&global_note_path()).unwrap();
+        let treasury = load_wallet("treasury").unwrap();
+
+        // An unknown wallet or a malformed key pair is refused before anything is spent
+        let attempt = |change_to: &str| {
+            rt.block_on(buy(
+                &prover,
+                &contract,
+                &RecordingSink::new(),
+                "buyer",
+                1_000,
+                &SourceNote::Tranche,
+                "issuer",
+                Some(change_to),
+                None,
+            ))
+        };
+        assert!(attempt("nobody").unwrap_err().to_string().contains("Invalid --change-to"));
+        assert!(attempt("0x1234:abcd").unwrap_err().to_string().contains("viewing key"));
+        assert_eq!(contract.calls.lock().unwrap().as_slice(), ["mintBatch"]);
+
+        attempt("treasury").unwrap();
+        let change_value = tranche.value - 1_000;
+        let (path, change) = bonds_in(&dir)
+            .into_iter()
+            .find(|(p, _)| p.contains("bond_treasury_"))
+            .unwrap();
+        assert_eq!(change.value, change_value);
+        assert_eq!(change.owner, treasury.keys.public_spending_key_hex);
+        assert_eq!(change.nullifier, fr_to_hex(&treasury.keys.sign_nullifier(change.salt)));
+        assert!(!fs::read_dir(&dir).unwrap().flatten().any(|e| e
+            .file_name()
+            .to_string_lossy()
+            .starts_with("issuer_change_")));
+
+        // The change is the leaf the contract received, owned by the treasury key
+        let commitment = CircuitNote::new(
+            change_value,
+            change.salt,
+            treasury.keys.public_spending_key(),
+            tranche.asset_id,
+            tranche.maturity_date,
+        )
+        .unwrap()
+        .commitment();
+        assert_eq!(parse_commitment(&change.commitment), Some(commitment));
+        assert!(contract.commitments.lock().unwrap().contains(&fr_to_bytes32(&commitment)));
+
+        // Without the bond file, the treasury recovers the note from its memo
+        fs::remove_file(&path).unwrap();
+        let (_, memos) = scan_memos("treasury", None).unwrap();
+        assert_eq!(memos.len(), 1);
+        assert_eq!((memos[0].value, memos[0].salt), (change_value, change.salt));
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_onboard_catches_dropped_mint() {
+        let (_guard, _dir) = temp_data_dir("dropped_mint");
+
+        // The mint "confirms" but the contract never stores the commitment
+        let contract = MockBondContract::new();
+        *contract.drop_mints.lock().unwrap() = true;
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        assert!(rt.block_on(onboard(&contract, "issuer", None, false)).is_err());
+
+        assert_eq!(*contract.calls.lock().unwrap(), vec!["mintBatch".to_string()]);
+        assert!(TreeState::load().commitments().is_empty());
+        assert!(!Path::new(&global_note_path()).exists());
+        assert!(load_wallet("issuer").is_none());
+
+        // A contract that keeps it passes the read-back
+        let contract = MockBondContract::new();
+        rt.block_on(onboard(&contract, "issuer", None, true)).unwrap();
+        // The contract holds the local tree's leaves, as field elements and in order
+        let local: Vec<FixedBytes<32>> = TreeState::load()
+            .commitments()
+            .iter()
+            .map(|c| fr_to_bytes32(&parse_commitment(c).unwrap()))
+            .collect();
+        assert_eq!(local.len(), 2);
+        assert_eq!(*contract.commitments.lock().unwrap(), local);
+    }
+
+    #[test]
+    fn test_onboard_refuses_to_overwrite_a_wallet_without_force() {
+        let (_guard, dir) = temp_data_dir("onboard_twice");
+        let contract = MockBondContract::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", Some("first"), false)).unwrap();
+        let first = load_wallet("issuer").unwrap().keys.public_spending_key_hex;
+        let first_tranche = load_bond(&global_note_path()).unwrap();
+
+        // Same name again: keys kept, nothing minted
+        assert!(rt.block_on(onboard(&contract, "issuer", None, false)).is_err());
+        assert_eq!(load_wallet("issuer").unwrap().keys.public_spending_key_hex, first);
+        assert_eq!(contract.calls.lock().unwrap().len(), 1);
+
+        // With --force, a mint the contract drops leaves the wallet as it was
+        *contract.drop_mints.lock().unwrap() = true;
+        assert!(rt.block_on(onboard(&contract, "issuer", None, true)).is_err());
+        assert_eq!(load_wallet("issuer").unwrap().keys.public_spending_key_hex, first);
+        assert_eq!(load_bond(&global_note_path()).unwrap(), first_tranche);
+        assert_eq!(contract.calls.lock().unwrap().len(), 2);
+
+        *contract.drop_mints.lock().unwrap() = false;
+        rt.block_on(onboard(&contract, "issuer", None, true)).unwrap();
+        assert_ne!(load_wallet("issuer").unwrap().keys.public_spending_key_hex, first);
+        assert_eq!(contract.calls.lock().unwrap().len(), 3);
+
+        // Every forced onboard kept the keys it was about to replace
+        let backups: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|f| f.starts_with("issuer.json.bak-"))
+            .collect();
+        assert!(!backups.is_empty());
+        let kept: Wallet = serde_json::from_str(&fs::read_to_string(format!("{}/{}", dir, backups[0])).unwrap()).unwrap();
+        assert_eq!(kept.keys.public_spending_key_hex, first);
+
+        // ...and the tranche note those keys own, moved aside once the new one was minted
+        let tranche_name = Path::new(&global_note_path()).file_name().unwrap().to_string_lossy().to_string();
+        let tranche_backups: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|f| f.starts_with(&format!("{}.bak-", tranche_name)))
+            .collect();
+        assert_eq!(tranche_backups.len(), 1);
+        assert_eq!(load_bond(&format!("{}/{}", dir, tranche_backups[0])).unwrap(), first_tranche);
+        let tranche = load_bond(&global_note_path()).unwrap();
+        assert_ne!(tranche.owner, first_tranche.owner);
+        assert_eq!(tranche.owner, load_wallet("issuer").unwrap().keys.public_spending_key_hex);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_onboard_and_buy_agree_on_dummy() {
+        let (_guard, _dir) = temp_data_dir("dummy_agree");
+        let contract = MockBondContract::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+
+        // buy must find the very leaf onboard added for the issuer's dummy
+        let issuer = load_wallet("issuer").unwrap().keys;
+        let source = load_bond(&global_note_path()).unwrap();
+        let tree_state = TreeState::load();
+        let buyer = ShieldedKeys::from_seed([2u8; 32]);
+        let plan = plan_buy(&buyer, &issuer, &source, 1_000, &tree_state, (7, 8)).unwrap();
+        assert_eq!(plan.dummy_note_index, 1);
+        let dummy = &plan.witness.input_notes[1];
+        assert_eq!((dummy.value, dummy.salt), (0, DUMMY_SALT));
+        assert_eq!(fr_to_hex(&dummy.commitment()), tree_state.commitments()[1]);
+        assert_eq!(plan.dummy_nullifier, issuer.sign_nullifier(DUMMY_SALT));
+    }
+
+    #[test]
+    fn test_repair_tree_from_chain() {
+        let (_guard, _dir) = temp_data_dir("repair_tree");
+        let (_, _, source, full) = buy_fixture();
+        let commitment = parse_commitment(&source.commitment).unwrap();
+        let dummy = parse_commitment(&full.commitments()[1]).unwrap();
+
+        // The contract holds the dummy and the source note; the local tree only
+        // ever saw the dummy
+        let contract = MockBondContract::new();
+        *contract.commitments.lock().unwrap() = vec![fr_to_bytes32(&dummy), fr_to_bytes32(&commitment)];
+        let mut tree_state = TreeState::from_commitments(vec![fr_to_hex(&dummy)]);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        // Non-interactive sinks decline
+        let declined = rt.block_on(repair_tree_from_chain(&contract, &PrintSink, &mut tree_state, &commitment));
+        assert_eq!(declined, None);
+        assert_eq!(tree_state.commitments().len(), 1);
+
+        let events = RecordingSink::new();
+        let repaired = rt.block_on(repair_tree_from_chain(&contract, &events, &mut tree_state, &commitment));
+        assert_eq!(repaired, Some(1));
+        assert_eq!(*events.events.lock().unwrap(), vec!["confirm_repair:1".to_string()]);
+        assert_eq!(TreeState::load().find_commitment(&commitment), Some(1));
+
+        // A note the chain does not hold either is not offered
+        let stranger = CircuitNote::dummy(dummy, 1, 1);
+        let events = RecordingSink::new();
+        let none = rt.block_on(repair_tree_from_chain(&contract, &events, &mut tree_state, &stranger.commitment()));
+        assert_eq!(none, None);
+        assert!(events.events.lock().unwrap().is_empty());
+
+        // A leaf that is not a field element would shift the ones after it: no repair
+        let mut tree_state = TreeState::from_commitments(vec![fr_to_hex(&dummy)]);
+        contract.commitments.lock().unwrap()[0] = FixedBytes::repeat_byte(0xff);
+        let events = RecordingSink::new();
+        let refused = rt.block_on(repair_tree_from_chain(&contract, &events, &mut tree_state, &commitment));
+        assert_eq!(refused, None);
+        assert!(events.events.lock().unwrap().is_empty());
+        assert_eq!(tree_state.commitments().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_buy_rolls_tree_back() {
+        let (_guard, dir) = temp_data_dir("buy_rollback");
+        register("issuer").unwrap();
+        register("buyer").unwrap();
+        let issuer = load_wallet("issuer").unwrap();
+        let owner = issuer.keys.public_spending_key();
+        let note = CircuitNote::new(10_000, 42, owner, 1, 1893456000).unwrap();
+        let source = Bond {
+            schema_version: BOND_SCHEMA_VERSION,
+            commitment: fr_to_hex(&note.commitment()),
+            nullifier: fr_to_hex(&issuer.keys.sign_nullifier(42)),
+            value: 10_000,
+            salt: 42,
+            owner: issuer.keys.public_spending_key_hex.clone(),
+            asset_id: 1,
+            maturity_date: 1893456000,
+            created_at: String::new(),
+            label: None,
+            memo_hash: None,
+        };
+        source.save(&global_note_path()).unwrap();
+
+        // The local tree lacks the source note, which buy repairs from the chain;
+        // the dummy's nullifier is already spent, so the transfer is rejected
+        let contract = MockBondContract::new();
+        *contract.commitments.lock().unwrap() = vec![fr_to_bytes32(&note.commitment())];
+        contract.nullifiers.lock().unwrap().insert(fr_to_bytes32(&issuer.keys.sign_nullifier(DUMMY_SALT)));
+        TreeState::from_commitments(vec![fr_to_hex(&note.padding().commitment())]).save();
+        let before = TreeState::load().snapshot();
+
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let events = RecordingSink::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let result = rt.block_on(buy(
+            &prover,
+            &contract,
+            &events,
+            "buyer",
+            1_000,
+            &SourceNote::Path(global_note_path()),
+            "issuer",
+            None,
+            None,
+        ));
+        assert!(result.unwrap_err().to_string().contains("already spent"));
+        assert!(events.events.lock().unwrap().contains(&"confirm_repair:1".to_string()));
+        assert_eq!(TreeState::load().snapshot(), before);
+    }
+
+    #[test]
+    fn test_prepared_mint_calldata_matches_local_tree() {
+        let (_guard, dir) = temp_data_dir("prepared_mint");
+        register("issuer").unwrap();
+        let contract = MockBondContract::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let network = rt.block_on(contract.network()).unwrap();
+
+        let prepared = prepare_mint("issuer", 5_000, 1, 1893456000, network, Encoding::Base64).unwrap();
+        let file = format!("{}/mint.json", dir);
+        prepared.save(&file).unwrap();
+        assert!(TreeState::load().commitments().is_empty());
+
+        // The calldata mints the note and its padding dummy, as the tree will hold them
+        let minted = decode_mint_batch(&Encoding::Base64.decode(&prepared.calldata).unwrap()).unwrap();
+        let bond = &prepared.bond;
+        let owner = load_wallet("issuer").unwrap().keys.public_spending_key();
+        let note = CircuitNote::new(bond.value, bond.salt, owner, bond.asset_id, bond.maturity_date).unwrap();
+        let expected = [note.commitment(), note.padding().commitment()];
+        assert_eq!(minted, expected.iter().map(fr_to_bytes32).collect::<Vec<_>>());
+        assert_eq!(prepared.commitments, expected.iter().map(fr_to_hex).collect::<Vec<_>>());
+
+        let path = rt.block_on(submit_prepared_file(&contract, &file)).unwrap();
+        assert_eq!(*contract.commitments.lock().unwrap(), minted);
+        assert_eq!(TreeState::load().commitments(), &prepared.commitments[..]);
+        assert_eq!(load_bond(&path).unwrap().commitment, fr_to_hex(&expected[0]));
+
+        // Sending it twice, or to another deployment, is refused before anything is sent
+        let err = rt.block_on(submit_prepared_file(&contract, &file)).unwrap_err();
+        assert!(err.to_string().contains("already in the local tree"), "{}", err);
+        let mainnet = NetworkBinding { chain_id: 1, ..network };
+        let other = prepare_mint("issuer", 5_000, 1, 1893456000, mainnet, Encoding::Hex).unwrap();
+        other.save(&file).unwrap();
+        assert!(rt.block_on(submit_prepared_file(&contract, &file)).unwrap_err().to_string().contains("was prepared for"));
+        assert_eq!(*contract.calls.lock().unwrap(), vec!["mintBatch".to_string()]);
+    }
+
+    #[test]
+    fn test_proof_info_flags_evicted_root_as_stale() {
+        let (_guard, dir) = temp_data_dir("proof_info");
+        let contract = MockBondContract::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut tree_state = TreeState::load();
+        // A proof file with its public inputs, as bb leaves them
+        let write_proof = |name: &str, root: Fr| {
+            let proof_dir = format!("{}/{}", dir, name);
+            fs::create_dir_all(&proof_dir).unwrap();
+            let inputs = [root, u64_to_fr(11), u64_to_fr(12), u64_to_fr(13), u64_to_fr(14)];
+            let bytes: Vec<u8> = inputs.iter().flat_map(|fr| fr_to_bytes32(fr).0).collect();
+            fs::write(format!("{}/public_inputs", proof_dir), bytes).unwrap();
+            let proof = format!("{}/proof", proof_dir);
+            fs::write(&proof, [0u8; 32]).unwrap();
+            ProofMeta::from_bb_output(&proof, "mock", 0).unwrap().save(&proof).unwrap();
+            proof
+        };
+        let mut insert = |n: u64| {
+            tree_state.add_commitment(u64_to_fr(n));
+            contract.commitments.lock().unwrap().push(fr_to_bytes32(&u64_to_fr(n)));
+            tree_state.build_tree().root()
+        };
+
+        let old_root = insert(1);
+        let old = write_proof("old", old_root);
+        let info = rt.block_on(inspect_proof(&contract, &old)).unwrap();
+        assert!(info.current && !info.stale());
+        assert_eq!(info.root, fr_to_bytes32(&old_root));
+        assert_eq!(info.nullifiers, [fr_to_bytes32(&u64_to_fr(11)), fr_to_bytes32(&u64_to_fr(12))]);
+        assert_eq!(info.commitments, [fr_to_bytes32(&u64_to_fr(13)), fr_to_bytes32(&u64_to_fr(14))]);
+
+        // One insert later the root is recent, not current
+        insert(2);
+        let info = rt.block_on(inspect_proof(&contract, &old)).unwrap();
+        assert!(!info.current && info.recent && !info.stale());
+
+        // Enough later roots evict it from the local history: stale, though the
+        // contract still knows it
+        let latest = insert(3);
+        let mut tree_state = TreeState::load();
+        for n in 0..ROOT_HISTORY_SIZE as u64 {
+            tree_state.push_root(u64_to_fr(1_000 + n));
+        }
+        tree_state.save();
+        let info = rt.block_on(inspect_proof(&contract, &old)).unwrap();
+        assert!(info.stale());
+        assert_eq!(info.known_onchain, Some(true));
+        let fresh = write_proof("fresh", latest);
+        assert!(rt.block_on(inspect_proof(&contract, &fresh)).unwrap().current);
+
+        // A root the contract never built
+        let bogus = write_proof("bogus", u64_to_fr(999));
+        let info = rt.block_on(inspect_proof(&contract, &bogus)).unwrap();
+        assert!(info.stale());
+        assert_eq!(info.known_onchain, Some(false));
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sync_rebuilds_tree_in_chain_order() {
+        let (_guard, dir) = temp_data_dir("sync_tree");
+        let contract = MockBondContract::new();
+        let leaves: Vec<Fr> = (1..=4).map(u64_to_fr).collect();
+        contract.commitments.lock().unwrap().extend(leaves.iter().map(fr_to_bytes32));
+
+        // This machine inserted the same leaves in another order, plus one the chain lacks
+        let mut tree_state = TreeState::load();
+        for i in [1, 0, 3, 2] {
+            tree_state.add_commitment(leaves[i]);
+        }
+        let extra = u64_to_fr(99);
+        tree_state.add_commitment(extra);
+        let index = "0xabc";
+        tree_state.record_bond(&fr_to_hex(&leaves[0]), index).unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (status, kept) = rt.block_on(sync_tree(&contract)).unwrap();
+        assert_eq!(kept, 1);
+        let mut tree_state = TreeState::load();
+        let expected: Vec<String> = leaves.iter().chain([&extra]).map(fr_to_hex).collect();
+        assert_eq!(tree_state.commitments(), &expected[..]);
+        assert_eq!(status.leaves, 5);
+        assert_eq!(tree_state.bond_files(&leaves[0]), vec![index.to_string()]);
+
+        // Without local-only leaves the root is the contract's
+        contract.commitments.lock().unwrap().push(fr_to_bytes32(&extra));
+        let (status, kept) = rt.block_on(sync_tree(&contract)).unwrap();
+        assert_eq!(kept, 0);
+        assert!(status.matches());
+        assert!(TreeState::load().is_recent_root(&tree_state.build_tree().root()));
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sync_keeps_onboard_leaves_at_their_chain_index() {
+        let (_guard, dir) = temp_data_dir("sync_onboard");
+        let contract = MockBondContract::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        let later = u64_to_fr(7);
+        contract.commitments.lock().unwrap().push(fr_to_bytes32(&later));
+
+        // This machine lost the tranche's leaves and only saw the later one
+        TreeState::from_commitments(vec![fr_to_hex(&later)]).save();
+        let (status, kept) = rt.block_on(sync_tree(&contract)).unwrap();
+        assert_eq!(kept, 0);
+        assert!(status.matches());
+        let onchain: Vec<String> = contract
+            .commitments
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| fr_to_hex(&fr_from_bytes32(c).unwrap()))
+            .collect();
+        assert_eq!(TreeState::load().commitments(), &onchain[..]);
+        let tranche = load_bond(&global_note_path()).unwrap();
+        assert_eq!(TreeState::load().find_commitment(&fr_from_hex(&tranche.commitment).unwrap()), Some(0));
+
+        // A leaf that is not a field element is refused, not skipped
+        contract.commitments.lock().unwrap()[1] = FixedBytes::repeat_byte(0xff);
+        let err = rt.block_on(sync_tree(&contract)).unwrap_err();
+        assert!(err.contains("leaf 1"), "{}", err);
+        assert_eq!(TreeState::load().commitments(), &onchain[..]);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_mint_needs_no_anvil_key() {
+        let (_guard, dir) = temp_data_dir("mint_signer");
+        register("issuer").unwrap();
+        register("other").unwrap();
+        let (anvil, source) = resolve_signer(None, "issuer").unwrap();
+        assert_eq!(source, SignerSource::AnvilDefault);
+
+        // An issuer with its own key mints from its own address; others keep the default
+        let key = format!("0x{}", "42".repeat(32));
+        fs::write(signer_key_path("issuer"), format!("{}\n", key)).unwrap();
+        let (own, source) = resolve_signer(None, "issuer").unwrap();
+        assert_eq!(source, SignerSource::Wallet(signer_key_path("issuer")));
+        assert_ne!(own.address(), anvil.address());
+        assert_eq!(resolve_signer(None, "other").unwrap().1, SignerSource::AnvilDefault);
+
+        // --signer-key-file wins over the wallet's key
+        let file = format!("{}/relayer.key", dir);
+        fs::write(&file, format!("0x{}", "07".repeat(32))).unwrap();
+        let (flagged, source) = resolve_signer(Some(&file), "issuer").unwrap();
+        assert_eq!(source, SignerSource::KeyFile(file.clone()));
+        assert_ne!(flagged.address(), own.address());
+        fs::write(&file, "not a key").unwrap();
+        assert!(resolve_signer(Some(&file), "issuer").unwrap_err().contains("Invalid signer key"));
+
+        // Prepared mint calldata is unsigned: nothing in it names a sender
+        let network = NetworkBinding {
+            chain_id: 31337,
+            contract: PRIVATE_BOND_ADDRESS.parse().unwrap(),
+        };
+        let prepared = prepare_mint("issuer", 5_000, 1, 1893456000, network, Encoding::Hex).unwrap();
+        let out = format!("{}/mint.json", dir);
+        prepared.save(&out).unwrap();
+        let json = fs::read_to_string(&out).unwrap().to_lowercase();
+        assert!(!json.contains(ANVIL_DEV_KEY.trim_start_matches("0x")));
+        assert!(!json.contains(&hex::encode(anvil.address())));
+        assert!(!json.contains(&hex::encode(own.address())));
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_disclosure_rejects_a_note_the_contract_never_held() {
+        let (_guard, dir) = temp_data_dir("open_disclosure");
+        let contract = MockBondContract::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        // The contract holds the same leaves as the local tree, the tranche's and its dummy
+        *contract.commitments.lock().unwrap() = TreeState::load()
+            .commitments()
+            .iter()
+            .map(|c| fr_to_bytes32(&parse_commitment(c).unwrap()))
+            .collect();
+        let bond = load_bond(&global_note_path()).unwrap();
+        let disclosure = Disclosure::for_bond(&bond, &TreeState::load()).unwrap();
+
+        assert!(rt.block_on(check_disclosure_onchain(&contract, &disclosure)).is_ok());
+        // Still fine once later mints move the root on
+        contract.commitments.lock().unwrap().push(FixedBytes::repeat_byte(7));
+        assert!(rt.block_on(check_disclosure_onchain(&contract, &disclosure)).is_ok());
+
+        // A consistent package against a contract that never saw the note
+        let elsewhere = MockBondContract::new();
+        *elsewhere.commitments.lock().unwrap() = vec![FixedBytes::repeat_byte(7); 2];
+        let err = rt.block_on(check_disclosure_onchain(&elsewhere, &disclosure)).unwrap_err();
+        assert!(err.contains("holds neither"), "{}", err);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_spend_calldata_decodes_to_witness_and_proof() {
+        let (_guard, dir) = temp_data_dir("spend_calldata");
+        let (issuer, buyer, source, tree_state) = buy_fixture();
+        let plan = plan_buy(&buyer, &issuer, &source, 3_000, &tree_state, (7, 8)).unwrap();
+        fs::create_dir_all(format!("{}/proof", dir)).unwrap();
+        save_witness(&plan.witness, "0xabc");
+        let proof_path = format!("{}/proof/proof", dir);
+        fs::write(&proof_path, [0xde, 0xad, 0xbe, 0xef]).unwrap();
+
+        // The bond it spent finds the same witness by nullifier
+        let bond_path = format!("{}/source.json", dir);
+        let bond = Bond { nullifier: fr_to_hex(&issuer.sign_nullifier(source.salt)), ..source.clone() };
+        bond.save(&bond_path).unwrap();
+        let (bundle, calldata) = spend_calldata(Some(&bond_path), None, &proof_path).unwrap();
+        let (_, from_witness) = spend_calldata(None, Some(&witness_path("0xabc")), &proof_path).unwrap();
+        assert_eq!(calldata, from_witness);
+
+        let decoded = crate::contract::decode_bundle_calldata(&calldata).unwrap();
+        assert_eq!(decoded, bundle);
+        assert_eq!(decoded.method, "transfer");
+        assert_eq!(decoded.proof.as_ref(), [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(decoded.root, fr_to_bytes32(&plan.witness.root));
+        assert_eq!(decoded.nullifiers, [fr_to_bytes32(&plan.input_nullifier), fr_to_bytes32(&plan.dummy_nullifier)]);
+        assert_eq!(decoded.commitments, plan.witness.commitments_out.map(|c| fr_to_bytes32(&c)));
+
+        // A redemption witness is a burn carrying the maturity and the redeem flag
+        let mut redeem = plan.witness;
+        for note in redeem.output_notes.iter_mut() {
+            note.value = 0;
+        }
+        let decoded = crate::contract::decode_bundle_calldata(
+            &bundle_calldata(&witness_call(&redeem, vec![1])).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(decoded.method, "burn");
+        assert_eq!(decoded.maturity_date, Some(fr_to_bytes32(&Fr::from_str("1893456000").unwrap())));
+        assert_eq!(decoded.is_redeem, Some(fr_to_bytes32(&Fr::from_str("1").unwrap())));
+
+        let other = Bond { nullifier: fr_to_hex(&Fr::from_str("5").unwrap()), ..source };
+        other.save(&bond_path).unwrap();
+        assert!(spend_calldata(Some(&bond_path), None, &proof_path).unwrap_err().contains("No saved witness"));
+    }
+
+    #[test]
+    fn test_buy_resumes_after_dropped_connection() {
+        let (_guard, dir) = temp_data_dir("buy_resume");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        let issuer = load_wallet("issuer").unwrap();
+        let source = load_bond(&global_note_path()).unwrap();
+        let spends = format!("{}", fr_to_bytes32(&issuer.keys.sign_nullifier(source.salt)));
+        let attempt = |events: &RecordingSink| {
+            rt.block_on(buy(
+                &prover,
+                &contract,
+                events,
+                "buyer",
+                1_000,
+                &SourceNote::Tranche,
+                "issuer",
+                None,
+                None,
+            ))
+        };
+
+        // The transfer is mined but the connection drops before its receipt
+        *contract.drop_receipts.lock().unwrap() = true;
+        let err = attempt(&RecordingSink::new()).unwrap_err();
+        assert!(err.to_string().contains("connection dropped"), "{}", err);
+        *contract.drop_receipts.lock().unwrap() = false;
+        let recorded = Attempt::find("buy", &spends).unwrap().unwrap();
+        assert!(Path::new(&recorded.proof).exists());
+        assert!(recorded.tx_hash.is_some());
+        let leaves_before = TreeState::load().commitments().len();
+
+        // Out of retries: stop rather than guess
+        let mut exhausted = recorded.clone();
+        exhausted.retries = RETRY_BUDGET;
+        exhausted.save().unwrap();
+        assert!(attempt(&RecordingSink::new()).unwrap_err().to_string().contains("already retried"));
+        recorded.save().unwrap();
+
+        // The rerun sees the spend landed and only saves its outputs: no second proof,
+        // no second transfer, the same outputs
+        let events = RecordingSink::new();
+        let (path, bond) = attempt(&events).unwrap();
+        let recorded_events = events.events.lock().unwrap().clone();
+        assert!(!recorded_events.iter().any(|e| e == "proof_started" || e.starts_with("tx_sent")), "{:?}", recorded_events);
+        assert!(recorded_events.contains(&"tx_confirmed:transfer".to_string()));
+        assert_eq!(contract.calls.lock().unwrap().as_slice(), ["mintBatch", "transfer"]);
+        assert!(recorded.commitments.contains(&format!("{}", fr_to_bytes32(&fr_from_hex(&bond.commitment).unwrap()))));
+        assert_eq!(load_bond(&path).unwrap(), bond);
+        let tree = TreeState::load();
+        assert_eq!(tree.commitments().len(), leaves_before + 2);
+        let leaves: Vec<FixedBytes<32>> = tree.commitments().iter().map(|c| fr_to_bytes32(&fr_from_hex(c).unwrap())).collect();
+        // The two outputs, once each and in the contract's order after onboard's mint
+        assert_eq!(leaves[leaves_before..], contract.commitments.lock().unwrap()[2..]);
+        assert!(Attempt::find("buy", &spends).unwrap().is_none());
+        let logged = history::load_history(None).unwrap();
+        assert_eq!(logged.iter().filter(|r| r.command == "buy").count(), 1);
+        assert_eq!(Some(&logged.last().unwrap().tx_hash), recorded.tx_hash.as_ref());
+
+        // With the attempt done, a third run is an ordinary double spend
+        assert!(attempt(&RecordingSink::new()).unwrap_err().to_string().contains("already spent"));
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_fan_out_resumes_after_a_failed_hop() {
+        let (_guard, dir) = temp_data_dir("fan_out_resume");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("alice").unwrap();
+        register("bob").unwrap();
+        let issuer = load_wallet("issuer").unwrap();
+        let source = load_bond(&global_note_path()).unwrap();
+        let spends = format!("{}", fr_to_bytes32(&issuer.keys.sign_nullifier(source.salt)));
+        let recipients = parse_recipients("alice,1000\nbob,2000").unwrap();
+        let tranche = SourceNote::Path(global_note_path());
+        let bonds_of = |name: &str| {
+            fs::read_dir(&dir)
+                .unwrap()
+                .flatten()
+                .filter(|e| e.file_name().to_string_lossy().starts_with(&format!("bond_{}_", name)))
+                .count()
+        };
+
+        // The second hop's transfer reverts: the pads and the first hop are mined
+        *contract.reject_transfer.lock().unwrap() = Some(2);
+        let err = rt
+            .block_on(buy_fan_out(&prover, &contract, &RecordingSink::new(), &recipients, &tranche, "issuer", None))
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Hop 2 of 2"), "{}", err);
+        *contract.reject_transfer.lock().unwrap() = None;
+        assert_eq!(contract.calls.lock().unwrap().as_slice(), ["mintBatch", "mintBatch", "transfer"]);
+        assert_eq!(bonds_of("alice"), 1);
+        assert_eq!(bonds_of("bob"), 0);
+        let chain = Attempt::find("fan-out", &spends).unwrap().unwrap();
+        assert_eq!(chain.chain.len(), 2);
+
+        // The rerun rebuilds the same chain: no new pads, the first hop is not sent again
+        let saved = rt
+            .block_on(buy_fan_out(&prover, &contract, &RecordingSink::new(), &recipients, &tranche, "issuer", None))
+            .unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(
+            contract.calls.lock().unwrap().as_slice(),
+            ["mintBatch", "mintBatch", "transfer", "transfer"]
+        );
+        for name in ["alice", "bob"] {
+            assert_eq!(bonds_of(name), 1, "{}", name);
+        }
+        assert!(Attempt::find("fan-out", &spends).unwrap().is_none());
+        assert!(fs::read_dir(crate::utils::attempts_dir()).map_or(true, |mut entries| entries.next().is_none()));
+
+        // The local tree matches the contract's leaves
+        let tree = TreeState::load();
+        let leaves: Vec<FixedBytes<32>> = tree.commitments().iter().map(|c| fr_to_bytes32(&fr_from_hex(c).unwrap())).collect();
+        assert_eq!(leaves, *contract.commitments.lock().unwrap());
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_buy_rejects_spent_source_before_proving() {
+        let (_guard, dir) = temp_data_dir("buy_spent_source");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        let issuer = load_wallet("issuer").unwrap();
+        let source = load_bond(&global_note_path()).unwrap();
+        let nullifier = fr_to_bytes32(&issuer.keys.sign_nullifier(source.salt));
+        let attempt = |events: &RecordingSink| {
+            rt.block_on(buy(
+                &prover,
+                &contract,
+                events,
+                "buyer",
+                1_000,
+                &SourceNote::Tranche,
+                "issuer",
+                None,
+                None,
+            ))
+        };
+
+        // Spent on-chain (sold from another machine): nothing is proved or sent
+        contract.nullifiers.lock().unwrap().insert(nullifier);
+        let events = RecordingSink::new();
+        let e = attempt(&events).unwrap_err();
+        assert!(e.to_string().contains("already spent on-chain"), "{}", e);
+        assert!(events.events.lock().unwrap().is_empty());
+        assert_eq!(contract.calls.lock().unwrap().as_slice(), ["mintBatch"]);
+
+        // Spent in this wallet's history, before the node has seen it
+        contract.nullifiers.lock().unwrap().clear();
+        history::append_history(&HistoryRecord {
+            timestamp: Utc::now().to_rfc3339(),
+            command: "buy".to_string(),
+            nullifiers: vec![format!("{}", nullifier)],
+            commitments: Vec::new(),
+            value: Some(1_000),
+            tx_hash: String::new(),
+            network: String::new(),
+            settlement_ref: None,
+            settlement_account: None,
+        })
+        .unwrap();
+        let events = RecordingSink::new();
+        let e = attempt(&events).unwrap_err();
+        assert!(e.to_string().contains("already spent") && e.to_string().contains("history.jsonl"), "{}", e);
+        assert!(events.events.lock().unwrap().is_empty());
+        assert_eq!(contract.calls.lock().unwrap().as_slice(), ["mintBatch"]);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_buy_flow_against_mocks() {
+        let (_guard, dir) = temp_data_dir("buy_flow");
+
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        assert_eq!(TreeState::load().commitments().len(), 2);
+
+        let issuer = load_wallet("issuer").unwrap();
+        let source = load_bond(&global_note_path()).unwrap();
+        let events = RecordingSink::new();
+        let (buyer_path, buyer_bond) = rt
+            .block_on(buy(
+                &prover,
+                &contract,
+                &events,
+                "buyer",
+                1_000,
+                &SourceNote::Path(global_note_path()),
+                "issuer",
+                None,
+                Some("first lot"),
+            ))
+            .unwrap();
+        let saved = load_bond(&buyer_path).unwrap();
+        assert_eq!(saved.commitment, buyer_bond.commitment);
+        assert_eq!(saved.label.as_deref(), Some("first lot"));
+
+        // Both outputs appended locally and on the mock contract
+        let tree_state = TreeState::load();
+        assert_eq!(tree_state.commitments().len(), 4);
+        assert_eq!(contract.commitments.lock().unwrap().len(), 4);
+        assert_eq!(contract.calls.lock().unwrap().as_slice(), ["mintBatch", "transfer"]);
+        assert_eq!(
+            events.events.lock().unwrap().as_slice(),
+            [
+                "confirm:buy",
+                "witness_built",
+                "proof_started",
+                "proof_done",
+                "tx_sent:transfer",
+                "tx_confirmed:transfer"
+            ]
+        );
+
+        // Issuer's source note and dummy are spent
+        let spent = fr_to_bytes32(&issuer.keys.sign_nullifier(source.salt));
+        let dummy_spent = fr_to_bytes32(&issuer.keys.sign_nullifier(0));
+        assert!(contract.is_spent(&spent));
+        assert!(contract.is_spent(&dummy_spent));
+
+        // Onboard's mint and the transfer are logged, with public data only
+        let logged = history::load_history(None).unwrap();
+        let commands: Vec<&str> = logged.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, ["onboard", "buy"]);
+        assert_eq!(logged[1].value, Some(1_000));
+        assert!(logged[1].nullifiers.contains(&format!("{}", spent)));
+        let buyer_commitment = fr_to_bytes32(&parse_commitment(&buyer_bond.commitment).unwrap());
+        assert!(logged[1].commitments.contains(&format!("{}", buyer_commitment)));
+        assert_eq!(logged[1].network, contract.network.to_string());
+
+        // Buyer and change notes persisted, and the witness saved under the tx hash
+        let files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        assert!(files.iter().any(|f| f.starts_with("bond_buyer_")));
+        assert!(files.iter().any(|f| f.starts_with("issuer_change_")));
+        let witness_file = files.iter().find(|f| f.starts_with("witness_0x")).unwrap();
+
+        let witness_json = fs::read_to_string(format!("{}/{}", dir, witness_file)).unwrap();
+        assert!(!witness_json.contains("private_key"));
+        let issuer_key = load_wallet("issuer").unwrap().keys.get_private_spending_key();
+        assert!(!witness_json.contains(&fr_to_hex(&issuer_key)));
+
+        // The exported public-input layout, applied to the saved witness, is what buy submitted
+        let witness = WitnessBuilder::load_json(&format!("{}/{}", dir, witness_file)).unwrap();
+        let expected: Vec<FixedBytes<32>> = TRANSFER_PUBLIC_INPUTS
+            .iter()
+            .map(|name| fr_to_bytes32(&witness.public_input(name).unwrap()))
+            .collect();
+        assert_eq!(contract.public_inputs.lock().unwrap().as_slice(), [expected]);
+        assert!(std::path::Path::new(&prover.public_inputs_path()).exists());
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_reconstructed_bond_matches_bought_one() {
+        let (_guard, dir) = temp_data_dir("reconstruct");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        let (_, original) = rt
+            .block_on(buy(
+                &prover,
+                &contract,
+                &RecordingSink::new(),
+                "buyer",
+                1_000,
+                &SourceNote::Tranche,
+                "issuer",
+                None,
+                None,
+            ))
+            .unwrap();
+        let fields = |wallet: &str, value: u64| {
+            let (salt, owner) = (original.salt, original.owner.as_str());
+            reconstruct_bond(wallet, value, salt, owner, original.asset_id, original.maturity_date)
+        };
+
+        // The owner gets the same note back, nullifier included
+        let rebuilt = fields("buyer", original.value).unwrap();
+        assert_eq!(
+            Bond {
+                created_at: original.created_at.clone(),
+                label: original.label.clone(),
+                ..rebuilt.clone()
+            },
+            original
+        );
+
+        // Anyone else can rebuild it too, but cannot know its nullifier
+        let rebuilt = fields("issuer", original.value).unwrap();
+        assert_eq!(rebuilt.commitment, original.commitment);
+        assert!(rebuilt.nullifier.is_empty());
+
+        // A misremembered field hashes to a commitment the tree does not hold
+        assert!(fields("buyer", original.value + 1).unwrap_err().contains("not in the local tree"));
+
+        // The command writes and indexes the file but never overwrites one
+        let out = format!("{}/bond_buyer_restored.json", dir);
+        reconstruct("buyer", original.value, original.salt, &original.owner, 1, original.maturity_date, &out).unwrap();
+        assert_eq!(load_bond(&out).unwrap().nullifier, original.nullifier);
+        let commitment = parse_commitment(&original.commitment).unwrap();
+        assert!(TreeState::load().bond_files(&commitment).contains(&out));
+        fs::write(&out, "{}").unwrap();
+        assert!(reconstruct("buyer", original.value, original.salt, &original.owner, 1, original.maturity_date, &out).is_err());
+        assert_eq!(fs::read_to_string(&out).unwrap(), "{}");
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// Issuer and buyer keys, a source note and an in-memory tree holding it and its dummy
+    fn buy_fixture() -> (ShieldedKeys, ShieldedKeys, Bond, TreeState) {
+        let issuer = ShieldedKeys::from_seed([1u8; 32]);
+        let buyer = ShieldedKeys::from_seed([2u8; 32]);
+        let owner = issuer.public_spending_key();
+        let note = CircuitNote {
+            value: 10_000,
+            salt: 42,
+            owner,
+            asset_id: 1,
+            maturity_date: 1893456000,
+            memo_hash: None,
+        };
+        let commitment = note.commitment();
+        let dummy = CircuitNote::dummy(owner, 1, 1893456000).commitment();
+        let source = Bond {
+            schema_version: BOND_SCHEMA_VERSION,
+            commitment: fr_to_hex(&commitment),
+            nullifier: fr_to_hex(&issuer.sign_nullifier(42)),
+            value: 10_000,
+            salt: 42,
+            owner: issuer.public_spending_key_hex.clone(),
+            asset_id: 1,
+            maturity_date: 1893456000,
+            created_at: String::new(),
+            label: None,
+            memo_hash: None,
+        };
+        let tree_state = TreeState::from_commitments(vec![fr_to_hex(&commitment), fr_to_hex(&dummy)]);
+        (issuer, buyer, source, tree_state)
+    }
+
+    #[test]
+    fn test_plan_fan_out_to_three_recipients() {
+        let (issuer, _, source, _) = buy_fixture();
+        let recipient = |seed: u8, value| FanOutRecipient {
+            name: format!("r{}", seed),
+            spending_key: u64_to_fr(seed as u64),
+            viewing_key: [seed; 32],
+            value,
+            keys: None,
+        };
+        let recipients = [recipient(1, 1_000), recipient(2, 2_500), recipient(3, 3_000)];
+        let salts = [(11, 12, 13), (21, 22, 23), (31, 32, 33)];
+        let hops = plan_fan_out(&issuer, &source, &recipients, &salts).unwrap();
+        assert_eq!(hops.len(), 3);
+
+        let owner = issuer.public_spending_key();
+        for (hop, recipient) in hops.iter().zip(&recipients) {
+            let expected = CircuitNote {
+                value: recipient.value,
+                salt: salts[hop.recipient].0,
+                owner: recipient.spending_key,
+                asset_id: 1,
+                maturity_date: 1893456000,
+                memo_hash: None,
+            };
+            assert_eq!(hop.output.commitment(), expected.commitment());
+            // Each hop conserves value and keeps the change with the issuer
+            assert_eq!(hop.input.value + hop.pad.value, hop.output.value + hop.change.value);
+            assert_eq!(hop.change.owner, owner);
+        }
+        // Hops chain through the change, padded by the dummy and then fresh pads
+        assert_eq!(hops[0].input.salt, source.salt);
+        assert_eq!(hops[0].pad.commitment(), CircuitNote::dummy(owner, 1, 1893456000).commitment());
+        assert_eq!(hops[1].input.commitment(), hops[0].change.commitment());
+        assert_eq!(hops[2].input.commitment(), hops[1].change.commitment());
+        assert_eq!((hops[1].pad.value, hops[1].pad.salt), (0, 23));
+        assert_eq!(hops[2].change.value, 10_000 - 6_500);
+
+        assert!(plan_fan_out(&issuer, &source, &[recipient(1, 6_000), recipient(2, 4_001)], &salts[..2])
+            .unwrap_err()
+            .to_string().contains("must not exceed"));
+        assert!(plan_fan_out(&issuer, &source, &[recipient(1, 0)], &salts[..1]).is_err());
+    }
+
+    #[test]
+    fn test_parse_recipients() {
+        let viewing = "ab".repeat(32);
+        let pubkey = fr_to_hex(&u64_to_fr(5));
+        let csv = format!("# syndicate\n{}:{},700\n\n{}:{}, 300\n", pubkey, viewing, pubkey, viewing);
+        let recipients = parse_recipients(&csv).unwrap();
+        assert_eq!(recipients.iter().map(|r| r.value).collect::<Vec<_>>(), [700, 300]);
+        assert_eq!(recipients[0].spending_key, u64_to_fr(5));
+        assert_eq!(recipients[0].viewing_key, [0xab; 32]);
+        assert!(recipients[0].keys.is_none());
+
+        assert!(parse_recipients("").is_err());
+        assert!(parse_recipients(&format!("{}:{},lots", pubkey, viewing)).is_err());
+        assert!(parse_recipients(&format!("{}:abcd,5", pubkey)).unwrap_err().to_string().contains("viewing key"));
+    }
+
+    #[test]
+    fn test_plan_buy_builds_expected_notes() {
+        let (issuer, buyer, source, tree_state) = buy_fixture();
+        let plan = plan_buy(&buyer, &issuer, &source, 3_000, &tree_state, (7, 8)).unwrap();
+
+        // Value is conserved across the split
+        assert_eq!(plan.change_value, 7_000);
+        let [out0, out1] = &plan.witness.output_notes;
+        assert_eq!(out0.value + out1.value, source.value);
+
+        let expected_buyer = CircuitNote {
+            value: 3_000,
+            salt: 7,
+            owner: buyer.public_spending_key(),
+            asset_id: 1,
+            maturity_date: 1893456000,
+            memo_hash: None,
+        }
+        .commitment();
+        let expected_change = CircuitNote {
+            value: 7_000,
+            salt: 8,
+            owner: issuer.public_spending_key(),
+            asset_id: 1,
+            maturity_date: 1893456000,
+            memo_hash: None,
+        }
+        .commitment();
+        assert_eq!(plan.buyer_commitment, expected_buyer);
+        assert_eq!(plan.change_commitment, expected_change);
+        assert_eq!(plan.witness.commitments_out, [expected_buyer, expected_change]);
+
+        assert_eq!(plan.input_nullifier, issuer.sign_nullifier(42));
+        assert_eq!(plan.dummy_nullifier, issuer.sign_nullifier(0));
+        assert_eq!((plan.real_note_index, plan.dummy_note_index), (0, 1));
+        assert_eq!(plan.witness.root, tree_state.build_tree().root());
+    }
+
+    #[test]
+    fn test_plan_buy_whole_note() {
+        let (issuer, buyer, source, tree_state) = buy_fixture();
+        let plan = plan_buy(&buyer, &issuer, &source, 10_000, &tree_state, (7, 8)).unwrap();
+
+        // The buyer takes everything; change is a zero-value note back to the issuer
+        assert_eq!(plan.change_value, 0);
+        // (outputs are in canonical order, so find the change by value)
+        let change = plan.witness.output_notes.iter().find(|n| n.value == 0).unwrap();
+        let bought = plan.witness.output_notes.iter().find(|n| n.value == 10_000).unwrap();
+        assert_eq!(change.owner, issuer.public_spending_key());
+        assert_eq!(bought.owner, buyer.public_spending_key());
+        assert_eq!(plan.change_commitment, change.commitment());
+
+        // One unit of change is the smallest split
+        let plan = plan_buy(&buyer, &issuer, &source, 9_999, &tree_state, (7, 8)).unwrap();
+        assert_eq!(plan.change_value, 1);
+    }
+
+    #[test]
+    fn test_plan_buy_rejects_bad_inputs() {
+        let (issuer, buyer, source, tree_state) = buy_fixture();
+
+        // More than the note holds, or nothing at all
+        let err = plan_buy(&buyer, &issuer, &source, 10_001, &tree_state, (7, 8)).err().unwrap();
+        assert!(err.to_string().contains("must not exceed"), "{}", err);
+        let err = plan_buy(&buyer, &issuer, &source, 0, &tree_state, (7, 8)).err().unwrap();
+        assert!(err.to_string().contains("greater than zero"), "{}", err);
+
+        // Source note missing from the tree
+        let empty = TreeState::default();
+        let err = plan_buy(&buyer, &issuer, &source, 3_000, &empty, (7, 8)).err().unwrap();
+        assert!(err.to_string().contains("Source note commitment not found"));
+
+        // Dummy note missing from the tree
+        let only_source = TreeState::from_commitments(vec![tree_state.commitments()[0].clone()]);
+        let err = plan_buy(&buyer, &issuer, &source, 3_000, &only_source, (7, 8)).err().unwrap();
+        assert!(err.to_string().contains("Dummy note commitment not found"));
+    }
+
+    /// Save `bond` under `name` in the data directory
+    fn save_bond(name: &str, bond: &Bond) {
+        ensure_data_dir().unwrap();
+        let path = format!("{}/{}.json", data_dir(), name);
+        fs::write(&path, serde_json::to_string_pretty(bond).unwrap()).unwrap();
+        index_bond(&mut TreeState::load(), &path, bond);
+    }
+
+    #[test]
+    fn test_tree_lines() {
+        let empty = TreeState::from_commitments(Vec::new());
+        let lines = tree_lines(&empty, None, true).unwrap();
+        assert_eq!(lines.len(), 2 + TREE_HEIGHT + 1);
+        assert_eq!(lines[1], format!("Leaves: 0 of {}", MAX_LEAVES));
+        assert_eq!(lines.last().unwrap(), &format!("Level 0: {}", ["-"; MAX_LEAVES].join(" ")));
+
+        // Nodes show the same padded hex the tree state stores, so prefixes match the files
+        let tree_state = TreeState::from_commitments(vec!["0x1".to_string(), "0x2".to_string()]);
+        let lines = tree_lines(&tree_state, Some(0), false).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], format!("Level 0: 0x00000000 0x00000000 {}", ["-"; MAX_LEAVES - 2].join(" ")));
+        let root = fr_to_hex(&tree_state.build_tree().root());
+        let top = &tree_lines(&tree_state, Some(TREE_HEIGHT), false).unwrap()[2];
+        assert_eq!(top, &format!("Level {}: {}", TREE_HEIGHT, short(&root, 10)));
+
+        assert_eq!(tree_lines(&tree_state, None, false).unwrap().len(), 2);
+        let err = tree_lines(&tree_state, Some(TREE_HEIGHT + 1), false).unwrap_err();
+        assert!(err.contains("does not exist"), "{}", err);
+    }
+
+    #[test]
+    fn test_import_note_file() {
+        let (_guard, dir) = temp_data_dir("import_note");
+        let (issuer, buyer, source, mut tree_state) = buy_fixture();
+        for (name, keys) in [("issuer", issuer.clone()), ("buyer", buyer)] {
+            let wallet = Wallet {
+                schema_version: WALLET_SCHEMA_VERSION,
+                keys,
+                created_at: String::new(),
+            };
+            save_wallet(name, &wallet).unwrap();
+        }
+        tree_state.save();
+        // Outside the data directory's top level, which gets indexed as bond files
+        fs::create_dir(format!("{}/inbox", dir)).unwrap();
+        let incoming = format!("{}/inbox/note.json", dir);
+        let hand_over = |bond: &Bond| {
+            fs::write(&incoming, serde_json::to_string(bond).unwrap()).unwrap();
+            incoming.as_str()
+        };
+
+        // Someone else's note
+        let err = import_note_file("buyer", hand_over(&source)).unwrap_err();
+        assert!(err.contains("not wallet 'buyer'"), "{}", err);
+
+        // A real note for this wallet that never made it into the tree
+        let absent = CircuitNote::new(500, 43, issuer.public_spending_key(), 1, 1893456000).unwrap();
+        let bond = Bond {
+            commitment: fr_to_hex(&absent.commitment()),
+            value: 500,
+            salt: 43,
+            ..source.clone()
+        };
+        let err = import_note_file("issuer", hand_over(&bond)).unwrap_err();
+        assert!(err.contains("not in the local tree"), "{}", err);
+
+        // Fields that don't hash to the commitment
+        let tampered = Bond { value: 20_000, ..source.clone() };
+        let err = import_note_file("issuer", hand_over(&tampered)).unwrap_err();
+        assert!(err.contains("not the file's commitment"), "{}", err);
+
+        // The owner's own note, with whatever nullifier the sender wrote
+        let sent = Bond { nullifier: "unknown".to_string(), ..source.clone() };
+        let path = import_note_file("issuer", hand_over(&sent)).unwrap();
+        let imported = load_bond(&path).unwrap();
+        assert_eq!(imported.nullifier, source.nullifier);
+        assert_eq!(imported.commitment, source.commitment);
+        assert!(TreeState::load()
+            .indexed_bonds()
+            .iter()
+            .any(|(p, _)| *p == path));
+        let err = import_note_file("issuer", hand_over(&sent)).unwrap_err();
+        assert!(err.contains("already imported"), "{}", err);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_migrate_keys_waits_for_unspent_notes() {
+        let (_guard, dir) = temp_data_dir("migrate_keys");
+        let legacy = ShieldedKeys::from_seed_versioned([6u8; 32], crate::keys::KDF_V1).unwrap();
+        save_wallet(
+            "old",
+            &Wallet {
+                schema_version: WALLET_SCHEMA_VERSION,
+                keys: legacy.clone(),
+                created_at: "2030-01-01T00:00:00Z".to_string(),
+            },
+        )
+        .unwrap();
+        let (_, _, source, _) = buy_fixture();
+        save_bond(
+            "bond_old",
+            &Bond {
+                owner: legacy.public_spending_key_hex.clone(),
+                ..source
+            },
+        );
+        let contract = MockBondContract::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let err = rt.block_on(migrate_keys(&contract, "old")).err().unwrap();
+        assert!(err.to_string().contains("1 unspent notes"), "{}", err);
+        assert_eq!(load_wallet("old").unwrap().keys.kdf_version(), crate::keys::KDF_V1);
+
+        // Once the note is spent the keys move to v2, same seed and viewing key
+        let spent = fr_to_bytes32(&legacy.sign_nullifier(source.salt));
+        contract.nullifiers.lock().unwrap().insert(spent);
+        let backup = rt.block_on(migrate_keys(&contract, "old")).unwrap();
+        let migrated = load_wallet("old").unwrap();
+        assert_eq!(migrated.keys.kdf_version(), CURRENT_KDF_VERSION);
+        assert_ne!(migrated.keys.public_spending_key(), legacy.public_spending_key());
+        assert_eq!(migrated.keys.public_viewing_key(), legacy.public_viewing_key());
+        assert_eq!(migrated.created_at, "2030-01-01T00:00:00Z");
+        let kept: Wallet = serde_json::from_str(&fs::read_to_string(backup).unwrap()).unwrap();
+        assert_eq!(kept.keys.public_spending_key(), legacy.public_spending_key());
+
+        assert!(rt.block_on(migrate_keys(&contract, "old")).err().unwrap().to_string().contains("already"));
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_prune_archives_spent_notes() {
+        let (_guard, dir) = temp_data_dir("prune");
+        let (issuer, _, source, _) = buy_fixture();
+        save_wallet(
+            "issuer",
+            &Wallet {
+                schema_version: WALLET_SCHEMA_VERSION,
+                keys: issuer.clone(),
+                created_at: String::new(),
+            },
+        )
+        .unwrap();
+        let live = Bond {
+            commitment: fr_to_hex(&crate::utils::u64_to_fr(7)),
+            nullifier: fr_to_hex(&issuer.sign_nullifier(43)),
+            salt: 43,
+            value: 2_500,
+            ..source.clone()
+        };
+        save_bond("bond_spent", &source);
+        save_bond("bond_live", &live);
+        let contract = MockBondContract::new();
+        contract
+            .nullifiers
+            .lock()
+            .unwrap()
+            .insert(fr_to_bytes32(&issuer.sign_nullifier(source.salt)));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        // A dry run only reports
+        let report = rt.block_on(prune_spent(&contract, true)).unwrap();
+        assert_eq!(report.archived.len(), 1);
+        assert_eq!(report.kept, 1);
+        assert!(Path::new(&format!("{}/bond_spent.json", dir)).exists());
+
+        let report = rt.block_on(prune_spent(&contract, false)).unwrap();
+        let archived = format!("{}/bond_spent.json", archive_dir());
+        assert_eq!(report.archived, [(format!("{}/bond_spent.json", dir), archived.clone())]);
+        assert!(!Path::new(&format!("{}/bond_spent.json", dir)).exists());
+        assert!(Path::new(&format!("{}/bond_live.json", dir)).exists());
+        assert_eq!(load_bond(&archived).unwrap().commitment, source.commitment);
+
+        // Live scans skip the archive and the index forgets the moved file
+        let wallet = load_wallet("issuer").unwrap();
+        let owned: Vec<String> = owned_bonds(&wallet).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(owned, [format!("{}/bond_live.json", dir)]);
+        let balance = rt.block_on(wallet_balance(&contract, "issuer")).unwrap();
+        assert_eq!(balance.total, 2_500);
+        let history = archived_notes("issuer").unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].path, archived);
+
+        // Nothing left to prune
+        let report = rt.block_on(prune_spent(&contract, false)).unwrap();
+        assert!(report.archived.is_empty());
+        assert_eq!(report.kept, 1);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_diff_lists_the_note_a_backup_lacks() {
+        let (_guard, dir) = temp_data_dir("diff");
+        let backup = format!("{}_backup", dir);
+        let (issuer, _, source, _) = buy_fixture();
+        let note = |salt: u64, value: u64| Bond {
+            commitment: fr_to_hex(&crate::utils::u64_to_fr(salt * 1_000)),
+            nullifier: fr_to_hex(&issuer.sign_nullifier(salt)),
+            salt,
+            value,
+            ..source.clone()
+        };
+        save_bond("bond_a", &note(41, 1_000));
+        save_bond("bond_b", &note(42, 2_000));
+        fs::create_dir_all(&backup).unwrap();
+        for name in ["bond_a.json", "bond_b.json"] {
+            fs::copy(format!("{}/{}", dir, name), format!("{}/{}", backup, name)).unwrap();
+        }
+        assert!(diff_note_sets(&dir, &backup).unwrap().is_empty());
+
+        // The live wallet received one more note after the backup was taken
+        let newer = note(43, 3_000);
+        save_bond("bond_c", &newer);
+        let newer_path = format!("{}/bond_c.json", dir);
+        let diff = diff_note_sets(&dir, &backup).unwrap();
+        assert_eq!(diff.only_here, [NoteSummary::new(&newer_path, &newer)]);
+        assert!(diff.only_there.is_empty() && diff.spent_only_here.is_empty() && diff.spent_only_there.is_empty());
+        let reverse = diff_note_sets(&backup, &dir).unwrap();
+        assert_eq!(reverse.only_there, diff.only_here);
+        assert!(reverse.only_here.is_empty());
+
+        // A spend the backup never saw, logged or by redeeming a file
+        fs::rename(format!("{}/bond_a.json", dir), format!("{}/bond_a_REDEEMED.json", dir)).unwrap();
+        let diff = diff_note_sets(&dir, &backup).unwrap();
+        assert_eq!(diff.spent_only_here, [note(41, 1_000).nullifier.to_lowercase()]);
+        assert_eq!(diff.only_here.len(), 1);
+        assert!(diff_note_sets(&dir, &format!("{}/missing", dir)).is_err());
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&backup);
+    }
+
+    #[test]
+    fn test_select_source_note() {
+        let (_guard, _dir) = temp_data_dir("select_source");
+        let contract = MockBondContract::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let (issuer_keys, _, source, _) = buy_fixture();
+        let issuer = Wallet {
+            schema_version: WALLET_SCHEMA_VERSION,
+            keys: issuer_keys,
+            created_at: String::new(),
+        };
+        let smaller = Bond {
+            commitment: "0x1234".to_string(),
+            value: 500,
+            salt: 43,
+            ..source.clone()
+        };
+        save_bond("tranche", &source);
+        save_bond("smaller", &smaller);
+        let select = |s: SourceNote| rt.block_on(select_source_note(&contract, &issuer, &s));
+
+        // Unique match, by commitment (any formatting) and by largest value
+        let hex = source.commitment.trim_start_matches("0x");
+        let (path, _) = select(SourceNote::Commitment(hex.to_string())).unwrap();
+        assert!(path.ends_with("tranche.json"));
+        let (path, bond) = select(SourceNote::Auto { asset_id: 1 }).unwrap();
+        assert!(path.ends_with("tranche.json"));
+        assert_eq!(bond.value, 10_000);
+
+        // No match: unknown commitment, other asset, or the note already spent
+        assert!(select(SourceNote::Commitment("0x99".to_string())).is_err());
+        assert!(select(SourceNote::Auto { asset_id: 2 }).is_err());
+        let spent = fr_to_bytes32(&issuer.keys.sign_nullifier(43));
+        contract.nullifiers.lock().unwrap().insert(spent);
+        assert!(select(SourceNote::Commitment("0x1234".to_string())).is_err());
+
+        // Ambiguous: a second file with the same commitment and value
+        save_bond("tranche_copy", &source);
+        let err = select(SourceNote::Commitment(hex.to_string())).err().unwrap();
+        assert!(err.contains("Several owned notes"));
+        let err = select(SourceNote::Auto { asset_id: 1 }).err().unwrap();
+        assert!(err.contains("Several owned notes"));
+
+        // No source flag: onboard's tranche file, but only for the issuer that owns it
+        let no_flags = SourceNote::from_args(None, None, false, 1);
+        assert!(select(no_flags).err().unwrap().contains("No tranche note"));
+        source.save(&global_note_path()).unwrap();
+        let (path, bond) = select(SourceNote::from_args(None, None, false, 1)).unwrap();
+        assert_eq!(path, global_note_path());
+        assert_eq!(bond.commitment, source.commitment);
+        let stranger = Wallet {
+            schema_version: WALLET_SCHEMA_VERSION,
+            keys: ShieldedKeys::from_seed([9u8; 32]),
+            created_at: String::new(),
+        };
+        let err = rt.block_on(select_source_note(&contract, &stranger, &SourceNote::Tranche)).err().unwrap();
+        assert!(err.contains("not owned by this issuer"), "{}", err);
+
+        // An explicit path overrides the tranche
+        let smaller_path = format!("{}/smaller.json", data_dir());
+        let explicit = SourceNote::from_args(Some(smaller_path.clone()), None, false, 1);
+        let (path, bond) = select(explicit).unwrap();
+        assert_eq!((path, bond.value), (smaller_path, 500));
+
+        set_data_dir(None);
+    }
+
+    #[test]
+    fn test_select_source_note_offline_uses_the_local_spent_set() {
+        let (_guard, _dir) = temp_data_dir("select_source_offline");
+        let contract = MockBondContract::new();
+        *contract.offline.lock().unwrap() = true;
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let (issuer_keys, _, source, _) = buy_fixture();
+        let issuer = Wallet {
+            schema_version: WALLET_SCHEMA_VERSION,
+            keys: issuer_keys,
+            created_at: String::new(),
+        };
+        let smaller = Bond {
+            commitment: "0x1234".to_string(),
+            value: 500,
+            salt: 43,
+            ..source.clone()
+        };
+        save_bond("tranche", &source);
+        save_bond("smaller", &smaller);
+        let select = |s: SourceNote| rt.block_on(select_source_note(&contract, &issuer, &s));
+
+        // The contract cannot be asked, yet both ways of picking a note still work
+        let (path, _) = select(SourceNote::Commitment(source.commitment.clone())).unwrap();
+        assert!(path.ends_with("tranche.json"));
+        let (path, _) = select(SourceNote::Auto { asset_id: 1 }).unwrap();
+        assert!(path.ends_with("tranche.json"));
+
+        // A nullifier in the synced spent set rules its note out, as the contract would
+        let mut spent = SpentNullifiers::default();
+        spent.extend(&[(1, fr_to_bytes32(&issuer.keys.sign_nullifier(source.salt)))]);
+        spent.save().unwrap();
+        assert!(select(SourceNote::Commitment(source.commitment.clone())).is_err());
+        let (path, bond) = select(SourceNote::Auto { asset_id: 1 }).unwrap();
+        assert!(path.ends_with("smaller.json"));
+        assert_eq!(bond.value, 500);
+
+        set_data_dir(None);
+    }
+
+    #[test]
+    fn test_open_memo_for_recipient_only() {
+        let sender = ShieldedKeys::from_seed([1u8; 32]);
+        let recipient = ShieldedKeys::from_seed([2u8; 32]);
+        let other = ShieldedKeys::from_seed([3u8; 32]);
+        let note = Note {
+            value: 300,
+            salt: 42,
+            owner: 7,
+            asset_id: 1,
+            maturity_date: 1893456000,
+        };
+        let memo = Note::encrypt(&sender, recipient.public_viewing_key(), &note).unwrap();
+        let memo_hex = hex::encode(&memo.ciphertext);
+        let sender_pubkey = format!("0x{}", hex::encode(sender.public_viewing_key()));
+
+        let opened = open_memo(&recipient, &memo_hex, &sender_pubkey).unwrap().unwrap();
+        assert_eq!((opened.value, opened.salt), (300, 42));
+
+        // Wrong recipient: AEAD authentication fails
+        assert!(open_memo(&other, &memo_hex, &sender_pubkey).unwrap().is_none());
+
+        assert!(open_memo(&recipient, "zz", &sender_pubkey).is_err());
+    }
+
+    #[test]
+    fn test_mismatched_memo_is_detected() {
+        use crate::scheme::MemoBound;
+
+        let sender = ShieldedKeys::from_seed([1u8; 32]);
+        let recipient = ShieldedKeys::from_seed([2u8; 32]);
+        let owner = recipient.public_spending_key();
+        let note = CircuitNote::new(300, 42, owner, 1, 1893456000)
+            .unwrap()
+            .bound_to_memo_under(&MemoBound, &sender, recipient.public_viewing_key())
+            .unwrap();
+        let tree_state = TreeState::from_commitments(vec![fr_to_hex(&MemoBound.commit(&note))]);
+        let seal = |note: &Note| Note::encrypt(&sender, recipient.public_viewing_key(), note).unwrap();
+        let matches = |plain: &Note, memo: &notes::Memo| {
+            memo_matches_commitment(&MemoBound, plain, owner, memo, &tree_state)
+        };
+
+        // The memo the note was bound to matches its commitment
+        let honest = Note::describing(&note);
+        assert!(matches(&honest, &seal(&honest)));
+
+        // A memo claiming more value than was committed matches nothing
+        let inflated = Note { value: 3_000, ..honest.clone() };
+        assert!(!matches(&inflated, &seal(&inflated)));
+
+        // Nor does the right plaintext under another ciphertext
+        let mut altered = seal(&honest);
+        altered.ciphertext.push(0);
+        assert!(!matches(&honest, &altered));
+
+        // A commitment made without the memo does not vouch for it
+        let unbound = TreeState::from_commitments(vec![fr_to_hex(&MemoBound.commit(&note.clone().with_memo_hash(None)))]);
+        assert!(!memo_matches_commitment(&MemoBound, &honest, owner, &seal(&honest), &unbound));
+    }
+
+    #[test]
+    fn test_proof_for_other_network_is_rejected() {
+        let dir = std::env::temp_dir().join(format!("wallet_proof_network_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let proof_path = dir.join("proof").to_string_lossy().to_string();
+        fs::write(&proof_path, [1u8; 32]).unwrap();
+
+        let anvil = MockBondContract {
+            network: NetworkBinding {
+                chain_id: 31337,
+                contract: PRIVATE_BOND_ADDRESS.parse().unwrap(),
+            },
+            ..Default::default()
+        };
+        let mainnet = MockBondContract {
+            network: NetworkBinding {
+                chain_id: 1,
+                ..anvil.network
+            },
+            ..Default::default()
+        };
+        bind_proof_network(&proof_path, &anvil.network).unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        assert_eq!(rt.block_on(load_bound_proof(&anvil, &proof_path)).unwrap(), [1u8; 32]);
+        let err = rt.block_on(load_bound_proof(&mainnet, &proof_path)).unwrap_err();
+        assert!(err.contains("refusing to submit"));
+
+        // Nor is one whose metadata shows the wrong public-input count
+        fs::write(dir.join("public_inputs"), [0u8; 4 * 32]).unwrap();
+        ProofMeta::from_bb_output(&proof_path, "0.87.0", 1).unwrap().save(&proof_path).unwrap();
+        let err = rt.block_on(load_bound_proof(&anvil, &proof_path)).unwrap_err();
+        assert!(err.contains("4 public inputs"), "{}", err);
+        fs::write(dir.join("public_inputs"), [0u8; 5 * 32]).unwrap();
+        ProofMeta::from_bb_output(&proof_path, "0.87.0", 1).unwrap().save(&proof_path).unwrap();
+        assert!(rt.block_on(load_bound_proof(&anvil, &proof_path)).is_ok());
+
+        // A proof with no recorded network is not submitted either
+        fs::remove_file(proof_network_path(&proof_path)).unwrap();
+        assert!(rt.block_on(load_bound_proof(&anvil, &proof_path)).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_watch_dry_run_reports_matured_unspent_notes() {
+        let (_guard, dir) = temp_data_dir("watch");
+        register("holder").unwrap();
+        let holder = load_wallet("holder").unwrap();
+
+        let bond = |salt: u64, maturity_date: u64| Bond {
+            schema_version: BOND_SCHEMA_VERSION,
+            commitment: fr_to_hex(&Fr::from_str(&salt.to_string()).unwrap()),
+            nullifier: String::new(),
+            value: 10,
+            salt,
+            owner: holder.keys.public_spending_key_hex.clone(),
+            asset_id: 1,
+            maturity_date,
+            created_at: Utc::now().to_rfc3339(),
+            label: None,
+            memo_hash: None,
+        };
+        let write = |name: &str, b: &Bond| {
+            fs::write(format!("{}/{}", dir, name), serde_json::to_string(b).unwrap()).unwrap()
+        };
+        write("bond_holder_matured.json", &bond(1, 1));
+        write("bond_holder_spent.json", &bond(2, 1));
+        write("bond_holder_future.json", &bond(3, u64::MAX));
+        write("bond_holder_done_REDEEMED.json", &bond(4, 1));
+
+        let contract = MockBondContract::new();
+        contract
+            .nullifiers
+            .lock()
+            .unwrap()
+            .insert(fr_to_bytes32(&holder.keys.sign_nullifier(2)));
+        let prover = MockProver::new(&format!("{}/proof", dir));
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let report = rt.block_on(Watcher::default().poll(&prover, &contract, &PrintSink, "holder", true));
+        assert_eq!(report.due, vec![format!("{}/bond_holder_matured.json", dir)]);
+        assert!(report.redeemed.is_empty());
+        assert!(contract.calls.lock().unwrap().is_empty());
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_watch_backoff_doubles_and_caps() {
+        assert_eq!(
+            (1..=8).map(backoff_polls).collect::<Vec<_>>(),
+            [1, 2, 4, 8, 16, 32, 32, 32]
+        );
+    }
+
+    #[test]
+    fn test_root_status_against_mocked_rpc() {
+        use crate::contract::AlloyBondContract;
+        use alloy::providers::{mock::Asserter, Provider, ProviderBuilder};
+
+        let (_guard, dir) = temp_data_dir("root");
+        let mut tree_state = TreeState::load();
+        tree_state.add_commitment(Fr::from_str("11").unwrap());
+        tree_state.add_commitment(Fr::from_str("22").unwrap());
+        let local = fr_to_bytes32(&tree_state.build_tree().root());
+
+        let asserter = Asserter::new();
+        let provider = ProviderBuilder::new()
+            .connect_mocked_client(asserter.clone())
+            .erased();
+        let contract =
+            AlloyBondContract::from_provider(PRIVATE_BOND_ADDRESS.parse().unwrap(), provider);
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        // buildMerkleRoot() returns a bare bytes32
+        asserter.push_success(&Bytes::from(local.to_vec()));
+        let status = rt.block_on(root_status(&contract)).unwrap();
+        assert_eq!(status.onchain, local);
+        assert_eq!(status.leaves, 2);
+        assert!(status.matches());
+
+        asserter.push_success(&Bytes::from(vec![0xab; 32]));
+        let status = rt.block_on(root_status(&contract)).unwrap();
+        assert_eq!(status.onchain, FixedBytes::from([0xab; 32]));
+        assert!(!status.matches());
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_spent_set_rebuilt_from_nullifier_events() {
+        use crate::contract::{AlloyBondContract, PrivateBond};
+        use alloy::primitives::Log as EventLog;
+        use alloy::providers::{mock::Asserter, Provider, ProviderBuilder};
+        use alloy::rpc::types::Log;
+        use alloy::sol_types::SolEvent;
+
+        let (_guard, dir) = temp_data_dir("spent_events");
+        let address = PRIVATE_BOND_ADDRESS.parse().unwrap();
+        let asserter = Asserter::new();
+        let provider = ProviderBuilder::new()
+            .connect_mocked_client(asserter.clone())
+            .erased();
+        let contract = AlloyBondContract::from_provider(address, provider);
+        // eth_getLogs answers with NullifierSpent logs: topic0 the event, topic1 the nullifier
+        let logs = |events: &[(u64, FixedBytes<32>)]| -> Vec<Log> {
+            events
+                .iter()
+                .map(|&(block, nullifier)| Log {
+                    inner: EventLog {
+                        address,
+                        data: PrivateBond::NullifierSpent { nullifier }.encode_log_data(),
+                    },
+                    block_number: Some(block),
+                    ..Default::default()
+                })
+                .collect()
+        };
+
+        let (issuer, _, source, _) = buy_fixture();
+        let ours = fr_to_bytes32(&issuer.sign_nullifier(source.salt));
+        let other = FixedBytes::from([0x0au8; 32]);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        asserter.push_success(&logs(&[(5, other), (5, ours), (9, FixedBytes::from([0x0bu8; 32]))]));
+        let (set, added) = rt.block_on(sync_nullifier_set(&contract, 0)).unwrap();
+        assert_eq!(added, 3);
+        assert_eq!((set.from_block, set.last_block), (0, 9));
+        let expected: BTreeSet<String> = [other, ours, FixedBytes::from([0x0bu8; 32])]
+            .iter()
+            .map(|n| format!("{}", n))
+            .collect();
+        assert_eq!(set.nullifiers, expected);
+        assert_eq!(SpentNullifiers::load().unwrap(), set);
+
+        // The synced set guards a spend even when the node says nothing is spent
+        let err = rt
+            .block_on(check_source_unspent(&MockBondContract::new(), &issuer, &source))
+            .unwrap_err();
+        assert!(err.contains("spent_nullifiers.json"), "{}", err);
+
+        // A later --from-block adds to the saved set; block 0 rebuilds it
+        let newer = FixedBytes::from([0x0cu8; 32]);
+        asserter.push_success(&logs(&[(12, newer)]));
+        let (set, added) = rt.block_on(sync_nullifier_set(&contract, 10)).unwrap();
+        assert_eq!((added, set.nullifiers.len(), set.from_block, set.last_block), (1, 4, 0, 12));
+        asserter.push_success(&logs(&[(12, newer)]));
+        let (set, _) = rt.block_on(sync_nullifier_set(&contract, 0)).unwrap();
+        assert!(set.contains(&newer) && !set.contains(&ours));
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_offline_mode_never_calls_the_node() {
+        use crate::calldata::{CalldataBundle, Encoding};
+        use crate::contract::{AlloyBondContract, OfflineExport};
+        use alloy::providers::{mock::Asserter, Provider, ProviderBuilder};
+
+        let (_guard, dir) = temp_data_dir("offline");
+
+        // Any RPC request would consume the sentinel response
+        let asserter = Asserter::new();
+        asserter.push_failure_msg("offline mode contacted the node");
+        let provider = ProviderBuilder::new()
+            .connect_mocked_client(asserter.clone())
+            .erased();
+        let contract =
+            AlloyBondContract::from_provider(PRIVATE_BOND_ADDRESS.parse().unwrap(), provider)
+                .offline(OfflineExport {
+                    chain_id: 31337,
+                    encoding: Encoding::Base64,
+                });
+        let prover = MockProver::new(&format!("{}/proof", dir));
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        let issuer = load_wallet("issuer").unwrap();
+        let source = load_bond(&global_note_path()).unwrap();
+        rt.block_on(buy(
+            &prover,
+            &contract,
+            &PrintSink,
+            "buyer",
+            1_000,
+            &SourceNote::Path(global_note_path()),
+            "issuer",
+            None,
+            None,
+        ))
+        .unwrap();
+
+        // Queries that need the chain fail instead of reaching out
+        let err = rt.block_on(root_status(&contract)).unwrap_err();
+        assert!(err.contains("Offline mode"), "{}", err);
+        assert_eq!(asserter.read_q().len(), 1);
+
+        // Local tree advanced as if both calls had been mined
+        assert_eq!(TreeState::load().commitments().len(), 4);
+
+        let files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        assert!(files.iter().any(|f| f.starts_with("calldata_mintBatch_")));
+        let transfer = files.iter().find(|f| f.starts_with("calldata_transfer_")).unwrap();
+        let bundle = CalldataBundle::load(&format!("{}/{}", dir, transfer)).unwrap();
+        assert_eq!(
+            bundle.nullifiers[0],
+            fr_to_bytes32(&issuer.keys.sign_nullifier(source.salt))
+        );
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// Prover that fails every witness with `error`
+    struct BrokenProver(WalletError);
+
+    impl Prover for BrokenProver {
+        async fn prove(&self, _witness: &WitnessBuilder) -> Result<String, WalletError> {
+            Err(self.0.clone())
+        }
+
+        async fn prove_leg(&self, _witness: &WitnessBuilder, _leg: &str) -> Result<String, WalletError> {
+            Err(self.0.clone())
+        }
+    }
+
+    /// Sink that lets someone else spend the note between confirmation and submission
+    struct RacingSink<'a>(&'a MockBondContract);
+
+    impl EventSink for RacingSink<'_> {
+        fn confirm(&self, action: &PendingAction) -> bool {
+            self.0.nullifiers.lock().unwrap().insert(action.nullifier.parse().unwrap());
+            true
+        }
+    }
+
+    #[test]
+    fn test_sub_account_salts_persist_in_the_base_wallet() {
+        let (_guard, dir) = temp_data_dir("account_salts");
+        register("carol").unwrap();
+        let mut stale = load_wallet("carol").unwrap();
+
+        let mut account = load_wallet("carol@2").unwrap();
+        let first = draw_salts("carol@2", &mut account, 2).unwrap();
+        // A fresh load resumes the sequence instead of repeating it
+        let mut account = load_wallet("carol@2").unwrap();
+        assert_eq!(account.keys.salt_counter(), 2);
+        let next = draw_salts("carol@2", &mut account, 1).unwrap();
+        assert!(!first.contains(&next[0]));
+
+        // The base wallet drawing from a copy loaded earlier leaves the account's counter
+        draw_salts("carol", &mut stale, 1).unwrap();
+        assert_eq!(load_wallet("carol").unwrap().keys.salt_counter(), 1);
+        assert_eq!(load_wallet("carol@2").unwrap().keys.salt_counter(), 3);
+        assert_eq!(load_wallet("carol@1").unwrap().keys.salt_counter(), 0);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_failures_map_to_documented_exit_codes() {
+        let (_guard, dir) = temp_data_dir("exit_codes");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        let tranche = SourceNote::Path(global_note_path());
+        let code = |result: Result<(String, Bond), WalletError>| result.unwrap_err().exit_code();
+
+        assert_eq!(
+            code(rt.block_on(buy(&prover, &contract, &PrintSink, "ghost", 1_000, &tranche, "issuer", None, None))),
+            2
+        );
+        assert_eq!(
+            code(rt.block_on(buy(&prover, &contract, &PrintSink, "buyer", 100_000_001, &tranche, "issuer", None, None))),
+            3
+        );
+        let crashed = BrokenProver(WalletError::ProofFailed("nargo execute failed: Cannot satisfy constraint".to_string()));
+        assert_eq!(
+            code(rt.block_on(buy(&crashed, &contract, &PrintSink, "buyer", 1_000, &tranche, "issuer", None, None))),
+            4
+        );
+        let missing = BrokenProver(WalletError::ToolingMissing("Failed to run nargo: not found".to_string()));
+        assert_eq!(
+            code(rt.block_on(buy(&missing, &contract, &PrintSink, "buyer", 1_000, &tranche, "issuer", None, None))),
+            6
+        );
+        let race = RacingSink(&contract);
+        assert_eq!(
+            code(rt.block_on(buy(&prover, &contract, &race, "buyer", 1_000, &tranche, "issuer", None, None))),
+            5
+        );
+        // A message that merely mentions another kind's words keeps its own code
+        let change = rt.block_on(buy(&prover, &contract, &PrintSink, "buyer", 1_000, &tranche, "issuer", Some("not found"), None));
+        assert_eq!(code(change), 1);
+
+        // The commands that only print still report their kind to main
+        assert_eq!(rt.block_on(balance(&contract, "ghost", false)).unwrap_err().exit_code(), 2);
+        assert_eq!(import_note("ghost", "missing.json").unwrap_err().exit_code(), 2);
+        assert_eq!(import_note("buyer", "missing.json").unwrap_err().exit_code(), 1);
+        let traded = rt.block_on(trade(&prover, &contract, &PrintSink, "ghost", "a.json", "buyer", "b.json"));
+        assert_eq!(traded.unwrap_err().exit_code(), 2);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_matured_notes_only_redeem_and_immature_ones_never_do() {
+        let (_guard, dir) = temp_data_dir("maturity_policy");
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let events = RecordingSink::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(onboard(&contract, "issuer", None, false)).unwrap();
+        register("buyer").unwrap();
+        let tranche = SourceNote::Path(global_note_path());
+
+        // The tranche matures in 2030: it can be sold but not redeemed yet
+        let redeemed =
+            rt.block_on(redeem(&prover, &contract, &events, "issuer", &global_note_path(), None, None, false));
+        assert!(redeemed.unwrap_err().to_string().ends_with("days until maturity"));
+
+        // Once matured it can no longer be sold, whichever way it is picked
+        let mut bond = load_bond(&global_note_path()).unwrap();
+        bond.maturity_date = 1;
+        bond.save(&global_note_path()).unwrap();
+        let bought = rt.block_on(buy(&prover, &contract, &events, "buyer", 1_000, &tranche, "issuer", None, None));
+        assert!(bought.unwrap_err().to_string().contains("can only be redeemed"));
+        let auto = SourceNote::Auto { asset_id: 1 };
+        assert!(rt.block_on(buy(&prover, &contract, &events, "buyer", 1_000, &auto, "issuer", None, None)).is_err());
+        let recipients = parse_recipients("buyer,1000").unwrap();
+        let fanned = rt.block_on(buy_fan_out(&prover, &contract, &events, &recipients, &tranche, "issuer", None));
+        assert!(fanned.unwrap_err().to_string().contains("can only be redeemed"));
+
+        // Nothing was proved or sent past the onboarding mint
+        assert!(events.events.lock().unwrap().is_empty());
+        assert_eq!(contract.calls.lock().unwrap().as_slice(), ["mintBatch"]);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_maturity_boundaries() {
+        let maturity = 1893456000;
+        let bond = Bond {
+            schema_version: BOND_SCHEMA_VERSION,
+            commitment: String::new(),
+            nullifier: String::new(),
+            value: 1,
+            salt: 1,
+            owner: String::new(),
+            asset_id: 1,
+            maturity_date: maturity,
+            created_at: String::new(),
+            label: None,
+            memo_hash: None,
+        };
+
+        // One second before maturity: still tradeable, one day left
+        assert!(MaturityPolicy::at(maturity - 1).check_transfer(&bond).is_ok());
+        assert_eq!(
+            MaturityPolicy::at(maturity - 1).check_redeem(&bond).unwrap_err(),
+            "1 days until maturity"
+        );
+        assert_eq!(maturity_label(maturity, maturity - 1), "🟢 1 days remaining");
+
+        // At and after maturity: matured, never an underflowed day count
+        for now in [maturity, maturity + 1] {
+            assert!(MaturityPolicy::at(now).check_transfer(&bond).is_err());
+            assert!(MaturityPolicy::at(now).check_redeem(&bond).is_ok());
+            assert_eq!(maturity_label(maturity, now), "🔴 Matured");
+        }
+        assert_eq!(maturity_status(0, u64::MAX), MaturityStatus::Matured);
+    }
+}
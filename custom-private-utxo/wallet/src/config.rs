@@ -1,6 +1,62 @@
+use crate::scheme::SchemeVersion;
+use crate::utils::{Endianness, TreeBackend};
+
 /// Contract configuration for local Anvil deployment
 pub const PRIVATE_BOND_ADDRESS: &str = "0xdc64a140aa3e981100a9beca4e685f962f0cf6c9";
 pub const RPC_URL: &str = "http://127.0.0.1:8545";
+/// Chain id of the deployment, used to bind proofs when running with `--offline`
+pub const CHAIN_ID: u64 = 31337;
+
+/// Anvil's first dev account, which signs for any wallet that has no key of its
+/// own (`resolve_signer`). Every call it sends shares one public sender, so
+/// tranches minted with it are trivially linked to each other.
+pub const ANVIL_DEV_KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+/// keccak256 of the PrivateBond runtime bytecode per chain id, checked against the
+/// code at `PRIVATE_BOND_ADDRESS` by `--network-check`. Pin a deployment with
+/// `cast keccak $(cast code <address>)`; chains without an entry fail the check.
+pub const EXPECTED_CODE_HASHES: &[(u64, &str)] = &[];
+
+/// Chain reads (nullifier checks) awaiting a response at once; lower it for
+/// rate-limited public RPC providers (`--rpc-max-in-flight`)
+pub const RPC_MAX_IN_FLIGHT: usize = 4;
+
+/// Byte order the deployment's verifier reads field elements in: big-endian to
+/// match Solidity `bytes32`, little-endian for verifiers that encode that way.
+/// Files in the data directory are always big-endian regardless.
+pub const FIELD_ENDIANNESS: Endianness = Endianness::Big;
+
+/// Roots `TreeState` remembers, newest last. The contract accepts every root it has
+/// built (`knownRoots`), so a proof against one of these still verifies after
+/// later inserts; the bound only keeps the local history from growing.
+pub const ROOT_HISTORY_SIZE: usize = 32;
+
+/// Tree that spends read their root and merkle paths from. `Paged` keeps it in
+/// `data/tree_pages.bin` so a deep tree is not rebuilt in memory by every command.
+pub const TREE_BACKEND: TreeBackend = TreeBackend::Memory;
+
+/// Leaves a batched `TreeState` (`begin_batch`) buffers before writing the state file
+/// anyway, so a crash mid-import loses at most this many unsaved inserts
+pub const TREE_BATCH_FLUSH: usize = 4096;
+
+/// Times a command may be rerun against one unfinished attempt (`attempt::Attempt`)
+/// before it stops and asks for the transaction to be checked by hand
+pub const RETRY_BUDGET: u32 = 3;
 
 /// Asset ID for bonds
 pub const ASSET_ID: u64 = 1;
+
+/// Number of input slots the deployed JoinSplit circuit has.
+/// Whole-note spends fill the first with the note and pad the rest with the dummy.
+/// Only 2 can be submitted: the deployed contract takes two nullifiers, so
+/// `redeem` refuses a 1-input circuit (`prover::check_submittable`).
+pub const CIRCUIT_INPUTS: usize = 2;
+
+/// Commitment and nullifier hashing of the deployed circuit version.
+/// `SchemeVersion::MemoBound` is for a circuit whose `note_commit` also takes the
+/// note's memo hash; one that orders or picks note fields differently needs its own
+/// `CommitmentScheme`
+pub const COMMITMENT_SCHEME: SchemeVersion = SchemeVersion::V1;
+
+/// Noir circuit project the real prover runs nargo/bb in
+pub const CIRCUIT_DIR: &str = "../circuits";
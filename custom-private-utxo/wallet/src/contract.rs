@@ -0,0 +1,1387 @@
+//! PrivateBond contract access behind a trait, so commands can run against a mock
+
+use alloy::{
+    contract::{CallBuilder, CallDecoder},
+    network::{Ethereum, Network},
+    primitives::{keccak256, Address, Bytes, FixedBytes, TxHash, U256},
+    providers::{DynProvider, PendingTransactionBuilder, Provider, ProviderBuilder},
+    signers::local::PrivateKeySigner,
+    sol,
+    sol_types::SolCall,
+};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::calldata::{CalldataBundle, Encoding};
+use crate::config::{ANVIL_DEV_KEY, RPC_MAX_IN_FLIGHT};
+use crate::merkle::FixedMerkleTree;
+use crate::utils::{data_dir, fr_from_bytes32, fr_to_bytes32, signer_key_path};
+
+// Contract ABI - loaded from Foundry compilation output
+sol!(
+    #[sol(rpc, ignore_unlinked)]
+    PrivateBond,
+    "../contracts/out/PrivateBond.sol/PrivateBond.json"
+);
+
+/// Why a contract submission did not confirm
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxError {
+    /// The transaction was rejected before it reached the mempool
+    Send(String),
+    /// The node refused the call because the contract reverted it (e.g. a spent
+    /// nullifier or an unknown root)
+    Reverted(String),
+    /// The transaction was sent but its confirmation could not be observed
+    Unconfirmed { tx_hash: TxHash, reason: String },
+    /// The transaction was sent but not mined within the confirmation timeout; it
+    /// may still be mined later
+    Pending { tx_hash: TxHash, waited: Duration },
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::Send(reason) | TxError::Reverted(reason) => write!(f, "{}", reason),
+            TxError::Unconfirmed { tx_hash, reason } => {
+                write!(f, "{:?} sent but not confirmed: {}", tx_hash, reason)
+            }
+            TxError::Pending { tx_hash, waited } => {
+                write!(f, "{:?} sent but not confirmed within {}s", tx_hash, waited.as_secs())
+            }
+        }
+    }
+}
+
+/// Deployment a proof is meant for. The circuit has no domain input, so the
+/// binding is recorded alongside the proof file and checked before submission.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkBinding {
+    pub chain_id: u64,
+    pub contract: Address,
+}
+
+impl fmt::Display for NetworkBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chain {} contract {}", self.chain_id, self.contract)
+    }
+}
+
+/// Sidecar file recording which network a proof was generated for
+pub fn proof_network_path(proof_path: &str) -> String {
+    format!("{}.network.json", proof_path)
+}
+
+/// Record the network next to a freshly generated proof
+pub fn bind_proof_network(proof_path: &str, network: &NetworkBinding) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(network)
+        .map_err(|e| format!("Failed to serialize network binding: {}", e))?;
+    fs::write(proof_network_path(proof_path), json)
+        .map_err(|e| format!("Failed to write network binding: {}", e))
+}
+
+/// Refuse a proof that was generated for a different network (or has no binding)
+pub fn check_proof_network(proof_path: &str, network: &NetworkBinding) -> Result<(), String> {
+    let path = proof_network_path(proof_path);
+    let json = fs::read_to_string(&path)
+        .map_err(|_| format!("Proof {} has no network binding ({})", proof_path, path))?;
+    let bound: NetworkBinding = serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid network binding {}: {}", path, e))?;
+    if &bound != network {
+        return Err(format!(
+            "Proof was generated for {}, refusing to submit to {}",
+            bound, network
+        ));
+    }
+    Ok(())
+}
+
+/// `--network-check`: refuse a contract address with no code, or whose code does not
+/// hash to the keccak256 pinned for the connected chain in `pinned` (chain id, hash)
+pub async fn check_contract_code(
+    contract: &impl BondContract,
+    pinned: &[(u64, &str)],
+) -> Result<(), String> {
+    let network = contract.network().await.map_err(|e| e.to_string())?;
+    let expected = pinned
+        .iter()
+        .find(|(chain_id, _)| *chain_id == network.chain_id)
+        .ok_or_else(|| format!("No bytecode hash pinned for chain {} in config", network.chain_id))?
+        .1;
+    let expected: FixedBytes<32> = expected
+        .parse()
+        .map_err(|e| format!("Invalid pinned bytecode hash {}: {}", expected, e))?;
+    let code = contract.code().await.map_err(|e| e.to_string())?;
+    if code.is_empty() {
+        return Err(format!("No contract code at {}", network));
+    }
+    let actual = keccak256(&code);
+    if actual != expected {
+        return Err(format!(
+            "Contract code at {} hashes to {}, expected {}; refusing to submit to it",
+            network, actual, expected
+        ));
+    }
+    Ok(())
+}
+
+/// State-changing PrivateBond calls used by the wallet commands
+pub trait BondContract {
+    /// Chain and address submissions go to
+    fn network(&self) -> impl Future<Output = Result<NetworkBinding, TxError>> + Send;
+
+    /// Runtime bytecode at the contract address (empty if nothing is deployed there)
+    fn code(&self) -> impl Future<Output = Result<Bytes, TxError>> + Send;
+
+    /// Root the contract currently builds from its commitments
+    fn current_root(&self) -> impl Future<Output = Result<FixedBytes<32>, TxError>> + Send;
+
+    /// Whether the contract has built `root` at some point (`knownRoots`), so a
+    /// proof against it still verifies
+    fn root_known(&self, root: FixedBytes<32>) -> impl Future<Output = Result<bool, TxError>> + Send;
+
+    /// Whether a nullifier has already been spent on-chain
+    fn nullifier_spent(
+        &self,
+        nullifier: FixedBytes<32>,
+    ) -> impl Future<Output = Result<bool, TxError>> + Send;
+
+    /// `nullifier_spent` for many notes at once, in order
+    fn nullifiers_spent(
+        &self,
+        nullifiers: Vec<FixedBytes<32>>,
+    ) -> impl Future<Output = Result<Vec<bool>, TxError>> + Send;
+
+    /// Commitment the contract stored at leaf `index` (an error past the end of the list);
+    /// `None` in offline mode, where calls are only exported and nothing is on-chain yet
+    fn commitment_at(
+        &self,
+        index: u64,
+    ) -> impl Future<Output = Result<Option<FixedBytes<32>>, TxError>> + Send;
+
+    /// `NullifierSpent` events from `from_block` on, as (block, nullifier) in log order
+    fn spend_events(
+        &self,
+        from_block: u64,
+    ) -> impl Future<Output = Result<Vec<(u64, FixedBytes<32>)>, TxError>> + Send;
+
+    fn mint_batch(
+        &self,
+        commitments: Vec<FixedBytes<32>>,
+    ) -> impl Future<Output = Result<TxHash, TxError>> + Send;
+
+    fn transfer(
+        &self,
+        proof: Bytes,
+        root: FixedBytes<32>,
+        nullifiers: [FixedBytes<32>; 2],
+        commitments: [FixedBytes<32>; 2],
+    ) -> impl Future<Output = Result<TxHash, TxError>> + Send;
+
+    fn burn(
+        &self,
+        proof: Bytes,
+        root: FixedBytes<32>,
+        nullifiers: [FixedBytes<32>; 2],
+        commitments: [FixedBytes<32>; 2],
+        maturity_date: FixedBytes<32>,
+        is_redeem: FixedBytes<32>,
+    ) -> impl Future<Output = Result<TxHash, TxError>> + Send;
+
+    fn atomic_swap(
+        &self,
+        proof_a: Bytes,
+        public_inputs_a: Vec<FixedBytes<32>>,
+        proof_b: Bytes,
+        public_inputs_b: Vec<FixedBytes<32>>,
+    ) -> impl Future<Output = Result<TxHash, TxError>> + Send;
+}
+
+/// EIP-1559 fee caps in wei; any cap left unset is estimated from the provider
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FeeConfig {
+    pub max_fee_per_gas: Option<u128>,
+    pub max_priority_fee_per_gas: Option<u128>,
+}
+
+impl FeeConfig {
+    /// Caps for a same-nonce replacement: both raised by 12.5%, above the 10% bump
+    /// nodes require before they accept a replacement into the mempool
+    pub fn bumped(&self) -> FeeConfig {
+        let bump = |fee: u128| fee + fee / 8 + 1;
+        FeeConfig {
+            max_fee_per_gas: self.max_fee_per_gas.map(bump),
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas.map(bump),
+        }
+    }
+}
+
+/// `--replace-by-fee` policy: re-send under the same nonce with bumped fees while unconfirmed
+#[derive(Debug, Clone, Copy)]
+pub struct Replacement {
+    /// How long an attempt may stay unmined before it is replaced
+    pub after: Duration,
+    /// Interval between receipt checks
+    pub poll: Duration,
+    /// Total attempts, the first submission included
+    pub max_attempts: u32,
+}
+
+/// One logical transaction that can be re-sent under a fixed nonce
+pub trait Resubmittable {
+    /// Next nonce of the sending account, shared by every attempt
+    fn nonce(&self) -> impl Future<Output = Result<u64, TxError>> + Send;
+
+    fn send(
+        &self,
+        nonce: u64,
+        fees: FeeConfig,
+    ) -> impl Future<Output = Result<TxHash, TxError>> + Send;
+
+    /// Whether a receipt exists for this attempt
+    fn mined(&self, tx_hash: TxHash) -> impl Future<Output = Result<bool, TxError>> + Send;
+}
+
+/// Submit `tx`, replacing it with higher fees each time `policy.after` passes without
+/// a receipt. Earlier attempts stay candidates, since any one of them may be mined;
+/// the first mined hash is returned once and no further attempt is sent.
+pub async fn submit_replacing(
+    tx: &impl Resubmittable,
+    fees: FeeConfig,
+    policy: Replacement,
+) -> Result<TxHash, TxError> {
+    if fees.max_fee_per_gas.is_none() || fees.max_priority_fee_per_gas.is_none() {
+        return Err(TxError::Send(
+            "Replace-by-fee needs both fee caps (set them or let estimation fill them)".to_string(),
+        ));
+    }
+    let nonce = tx.nonce().await?;
+    let mut fees = fees;
+    let mut sent: Vec<TxHash> = Vec::new();
+    for attempt in 1..=policy.max_attempts {
+        match tx.send(nonce, fees).await {
+            Ok(tx_hash) => {
+                println!("   📤 Attempt {} (nonce {}): {:?}", attempt, nonce, tx_hash);
+                sent.push(tx_hash);
+            }
+            // Typically "nonce too low": an earlier attempt was mined meanwhile
+            Err(e) if !sent.is_empty() => println!("   ⚠️  Replacement rejected: {}", e),
+            Err(e) => return Err(e),
+        }
+
+        let deadline = tokio::time::Instant::now() + policy.after;
+        loop {
+            for tx_hash in &sent {
+                if tx.mined(*tx_hash).await? {
+                    return Ok(*tx_hash);
+                }
+            }
+            if tokio::time::Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(policy.poll).await;
+        }
+
+        fees = fees.bumped();
+        if attempt < policy.max_attempts {
+            println!(
+                "   ⏫ Not mined after {}s, replacing with maxFeePerGas={} wei",
+                policy.after.as_secs(),
+                fees.max_fee_per_gas.unwrap_or_default()
+            );
+        }
+    }
+    Err(TxError::Unconfirmed {
+        tx_hash: *sent.last().expect("first attempt was sent"),
+        reason: format!("not mined after {} attempts", policy.max_attempts),
+    })
+}
+
+/// Bounds on bursts of chain reads, so commands that check many notes get through
+/// rate-limited providers
+#[derive(Debug, Clone, Copy)]
+pub struct RpcLimit {
+    /// Requests awaiting a response at once
+    pub max_in_flight: usize,
+    /// Minimum spacing between request starts
+    pub min_interval: Duration,
+    /// Retries of a throttled request, waiting twice as long before each
+    pub max_retries: u32,
+}
+
+impl Default for RpcLimit {
+    fn default() -> Self {
+        RpcLimit {
+            max_in_flight: RPC_MAX_IN_FLIGHT,
+            min_interval: Duration::ZERO,
+            max_retries: 5,
+        }
+    }
+}
+
+/// Whether the node turned a request away for exceeding its rate limit
+fn is_throttled(e: &TxError) -> bool {
+    let reason = e.to_string().to_lowercase();
+    reason.contains("429") || reason.contains("too many requests") || reason.contains("rate limit")
+}
+
+/// Run `fetch` for every item under `limit`: at most `max_in_flight` at once, started
+/// at least `min_interval` apart, throttled ones retried with backoff from 250ms.
+/// Results are in the order of `items`; `progress(done, total)` follows completions.
+pub async fn fetch_limited<T, R, Fut>(
+    items: Vec<T>,
+    limit: RpcLimit,
+    mut progress: impl FnMut(usize, usize),
+    fetch: impl Fn(T) -> Fut,
+) -> Vec<Result<R, TxError>>
+where
+    T: Clone,
+    Fut: Future<Output = Result<R, TxError>>,
+{
+    let total = items.len();
+    let next_start = tokio::sync::Mutex::new(tokio::time::Instant::now());
+    let (fetch, next_start) = (&fetch, &next_start);
+    let mut pending = stream::iter(items.into_iter().enumerate().map(|(i, item)| async move {
+        let mut backoff = Duration::from_millis(250);
+        let mut retries = 0;
+        loop {
+            {
+                let mut next = next_start.lock().await;
+                tokio::time::sleep_until(*next).await;
+                *next = tokio::time::Instant::now() + limit.min_interval;
+            }
+            match fetch(item.clone()).await {
+                Err(e) if is_throttled(&e) && retries < limit.max_retries => {
+                    retries += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return (i, result),
+            }
+        }
+    }))
+    .buffer_unordered(limit.max_in_flight.max(1));
+
+    let mut results: Vec<Option<Result<R, TxError>>> = (0..total).map(|_| None).collect();
+    let mut done = 0;
+    while let Some((i, result)) = pending.next().await {
+        results[i] = Some(result);
+        done += 1;
+        progress(done, total);
+    }
+    results.into_iter().map(|r| r.expect("every item is fetched")).collect()
+}
+
+/// A prepared contract call re-sent through the provider
+struct AlloyResubmit<'a, D> {
+    call: CallBuilder<&'a DynProvider, D>,
+    from: Address,
+}
+
+impl<D: CallDecoder + Clone + Send + Sync> Resubmittable for AlloyResubmit<'_, D> {
+    async fn nonce(&self) -> Result<u64, TxError> {
+        self.call
+            .provider
+            .get_transaction_count(self.from)
+            .pending()
+            .await
+            .map_err(|e| TxError::Send(format!("Failed to query nonce: {}", e)))
+    }
+
+    async fn send(&self, nonce: u64, fees: FeeConfig) -> Result<TxHash, TxError> {
+        apply_fees(self.call.clone(), fees)
+            .nonce(nonce)
+            .send()
+            .await
+            .map(|pending| *pending.tx_hash())
+            .map_err(send_error)
+    }
+
+    async fn mined(&self, tx_hash: TxHash) -> Result<bool, TxError> {
+        self.call
+            .provider
+            .get_transaction_receipt(tx_hash)
+            .await
+            .map(|receipt| receipt.is_some())
+            .map_err(|e| TxError::Send(format!("Failed to query receipt: {}", e)))
+    }
+}
+
+/// Set whichever fee caps are known on a contract call
+pub fn apply_fees<P: Provider<N>, D: CallDecoder, N: Network>(
+    mut call: CallBuilder<P, D, N>,
+    fees: FeeConfig,
+) -> CallBuilder<P, D, N> {
+    if let Some(max_fee) = fees.max_fee_per_gas {
+        call = call.max_fee_per_gas(max_fee);
+    }
+    if let Some(priority_fee) = fees.max_priority_fee_per_gas {
+        call = call.max_priority_fee_per_gas(priority_fee);
+    }
+    call
+}
+
+/// Offline mode: nothing is sent to the node. `mintBatch`, `transfer` and `burn`
+/// are written to the data directory for submission from an online machine,
+/// and calls that need chain state fail.
+#[derive(Debug, Clone, Copy)]
+pub struct OfflineExport {
+    /// Chain the exported calls are meant for; proofs are bound to it
+    pub chain_id: u64,
+    pub encoding: Encoding,
+}
+
+/// `mintBatch` as written in offline mode: ready-to-send calldata
+#[derive(Serialize)]
+struct ExportedCall {
+    method: &'static str,
+    chain_id: u64,
+    to: Address,
+    encoding: Encoding,
+    calldata: String,
+}
+
+/// ABI-encoded `mintBatch(commitments)`
+pub fn mint_batch_calldata(commitments: Vec<FixedBytes<32>>) -> Vec<u8> {
+    PrivateBond::mintBatchCall {
+        _commitments: commitments,
+    }
+    .abi_encode()
+}
+
+/// The commitments a `mintBatch` calldata mints
+pub fn decode_mint_batch(calldata: &[u8]) -> Result<Vec<FixedBytes<32>>, String> {
+    PrivateBond::mintBatchCall::abi_decode(calldata)
+        .map(|call| call._commitments)
+        .map_err(|e| format!("Not mintBatch calldata: {}", e))
+}
+
+/// ABI-encoded `transfer` or `burn` call with a bundle's arguments
+pub fn bundle_calldata(bundle: &CalldataBundle) -> Result<Vec<u8>, String> {
+    match (bundle.method.as_str(), bundle.maturity_date, bundle.is_redeem) {
+        ("transfer", None, None) => Ok(PrivateBond::transferCall {
+            proof: bundle.proof.clone(),
+            root: bundle.root,
+            nullifiersIn: bundle.nullifiers,
+            commitmentsOut: bundle.commitments,
+        }
+        .abi_encode()),
+        ("burn", Some(maturity_date), Some(is_redeem)) => Ok(PrivateBond::burnCall {
+            proof: bundle.proof.clone(),
+            root: bundle.root,
+            nullifiersIn: bundle.nullifiers,
+            commitmentsOut: bundle.commitments,
+            inputMaturityDate: maturity_date,
+            isRedeem: is_redeem,
+        }
+        .abi_encode()),
+        ("transfer", _, _) => Err("transfer takes no maturity date or redeem flag".to_string()),
+        ("burn", _, _) => Err("burn needs a maturity date and a redeem flag".to_string()),
+        (method, _, _) => Err(format!("No calldata for method '{}' (expected transfer or burn)", method)),
+    }
+}
+
+/// The bundle a `transfer` or `burn` calldata carries
+pub fn decode_bundle_calldata(calldata: &[u8]) -> Result<CalldataBundle, String> {
+    if let Ok(call) = PrivateBond::transferCall::abi_decode(calldata) {
+        return Ok(CalldataBundle {
+            method: "transfer".to_string(),
+            proof: call.proof,
+            root: call.root,
+            nullifiers: call.nullifiersIn,
+            commitments: call.commitmentsOut,
+            maturity_date: None,
+            is_redeem: None,
+        });
+    }
+    PrivateBond::burnCall::abi_decode(calldata)
+        .map(|call| CalldataBundle {
+            method: "burn".to_string(),
+            proof: call.proof,
+            root: call.root,
+            nullifiers: call.nullifiersIn,
+            commitments: call.commitmentsOut,
+            maturity_date: Some(call.inputMaturityDate),
+            is_redeem: Some(call.isRedeem),
+        })
+        .map_err(|e| format!("Not transfer or burn calldata: {}", e))
+}
+
+fn offline_error(what: &str) -> TxError {
+    TxError::Send(format!(
+        "Offline mode: cannot {} without network access; run this on an online machine",
+        what
+    ))
+}
+
+/// Where the key that sends a wallet's contract calls came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignerSource {
+    /// `--signer-key-file`
+    KeyFile(String),
+    /// The wallet's own `signer_key_path`
+    Wallet(String),
+    /// `ANVIL_DEV_KEY`, shared by every wallet without a key
+    AnvilDefault,
+}
+
+impl fmt::Display for SignerSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignerSource::KeyFile(path) => write!(f, "key file {}", path),
+            SignerSource::Wallet(path) => write!(f, "wallet key {}", path),
+            SignerSource::AnvilDefault => write!(f, "Anvil's shared dev account"),
+        }
+    }
+}
+
+fn read_signer_key(path: &str) -> Result<PrivateKeySigner, String> {
+    let key = fs::read_to_string(path).map_err(|e| format!("Failed to read signer key {}: {}", path, e))?;
+    key.trim()
+        .parse()
+        .map_err(|e| format!("Invalid signer key in {}: {}", path, e))
+}
+
+/// Key that signs `wallet_name`'s contract calls: `key_file` if given, else the
+/// wallet's own `signer_key_path` if it has one, else Anvil's first account. An
+/// issuer with its own key mints from its own address rather than the one every
+/// other issuer shares; one that wants no sender tied to it at all exports the
+/// call with `prepare-mint` for a relayer to send.
+pub fn resolve_signer(key_file: Option<&str>, wallet_name: &str) -> Result<(PrivateKeySigner, SignerSource), String> {
+    if let Some(path) = key_file {
+        return Ok((read_signer_key(path)?, SignerSource::KeyFile(path.to_string())));
+    }
+    let path = signer_key_path(wallet_name);
+    if fs::metadata(&path).is_ok() {
+        return Ok((read_signer_key(&path)?, SignerSource::Wallet(path)));
+    }
+    Ok((ANVIL_DEV_KEY.parse().expect("valid private key"), SignerSource::AnvilDefault))
+}
+
+/// PrivateBond deployment reached over JSON-RPC with a local signer
+pub struct AlloyBondContract {
+    contract: PrivateBond::PrivateBondInstance<DynProvider>,
+    fees: FeeConfig,
+    offline: Option<OfflineExport>,
+    /// Signing account, needed to look up the nonce for replacements
+    from: Option<Address>,
+    replacement: Option<Replacement>,
+    rpc_limit: RpcLimit,
+    /// How long to watch a sent transaction before reporting it pending
+    confirm_timeout: Duration,
+}
+
+/// Default `--confirm-timeout`
+pub const DEFAULT_CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+
+impl AlloyBondContract {
+    pub async fn connect(
+        rpc_url: &str,
+        address: Address,
+        signer: PrivateKeySigner,
+    ) -> Result<Self, String> {
+        let from = signer.address();
+        let provider = ProviderBuilder::new()
+            .wallet(signer)
+            .connect(rpc_url)
+            .await
+            .map_err(|e| format!("Failed to configure provider: {}", e))?
+            .erased();
+        Ok(AlloyBondContract {
+            contract: PrivateBond::new(address, provider),
+            fees: FeeConfig::default(),
+            offline: None,
+            from: Some(from),
+            replacement: None,
+            rpc_limit: RpcLimit::default(),
+            confirm_timeout: DEFAULT_CONFIRM_TIMEOUT,
+        })
+    }
+
+    /// Wrap an already configured provider (e.g. a mocked one in tests)
+    pub fn from_provider(address: Address, provider: DynProvider) -> Self {
+        AlloyBondContract {
+            contract: PrivateBond::new(address, provider),
+            fees: FeeConfig::default(),
+            offline: None,
+            from: None,
+            replacement: None,
+            rpc_limit: RpcLimit::default(),
+            confirm_timeout: DEFAULT_CONFIRM_TIMEOUT,
+        }
+    }
+
+    /// Use explicit fee caps instead of relying purely on estimation
+    pub fn with_fees(mut self, fees: FeeConfig) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Bound the chain reads of many-note commands, see [`fetch_limited`]
+    pub fn with_rpc_limit(mut self, rpc_limit: RpcLimit) -> Self {
+        self.rpc_limit = rpc_limit;
+        self
+    }
+
+    /// Give up watching a sent transaction after `timeout`, see [`confirm`]
+    pub fn with_confirm_timeout(mut self, timeout: Duration) -> Self {
+        self.confirm_timeout = timeout;
+        self
+    }
+
+    /// Replace submissions that stay unmined, see [`submit_replacing`]
+    pub fn with_replacement(mut self, replacement: Replacement) -> Self {
+        self.replacement = Some(replacement);
+        self
+    }
+
+    /// Send a state-changing call and wait for it to be mined
+    async fn submit<D: CallDecoder + Clone + Send + Sync>(
+        &self,
+        call: CallBuilder<&DynProvider, D>,
+    ) -> Result<TxHash, TxError> {
+        let fees = self.effective_fees().await;
+        match (self.replacement, self.from) {
+            (None, _) => confirm(apply_fees(call, fees).send().await, self.confirm_timeout).await,
+            (Some(policy), Some(from)) => {
+                submit_replacing(&AlloyResubmit { call, from }, fees, policy).await
+            }
+            (Some(_), None) => Err(TxError::Send(
+                "Replace-by-fee needs the signing account".to_string(),
+            )),
+        }
+    }
+
+    /// Never contact the node; export state-changing calls instead
+    pub fn offline(mut self, export: OfflineExport) -> Self {
+        self.offline = Some(export);
+        self
+    }
+
+    /// Write an exported call to `data/calldata_<method>_<id>.json`. The returned
+    /// hash is keccak256 of the ABI calldata: an identifier, not a transaction hash.
+    fn export(
+        &self,
+        method: &str,
+        calldata: &[u8],
+        json: Result<String, String>,
+    ) -> Result<TxHash, TxError> {
+        let id = keccak256(calldata);
+        let path = format!(
+            "{}/calldata_{}_{}.json",
+            data_dir(),
+            method,
+            hex::encode(&id[..8])
+        );
+        let json = json.map_err(TxError::Send)?;
+        fs::write(&path, json)
+            .map_err(|e| TxError::Send(format!("Failed to write {}: {}", path, e)))?;
+        println!("   📦 Offline: {}() written to {}, not submitted", method, path);
+        Ok(id)
+    }
+
+    /// Fill unset fee caps from the provider's estimate and print what will be used
+    async fn effective_fees(&self) -> FeeConfig {
+        let mut fees = self.fees;
+        if fees.max_fee_per_gas.is_none() || fees.max_priority_fee_per_gas.is_none() {
+            match self.contract.provider().estimate_eip1559_fees().await {
+                Ok(estimate) => {
+                    fees.max_fee_per_gas.get_or_insert(estimate.max_fee_per_gas);
+                    fees.max_priority_fee_per_gas
+                        .get_or_insert(estimate.max_priority_fee_per_gas);
+                }
+                Err(e) => println!("   ⚠️  Fee estimation failed: {}", e),
+            }
+        }
+        match (fees.max_fee_per_gas, fees.max_priority_fee_per_gas) {
+            (Some(max_fee), Some(priority_fee)) => println!(
+                "   ⛽ maxFeePerGas={} wei, maxPriorityFeePerGas={} wei",
+                max_fee, priority_fee
+            ),
+            _ => println!("   ⛽ Fees left to the provider's defaults"),
+        }
+        fees
+    }
+}
+
+/// Classify a failed send from the node's JSON-RPC error: code 3 is geth's
+/// "execution reverted" with revert data, and a revert without data is reported
+/// with that message and the generic server-error code
+fn send_error(e: alloy::contract::Error) -> TxError {
+    let reverted = match &e {
+        alloy::contract::Error::TransportError(rpc) => rpc
+            .as_error_resp()
+            .is_some_and(|resp| resp.code == 3 || resp.message.starts_with("execution reverted")),
+        _ => false,
+    };
+    if reverted {
+        TxError::Reverted(e.to_string())
+    } else {
+        TxError::Send(e.to_string())
+    }
+}
+
+/// Wait up to `timeout` for a sent transaction to be mined. A node that never mines
+/// it (e.g. underpriced) yields `TxError::Pending` with the hash rather than a hang.
+async fn confirm(
+    sent: Result<PendingTransactionBuilder<Ethereum>, alloy::contract::Error>,
+    timeout: Duration,
+) -> Result<TxHash, TxError> {
+    let pending = sent.map_err(send_error)?;
+    let tx_hash = *pending.tx_hash();
+    match tokio::time::timeout(timeout, pending.watch()).await {
+        Ok(watched) => watched.map_err(|e| TxError::Unconfirmed {
+            tx_hash,
+            reason: e.to_string(),
+        }),
+        Err(_) => Err(TxError::Pending {
+            tx_hash,
+            waited: timeout,
+        }),
+    }
+}
+
+impl BondContract for AlloyBondContract {
+    async fn network(&self) -> Result<NetworkBinding, TxError> {
+        if let Some(offline) = self.offline {
+            return Ok(NetworkBinding {
+                chain_id: offline.chain_id,
+                contract: *self.contract.address(),
+            });
+        }
+        let chain_id = self
+            .contract
+            .provider()
+            .get_chain_id()
+            .await
+            .map_err(|e| TxError::Send(format!("Failed to query chain id: {}", e)))?;
+        Ok(NetworkBinding {
+            chain_id,
+            contract: *self.contract.address(),
+        })
+    }
+
+    async fn code(&self) -> Result<Bytes, TxError> {
+        if self.offline.is_some() {
+            return Err(offline_error("fetch the contract code"));
+        }
+        self.contract
+            .provider()
+            .get_code_at(*self.contract.address())
+            .await
+            .map_err(|e| TxError::Send(format!("Failed to fetch contract code: {}", e)))
+    }
+
+    async fn current_root(&self) -> Result<FixedBytes<32>, TxError> {
+        if self.offline.is_some() {
+            return Err(offline_error("query the contract root"));
+        }
+        self.contract
+            .buildMerkleRoot()
+            .call()
+            .await
+            .map_err(|e| TxError::Send(format!("Failed to query root: {}", e)))
+    }
+
+    async fn root_known(&self, root: FixedBytes<32>) -> Result<bool, TxError> {
+        if self.offline.is_some() {
+            return Err(offline_error("query the contract's known roots"));
+        }
+        self.contract
+            .knownRoots(root)
+            .call()
+            .await
+            .map_err(|e| TxError::Send(format!("Failed to query known roots: {}", e)))
+    }
+
+    async fn nullifier_spent(&self, nullifier: FixedBytes<32>) -> Result<bool, TxError> {
+        if self.offline.is_some() {
+            return Err(offline_error("check whether a note is spent"));
+        }
+        self.contract
+            .nullifiers(nullifier)
+            .call()
+            .await
+            .map_err(|e| TxError::Send(format!("Failed to query nullifier: {}", e)))
+    }
+
+    async fn nullifiers_spent(&self, nullifiers: Vec<FixedBytes<32>>) -> Result<Vec<bool>, TxError> {
+        if self.offline.is_some() {
+            return Err(offline_error("check whether notes are spent"));
+        }
+        let report = |done: usize, total: usize| {
+            if total >= 10 && (done.is_multiple_of(10) || done == total) {
+                println!("   📡 Checked {} of {} nullifiers", done, total);
+            }
+        };
+        fetch_limited(nullifiers, self.rpc_limit, report, |n| self.nullifier_spent(n))
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    async fn commitment_at(&self, index: u64) -> Result<Option<FixedBytes<32>>, TxError> {
+        if self.offline.is_some() {
+            return Ok(None);
+        }
+        self.contract
+            .commitments(U256::from(index))
+            .call()
+            .await
+            .map(Some)
+            .map_err(|e| TxError::Send(format!("Failed to read commitment {}: {}", index, e)))
+    }
+
+    async fn spend_events(&self, from_block: u64) -> Result<Vec<(u64, FixedBytes<32>)>, TxError> {
+        if self.offline.is_some() {
+            return Err(offline_error("read spend events"));
+        }
+        let logs = self
+            .contract
+            .NullifierSpent_filter()
+            .from_block(from_block)
+            .query()
+            .await
+            .map_err(|e| TxError::Send(format!("Failed to read NullifierSpent events: {}", e)))?;
+        Ok(logs
+            .into_iter()
+            .map(|(event, log)| (log.block_number.unwrap_or(from_block), event.nullifier))
+            .collect())
+    }
+
+    async fn mint_batch(&self, commitments: Vec<FixedBytes<32>>) -> Result<TxHash, TxError> {
+        if let Some(offline) = self.offline {
+            let calldata = mint_batch_calldata(commitments);
+            let call = ExportedCall {
+                method: "mintBatch",
+                chain_id: offline.chain_id,
+                to: *self.contract.address(),
+                encoding: offline.encoding,
+                calldata: offline.encoding.encode(&calldata),
+            };
+            let json = serde_json::to_string_pretty(&call).map_err(|e| e.to_string());
+            return self.export("mintBatch", &calldata, json);
+        }
+        self.submit(self.contract.mintBatch(commitments)).await
+    }
+
+    async fn transfer(
+        &self,
+        proof: Bytes,
+        root: FixedBytes<32>,
+        nullifiers: [FixedBytes<32>; 2],
+        commitments: [FixedBytes<32>; 2],
+    ) -> Result<TxHash, TxError> {
+        if let Some(offline) = self.offline {
+            let bundle = CalldataBundle {
+                method: "transfer".to_string(),
+                proof,
+                root,
+                nullifiers,
+                commitments,
+                maturity_date: None,
+                is_redeem: None,
+            };
+            let calldata = bundle_calldata(&bundle).map_err(TxError::Send)?;
+            return self.export("transfer", &calldata, bundle.to_json(offline.encoding));
+        }
+        self.submit(self.contract.transfer(proof, root, nullifiers, commitments))
+            .await
+    }
+
+    async fn burn(
+        &self,
+        proof: Bytes,
+        root: FixedBytes<32>,
+        nullifiers: [FixedBytes<32>; 2],
+        commitments: [FixedBytes<32>; 2],
+        maturity_date: FixedBytes<32>,
+        is_redeem: FixedBytes<32>,
+    ) -> Result<TxHash, TxError> {
+        if let Some(offline) = self.offline {
+            let bundle = CalldataBundle {
+                method: "burn".to_string(),
+                proof,
+                root,
+                nullifiers,
+                commitments,
+                maturity_date: Some(maturity_date),
+                is_redeem: Some(is_redeem),
+            };
+            let calldata = bundle_calldata(&bundle).map_err(TxError::Send)?;
+            return self.export("burn", &calldata, bundle.to_json(offline.encoding));
+        }
+        self.submit(self.contract.burn(
+            proof,
+            root,
+            nullifiers,
+            commitments,
+            maturity_date,
+            is_redeem,
+        ))
+        .await
+    }
+
+    async fn atomic_swap(
+        &self,
+        proof_a: Bytes,
+        public_inputs_a: Vec<FixedBytes<32>>,
+        proof_b: Bytes,
+        public_inputs_b: Vec<FixedBytes<32>>,
+    ) -> Result<TxHash, TxError> {
+        if self.offline.is_some() {
+            return Err(offline_error("submit atomicSwap"));
+        }
+        self.submit(
+            self.contract
+                .atomicSwap(proof_a, public_inputs_a, proof_b, public_inputs_b),
+        )
+        .await
+    }
+}
+
+/// In-memory stand-in for the contract: records commitments and spent nullifiers
+/// and enforces the same double-spend checks. Proofs are not verified.
+#[derive(Default)]
+pub struct MockBondContract {
+    pub network: NetworkBinding,
+    pub commitments: Mutex<Vec<FixedBytes<32>>>,
+    pub nullifiers: Mutex<HashSet<FixedBytes<32>>>,
+    /// `NullifierSpent` events emitted, with the call number standing in for the block
+    pub spend_log: Mutex<Vec<(u64, FixedBytes<32>)>>,
+    /// Public inputs of each transfer/burn, in the order the contract hands them to the verifier
+    pub public_inputs: Mutex<Vec<Vec<FixedBytes<32>>>>,
+    /// Names of the calls made, in order
+    pub calls: Mutex<Vec<String>>,
+    /// Root to report; built from `commitments` when unset
+    pub root: Mutex<Option<FixedBytes<32>>>,
+    /// Confirm `mintBatch` without storing anything, like a contract that drops commitments
+    pub drop_mints: Mutex<bool>,
+    /// Runtime bytecode reported at the address
+    pub code: Mutex<Bytes>,
+    /// Apply transfers but report them unconfirmed, like a connection that drops
+    /// after the transaction was sent
+    pub drop_receipts: Mutex<bool>,
+    /// Refuse chain reads the way `AlloyBondContract` does in offline mode
+    pub offline: Mutex<bool>,
+    /// Revert the n-th `transfer` call (counting from 1) before it changes anything
+    pub reject_transfer: Mutex<Option<usize>>,
+}
+
+impl MockBondContract {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_spent(&self, nullifier: &FixedBytes<32>) -> bool {
+        self.nullifiers.lock().unwrap().contains(nullifier)
+    }
+
+    fn check_online(&self, what: &str) -> Result<(), TxError> {
+        match *self.offline.lock().unwrap() {
+            true => Err(offline_error(what)),
+            false => Ok(()),
+        }
+    }
+
+    /// Deterministic fake hash for the n-th call
+    fn record(&self, call: &str) -> TxHash {
+        let mut calls = self.calls.lock().unwrap();
+        calls.push(call.to_string());
+        keccak256(format!("{}:{}", calls.len(), call))
+    }
+
+    fn record_public_inputs(
+        &self,
+        root: FixedBytes<32>,
+        nullifiers: [FixedBytes<32>; 2],
+        commitments: [FixedBytes<32>; 2],
+    ) {
+        self.public_inputs.lock().unwrap().push(vec![
+            root,
+            nullifiers[0],
+            nullifiers[1],
+            commitments[0],
+            commitments[1],
+        ]);
+    }
+
+    /// Reverts with the contract's require messages, worded like a node reports them
+    fn spend(&self, nullifiers: &[FixedBytes<32>]) -> Result<(), TxError> {
+        let mut spent = self.nullifiers.lock().unwrap();
+        for (i, nullifier) in nullifiers.iter().enumerate() {
+            if spent.contains(nullifier) {
+                return Err(TxError::Reverted(format!("execution reverted: Note {} already spent", i)));
+            }
+        }
+        if nullifiers.len() == 2 && nullifiers[0] == nullifiers[1] {
+            return Err(TxError::Reverted("execution reverted: Identical nullifiers".to_string()));
+        }
+        spent.extend(nullifiers.iter().copied());
+        let block = self.calls.lock().unwrap().len() as u64 + 1;
+        self.spend_log
+            .lock()
+            .unwrap()
+            .extend(nullifiers.iter().map(|n| (block, *n)));
+        Ok(())
+    }
+}
+
+impl BondContract for MockBondContract {
+    async fn network(&self) -> Result<NetworkBinding, TxError> {
+        Ok(self.network)
+    }
+
+    async fn code(&self) -> Result<Bytes, TxError> {
+        self.check_online("fetch the contract code")?;
+        Ok(self.code.lock().unwrap().clone())
+    }
+
+    async fn current_root(&self) -> Result<FixedBytes<32>, TxError> {
+        self.check_online("query the contract root")?;
+        if let Some(root) = *self.root.lock().unwrap() {
+            return Ok(root);
+        }
+        let mut tree = FixedMerkleTree::new();
+        for commitment in self.commitments.lock().unwrap().iter() {
+            let leaf = fr_from_bytes32(commitment)
+                .ok_or_else(|| TxError::Send(format!("{} is not a field element", commitment)))?;
+            tree.insert(leaf);
+        }
+        Ok(fr_to_bytes32(&tree.root()))
+    }
+
+    async fn root_known(&self, root: FixedBytes<32>) -> Result<bool, TxError> {
+        self.check_online("query the contract's known roots")?;
+        if *self.root.lock().unwrap() == Some(root) {
+            return Ok(true);
+        }
+        let mut tree = FixedMerkleTree::new();
+        for commitment in self.commitments.lock().unwrap().iter() {
+            if let Some(leaf) = fr_from_bytes32(commitment) {
+                tree.insert(leaf);
+                if fr_to_bytes32(&tree.root()) == root {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    async fn nullifier_spent(&self, nullifier: FixedBytes<32>) -> Result<bool, TxError> {
+        self.check_online("check whether a note is spent")?;
+        Ok(self.is_spent(&nullifier))
+    }
+
+    async fn nullifiers_spent(&self, nullifiers: Vec<FixedBytes<32>>) -> Result<Vec<bool>, TxError> {
+        self.check_online("check whether notes are spent")?;
+        Ok(nullifiers.iter().map(|n| self.is_spent(n)).collect())
+    }
+
+    async fn commitment_at(&self, index: u64) -> Result<Option<FixedBytes<32>>, TxError> {
+        let commitments = self.commitments.lock().unwrap();
+        match commitments.get(index as usize) {
+            Some(commitment) => Ok(Some(*commitment)),
+            None => Err(TxError::Send(format!("Commitment index {} out of range", index))),
+        }
+    }
+
+    async fn spend_events(&self, from_block: u64) -> Result<Vec<(u64, FixedBytes<32>)>, TxError> {
+        self.check_online("read spend events")?;
+        let log = self.spend_log.lock().unwrap();
+        Ok(log.iter().filter(|(block, _)| *block >= from_block).copied().collect())
+    }
+
+    async fn mint_batch(&self, commitments: Vec<FixedBytes<32>>) -> Result<TxHash, TxError> {
+        if !*self.drop_mints.lock().unwrap() {
+            self.commitments.lock().unwrap().extend(commitments);
+        }
+        Ok(self.record("mintBatch"))
+    }
+
+    async fn transfer(
+        &self,
+        _proof: Bytes,
+        root: FixedBytes<32>,
+        nullifiers: [FixedBytes<32>; 2],
+        commitments: [FixedBytes<32>; 2],
+    ) -> Result<TxHash, TxError> {
+        let transfers = self.calls.lock().unwrap().iter().filter(|c| *c == "transfer").count();
+        if *self.reject_transfer.lock().unwrap() == Some(transfers + 1) {
+            return Err(TxError::Reverted("execution reverted: Invalid proof".to_string()));
+        }
+        self.spend(&nullifiers)?;
+        self.record_public_inputs(root, nullifiers, commitments);
+        self.commitments.lock().unwrap().extend(commitments);
+        let tx_hash = self.record("transfer");
+        if *self.drop_receipts.lock().unwrap() {
+            return Err(TxError::Unconfirmed {
+                tx_hash,
+                reason: "connection dropped".to_string(),
+            });
+        }
+        Ok(tx_hash)
+    }
+
+    async fn burn(
+        &self,
+        _proof: Bytes,
+        root: FixedBytes<32>,
+        nullifiers: [FixedBytes<32>; 2],
+        commitments: [FixedBytes<32>; 2],
+        _maturity_date: FixedBytes<32>,
+        _is_redeem: FixedBytes<32>,
+    ) -> Result<TxHash, TxError> {
+        self.spend(&nullifiers)?;
+        self.record_public_inputs(root, nullifiers, commitments);
+        self.commitments.lock().unwrap().extend(commitments);
+        Ok(self.record("burn"))
+    }
+
+    async fn atomic_swap(
+        &self,
+        _proof_a: Bytes,
+        public_inputs_a: Vec<FixedBytes<32>>,
+        _proof_b: Bytes,
+        public_inputs_b: Vec<FixedBytes<32>>,
+    ) -> Result<TxHash, TxError> {
+        self.spend(&[public_inputs_a[1], public_inputs_b[1]])?;
+        self.commitments
+            .lock()
+            .unwrap()
+            .extend([public_inputs_a[2], public_inputs_b[2]]);
+        Ok(self.record("atomicSwap"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::mock::Asserter;
+
+    #[test]
+    fn test_fee_flags_applied_to_request() {
+        let provider = ProviderBuilder::new().connect_mocked_client(Asserter::new());
+        let contract = PrivateBond::new(Address::ZERO, provider);
+        let fees = FeeConfig {
+            max_fee_per_gas: Some(30_000_000_000),
+            max_priority_fee_per_gas: Some(2_000_000_000),
+        };
+
+        let request =
+            apply_fees(contract.mintBatch(vec![FixedBytes::ZERO]), fees).into_transaction_request();
+        assert_eq!(request.max_fee_per_gas, Some(30_000_000_000));
+        assert_eq!(request.max_priority_fee_per_gas, Some(2_000_000_000));
+
+        let request =
+            apply_fees(contract.mintBatch(vec![]), FeeConfig::default()).into_transaction_request();
+        assert_eq!(request.max_fee_per_gas, None);
+        assert_eq!(request.max_priority_fee_per_gas, None);
+    }
+
+    /// Node stand-in: records every attempt, mines the attempts in `mines` and
+    /// rejects sends from attempt `rejects_from` on (as a node does once the nonce is used)
+    struct MockNode {
+        /// (attempt, sends after which its receipt appears), attempts counted from 1
+        mines: Vec<(usize, usize)>,
+        rejects_from: Option<usize>,
+        sent: Mutex<Vec<(u64, FeeConfig)>>,
+        nonce_queries: Mutex<usize>,
+    }
+
+    impl MockNode {
+        fn new(mines: Vec<(usize, usize)>, rejects_from: Option<usize>) -> Self {
+            MockNode {
+                mines,
+                rejects_from,
+                sent: Mutex::new(Vec::new()),
+                nonce_queries: Mutex::new(0),
+            }
+        }
+    }
+
+    impl Resubmittable for MockNode {
+        async fn nonce(&self) -> Result<u64, TxError> {
+            *self.nonce_queries.lock().unwrap() += 1;
+            Ok(7)
+        }
+
+        async fn send(&self, nonce: u64, fees: FeeConfig) -> Result<TxHash, TxError> {
+            let mut sent = self.sent.lock().unwrap();
+            sent.push((nonce, fees));
+            if self.rejects_from.is_some_and(|n| sent.len() >= n) {
+                return Err(TxError::Send("nonce too low".to_string()));
+            }
+            Ok(TxHash::from([sent.len() as u8; 32]))
+        }
+
+        async fn mined(&self, tx_hash: TxHash) -> Result<bool, TxError> {
+            let sends = self.sent.lock().unwrap().len();
+            let attempt = tx_hash[0] as usize;
+            Ok(self.mines.iter().any(|&(a, after)| a == attempt && sends >= after))
+        }
+    }
+
+    const POLICY: Replacement = Replacement {
+        after: Duration::from_millis(30),
+        poll: Duration::from_millis(5),
+        max_attempts: 3,
+    };
+
+    const FEES: FeeConfig = FeeConfig {
+        max_fee_per_gas: Some(1_000),
+        max_priority_fee_per_gas: Some(100),
+    };
+
+    #[test]
+    fn test_stuck_transfer_is_replaced() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        // First attempt times out; the replacement is mined
+        let node = MockNode::new(vec![(2, 2)], None);
+        let tx_hash = rt.block_on(submit_replacing(&node, FEES, POLICY)).unwrap();
+        assert_eq!(tx_hash, TxHash::from([2u8; 32]));
+        let sent = node.sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(*node.nonce_queries.lock().unwrap(), 1);
+        assert!(sent.iter().all(|(nonce, _)| *nonce == 7));
+        assert_eq!(sent[1].1, FEES.bumped());
+        assert!(sent[1].1.max_fee_per_gas.unwrap() * 10 > 11 * 1_000);
+
+        // The original is mined while its replacement is rejected: the original's
+        // hash comes back, once, and no third attempt is sent
+        let node = MockNode::new(vec![(1, 2)], Some(2));
+        let tx_hash = rt.block_on(submit_replacing(&node, FEES, POLICY)).unwrap();
+        assert_eq!(tx_hash, TxHash::from([1u8; 32]));
+        assert_eq!(node.sent.lock().unwrap().len(), 2);
+
+        // Never mined: every attempt used, reported unconfirmed
+        let node = MockNode::new(vec![], None);
+        let err = rt.block_on(submit_replacing(&node, FEES, POLICY)).unwrap_err();
+        assert!(
+            matches!(err, TxError::Unconfirmed { tx_hash, .. } if tx_hash == TxHash::from([3u8; 32]))
+        );
+        assert_eq!(node.sent.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_unmined_transfer_times_out_with_pending_hash() {
+        // The node accepts the transaction and then never reports a receipt
+        let asserter = Asserter::new();
+        let provider = ProviderBuilder::new()
+            .disable_recommended_fillers()
+            .connect_mocked_client(asserter.clone())
+            .erased();
+        let sent = TxHash::from([0x5e; 32]);
+        asserter.push_success(&sent);
+        asserter.push_success(&Option::<()>::None); // eth_getTransactionReceipt: not mined
+        let contract = AlloyBondContract::from_provider(Address::ZERO, provider)
+            .with_fees(FEES)
+            .with_confirm_timeout(Duration::from_millis(200));
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let start = std::time::Instant::now();
+        let err = rt
+            .block_on(contract.transfer(
+                Bytes::new(),
+                FixedBytes::ZERO,
+                [FixedBytes::ZERO; 2],
+                [FixedBytes::ZERO; 2],
+            ))
+            .unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(
+            matches!(err, TxError::Pending { tx_hash, waited } if tx_hash == sent && waited == Duration::from_millis(200)),
+            "{:?}",
+            err
+        );
+        // Reported with the hash and its own exit code, so a script can check it later
+        assert!(err.to_string().contains(&format!("{:?}", sent)));
+        assert_eq!(crate::error::WalletError::from(err).exit_code(), 7);
+        assert_eq!(DEFAULT_CONFIRM_TIMEOUT, Duration::from_secs(120));
+    }
+
+    #[test]
+    fn test_network_check_rejects_unexpected_code() {
+        let deployed = Bytes::from_static(&[0x60, 0x80, 0x60, 0x40]);
+        let pinned_hash = keccak256(&deployed).to_string();
+        let pinned = [(31337, pinned_hash.as_str())];
+        let contract = MockBondContract {
+            network: NetworkBinding {
+                chain_id: 31337,
+                contract: Address::repeat_byte(0xdc),
+            },
+            ..Default::default()
+        };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        // Nothing deployed at the address
+        let err = rt.block_on(check_contract_code(&contract, &pinned)).unwrap_err();
+        assert!(err.contains("No contract code"), "{}", err);
+
+        // Something else deployed there
+        *contract.code.lock().unwrap() = Bytes::from_static(&[0x60, 0x80, 0x60, 0x41]);
+        let err = rt.block_on(check_contract_code(&contract, &pinned)).unwrap_err();
+        assert!(err.contains("expected") && err.contains(&pinned_hash), "{}", err);
+
+        *contract.code.lock().unwrap() = deployed;
+        rt.block_on(check_contract_code(&contract, &pinned)).unwrap();
+
+        // A chain with no pin fails rather than passing unchecked
+        let err = rt.block_on(check_contract_code(&contract, &[])).unwrap_err();
+        assert!(err.contains("No bytecode hash pinned for chain 31337"), "{}", err);
+    }
+
+    #[test]
+    fn test_fetch_limited_respects_max_in_flight() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let calls = AtomicUsize::new(0);
+        let limit = RpcLimit {
+            max_in_flight: 3,
+            ..RpcLimit::default()
+        };
+        let mut reported = Vec::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let results = rt.block_on(fetch_limited(
+            (0..20u64).collect(),
+            limit,
+            |done, total| reported.push((done, total)),
+            |n| {
+                let (in_flight, peak, calls) = (&in_flight, &peak, &calls);
+                async move {
+                    // The node throttles the last request once
+                    if calls.fetch_add(1, Ordering::SeqCst) == 19 {
+                        return Err(TxError::Send("HTTP error 429 Too Many Requests".to_string()));
+                    }
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    Ok(n * 2)
+                }
+            },
+        ));
+
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        assert_eq!(calls.load(Ordering::SeqCst), 21);
+        let values: Vec<u64> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(values, (0..20u64).map(|n| n * 2).collect::<Vec<_>>());
+        assert_eq!(reported.len(), 20);
+        assert_eq!(reported.last(), Some(&(20, 20)));
+
+        // Other errors are not retried
+        let fails = rt.block_on(fetch_limited(vec![1u64], limit, |_, _| {}, |_| async {
+            Err::<u64, _>(TxError::Send("execution reverted".to_string()))
+        }));
+        assert_eq!(fails, vec![Err(TxError::Send("execution reverted".to_string()))]);
+    }
+}
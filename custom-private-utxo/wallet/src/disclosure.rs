@@ -0,0 +1,209 @@
+//! Selective disclosure: the full fields of one note and its merkle inclusion
+//! proof, encrypted to an authority's viewing key, so a regulator can check a
+//! specific note without being handed the wallet seed
+
+use blake2::{Blake2b512, Digest};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit, Nonce};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use std::fs;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::field::Fr;
+use crate::keys::{parse_public_spending_key, ShieldedKeys};
+use crate::merkle::{CircuitMerklePath, FixedMerkleTree};
+use crate::prover::CircuitNote;
+use crate::utils::{fr_to_hex, parse_commitment, Bond, FrHex, TreeState};
+
+/// What the authority learns about the note
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Disclosure {
+    pub value: u64,
+    pub salt: u64,
+    #[serde_as(as = "FrHex")]
+    pub owner: Fr,
+    pub asset_id: u64,
+    pub maturity_date: u64,
+    /// Memo hash the commitment binds, for notes committed under `scheme::MemoBound`
+    #[serde_as(as = "Option<FrHex>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo_hash: Option<Fr>,
+    #[serde_as(as = "FrHex")]
+    pub commitment: Fr,
+    pub leaf_index: usize,
+    pub path: CircuitMerklePath,
+    /// Root `path` leads to; compare it with the contract's
+    #[serde_as(as = "FrHex")]
+    pub root: Fr,
+}
+
+impl Disclosure {
+    /// Disclose `bond`, located in the local tree
+    pub fn for_bond(bond: &Bond, tree_state: &TreeState) -> Result<Self, String> {
+        let owner = parse_public_spending_key(&bond.owner).map_err(|e| e.to_string())?;
+        let note = CircuitNote::new(bond.value, bond.salt, owner, bond.asset_id, bond.maturity_date)
+            .map_err(|e| format!("Invalid note: {}", e))?
+            .with_memo_hash(bond.memo_hash);
+        let commitment = note.commitment();
+        if parse_commitment(&bond.commitment) != Some(commitment) {
+            return Err(format!(
+                "Note fields hash to {}, not the file's commitment {}",
+                fr_to_hex(&commitment),
+                bond.commitment
+            ));
+        }
+        let leaf_index = tree_state
+            .find_commitment(&commitment)
+            .ok_or_else(|| format!("Commitment {} is not in the local tree", fr_to_hex(&commitment)))?;
+        let tree = tree_state.proof_tree()?;
+        Ok(Disclosure {
+            value: bond.value,
+            salt: bond.salt,
+            owner,
+            asset_id: bond.asset_id,
+            maturity_date: bond.maturity_date,
+            memo_hash: bond.memo_hash,
+            commitment,
+            leaf_index,
+            path: tree.generate_proof(leaf_index)?,
+            root: tree.root(),
+        })
+    }
+
+    /// Check the fields hash to `commitment` and `path` leads from it to `root`
+    pub fn verify(&self) -> Result<(), String> {
+        let note = CircuitNote::new_unchecked(
+            self.value,
+            self.salt,
+            self.owner,
+            self.asset_id,
+            self.maturity_date,
+        )
+        .with_memo_hash(self.memo_hash);
+        if note.commitment() != self.commitment {
+            return Err("Disclosed fields do not hash to the disclosed commitment".to_string());
+        }
+        if !FixedMerkleTree::verify_batch(&[(self.commitment, self.path.clone())], self.root)[0] {
+            return Err("Merkle path does not lead from the commitment to the disclosed root".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A `Disclosure` sealed to one authority's X25519 viewing key under a one-off key
+/// of its own, so the discloser's wallet is not needed to open it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DisclosurePackage {
+    /// Viewing key the package is sealed to (hex)
+    pub recipient: String,
+    /// One-off X25519 key of this package (hex)
+    pub ephemeral_pubkey: String,
+    /// ChaCha20-Poly1305 encrypted `Disclosure` JSON (hex)
+    pub ciphertext: String,
+}
+
+/// BLAKE2b(shared_secret || ephemeral_pub || recipient_pub), as memos derive theirs
+fn package_key(shared_secret: &[u8; 32], ephemeral: &[u8; 32], recipient: &[u8; 32]) -> ChaCha20Poly1305 {
+    let mut hasher = Blake2b512::new();
+    hasher.update(shared_secret);
+    hasher.update(ephemeral);
+    hasher.update(recipient);
+    ChaCha20Poly1305::new(hasher.finalize()[..32].into())
+}
+
+impl DisclosurePackage {
+    pub fn seal(disclosure: &Disclosure, recipient: &[u8; 32]) -> Result<Self, String> {
+        let secret = StaticSecret::from(rand::thread_rng().gen::<[u8; 32]>());
+        let ephemeral = PublicKey::from(&secret).to_bytes();
+        let shared = secret.diffie_hellman(&PublicKey::from(*recipient)).to_bytes();
+        let plaintext = serde_json::to_vec(disclosure)
+            .map_err(|e| format!("Failed to serialize disclosure: {}", e))?;
+        // The key is fresh for every package, so a fixed nonce is never reused
+        let ciphertext = package_key(&shared, &ephemeral, recipient)
+            .encrypt(Nonce::from_slice(&[0u8; 12]), plaintext.as_ref())
+            .map_err(|e| format!("Encryption failed: {}", e))?;
+        Ok(DisclosurePackage {
+            recipient: hex::encode(recipient),
+            ephemeral_pubkey: hex::encode(ephemeral),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Decrypt with the authority's keys; fails for anyone else
+    pub fn open(&self, keys: &ShieldedKeys) -> Result<Disclosure, String> {
+        let ephemeral: [u8; 32] = hex::decode(&self.ephemeral_pubkey)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| "Package key is not a 32-byte hex X25519 key".to_string())?;
+        let ciphertext = hex::decode(&self.ciphertext)
+            .map_err(|e| format!("Package ciphertext is not valid hex: {}", e))?;
+        let plaintext = package_key(&keys.ecdh(&ephemeral), &ephemeral, keys.public_viewing_key())
+            .decrypt(Nonce::from_slice(&[0u8; 12]), ciphertext.as_ref())
+            .map_err(|_| "Package is not sealed to this wallet's viewing key".to_string())?;
+        serde_json::from_slice(&plaintext).map_err(|e| format!("Invalid disclosure: {}", e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize disclosure package: {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid disclosure package {}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::BOND_SCHEMA_VERSION;
+
+    #[test]
+    fn test_only_the_authority_opens_a_disclosure() {
+        let holder = ShieldedKeys::from_seed([1u8; 32]);
+        let authority = ShieldedKeys::from_seed([8u8; 32]);
+        let bystander = ShieldedKeys::from_seed([9u8; 32]);
+        let note = CircuitNote::new(2_500, 77, holder.public_spending_key(), 1, 1893456000).unwrap();
+        let bond = Bond {
+            schema_version: BOND_SCHEMA_VERSION,
+            commitment: fr_to_hex(&note.commitment()),
+            nullifier: fr_to_hex(&holder.sign_nullifier(77)),
+            value: 2_500,
+            salt: 77,
+            owner: holder.public_spending_key_hex.clone(),
+            asset_id: 1,
+            maturity_date: 1893456000,
+            created_at: String::new(),
+            label: None,
+            memo_hash: None,
+        };
+        let tree_state = TreeState::from_commitments(vec![
+            fr_to_hex(&note.padding().commitment()),
+            bond.commitment.clone(),
+        ]);
+
+        let disclosure = Disclosure::for_bond(&bond, &tree_state).unwrap();
+        let package = DisclosurePackage::seal(&disclosure, authority.public_viewing_key()).unwrap();
+
+        let opened = package.open(&authority).unwrap();
+        opened.verify().unwrap();
+        assert_eq!((opened.value, opened.salt, opened.leaf_index), (2_500, 77, 1));
+        assert_eq!(opened.owner, holder.public_spending_key());
+        assert_eq!(opened.root, tree_state.build_tree().root());
+
+        assert!(package.open(&bystander).is_err());
+        assert!(package.open(&holder).is_err());
+
+        // Fields that do not match the commitment, or a path to another leaf, fail
+        let mut forged = opened.clone();
+        forged.value = 25_000;
+        assert!(forged.verify().is_err());
+        let mut moved = opened;
+        moved.path = tree_state.build_tree().generate_proof(0);
+        assert!(moved.verify().is_err());
+    }
+}
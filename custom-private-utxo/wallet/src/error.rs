@@ -0,0 +1,92 @@
+//! Why a command failed, as a process exit code scripts and CI can branch on.
+//! Commands return the kind where the failure is detected (a wallet lookup, the
+//! prover, the contract); errors from helpers that only report a message convert
+//! to `Other`. The codes are part of the CLI's interface: a variant keeps its
+//! code, and new kinds take new numbers.
+
+use std::fmt;
+
+use crate::contract::TxError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletError {
+    /// A named wallet file does not exist (exit code 2)
+    WalletNotFound(String),
+    /// The note spent holds less than the command moves (3)
+    InsufficientValue(String),
+    /// The prover (nargo execute or bb prove) rejected the witness or crashed (4)
+    ProofFailed(String),
+    /// The contract reverted the call (5)
+    TxReverted(String),
+    /// nargo or bb could not be started (6)
+    ToolingMissing(String),
+    /// The transaction was sent but not mined within `--confirm-timeout` (7)
+    TxPending(String),
+    /// Anything else (1)
+    Other(String),
+}
+
+impl WalletError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WalletError::Other(_) => 1,
+            WalletError::WalletNotFound(_) => 2,
+            WalletError::InsufficientValue(_) => 3,
+            WalletError::ProofFailed(_) => 4,
+            WalletError::TxReverted(_) => 5,
+            WalletError::ToolingMissing(_) => 6,
+            WalletError::TxPending(_) => 7,
+        }
+    }
+
+    /// No wallet file named `name`
+    pub fn wallet_not_found(name: &str) -> Self {
+        WalletError::WalletNotFound(format!("Wallet '{}' not found", name))
+    }
+
+    /// The same kind with its message rewritten, e.g. to name the step that failed
+    pub fn map_message(self, f: impl FnOnce(String) -> String) -> Self {
+        match self {
+            WalletError::WalletNotFound(m) => WalletError::WalletNotFound(f(m)),
+            WalletError::InsufficientValue(m) => WalletError::InsufficientValue(f(m)),
+            WalletError::ProofFailed(m) => WalletError::ProofFailed(f(m)),
+            WalletError::TxReverted(m) => WalletError::TxReverted(f(m)),
+            WalletError::ToolingMissing(m) => WalletError::ToolingMissing(f(m)),
+            WalletError::TxPending(m) => WalletError::TxPending(f(m)),
+            WalletError::Other(m) => WalletError::Other(f(m)),
+        }
+    }
+}
+
+impl From<String> for WalletError {
+    fn from(message: String) -> Self {
+        WalletError::Other(message)
+    }
+}
+
+impl From<TxError> for WalletError {
+    fn from(e: TxError) -> Self {
+        let message = e.to_string();
+        match e {
+            TxError::Reverted(_) => WalletError::TxReverted(message),
+            TxError::Pending { .. } => WalletError::TxPending(message),
+            TxError::Send(_) | TxError::Unconfirmed { .. } => WalletError::Other(message),
+        }
+    }
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletError::WalletNotFound(m)
+            | WalletError::InsufficientValue(m)
+            | WalletError::ProofFailed(m)
+            | WalletError::TxReverted(m)
+            | WalletError::ToolingMissing(m)
+            | WalletError::TxPending(m)
+            | WalletError::Other(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl std::error::Error for WalletError {}
@@ -0,0 +1,301 @@
+//! Progress events emitted by the wallet commands, for front-ends that need
+//! more than stdout (e.g. a progress bar during proving)
+
+use alloy::primitives::TxHash;
+use std::io::{self, BufRead, Write};
+use std::sync::Mutex;
+
+use crate::prover::WitnessBuilder;
+use crate::utils::fr_to_hex;
+
+/// An irreversible on-chain action awaiting confirmation
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingAction {
+    /// `buy` or `redeem`
+    pub action: String,
+    pub value: u64,
+    /// Nullifier of the note the action spends (bytes32 hex)
+    pub nullifier: String,
+    /// Who receives the value; `None` for a burn
+    pub recipient: Option<String>,
+}
+
+impl std::fmt::Display for PendingAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "   Action:    {}", self.action)?;
+        writeln!(f, "   Value:     {}", self.value)?;
+        writeln!(f, "   Spends:    {}", self.nullifier)?;
+        write!(f, "   Recipient: {}", self.recipient.as_deref().unwrap_or("none (burn)"))
+    }
+}
+
+/// Receives progress callbacks from the wallet commands. Every callback
+/// defaults to a no-op, so a sink only implements the events it cares about.
+pub trait EventSink: Sync {
+    /// An irreversible action is about to be proved and submitted; returning false
+    /// cancels it. Defaults to going ahead.
+    fn confirm(&self, _action: &PendingAction) -> bool {
+        true
+    }
+
+    /// The local tree lacks `missing` commitments the contract holds, among them the
+    /// note being spent; returning true appends them to the local tree. Defaults to
+    /// leaving the tree alone.
+    fn confirm_repair(&self, _missing: usize) -> bool {
+        false
+    }
+
+    /// A witness is ready and about to be proved
+    fn on_witness_built(&self, _witness: &WitnessBuilder) {}
+
+    /// Proving has started; this is the slow step
+    fn on_proof_started(&self) {}
+
+    /// The proof was written to `proof_path`
+    fn on_proof_done(&self, _proof_path: &str) {}
+
+    /// A contract call (`transfer`, `burn`, `atomicSwap`) is being submitted
+    fn on_tx_sent(&self, _method: &str) {}
+
+    /// The contract call was mined
+    fn on_tx_confirmed(&self, _method: &str, _tx_hash: TxHash) {}
+}
+
+/// Sink used by the CLI: prints each event to stdout
+pub struct PrintSink;
+
+impl EventSink for PrintSink {
+    fn on_witness_built(&self, witness: &WitnessBuilder) {
+        println!("   🧾 Witness built (root: {})", fr_to_hex(&witness.root));
+    }
+
+    fn on_proof_started(&self) {
+        println!("\n🔐 Generating ZK proof...");
+    }
+
+    fn on_proof_done(&self, proof_path: &str) {
+        println!("   ✅ Proof saved to: {}", proof_path);
+    }
+
+    fn on_tx_sent(&self, method: &str) {
+        println!("\n📡 Calling contract {}()...", method);
+    }
+
+    fn on_tx_confirmed(&self, method: &str, tx_hash: TxHash) {
+        println!("   ✅ {} confirmed: {:?}", method, tx_hash);
+    }
+}
+
+/// What running a CLI command can change, which decides what it may ask before
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reversibility {
+    /// Only reads local files and the chain; never asks anything
+    ReadOnly,
+    /// Writes local files that can be rebuilt or restored (wallets, the tree,
+    /// indexes, archives); `--assume-yes-safe` answers its questions
+    Reversible,
+    /// Spends, burns or mints on-chain; asks unless `--yes`
+    Irreversible,
+}
+
+/// Classification of every CLI command, by its command-line name. A command not
+/// listed here is treated as irreversible, so a new one asks until it is classified.
+pub fn reversibility(command: &str) -> Reversibility {
+    match command {
+        "balance" | "history" | "root" | "tree" | "info" | "scan" | "inspect-memo" | "sign-challenge"
+        | "verify-challenge" | "proof-info" | "calldata" | "diff" => Reversibility::ReadOnly,
+        "register" | "import" | "migrate-keys" | "prepare-mint" | "export-vk" | "prune" | "sync" | "sync-nullifiers" | "reindex"
+        | "rename" | "register-asset" | "disclose" | "open-disclosure" | "import-note" | "reconstruct" => {
+            Reversibility::Reversible
+        }
+        _ => Reversibility::Irreversible,
+    }
+}
+
+/// CLI sink for interactive commands: prints like `PrintSink` and asks before
+/// acting according to `policy`, the running command's `reversibility`. Spends
+/// go to `prompt` unless `yes` (`--yes`); local tree repairs are also answered by
+/// `assume_safe` (`--assume-yes-safe`). A read-only command never asks and refuses both.
+pub struct PromptSink<F> {
+    pub policy: Reversibility,
+    pub yes: bool,
+    pub assume_safe: bool,
+    pub prompt: F,
+}
+
+impl<F> PromptSink<F> {
+    /// Asked once before an irreversible command that confirms no spend of its
+    /// own (a mint, or a loop redeeming on its own); everything else goes ahead
+    pub fn confirm_command(&self, command: &str) -> bool {
+        self.policy != Reversibility::Irreversible || self.yes || {
+            println!("\n⚠️  `{}` acts on-chain and cannot be undone", command);
+            ask_proceed("Proceed?")
+        }
+    }
+}
+
+impl<F: Fn(&PendingAction) -> bool + Sync> EventSink for PromptSink<F> {
+    fn confirm(&self, action: &PendingAction) -> bool {
+        self.policy == Reversibility::Irreversible && (self.yes || (self.prompt)(action))
+    }
+
+    fn confirm_repair(&self, missing: usize) -> bool {
+        if self.policy == Reversibility::ReadOnly {
+            return false;
+        }
+        self.yes || self.assume_safe || {
+            println!(
+                "\n⚠️  The local merkle tree is missing {} commitment(s) the contract holds",
+                missing
+            );
+            ask_proceed("Add them to data/tree_state.json?")
+        }
+    }
+
+    fn on_witness_built(&self, witness: &WitnessBuilder) {
+        PrintSink.on_witness_built(witness)
+    }
+
+    fn on_proof_started(&self) {
+        PrintSink.on_proof_started()
+    }
+
+    fn on_proof_done(&self, proof_path: &str) {
+        PrintSink.on_proof_done(proof_path)
+    }
+
+    fn on_tx_sent(&self, method: &str) {
+        PrintSink.on_tx_sent(method)
+    }
+
+    fn on_tx_confirmed(&self, method: &str, tx_hash: TxHash) {
+        PrintSink.on_tx_confirmed(method, tx_hash)
+    }
+}
+
+/// Show the action and read `y`/`yes` from stdin; anything else declines
+pub fn stdin_prompt(action: &PendingAction) -> bool {
+    println!("\n⚠️  This cannot be undone:\n{}", action);
+    ask_proceed("Proceed?")
+}
+
+/// Ask `question` and read `y`/`yes` from stdin
+fn ask_proceed(question: &str) -> bool {
+    print!("   {} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Test sink: records the name of each event in the order received
+#[derive(Default)]
+pub struct RecordingSink {
+    pub events: Mutex<Vec<String>>,
+}
+
+impl RecordingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl EventSink for RecordingSink {
+    fn confirm(&self, action: &PendingAction) -> bool {
+        self.push(format!("confirm:{}", action.action));
+        true
+    }
+
+    fn confirm_repair(&self, missing: usize) -> bool {
+        self.push(format!("confirm_repair:{}", missing));
+        true
+    }
+
+    fn on_witness_built(&self, _witness: &WitnessBuilder) {
+        self.push("witness_built".to_string());
+    }
+
+    fn on_proof_started(&self) {
+        self.push("proof_started".to_string());
+    }
+
+    fn on_proof_done(&self, _proof_path: &str) {
+        self.push("proof_done".to_string());
+    }
+
+    fn on_tx_sent(&self, method: &str) {
+        self.push(format!("tx_sent:{}", method));
+    }
+
+    fn on_tx_confirmed(&self, method: &str, _tx_hash: TxHash) {
+        self.push(format!("tx_confirmed:{}", method));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sink(command: &str, yes: bool, assume_safe: bool) -> PromptSink<impl Fn(&PendingAction) -> bool + Sync> {
+        PromptSink {
+            policy: reversibility(command),
+            yes,
+            assume_safe,
+            prompt: |action: &PendingAction| -> bool { panic!("prompted for {}", action.action) },
+        }
+    }
+
+    #[test]
+    fn test_only_irreversible_commands_prompt() {
+        let read_only = [
+            "balance", "history", "root", "tree", "info", "scan", "inspect-memo", "sign-challenge",
+            "verify-challenge", "proof-info", "calldata", "diff",
+        ];
+        let reversible = [
+            "register", "import", "migrate-keys", "prepare-mint", "export-vk", "prune", "sync", "sync-nullifiers", "reindex",
+            "rename", "register-asset", "disclose", "open-disclosure", "import-note", "reconstruct",
+        ];
+        let irreversible = ["onboard", "submit-prepared", "buy", "trade", "redeem", "watch", "serve"];
+        for (class, commands) in [
+            (Reversibility::ReadOnly, &read_only[..]),
+            (Reversibility::Reversible, &reversible[..]),
+            (Reversibility::Irreversible, &irreversible[..]),
+        ] {
+            for command in commands {
+                assert_eq!(reversibility(command), class, "{}", command);
+            }
+        }
+        // An unclassified command asks
+        assert_eq!(reversibility("new-command"), Reversibility::Irreversible);
+
+        let action = PendingAction {
+            action: "redeem".to_string(),
+            value: 1,
+            nullifier: format!("0x{:064x}", 1),
+            recipient: None,
+        };
+        // Read-only commands never reach the prompt, whatever the flags, and change nothing
+        for command in read_only {
+            let read_only = sink(command, false, false);
+            assert!(read_only.confirm_command(command));
+            assert!(!read_only.confirm(&action));
+            assert!(!read_only.confirm_repair(3));
+        }
+        // --assume-yes-safe answers a tree repair but not a spend
+        let safe = sink("redeem", false, true);
+        assert!(safe.confirm_repair(3));
+        let asked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| safe.confirm(&action)));
+        assert!(asked.is_err());
+        // --yes answers both
+        let yes = sink("redeem", true, false);
+        assert!(yes.confirm(&action));
+        assert!(yes.confirm_repair(3));
+        assert!(yes.confirm_command("redeem"));
+    }
+}
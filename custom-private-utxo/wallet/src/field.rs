@@ -0,0 +1,64 @@
+//! The scalar field every commitment, nullifier and merkle node lives in.
+//!
+//! The circuit hashes with `poseidon::bn254` and the contract verifies over BN254,
+//! so the wallet uses poseidon-rs's BN254 `Fr`. Everything else imports the field
+//! and the hash from here: targeting another curve (BLS12-381, Grumpkin) means a
+//! field and Poseidon parameters to match it, swapped in below, plus `CURVE` and
+//! `MODULUS_HEX`. Fixed-width encodings (`fr_to_bytes32`, 64-hex-digit keys) assume
+//! a field of at most 256 bits.
+
+use ff::PrimeField;
+use num_bigint::BigUint;
+use std::sync::OnceLock;
+
+use crate::utils::{fr_to_bytes32_with, Endianness};
+
+pub use poseidon_rs::{Fr, FrRepr, Poseidon};
+
+/// Curve whose scalar field `Fr` is; must match the circuit and the verifier
+pub const CURVE: &str = "bn254";
+
+/// Scalar field modulus of `CURVE`, big-endian hex
+pub const MODULUS_HEX: &str = "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001";
+
+// A field of another size is a different curve: fail the build, not the proofs
+const _: () = assert!(Fr::NUM_BITS == 254, "Fr is not the BN254 scalar field");
+
+/// Shared Poseidon instance (loading the round constants is expensive); every
+/// commitment, nullifier, key and merkle hash goes through it
+pub fn poseidon() -> &'static Poseidon {
+    static POSEIDON: OnceLock<Poseidon> = OnceLock::new();
+    POSEIDON.get_or_init(Poseidon::new)
+}
+
+/// The integer in 0..modulus an element stands for
+pub fn fr_to_biguint(fr: &Fr) -> BigUint {
+    BigUint::from_bytes_be(&fr_to_bytes32_with(fr, Endianness::Big).0)
+}
+
+/// Modulus of the active field, as an integer
+pub fn modulus() -> BigUint {
+    let modulus_bytes: Vec<u8> = Fr::char()
+        .as_ref()
+        .iter()
+        .flat_map(|limb| limb.to_le_bytes())
+        .collect();
+    BigUint::from_bytes_le(&modulus_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+
+    #[test]
+    fn test_modulus_matches_configured_curve() {
+        assert_eq!(CURVE, "bn254");
+        assert_eq!(format!("0x{:064x}", modulus()), MODULUS_HEX);
+
+        // -1 is the largest element, one below the modulus
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        assert_eq!(fr_to_biguint(&minus_one) + 1u32, modulus());
+    }
+}
@@ -0,0 +1,261 @@
+//! Log of the wallet's confirmed transactions, for reconciling private bond
+//! activity against the chain. Only what the contract call made public is
+//! recorded: nullifiers, commitments and the value moved, never salts or seeds.
+//! A redemption also records the off-chain payment it settled against.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use crate::utils::{ensure_data_dir, history_path};
+
+/// Columns of `to_csv`, in order
+pub const CSV_HEADER: &str =
+    "timestamp,command,nullifiers,commitments,value,tx_hash,network,settlement_ref,settlement_account";
+
+/// One confirmed contract call
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// RFC 3339, UTC
+    pub timestamp: String,
+    /// Wallet command that sent the call (`buy`, `trade`, `redeem`, ...)
+    pub command: String,
+    /// Nullifiers the call spent (bytes32 hex)
+    pub nullifiers: Vec<String>,
+    /// Commitments the call added (bytes32 hex)
+    pub commitments: Vec<String>,
+    /// Value the command moved; 0 for zero-value pad mints, `None` for a
+    /// redemption run with `--private`
+    pub value: Option<u64>,
+    pub tx_hash: String,
+    /// Deployment the call went to, as `NetworkBinding` displays it
+    pub network: String,
+    /// `redeem --settlement-ref`: the off-chain payment the burn settled against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settlement_ref: Option<String>,
+    /// `redeem --settlement-account`: the account that payment went to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settlement_account: Option<String>,
+}
+
+impl HistoryRecord {
+    fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.timestamp)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+/// Append `record` to `data/history.jsonl`
+pub fn append_history(record: &HistoryRecord) -> Result<(), String> {
+    ensure_data_dir().map_err(|e| e.to_string())?;
+    let line = serde_json::to_string(record).map_err(|e| format!("Failed to serialize history record: {}", e))?;
+    let path = history_path();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+/// `--since`: a `YYYY-MM-DD` date (midnight UTC) or an RFC 3339 timestamp
+pub fn parse_since(since: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    DateTime::parse_from_rfc3339(since)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid date '{}': expected YYYY-MM-DD or an RFC 3339 timestamp", since))
+}
+
+/// Logged records, oldest first, keeping only those at or after `since`
+pub fn load_history(since: Option<DateTime<Utc>>) -> Result<Vec<HistoryRecord>, String> {
+    load_history_at(&history_path(), since)
+}
+
+/// `load_history` from another log, e.g. a backup's `history.jsonl`
+pub fn load_history_at(path: &str, since: Option<DateTime<Utc>>) -> Result<Vec<HistoryRecord>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path, e)),
+    };
+    let mut records = Vec::new();
+    for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let record: HistoryRecord =
+            serde_json::from_str(line).map_err(|e| format!("{} line {}: {}", path, n + 1, e))?;
+        if since.is_none_or(|since| record.time().is_some_and(|t| t >= since)) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// Nullifiers this wallet's logged calls have spent: a local record of spends
+/// that needs no node
+pub fn spent_nullifiers() -> Result<HashSet<String>, String> {
+    Ok(load_history(None)?
+        .into_iter()
+        .flat_map(|r| r.nullifiers)
+        .map(|n| n.to_lowercase())
+        .collect())
+}
+
+/// Quote a field that holds a separator or quote, doubling its quotes
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Split one CSV line, undoing `csv_field`
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Records as CSV with `CSV_HEADER`; nullifier and commitment lists are `;`-separated
+pub fn to_csv(records: &[HistoryRecord]) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for r in records {
+        let row = [
+            r.timestamp.clone(),
+            r.command.clone(),
+            r.nullifiers.join(";"),
+            r.commitments.join(";"),
+            r.value.map(|v| v.to_string()).unwrap_or_default(),
+            r.tx_hash.clone(),
+            r.network.clone(),
+            r.settlement_ref.clone().unwrap_or_default(),
+            r.settlement_account.clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Read back what `to_csv` wrote
+pub fn from_csv(csv: &str) -> Result<Vec<HistoryRecord>, String> {
+    let mut lines = csv.lines();
+    if lines.next() != Some(CSV_HEADER) {
+        return Err(format!("Not a history export: expected header '{}'", CSV_HEADER));
+    }
+    let list = |field: &str| -> Vec<String> {
+        field.split(';').filter(|s| !s.is_empty()).map(str::to_string).collect()
+    };
+    let optional = |field: &str| (!field.is_empty()).then(|| field.to_string());
+    lines
+        .enumerate()
+        .map(|(n, line)| {
+            let f = split_csv_line(line);
+            if f.len() != 9 {
+                return Err(format!("Row {}: {} columns, expected 9", n + 1, f.len()));
+            }
+            let value = match f[4].as_str() {
+                "" => None,
+                v => Some(v.parse().map_err(|_| format!("Row {}: invalid value '{}'", n + 1, v))?),
+            };
+            Ok(HistoryRecord {
+                timestamp: f[0].clone(),
+                command: f[1].clone(),
+                nullifiers: list(&f[2]),
+                commitments: list(&f[3]),
+                value,
+                tx_hash: f[5].clone(),
+                network: f[6].clone(),
+                settlement_ref: optional(&f[7]),
+                settlement_account: optional(&f[8]),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{set_data_dir, temp_data_dir};
+
+    fn record(timestamp: &str, command: &str, value: u64) -> HistoryRecord {
+        HistoryRecord {
+            timestamp: timestamp.to_string(),
+            command: command.to_string(),
+            nullifiers: vec![format!("0x{:064x}", value), format!("0x{:064x}", value + 1)],
+            commitments: vec![format!("0x{:064x}", value + 2)],
+            value: Some(value),
+            tx_hash: format!("0x{:064x}", value + 3),
+            network: "chain 31337 contract 0x5FbDB2315678afecb367f032d93F642f64180aa3".to_string(),
+            settlement_ref: None,
+            settlement_account: None,
+        }
+    }
+
+    #[test]
+    fn test_history_csv_round_trip_and_since_filter() {
+        let (_guard, dir) = temp_data_dir("history");
+        assert!(load_history(None).unwrap().is_empty());
+
+        let logged = vec![
+            record("2026-01-02T09:00:00+00:00", "buy", 1_000),
+            record("2026-03-01T00:00:00+00:00", "trade", 250),
+            record("2026-03-15T17:30:00+00:00", "redeem", 750),
+        ];
+        for r in &logged {
+            append_history(r).unwrap();
+        }
+        assert_eq!(load_history(None).unwrap(), logged);
+
+        // A bare date starts at midnight UTC, so the record at midnight is kept
+        let since = load_history(Some(parse_since("2026-03-01").unwrap())).unwrap();
+        assert_eq!(since, logged[1..]);
+        let since = load_history(Some(parse_since("2026-03-01T00:00:01Z").unwrap())).unwrap();
+        assert_eq!(since, logged[2..]);
+        assert!(parse_since("March 1st").is_err());
+
+        let csv = to_csv(&since);
+        assert!(csv.starts_with(CSV_HEADER));
+        assert_eq!(from_csv(&csv).unwrap(), since);
+        let csv = to_csv(&logged);
+        assert_eq!(csv.lines().count(), 4);
+        assert_eq!(from_csv(&csv).unwrap(), logged);
+
+        // Fields holding separators survive the trip
+        let mut odd = record("2026-04-01T00:00:00+00:00", "buy", 5);
+        odd.network = "chain 1, \"mainnet\"".to_string();
+        assert_eq!(from_csv(&to_csv(std::slice::from_ref(&odd))).unwrap(), vec![odd]);
+
+        // A private redemption keeps its settlement details but not its value
+        let mut settled = record("2026-04-02T00:00:00+00:00", "redeem", 750);
+        settled.value = None;
+        settled.settlement_ref = Some("WIRE-2030-0001".to_string());
+        settled.settlement_account = Some("GB33BUKB20201555555555".to_string());
+        append_history(&settled).unwrap();
+        let logged = load_history(Some(parse_since("2026-04-02").unwrap())).unwrap();
+        assert_eq!(logged, vec![settled.clone()]);
+        let line = fs::read_to_string(history_path()).unwrap().lines().last().unwrap().to_string();
+        assert!(line.contains("WIRE-2030-0001") && !line.contains("750"), "{}", line);
+        assert_eq!(from_csv(&to_csv(&logged)).unwrap(), logged);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
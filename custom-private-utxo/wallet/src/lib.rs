@@ -0,0 +1,24 @@
+//! Private bond wallet: shielded keys, notes, merkle tree and proving helpers
+
+pub mod assets;
+pub mod attempt;
+pub mod babyjubjub;
+pub mod calldata;
+pub mod commands;
+pub mod config;
+pub mod contract;
+pub mod disclosure;
+pub mod error;
+pub mod events;
+pub mod field;
+pub mod history;
+pub mod keys;
+pub mod merkle;
+pub mod notes;
+pub mod paged_tree;
+pub mod passphrase;
+pub mod prover;
+pub mod scheme;
+pub mod server;
+pub mod spent;
+pub mod utils;
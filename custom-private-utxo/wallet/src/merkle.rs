@@ -1,5 +1,11 @@
-use poseidon_rs::{Fr, Poseidon};
 use ff::PrimeField;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use std::collections::HashMap;
+
+use crate::field::{poseidon, Fr};
+use crate::prover::WitnessBuilder;
+use crate::utils::FrHex;
 
 /// Tree height must match circuit's TREE_HEIGHT constant
 pub const TREE_HEIGHT: usize = 3;
@@ -14,17 +20,18 @@ fn zero() -> Fr {
 
 /// Hash two field elements using Poseidon
 fn hash2(left: Fr, right: Fr) -> Fr {
-    let hasher = Poseidon::new();
-    hasher.hash(vec![left, right]).expect("Poseidon hash failed")
+    poseidon().hash(vec![left, right]).expect("Poseidon hash failed")
 }
 
 /// Merkle path for proving note existence
 /// Matches the circuit's expected format
-#[derive(Clone, Debug)]
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CircuitMerklePath {
     /// 0 = current node is on left, 1 = current node is on right
     pub indices: [u8; TREE_HEIGHT],
     /// Sibling hashes at each level
+    #[serde_as(as = "[FrHex; TREE_HEIGHT]")]
     pub elements: [Fr; TREE_HEIGHT],
 }
 
@@ -36,154 +43,334 @@ impl CircuitMerklePath {
             elements: [zero(); TREE_HEIGHT],
         }
     }
+
+    /// This path's entry in the circuit's `path_indices`, e.g. `[1, 0, 0]`
+    pub fn indices_toml(&self) -> String {
+        WitnessBuilder::toml_array(&self.indices, |i| i.to_string())
+    }
+
+    /// This path's entry in `path_elements`: siblings leaf level first, as quoted hex
+    pub fn elements_toml(&self) -> String {
+        WitnessBuilder::toml_array(&self.elements, |e| {
+            format!("\"{}\"", WitnessBuilder::toml_integer(e))
+        })
+    }
+
+    /// The `path_indices` and `path_elements` Prover.toml lines for one path per
+    /// input note, in the circuit's input order
+    pub fn prover_toml(paths: &[CircuitMerklePath]) -> String {
+        format!(
+            "path_indices = {}\npath_elements = {}\n",
+            WitnessBuilder::toml_array(paths, Self::indices_toml),
+            WitnessBuilder::toml_array(paths, Self::elements_toml)
+        )
+    }
 }
 
-/// Fixed-height Merkle tree matching the circuit's TREE_HEIGHT
-pub struct FixedMerkleTree {
-    /// All levels of the tree, from leaves (level 0) to root (level TREE_HEIGHT)
+/// A binary proof of `TREE_HEIGHT` levels, as `MerkleTree` or `PagedTree` give it
+impl From<MerkleProof> for CircuitMerklePath {
+    fn from(proof: MerkleProof) -> Self {
+        let mut indices = [0u8; TREE_HEIGHT];
+        let mut elements = [zero(); TREE_HEIGHT];
+        for level in 0..TREE_HEIGHT {
+            indices[level] = proof.positions[level] as u8;
+            elements[level] = proof.siblings[level][0];
+        }
+        CircuitMerklePath { indices, elements }
+    }
+}
+
+/// Hash one node's children using Poseidon
+pub(crate) fn hash_children(children: &[Fr]) -> Fr {
+    poseidon().hash(children.to_vec()).expect("Poseidon hash failed")
+}
+
+/// Membership proof in a `MerkleTree`: at each level, the node's position among
+/// its parent's children and the other `arity - 1` children, left to right
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleProof {
+    pub positions: Vec<usize>,
+    pub siblings: Vec<Vec<Fr>>,
+}
+
+/// How far a level-by-level tree build has got: `level` of `levels` is done and
+/// holds `nodes` nodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BuildProgress {
+    pub level: usize,
+    pub levels: usize,
+    pub nodes: usize,
+}
+
+/// Fixed-depth Merkle tree whose nodes each hash `arity` children.
+/// Wider trees are shallower, so proofs have fewer levels for circuits that
+/// accept wider Poseidon hashes; the deployed circuit is binary.
+pub struct MerkleTree {
+    arity: usize,
+    depth: usize,
+    /// All levels of the tree, from leaves (level 0) to root (level `depth`)
     levels: Vec<Vec<Fr>>,
     /// Number of actual leaves inserted
     leaf_count: usize,
 }
 
-impl FixedMerkleTree {
-    /// Create a new empty tree
-    pub fn new() -> Self {
-        // Initialize with empty levels
-        let mut levels = Vec::with_capacity(TREE_HEIGHT + 1);
-        
-        // Level 0: leaves (initially all zeros)
-        levels.push(vec![zero(); MAX_LEAVES]);
-        
-        // Build empty tree levels
-        for level in 1..=TREE_HEIGHT {
-            let prev_len = levels[level - 1].len();
-            let this_len = (prev_len + 1) / 2;
-            let mut this_level = Vec::with_capacity(this_len);
-            
-            for i in 0..this_len {
-                let left = levels[level - 1].get(i * 2).copied().unwrap_or(zero());
-                let right = levels[level - 1].get(i * 2 + 1).copied().unwrap_or(zero());
-                this_level.push(hash2(left, right));
-            }
+impl MerkleTree {
+    /// Create an empty tree of `arity^depth` leaves.
+    /// Poseidon takes 2 to 16 inputs, which bounds the arity.
+    pub fn new(arity: usize, depth: usize) -> Self {
+        Self::from_leaves(arity, depth, &[], |_| {})
+    }
+
+    /// Build a tree holding `leaves` one level at a time, hashing each node once,
+    /// and call `on_level` as each level above the leaves is finished
+    pub fn from_leaves(
+        arity: usize,
+        depth: usize,
+        leaves: &[Fr],
+        mut on_level: impl FnMut(BuildProgress),
+    ) -> Self {
+        assert!((2..=16).contains(&arity), "Unsupported Merkle arity {}", arity);
+        let capacity = u32::try_from(depth)
+            .ok()
+            .and_then(|d| arity.checked_pow(d))
+            .unwrap_or_else(|| panic!("Merkle tree of arity {} and depth {} is too large", arity, depth));
+
+        if leaves.len() > capacity {
+            panic!("Merkle tree is full (max {} leaves)", capacity);
+        }
+
+        // Level 0: leaves padded with zeros, then each level hashes the one below
+        let mut levels = Vec::with_capacity(depth + 1);
+        let mut bottom = leaves.to_vec();
+        bottom.resize(capacity, zero());
+        levels.push(bottom);
+        for level in 1..=depth {
+            let this_level: Vec<Fr> = levels[level - 1].chunks(arity).map(hash_children).collect();
+            on_level(BuildProgress {
+                level,
+                levels: depth,
+                nodes: this_level.len(),
+            });
             levels.push(this_level);
         }
-        
-        FixedMerkleTree {
+
+        MerkleTree {
+            arity,
+            depth,
             levels,
-            leaf_count: 0,
+            leaf_count: leaves.len(),
         }
     }
-    
-    /// Create tree from existing commitments
-    pub fn from_leaves(leaves: &[Fr]) -> Self {
-        let mut tree = Self::new();
-        for leaf in leaves {
-            tree.insert(*leaf);
-        }
-        tree
+
+    pub fn arity(&self) -> usize {
+        self.arity
     }
-    
+
+    /// Maximum number of leaves (`arity^depth`)
+    pub fn capacity(&self) -> usize {
+        self.levels[0].len()
+    }
+
     /// Insert a new leaf (commitment) and update the tree
     pub fn insert(&mut self, leaf: Fr) -> usize {
-        if self.leaf_count >= MAX_LEAVES {
-            panic!("Merkle tree is full (max {} leaves)", MAX_LEAVES);
+        if self.leaf_count >= self.capacity() {
+            panic!("Merkle tree is full (max {} leaves)", self.capacity());
         }
-        
+
         let index = self.leaf_count;
         self.levels[0][index] = leaf;
         self.leaf_count += 1;
-        
+
         // Update path from leaf to root
         self.update_path(index);
-        
+
         index
     }
-    
+
     /// Update the tree along the path from a leaf to the root
     fn update_path(&mut self, leaf_index: usize) {
         let mut current_index = leaf_index;
-        
-        for level in 0..TREE_HEIGHT {
-            let left_idx = current_index - (current_index % 2);
-            let right_idx = left_idx + 1;
-            
-            let left = self.levels[level].get(left_idx).copied().unwrap_or(zero());
-            let right = self.levels[level].get(right_idx).copied().unwrap_or(zero());
-            
-            let parent_idx = current_index / 2;
-            self.levels[level + 1][parent_idx] = hash2(left, right);
-            
-            current_index = parent_idx;
+
+        for level in 0..self.depth {
+            let first = current_index - current_index % self.arity;
+            let parent = hash_children(&self.levels[level][first..first + self.arity]);
+            current_index /= self.arity;
+            self.levels[level + 1][current_index] = parent;
         }
     }
-    
+
     /// Get the current root
     pub fn root(&self) -> Fr {
-        self.levels[TREE_HEIGHT][0]
+        self.levels[self.depth][0]
     }
-    
+
+    /// Number of levels above the leaves
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Nodes at `level`, from 0 (the leaves) up to `depth` (the root alone)
+    pub fn level(&self, level: usize) -> Option<&[Fr]> {
+        self.levels.get(level).map(Vec::as_slice)
+    }
+
     /// Generate a Merkle proof for the leaf at the given index
-    pub fn generate_proof(&self, leaf_index: usize) -> CircuitMerklePath {
-        if leaf_index >= self.leaf_count && leaf_index >= MAX_LEAVES {
+    pub fn generate_proof(&self, leaf_index: usize) -> MerkleProof {
+        if leaf_index >= self.capacity() {
             panic!("Leaf index {} out of bounds", leaf_index);
         }
-        
-        let mut indices = [0u8; TREE_HEIGHT];
-        let mut elements = [zero(); TREE_HEIGHT];
-        
+
+        let mut positions = Vec::with_capacity(self.depth);
+        let mut siblings = Vec::with_capacity(self.depth);
         let mut current_index = leaf_index;
-        
-        for level in 0..TREE_HEIGHT {
-            // Determine if current node is on the right (1) or left (0)
-            let is_right = (current_index % 2) as u8;
-            indices[level] = is_right;
-            
-            // Get sibling
-            let sibling_index = if is_right == 1 {
-                current_index - 1
-            } else {
-                current_index + 1
-            };
-            
-            elements[level] = self.levels[level]
-                .get(sibling_index)
-                .copied()
-                .unwrap_or(zero());
-            
-            current_index = current_index / 2;
+
+        for level in 0..self.depth {
+            let position = current_index % self.arity;
+            let first = current_index - position;
+            let mut children = self.levels[level][first..first + self.arity].to_vec();
+            children.remove(position);
+            positions.push(position);
+            siblings.push(children);
+
+            current_index /= self.arity;
         }
-        
-        CircuitMerklePath { indices, elements }
+
+        MerkleProof { positions, siblings }
+    }
+
+    /// Root a proof leads to from `leaf`, or `None` if the proof is malformed for `arity`
+    pub fn root_from_proof(arity: usize, leaf: Fr, proof: &MerkleProof) -> Option<Fr> {
+        if proof.positions.len() != proof.siblings.len() {
+            return None;
+        }
+
+        let mut current = leaf;
+        for (&position, siblings) in proof.positions.iter().zip(&proof.siblings) {
+            if position >= arity || siblings.len() != arity - 1 {
+                return None;
+            }
+            let mut children = siblings.clone();
+            children.insert(position, current);
+            current = hash_children(&children);
+        }
+        Some(current)
+    }
+
+    /// Verify a proof against the current root
+    pub fn verify_proof(&self, leaf: Fr, proof: &MerkleProof) -> bool {
+        proof.positions.len() == self.depth
+            && Self::root_from_proof(self.arity, leaf, proof) == Some(self.root())
+    }
+
+    /// Get number of leaves
+    pub fn len(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Whether no leaves have been inserted yet
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+}
+
+/// Fixed-height binary Merkle tree matching the circuit's TREE_HEIGHT
+pub struct FixedMerkleTree {
+    tree: MerkleTree,
+}
+
+impl FixedMerkleTree {
+    /// Create a new empty tree
+    pub fn new() -> Self {
+        FixedMerkleTree {
+            tree: MerkleTree::new(2, TREE_HEIGHT),
+        }
+    }
+    
+    /// Create tree from existing commitments
+    pub fn from_leaves(leaves: &[Fr]) -> Self {
+        Self::from_leaves_with_progress(leaves, |_| {})
+    }
+
+    /// Create tree from existing commitments, reporting each finished level
+    pub fn from_leaves_with_progress(leaves: &[Fr], on_level: impl FnMut(BuildProgress)) -> Self {
+        FixedMerkleTree {
+            tree: MerkleTree::from_leaves(2, TREE_HEIGHT, leaves, on_level),
+        }
+    }
+    
+    /// Insert a new leaf (commitment) and update the tree
+    pub fn insert(&mut self, leaf: Fr) -> usize {
+        self.tree.insert(leaf)
+    }
+    
+    /// Get the current root
+    pub fn root(&self) -> Fr {
+        self.tree.root()
+    }
+
+    /// Nodes at `level`, from 0 (the leaves) up to `TREE_HEIGHT` (the root)
+    pub fn level(&self, level: usize) -> Option<&[Fr]> {
+        self.tree.level(level)
+    }
+    
+    /// Generate a Merkle proof for the leaf at the given index, in the circuit's format
+    pub fn generate_proof(&self, leaf_index: usize) -> CircuitMerklePath {
+        self.tree.generate_proof(leaf_index).into()
     }
     
     /// Verify a proof (for testing)
     pub fn verify_proof(&self, leaf: Fr, proof: &CircuitMerklePath) -> bool {
-        let mut current = leaf;
-        
-        for i in 0..TREE_HEIGHT {
-            let sibling = proof.elements[i];
-            let is_right = proof.indices[i];
-            
-            current = if is_right == 1 {
-                hash2(sibling, current)
-            } else {
-                hash2(current, sibling)
-            };
-        }
-        
-        current == self.root()
+        let proof = MerkleProof {
+            positions: proof.indices.iter().map(|&i| i as usize).collect(),
+            siblings: proof.elements.iter().map(|&e| vec![e]).collect(),
+        };
+        self.tree.verify_proof(leaf, &proof)
+    }
+
+    /// Verify many proofs against one root in a single pass.
+    /// Interior nodes shared between proofs are hashed once and reused.
+    pub fn verify_batch(items: &[(Fr, CircuitMerklePath)], root: Fr) -> Vec<bool> {
+        let mut cache: HashMap<(Fr, Fr), Fr> = HashMap::new();
+
+        items
+            .iter()
+            .map(|(leaf, proof)| {
+                let mut current = *leaf;
+                for i in 0..TREE_HEIGHT {
+                    let sibling = proof.elements[i];
+                    let pair = if proof.indices[i] == 1 {
+                        (sibling, current)
+                    } else {
+                        (current, sibling)
+                    };
+                    current = *cache.entry(pair).or_insert_with(|| hash2(pair.0, pair.1));
+                }
+                current == root
+            })
+            .collect()
     }
     
     /// Get number of leaves
     pub fn len(&self) -> usize {
-        self.leaf_count
+        self.tree.len()
+    }
+
+    /// Whether no leaves have been inserted yet
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+}
+
+impl Default for FixedMerkleTree {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ff::Field;
     
     #[test]
     fn test_empty_tree() {
@@ -191,7 +378,7 @@ mod tests {
         // Root of empty tree should be hash of zeros
         let expected_root = hash2(hash2(hash2(zero(), zero()), hash2(zero(), zero())), 
                                   hash2(hash2(zero(), zero()), hash2(zero(), zero())));
-        // Actually for our tree it's simpler since we initialize differently
+        assert_eq!(tree.root(), expected_root);
         println!("Empty tree root: {:?}", tree.root());
     }
     
@@ -238,8 +425,8 @@ mod tests {
         let private_key = Fr::from_str("999").unwrap();
         
         // owner_in = poseidon::hash_1([private_key])
-        let hasher = Poseidon::new();
-        let owner_in = hasher.hash(vec![private_key]).unwrap();
+        let hasher = poseidon();
+        let _owner_in = hasher.hash(vec![private_key]).unwrap();
         
         // Commitment calculation (simplified - just testing tree structure)
         let comm_in_0 = Fr::from_str("12345").unwrap(); // placeholder
@@ -260,5 +447,256 @@ mod tests {
         println!("Proof0 indices: {:?}", proof0.indices);
         println!("Proof1 indices: {:?}", proof1.indices);
     }
-}
 
+    #[test]
+    fn test_paths_render_as_circuit_prover_toml() {
+        // Leaves and path lines from circuits/Prover.toml: the tranche note and its dummy
+        let leaves = [
+            "0x1b581e24c1c9b2e04edd33bf1cf87dc2626a40cf3089b8cb8636db30f5610b89",
+            "0x565c12316ad10e143a81706cc73490177a1154df881475c8b8f0257a8c1d707",
+        ]
+        .map(|h| crate::utils::parse_commitment(h).unwrap());
+        let tree = FixedMerkleTree::from_leaves(&leaves);
+        assert_eq!(
+            crate::utils::fr_to_hex(&tree.root()),
+            "0x05bff49d71b7ee294fcf1f9694d811c1b2228cccc52d01e8deeb3eebc459f0c4"
+        );
+
+        let paths = [tree.generate_proof(0), tree.generate_proof(1)];
+        let rendered = CircuitMerklePath::prover_toml(&paths);
+        let expected = concat!(
+            "path_indices = [[0, 0, 0], [1, 0, 0]]\n",
+            "path_elements = [",
+            "[\"0x565c12316ad10e143a81706cc73490177a1154df881475c8b8f0257a8c1d707\", ",
+            "\"0x2098f5fb9e239eab3ceac3f27b81e481dc3124d55ffed523a839ee8446b64864\", ",
+            "\"0x1069673dcdb12263df301a6ff584a7ec261a44cb9dc68df067a4774460b1f1e1\"], ",
+            "[\"0x1b581e24c1c9b2e04edd33bf1cf87dc2626a40cf3089b8cb8636db30f5610b89\", ",
+            "\"0x2098f5fb9e239eab3ceac3f27b81e481dc3124d55ffed523a839ee8446b64864\", ",
+            "\"0x1069673dcdb12263df301a6ff584a7ec261a44cb9dc68df067a4774460b1f1e1\"]]\n",
+        );
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn test_verify_batch_mixed() {
+        let leaves: Vec<Fr> = (1..=5)
+            .map(|i| Fr::from_str(&(i * 100).to_string()).unwrap())
+            .collect();
+        let tree = FixedMerkleTree::from_leaves(&leaves);
+        let root = tree.root();
+
+        let mut items: Vec<(Fr, CircuitMerklePath)> = leaves
+            .iter()
+            .enumerate()
+            .map(|(i, leaf)| (*leaf, tree.generate_proof(i)))
+            .collect();
+
+        // Wrong leaf for a valid path
+        items.push((Fr::from_str("999").unwrap(), tree.generate_proof(0)));
+        // Valid leaf with a flipped index bit
+        let mut flipped = tree.generate_proof(2);
+        flipped.indices[0] ^= 1;
+        items.push((leaves[2], flipped));
+        // Valid leaf with a corrupted sibling
+        let mut corrupted = tree.generate_proof(4);
+        corrupted.elements[1] = Fr::from_str("1").unwrap();
+        items.push((leaves[4], corrupted));
+
+        let results = FixedMerkleTree::verify_batch(&items, root);
+        assert_eq!(
+            results,
+            vec![true, true, true, true, true, false, false, false]
+        );
+
+        // Batch results agree with individual verification
+        for ((leaf, proof), ok) in items.iter().zip(&results) {
+            assert_eq!(tree.verify_proof(*leaf, proof), *ok);
+        }
+
+        // Everything fails against a different root
+        let other_root = Fr::from_str("42").unwrap();
+        assert!(FixedMerkleTree::verify_batch(&items, other_root)
+            .iter()
+            .all(|ok| !ok));
+    }
+
+    /// Field element from two random words, so leaves span well beyond u64
+    fn leaf_from(words: (u64, u64)) -> Fr {
+        Fr::from_str(&(((words.0 as u128) << 64) | words.1 as u128).to_string()).unwrap()
+    }
+
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_proofs_verify_and_reject_mutations(
+            words in proptest::collection::vec(proptest::prelude::any::<(u64, u64)>(), 1..=MAX_LEAVES),
+            index_seed in proptest::prelude::any::<usize>(),
+            level in 0..TREE_HEIGHT,
+        ) {
+            let leaves: Vec<Fr> = words.into_iter().map(leaf_from).collect();
+            let tree = FixedMerkleTree::from_leaves(&leaves);
+            let index = index_seed % leaves.len();
+            let proof = tree.generate_proof(index);
+            proptest::prop_assert!(tree.verify_proof(leaves[index], &proof));
+
+            let mut mutated = proof.clone();
+            mutated.elements[level].add_assign(&Fr::one());
+            proptest::prop_assert!(!tree.verify_proof(leaves[index], &mutated));
+        }
+    }
+
+    #[test]
+    fn test_proof_regressions() {
+        let leaf = |i: u64| leaf_from((i, i));
+
+        // Single leaf: the root folds the leaf with empty subtrees at every level
+        let tree = FixedMerkleTree::from_leaves(&[leaf(1)]);
+        let empty = FixedMerkleTree::new();
+        let expected = hash2(hash2(hash2(leaf(1), zero()), empty.tree.levels[1][1]), empty.tree.levels[2][1]);
+        assert_eq!(tree.root(), expected);
+        assert!(tree.verify_proof(leaf(1), &tree.generate_proof(0)));
+
+        // Odd count: the last leaf pairs with an empty slot, not a duplicate of itself
+        let tree = FixedMerkleTree::from_leaves(&[leaf(1), leaf(2), leaf(3)]);
+        let proof = tree.generate_proof(2);
+        assert_eq!(proof.elements[0], zero());
+        assert!(tree.verify_proof(leaf(3), &proof));
+        let mut duplicated = proof.clone();
+        duplicated.elements[0] = leaf(3);
+        assert!(!tree.verify_proof(leaf(3), &duplicated));
+
+        // Full tree: the last index is on the right at every level
+        let leaves: Vec<Fr> = (0..MAX_LEAVES as u64).map(leaf).collect();
+        let tree = FixedMerkleTree::from_leaves(&leaves);
+        let proof = tree.generate_proof(MAX_LEAVES - 1);
+        assert_eq!(proof.indices, [1; TREE_HEIGHT]);
+        assert!(tree.verify_proof(leaves[MAX_LEAVES - 1], &proof));
+    }
+
+    #[test]
+    fn test_binary_arity_matches_fixed_tree() {
+        let leaves: Vec<Fr> = (1..=5u64).map(|i| leaf_from((i, i * 7))).collect();
+        let fixed = FixedMerkleTree::from_leaves(&leaves);
+        let mut tree = MerkleTree::new(2, TREE_HEIGHT);
+        for leaf in &leaves {
+            tree.insert(*leaf);
+        }
+        assert_eq!(tree.root(), fixed.root());
+        assert_eq!(tree.capacity(), MAX_LEAVES);
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = tree.generate_proof(i);
+            let circuit = fixed.generate_proof(i);
+            assert_eq!(proof.positions, circuit.indices.map(usize::from).to_vec());
+            assert_eq!(proof.siblings, circuit.elements.map(|e| vec![e]).to_vec());
+            assert!(tree.verify_proof(*leaf, &proof));
+        }
+
+        // An explicit zero leaf is the same tree as an empty slot
+        let mut padded = MerkleTree::new(2, TREE_HEIGHT);
+        for leaf in leaves.iter().chain([zero()].iter()) {
+            padded.insert(*leaf);
+        }
+        assert_eq!(padded.root(), tree.root());
+    }
+
+    #[test]
+    fn test_four_ary_proofs() {
+        let leaves: Vec<Fr> = (1..=11u64).map(|i| leaf_from((i, i * 7))).collect();
+        let mut tree = MerkleTree::new(4, 2);
+        for leaf in &leaves {
+            tree.insert(*leaf);
+        }
+        assert_eq!(tree.capacity(), 16);
+
+        // Root by hand: four 4-input hashes, then one over them
+        let group = |i: usize| {
+            hash_children(&[0, 1, 2, 3].map(|j| leaves.get(4 * i + j).copied().unwrap_or(zero())))
+        };
+        assert_eq!(tree.root(), hash_children(&[group(0), group(1), group(2), group(3)]));
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = tree.generate_proof(i);
+            assert_eq!(proof.positions, vec![i % 4, i / 4]);
+            assert!(proof.siblings.iter().all(|s| s.len() == 3));
+            assert!(tree.verify_proof(*leaf, &proof));
+        }
+
+        // Mutations: a sibling, the position, or the shape of a level
+        let proof = tree.generate_proof(6);
+        let mut corrupted = proof.clone();
+        corrupted.siblings[1][2].add_assign(&Fr::one());
+        assert!(!tree.verify_proof(leaves[6], &corrupted));
+        let mut moved = proof.clone();
+        moved.positions[0] = 3;
+        assert!(!tree.verify_proof(leaves[6], &moved));
+        let mut short = proof.clone();
+        short.siblings[0].pop();
+        assert_eq!(MerkleTree::root_from_proof(4, leaves[6], &short), None);
+        assert!(!tree.verify_proof(leaves[6], &short));
+        // A binary reading of the same proof is malformed
+        assert_eq!(MerkleTree::root_from_proof(2, leaves[6], &proof), None);
+
+        // Equivalent leaf sets give equal roots: trailing zero leaves are empty slots
+        let mut padded = MerkleTree::new(4, 2);
+        for leaf in leaves.iter().chain([zero(), zero()].iter()) {
+            padded.insert(*leaf);
+        }
+        assert_eq!(padded.root(), tree.root());
+    }
+
+    #[test]
+    fn test_build_progress_levels() {
+        let leaves: Vec<Fr> = (1..=11u64).map(|i| leaf_from((i, i * 7))).collect();
+        let mut seen = Vec::new();
+        let built = MerkleTree::from_leaves(4, 2, &leaves, |p| seen.push(p));
+        assert_eq!(
+            seen,
+            vec![
+                BuildProgress { level: 1, levels: 2, nodes: 4 },
+                BuildProgress { level: 2, levels: 2, nodes: 1 },
+            ]
+        );
+
+        let mut inserted = MerkleTree::new(4, 2);
+        for leaf in &leaves {
+            inserted.insert(*leaf);
+        }
+        assert_eq!(built.root(), inserted.root());
+
+        let mut levels = Vec::new();
+        FixedMerkleTree::from_leaves_with_progress(&leaves[..3], |p| levels.push(p.level));
+        assert_eq!(levels, (1..=TREE_HEIGHT).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[ignore] // Benchmark: cargo test --release -- --ignored bench_verify_batch --nocapture
+    fn bench_verify_batch() {
+        let leaves: Vec<Fr> = (1..=MAX_LEAVES)
+            .map(|i| Fr::from_str(&i.to_string()).unwrap())
+            .collect();
+        let tree = FixedMerkleTree::from_leaves(&leaves);
+        let items: Vec<(Fr, CircuitMerklePath)> = (0..1000)
+            .map(|i| (leaves[i % MAX_LEAVES], tree.generate_proof(i % MAX_LEAVES)))
+            .collect();
+
+        let start = std::time::Instant::now();
+        let individual: Vec<bool> = items
+            .iter()
+            .map(|(leaf, proof)| tree.verify_proof(*leaf, proof))
+            .collect();
+        let individual_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let batched = FixedMerkleTree::verify_batch(&items, tree.root());
+        let batch_time = start.elapsed();
+
+        assert_eq!(individual, batched);
+        assert!(batched.iter().all(|ok| *ok));
+        println!(
+            "1000 proofs: individual {:?}, batched {:?}",
+            individual_time, batch_time
+        );
+    }
+}
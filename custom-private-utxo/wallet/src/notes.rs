@@ -1,15 +1,103 @@
 use blake2::{Blake2b512, Digest};
 use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit, Nonce};
-use ff::PrimeField;
-use poseidon_rs::{Fr, Poseidon};
 use serde::{Deserialize, Serialize};
 
+use crate::field::{poseidon, Fr};
 use crate::keys::ShieldedKeys;
+use crate::prover::CircuitNote;
+use crate::utils::{fr_from_bytes32, u64_to_fr, Salt, Value};
 
 pub struct Memo {
     pub ciphertext: Vec<u8>,
 }
 
+impl Memo {
+    /// Poseidon hash of the ciphertext, the memo field of `scheme::MemoBound`
+    /// commitments. The length seeds the chain and each 31-byte chunk is one field
+    /// element, so every chunk is below the modulus and no two ciphertexts share
+    /// an encoding.
+    pub fn hash(&self) -> Fr {
+        let hasher = poseidon();
+        self.ciphertext
+            .chunks(31)
+            .fold(u64_to_fr(self.ciphertext.len() as u64), |acc, chunk| {
+                let mut word = [0u8; 32];
+                word[32 - chunk.len()..].copy_from_slice(chunk);
+                let chunk_fr = fr_from_bytes32(&word.into()).expect("31 bytes are below the modulus");
+                hasher.hash(vec![acc, chunk_fr]).unwrap()
+            })
+    }
+}
+
+/// A memo as kept in the memo store: the ciphertext next to the sender's public
+/// viewing key, so the recipient can decrypt it without the sender's wallet
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MemoEnvelope {
+    /// Sender's X25519 viewing key (hex)
+    pub sender_pubkey: String,
+    /// `Memo` ciphertext (hex)
+    pub ciphertext: String,
+    /// `view_tag` of the sender/recipient shared secret, in the clear. Envelopes
+    /// written before tags existed have none and are always trial-decrypted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view_tag: Option<u8>,
+}
+
+impl MemoEnvelope {
+    /// Encrypt `note` from `sender_keys` to the holder of `recipient_pubkey`
+    pub fn seal(
+        sender_keys: &ShieldedKeys,
+        recipient_pubkey: &[u8; 32],
+        note: &Note,
+    ) -> Result<Self, String> {
+        let memo = Note::encrypt(sender_keys, recipient_pubkey, note)?;
+        Ok(Self {
+            sender_pubkey: hex::encode(sender_keys.public_viewing_key()),
+            ciphertext: hex::encode(memo.ciphertext),
+            view_tag: Some(view_tag(&sender_keys.ecdh(recipient_pubkey))),
+        })
+    }
+
+    /// Whether the tag allows this memo to be for `recipient_keys`. Only a match, or
+    /// a missing tag, is worth decrypting; another recipient matches 1 time in 256.
+    pub fn tag_matches(&self, recipient_keys: &ShieldedKeys, sender_pubkey: &[u8; 32]) -> bool {
+        self.view_tag
+            .is_none_or(|tag| tag == view_tag(&recipient_keys.ecdh(sender_pubkey)))
+    }
+
+    pub fn sender(&self) -> Result<[u8; 32], String> {
+        hex::decode(&self.sender_pubkey)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| "Memo sender is not a 32-byte hex viewing key".to_string())
+    }
+
+    pub fn memo(&self) -> Result<Memo, String> {
+        let ciphertext = hex::decode(&self.ciphertext)
+            .map_err(|e| format!("Memo ciphertext is not valid hex: {}", e))?;
+        Ok(Memo { ciphertext })
+    }
+
+    /// Decrypt with the recipient's keys; `Ok(None)` if it isn't addressed to them
+    pub fn open(&self, recipient_keys: &ShieldedKeys) -> Result<Option<Note>, String> {
+        let sender = self.sender()?;
+        if !self.tag_matches(recipient_keys, &sender) {
+            return Ok(None);
+        }
+        Ok(Note::decrypt(recipient_keys, &sender, &self.memo()?).ok())
+    }
+}
+
+/// One byte of BLAKE2b over the ECDH shared secret, domain-separated from the memo
+/// key. It lets a scanner skip someone else's memo without deriving a key and
+/// running the AEAD, and reveals nothing beyond that 1-in-256 hint.
+pub fn view_tag(shared_secret: &[u8; 32]) -> u8 {
+    let mut hasher = Blake2b512::new();
+    hasher.update(b"private-bond/view-tag");
+    hasher.update(shared_secret);
+    hasher.finalize()[0]
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Note {
     pub value: u64,
@@ -20,27 +108,39 @@ pub struct Note {
 }
 
 impl Note {
+    /// The memo plaintext for `note`, as every sender seals it (the salt stands in
+    /// for the owner, whose key does not fit a `u64`)
+    pub fn describing(note: &CircuitNote) -> Self {
+        Note {
+            value: note.value,
+            salt: note.salt,
+            owner: note.salt,
+            asset_id: note.asset_id,
+            maturity_date: note.maturity_date,
+        }
+    }
+
     pub fn commit(&self) -> Fr {
-        let f_val = Fr::from_str(&self.value.to_string()).unwrap();
+        let f_val = Value(self.value).to_fr();
 
-        let f_owner = Fr::from_str(&self.owner.to_string()).unwrap();
+        let f_owner = u64_to_fr(self.owner);
 
-        let f_salt = Fr::from_str(&self.salt.to_string()).expect("Salt too large for field?");
+        let f_salt = Salt(self.salt).to_fr();
 
-        let f_asset = Fr::from_str(&self.asset_id.to_string()).unwrap();
+        let f_asset = u64_to_fr(self.asset_id);
 
-        let f_maturity_date = Fr::from_str(&self.maturity_date.to_string()).unwrap();
+        let f_maturity_date = u64_to_fr(self.maturity_date);
 
-        let hasher = Poseidon::new();
+        let hasher = poseidon();
         hasher
             .hash(vec![f_val, f_salt, f_owner, f_asset, f_maturity_date])
             .unwrap()
     }
 
     pub fn nullifer(&self, private_key: Fr) -> Fr {
-        let f_salt = Fr::from_str(&self.salt.to_string()).expect("Salt too large for field?");
+        let f_salt = Salt(self.salt).to_fr();
 
-        let hasher = Poseidon::new();
+        let hasher = poseidon();
         hasher.hash(vec![f_salt, private_key]).unwrap()
     }
 
@@ -55,7 +155,7 @@ impl Note {
 
         // 2. Derive key using BLAKE2b(shared_secret || alice_pub || bob_pub)
         let mut hasher = Blake2b512::new();
-        hasher.update(&shared_secret);
+        hasher.update(shared_secret);
         hasher.update(sender_keys.public_viewing_key());
         hasher.update(recipient_pubkey);
         let key_bytes = hasher.finalize();
@@ -86,7 +186,7 @@ impl Note {
 
         // 2. Derive key using BLAKE2b(shared_secret || sender_pub || recipient_pub)
         let mut hasher = Blake2b512::new();
-        hasher.update(&shared_secret);
+        hasher.update(shared_secret);
         hasher.update(sender_pubkey);
         hasher.update(recipient_keys.public_viewing_key());
         let key_bytes = hasher.finalize();
@@ -106,3 +206,57 @@ impl Note {
         Ok(note)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note() -> Note {
+        Note {
+            value: 300,
+            salt: 77,
+            owner: 1,
+            asset_id: 1,
+            maturity_date: 1893456000,
+        }
+    }
+
+    #[test]
+    fn test_view_tag_filters_memos_before_decryption() {
+        let sender = ShieldedKeys::from_seed([1u8; 32]);
+        let recipient = ShieldedKeys::from_seed([2u8; 32]);
+        let envelope = MemoEnvelope::seal(&sender, recipient.public_viewing_key(), &note()).unwrap();
+        let sender_pubkey = envelope.sender().unwrap();
+
+        // Both sides of the ECDH derive the same tag, and a match decrypts
+        assert_eq!(envelope.view_tag, Some(view_tag(&recipient.ecdh(&sender_pubkey))));
+        assert!(envelope.tag_matches(&recipient, &sender_pubkey));
+        assert_eq!(envelope.open(&recipient).unwrap().unwrap().salt, 77);
+
+        // Skipped on the tag alone by wallets it isn't for: among 64 of them, the
+        // ones whose tag happens to collide still fail to decrypt
+        let mut skipped = 0;
+        for seed in 3..67u8 {
+            let other = ShieldedKeys::from_seed([seed; 32]);
+            if envelope.tag_matches(&other, &sender_pubkey) {
+                assert!(Note::decrypt(&other, &sender_pubkey, &envelope.memo().unwrap()).is_err());
+            } else {
+                skipped += 1;
+            }
+            assert!(envelope.open(&other).unwrap().is_none());
+        }
+        assert!(skipped >= 60, "only {} of 64 skipped", skipped);
+
+        // A wrong tag hides the memo even from its recipient; no tag means a trial decrypt
+        let mut wrong = envelope.clone();
+        wrong.view_tag = envelope.view_tag.map(|tag| tag.wrapping_add(1));
+        assert!(wrong.open(&recipient).unwrap().is_none());
+        let json = format!(
+            r#"{{"sender_pubkey":"{}","ciphertext":"{}"}}"#,
+            envelope.sender_pubkey, envelope.ciphertext
+        );
+        let untagged: MemoEnvelope = serde_json::from_str(&json).unwrap();
+        assert_eq!(untagged.view_tag, None);
+        assert_eq!(untagged.open(&recipient).unwrap().unwrap().value, 300);
+    }
+}
@@ -0,0 +1,340 @@
+//! Merkle tree kept in a file, level after level, for trees too large to hold in
+//! memory. Building streams one level into the next; a proof reads only the
+//! `depth * arity` nodes on its path. Nodes, roots and proofs are exactly those
+//! of a `MerkleTree` of the same arity and depth over the same leaves.
+//!
+//! Layout: an 8-byte magic, arity and depth (u32 LE each), the leaf count (u64
+//! LE), then every level from the leaves up as 32-byte big-endian nodes.
+
+use alloy::primitives::FixedBytes;
+use ff::PrimeField;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use crate::field::Fr;
+use crate::merkle::{hash_children, MerkleProof};
+use crate::utils::{fr_from_bytes32_with, fr_to_bytes32_with, Endianness};
+
+const MAGIC: &[u8; 8] = b"BONDTREE";
+const HEADER_LEN: u64 = 24;
+const NODE_LEN: u64 = 32;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub struct PagedTree {
+    file: File,
+    arity: usize,
+    depth: usize,
+    leaf_count: usize,
+}
+
+impl PagedTree {
+    /// Write the tree over `leaves` to `path` (replacing it) and open it. Memory
+    /// stays at one group of `arity` nodes plus the I/O buffers, whatever the size.
+    pub fn build(
+        path: &str,
+        arity: usize,
+        depth: usize,
+        leaves: impl IntoIterator<Item = Fr>,
+    ) -> io::Result<Self> {
+        assert!((2..=16).contains(&arity), "Unsupported Merkle arity {}", arity);
+        let capacity = capacity(arity, depth)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        // Level 0: the leaves, padded with zeros
+        let mut writer = BufWriter::new(&file);
+        writer.seek(SeekFrom::Start(HEADER_LEN))?;
+        let mut leaf_count = 0;
+        for leaf in leaves {
+            if leaf_count == capacity {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Merkle tree is full (max {} leaves)", capacity),
+                ));
+            }
+            writer.write_all(&node_bytes(&leaf))?;
+            leaf_count += 1;
+        }
+        let zero = node_bytes(&Fr::from_str("0").unwrap());
+        for _ in leaf_count..capacity {
+            writer.write_all(&zero)?;
+        }
+        writer.flush()?;
+
+        // Each level hashes the one below it, read back in groups of `arity`
+        let mut nodes = capacity;
+        let mut below = HEADER_LEN;
+        for _ in 0..depth {
+            let mut reader = BufReader::new(File::open(path)?);
+            reader.seek(SeekFrom::Start(below))?;
+            let mut group = Vec::with_capacity(arity);
+            for _ in 0..nodes / arity {
+                group.clear();
+                for _ in 0..arity {
+                    group.push(read_node(&mut reader)?);
+                }
+                writer.write_all(&node_bytes(&hash_children(&group)))?;
+            }
+            writer.flush()?;
+            below += nodes as u64 * NODE_LEN;
+            nodes /= arity;
+        }
+        drop(writer);
+
+        let mut tree = PagedTree {
+            file,
+            arity,
+            depth,
+            leaf_count,
+        };
+        tree.write_header()?;
+        Ok(tree)
+    }
+
+    /// Open a tree `build` wrote, reading only its header
+    pub fn open(path: &str) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid(format!("{} is not a paged Merkle tree", path)));
+        }
+        let arity = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let depth = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+        let leaf_count = u64::from_le_bytes(header[16..24].try_into().unwrap()) as usize;
+        if !(2..=16).contains(&arity) {
+            return Err(invalid(format!("{}: unsupported arity {}", path, arity)));
+        }
+        if leaf_count > capacity(arity, depth)? {
+            return Err(invalid(format!("{}: {} leaves do not fit the tree", path, leaf_count)));
+        }
+        let tree = PagedTree {
+            file,
+            arity,
+            depth,
+            leaf_count,
+        };
+        if tree.file.metadata()?.len() != tree.offset(depth, 0) + NODE_LEN {
+            return Err(invalid(format!("{} is truncated or corrupt", path)));
+        }
+        Ok(tree)
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Number of levels above the leaves
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Number of actual leaves inserted
+    pub fn len(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Whether no leaves have been inserted yet
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    pub fn root(&self) -> io::Result<Fr> {
+        Ok(self.read_nodes(self.depth, 0, 1)?[0])
+    }
+
+    /// Same proof `MerkleTree::generate_proof` gives, read from disk one group per level
+    pub fn generate_proof(&self, leaf_index: usize) -> io::Result<MerkleProof> {
+        if leaf_index >= capacity(self.arity, self.depth)? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Leaf index {} out of bounds", leaf_index),
+            ));
+        }
+        let mut positions = Vec::with_capacity(self.depth);
+        let mut siblings = Vec::with_capacity(self.depth);
+        let mut current = leaf_index;
+        for level in 0..self.depth {
+            let position = current % self.arity;
+            let mut children = self.read_nodes(level, current - position, self.arity)?;
+            children.remove(position);
+            positions.push(position);
+            siblings.push(children);
+            current /= self.arity;
+        }
+        Ok(MerkleProof { positions, siblings })
+    }
+
+    /// Append a leaf and rehash its path, touching `depth` groups of the file
+    pub fn insert(&mut self, leaf: Fr) -> io::Result<usize> {
+        let capacity = capacity(self.arity, self.depth)?;
+        if self.leaf_count >= capacity {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Merkle tree is full (max {} leaves)", capacity),
+            ));
+        }
+        let index = self.leaf_count;
+        self.write_node(0, index, &leaf)?;
+        let mut current = index;
+        for level in 0..self.depth {
+            let first = current - current % self.arity;
+            let parent = hash_children(&self.read_nodes(level, first, self.arity)?);
+            current /= self.arity;
+            self.write_node(level + 1, current, &parent)?;
+        }
+        self.leaf_count += 1;
+        self.write_header()?;
+        Ok(index)
+    }
+
+    /// Byte offset of node `index` of `level`
+    fn offset(&self, level: usize, index: usize) -> u64 {
+        let nodes_below: u64 = (0..level)
+            .map(|l| (self.arity as u64).pow((self.depth - l) as u32))
+            .sum();
+        HEADER_LEN + (nodes_below + index as u64) * NODE_LEN
+    }
+
+    fn read_nodes(&self, level: usize, first: usize, count: usize) -> io::Result<Vec<Fr>> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(self.offset(level, first)))?;
+        (0..count).map(|_| read_node(&mut file)).collect()
+    }
+
+    fn write_node(&self, level: usize, index: usize, node: &Fr) -> io::Result<()> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(self.offset(level, index)))?;
+        file.write_all(&node_bytes(node))
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&(self.arity as u32).to_le_bytes());
+        header.extend_from_slice(&(self.depth as u32).to_le_bytes());
+        header.extend_from_slice(&(self.leaf_count as u64).to_le_bytes());
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.flush()
+    }
+}
+
+/// `arity^depth`, or an error for a tree too large to address
+fn capacity(arity: usize, depth: usize) -> io::Result<usize> {
+    u32::try_from(depth)
+        .ok()
+        .and_then(|d| arity.checked_pow(d))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Merkle tree of arity {} and depth {} is too large", arity, depth),
+            )
+        })
+}
+
+fn node_bytes(node: &Fr) -> [u8; 32] {
+    fr_to_bytes32_with(node, Endianness::Big).0
+}
+
+fn read_node(reader: &mut impl Read) -> io::Result<Fr> {
+    let mut bytes = [0u8; 32];
+    reader.read_exact(&mut bytes)?;
+    fr_from_bytes32_with(&FixedBytes(bytes), Endianness::Big)
+        .ok_or_else(|| invalid("Tree node is not a field element".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::MerkleTree;
+    use crate::utils::{set_data_dir, temp_data_dir, u64_to_fr};
+
+    #[test]
+    fn test_paged_proofs_match_in_memory_tree() {
+        let (_guard, dir) = temp_data_dir("paged_tree");
+        std::fs::create_dir_all(&dir).unwrap();
+        for (arity, depth, count) in [(2, 5, 21), (4, 3, 64), (3, 2, 0)] {
+            let leaves: Vec<Fr> = (1..=count).map(|i| u64_to_fr(i * 7919)).collect();
+            let path = format!("{}/tree_{}_{}.bin", dir, arity, depth);
+            let mut memory = MerkleTree::from_leaves(arity, depth, &leaves, |_| {});
+            let built = PagedTree::build(&path, arity, depth, leaves.iter().copied()).unwrap();
+            drop(built);
+
+            let mut paged = PagedTree::open(&path).unwrap();
+            assert_eq!((paged.arity(), paged.depth(), paged.len()), (arity, depth, leaves.len()));
+            assert_eq!(paged.root().unwrap(), memory.root());
+            for index in 0..memory.capacity() {
+                let proof = paged.generate_proof(index).unwrap();
+                assert_eq!(proof, memory.generate_proof(index));
+            }
+            for (index, leaf) in leaves.iter().enumerate() {
+                assert!(memory.verify_proof(*leaf, &paged.generate_proof(index).unwrap()));
+            }
+
+            // Appending rehashes the same path in both
+            if leaves.len() < memory.capacity() {
+                let leaf = u64_to_fr(424242);
+                assert_eq!(paged.insert(leaf).unwrap(), memory.insert(leaf));
+                assert_eq!(paged.root().unwrap(), memory.root());
+                let reopened = PagedTree::open(&path).unwrap();
+                assert_eq!(reopened.len(), memory.len());
+                assert_eq!(reopened.generate_proof(leaves.len()).unwrap(), memory.generate_proof(leaves.len()));
+            }
+        }
+
+        // A full tree refuses more leaves; a truncated file does not open
+        let path = format!("{}/full.bin", dir);
+        assert!(PagedTree::build(&path, 2, 1, (1..=3).map(u64_to_fr)).is_err());
+        let mut full = PagedTree::build(&path, 2, 1, (1..=2).map(u64_to_fr)).unwrap();
+        assert!(full.insert(u64_to_fr(3)).is_err());
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(HEADER_LEN + NODE_LEN).unwrap();
+        assert!(PagedTree::open(&path).is_err());
+
+        set_data_dir(None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    #[ignore] // Benchmark: cargo test --release -- --ignored bench_paged_tree_1m_leaves --nocapture
+    fn bench_paged_tree_1m_leaves() {
+        let (_guard, dir) = temp_data_dir("paged_tree_bench");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/tree.bin", dir);
+        let count: u64 = 1_000_000;
+
+        let start = std::time::Instant::now();
+        PagedTree::build(&path, 2, 20, (1..=count).map(u64_to_fr)).unwrap();
+        let build_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let tree = PagedTree::open(&path).unwrap();
+        let proofs: Vec<MerkleProof> = (0..1000u64)
+            .map(|i| tree.generate_proof((i * 997 % count) as usize).unwrap())
+            .collect();
+        let proof_time = start.elapsed();
+
+        let root = tree.root().unwrap();
+        for (i, proof) in proofs.iter().enumerate() {
+            let index = i as u64 * 997 % count;
+            assert_eq!(MerkleTree::root_from_proof(2, u64_to_fr(index + 1), proof), Some(root));
+        }
+        println!(
+            "1M leaves: build {:?}, 1000 proofs {:?} ({} MiB on disk, one path in memory per proof)",
+            build_time,
+            proof_time,
+            std::fs::metadata(&path).unwrap().len() >> 20
+        );
+
+        set_data_dir(None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
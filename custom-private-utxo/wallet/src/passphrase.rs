@@ -0,0 +1,236 @@
+//! Wallet keys sealed at rest under a passphrase. A wallet file holds either
+//! `keys` in the clear or `sealed_keys`, the same JSON encrypted under a key
+//! stretched from the passphrase. Commands take the passphrase from
+//! `WALLET_PASSPHRASE`; `serve` asks for it once at startup. While one is set,
+//! every wallet saved is sealed with it.
+
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit, Nonce};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::sync::{Mutex, RwLock};
+
+use crate::utils::data_dir;
+
+/// Environment variable commands read the passphrase from
+pub const PASSPHRASE_ENV: &str = "WALLET_PASSPHRASE";
+
+/// PBKDF2 iterations for newly sealed keys (OWASP's figure for HMAC-SHA256);
+/// each file records the count it was sealed with
+pub const PBKDF2_ROUNDS: u32 = 600_000;
+
+static PASSPHRASE: RwLock<Option<String>> = RwLock::new(None);
+
+/// A key stretched from the passphrase: salt, rounds and key
+type Stretched = (Vec<u8>, u32, [u8; 32]);
+
+/// Keys stretched from the current passphrase, so loading a wallet again does
+/// not run PBKDF2 again
+static STRETCHED: Mutex<Vec<Stretched>> = Mutex::new(Vec::new());
+
+/// Seal and open wallet keys with `passphrase` from now on (`None`: keys stay in the clear)
+pub fn set_passphrase(passphrase: Option<&str>) {
+    *PASSPHRASE.write().unwrap() = passphrase.map(str::to_string);
+    STRETCHED.lock().unwrap().clear();
+}
+
+/// Wallet keys as stored in a wallet file's `sealed_keys`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SealedKeys {
+    /// PBKDF2-HMAC-SHA256 iterations
+    pub rounds: u32,
+    /// PBKDF2 salt (hex)
+    pub salt: String,
+    /// ChaCha20-Poly1305 nonce (hex)
+    pub nonce: String,
+    /// Encrypted `keys` JSON (hex)
+    pub ciphertext: String,
+}
+
+/// PBKDF2-HMAC-SHA256 (RFC 8018) with one 32-byte output block
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], rounds: u32) -> [u8; 32] {
+    let prf = <Hmac<Sha256> as Mac>::new_from_slice(password).expect("HMAC takes keys of any length");
+    let mut block = prf.clone();
+    block.update(salt);
+    block.update(&1u32.to_be_bytes());
+    let mut u = block.finalize().into_bytes();
+    let mut out: [u8; 32] = u.into();
+    for _ in 1..rounds {
+        let mut next = prf.clone();
+        next.update(&u);
+        u = next.finalize().into_bytes();
+        out.iter_mut().zip(u.iter()).for_each(|(o, b)| *o ^= b);
+    }
+    out
+}
+
+/// The current passphrase stretched with `salt`
+fn stretched_key(salt: &[u8], rounds: u32) -> Result<[u8; 32], String> {
+    let passphrase = PASSPHRASE.read().unwrap().clone().ok_or_else(|| {
+        format!("wallet keys are sealed; set {} to the wallet passphrase", PASSPHRASE_ENV)
+    })?;
+    let mut stretched = STRETCHED.lock().unwrap();
+    if let Some((_, _, key)) = stretched.iter().find(|(s, r, _)| s == salt && *r == rounds) {
+        return Ok(*key);
+    }
+    let key = pbkdf2_sha256(passphrase.as_bytes(), salt, rounds);
+    stretched.push((salt.to_vec(), rounds, key));
+    Ok(key)
+}
+
+impl SealedKeys {
+    /// Encrypt `keys` under the current passphrase
+    fn seal(keys: &Json) -> Result<Self, String> {
+        // A salt already stretched in this process is reused, so saving a wallet
+        // again costs no PBKDF2; the nonce is fresh every time
+        let reused = STRETCHED
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, rounds, _)| *rounds == PBKDF2_ROUNDS)
+            .map(|(salt, _, _)| salt.clone());
+        let salt = reused.unwrap_or_else(|| rand::thread_rng().gen::<[u8; 16]>().to_vec());
+        let key = stretched_key(&salt, PBKDF2_ROUNDS)?;
+        let nonce: [u8; 12] = rand::thread_rng().gen();
+        let plaintext = serde_json::to_vec(keys).map_err(|e| e.to_string())?;
+        let ciphertext = ChaCha20Poly1305::new(&key.into())
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|e| format!("Encryption failed: {}", e))?;
+        Ok(SealedKeys {
+            rounds: PBKDF2_ROUNDS,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Decrypt with the current passphrase; a wrong one fails authentication
+    fn open(&self) -> Result<Json, String> {
+        let decode = |field: &str, value: &str| {
+            hex::decode(value).map_err(|e| format!("sealed_keys {} is not valid hex: {}", field, e))
+        };
+        let nonce: [u8; 12] = decode("nonce", &self.nonce)?
+            .try_into()
+            .map_err(|_| "sealed_keys nonce is not 12 bytes".to_string())?;
+        let key = stretched_key(&decode("salt", &self.salt)?, self.rounds)?;
+        let plaintext = ChaCha20Poly1305::new(&key.into())
+            .decrypt(Nonce::from_slice(&nonce), decode("ciphertext", &self.ciphertext)?.as_ref())
+            .map_err(|_| "wrong wallet passphrase".to_string())?;
+        serde_json::from_slice(&plaintext).map_err(|e| format!("Invalid sealed keys: {}", e))
+    }
+}
+
+/// Replace a wallet's `keys` with `sealed_keys` if a passphrase is set
+pub fn seal_wallet_json(json: &mut Json) -> Result<(), String> {
+    if PASSPHRASE.read().unwrap().is_none() {
+        return Ok(());
+    }
+    let Some(wallet) = json.as_object_mut() else {
+        return Err("wallet is not a JSON object".to_string());
+    };
+    let keys = wallet.remove("keys").ok_or("wallet has no keys")?;
+    let sealed = SealedKeys::seal(&keys)?;
+    wallet.insert("sealed_keys".to_string(), serde_json::to_value(sealed).map_err(|e| e.to_string())?);
+    Ok(())
+}
+
+/// A wallet file's JSON with `sealed_keys` opened back into `keys`; a file with
+/// its keys in the clear comes back as it is
+pub fn open_wallet_file(content: &str) -> Result<Cow<'_, str>, String> {
+    let mut json: Json = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let Some(sealed) = json.as_object_mut().and_then(|w| w.remove("sealed_keys")) else {
+        return Ok(Cow::Borrowed(content));
+    };
+    let sealed: SealedKeys = serde_json::from_value(sealed).map_err(|e| format!("Invalid sealed_keys: {}", e))?;
+    json["keys"] = sealed.open()?;
+    Ok(Cow::Owned(json.to_string()))
+}
+
+/// Take `passphrase` for this process after checking that it opens every sealed
+/// wallet in the data directory; returns how many there are
+pub fn unlock(passphrase: &str) -> Result<usize, String> {
+    if passphrase.is_empty() {
+        return Err("the wallet passphrase must not be empty".to_string());
+    }
+    set_passphrase(Some(passphrase));
+    let mut sealed = 0;
+    for entry in fs::read_dir(data_dir()).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(&path) else { continue };
+        if !content.contains("\"sealed_keys\"") {
+            continue;
+        }
+        if let Err(e) = open_wallet_file(&content) {
+            set_passphrase(None);
+            return Err(format!("{}: {}", path.display(), e));
+        }
+        sealed += 1;
+    }
+    Ok(sealed)
+}
+
+/// Ask for the passphrase on stdin. The terminal still echoes it; set
+/// `WALLET_PASSPHRASE` instead where that matters
+pub fn prompt_passphrase() -> io::Result<String> {
+    print!("🔒 Wallet passphrase: ");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::ShieldedKeys;
+    use crate::utils::{load_wallet, save_wallet, temp_data_dir, wallet_path, Wallet, WALLET_SCHEMA_VERSION};
+
+    #[test]
+    fn test_pbkdf2_sha256_vectors() {
+        // RFC 7914 section 11 (the PBKDF2-HMAC-SHA256 vectors, first 32 bytes)
+        assert_eq!(
+            hex::encode(pbkdf2_sha256(b"password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex::encode(pbkdf2_sha256(b"password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn test_sealed_wallet_needs_the_passphrase() {
+        let (_guard, _dir) = temp_data_dir("sealed_wallet");
+        let keys = ShieldedKeys::from_seed([5u8; 32]);
+        let wallet = Wallet {
+            schema_version: WALLET_SCHEMA_VERSION,
+            keys: keys.clone(),
+            created_at: String::new(),
+        };
+
+        set_passphrase(Some("correct horse"));
+        save_wallet("locked", &wallet).unwrap();
+        let file = fs::read_to_string(wallet_path("locked")).unwrap();
+        assert!(file.contains("sealed_keys"));
+        assert!(!file.contains(&hex::encode(keys.seed())));
+        assert_eq!(load_wallet("locked").unwrap().keys.seed(), keys.seed());
+
+        // Without the passphrase, or with another, the wallet does not open
+        set_passphrase(None);
+        assert!(load_wallet("locked").is_none());
+        assert!(unlock("wrong horse").unwrap_err().contains("wrong wallet passphrase"));
+        assert!(load_wallet("locked").is_none());
+
+        assert_eq!(unlock("correct horse").unwrap(), 1);
+        assert_eq!(load_wallet("locked").unwrap().keys.seed(), keys.seed());
+        set_passphrase(None);
+    }
+}
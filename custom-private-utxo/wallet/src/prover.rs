@@ -1,92 +1,355 @@
-use ff::PrimeField;
-use poseidon_rs::Fr;
+use alloy::primitives::{keccak256, FixedBytes};
+use ff::{Field, PrimeField};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use std::collections::BTreeMap;
 use std::fs;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::future::Future;
+use tokio::process::Command;
 
-use crate::merkle::{CircuitMerklePath, TREE_HEIGHT};
+use crate::error::WalletError;
+use crate::field::{self, fr_to_biguint, Fr};
+use crate::keys::ShieldedKeys;
+use crate::merkle::CircuitMerklePath;
+use crate::notes::Note;
+use crate::scheme::CommitmentScheme;
+use crate::utils::{fr_to_bytes32, u64_to_fr, FrHex};
+
+/// Salt of every dummy note; its nullifier is the owner's `sign_nullifier(DUMMY_SALT)`
+pub const DUMMY_SALT: u64 = 0;
 
 /// Represents a single note for circuit input
-#[derive(Clone, Debug)]
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CircuitNote {
     pub value: u64,
     pub salt: u64,
+    #[serde_as(as = "FrHex")]
     pub owner: Fr, // Public spending key as Fr
     pub asset_id: u64,
     pub maturity_date: u64,
+    /// `Memo::hash` of the memo the note was handed over with, under a scheme that
+    /// commits to it (`scheme::MemoBound`); `None` for notes sent without one
+    #[serde_as(as = "Option<FrHex>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo_hash: Option<Fr>,
+}
+
+/// Why a note's fields do not describe a note the protocol can carry
+#[derive(Debug, Clone, PartialEq)]
+pub enum NoteError {
+    /// Asset ids start at 1; 0 marks no asset
+    ZeroAssetId,
+    /// Zero owner is reserved for padding notes nobody can spend on their own
+    ZeroOwner,
+    /// A zero-coupon bond needs a maturity date
+    ZeroMaturity,
+    /// A new mint would already be redeemable
+    Matured { maturity_date: u64, now: u64 },
+}
+
+impl std::fmt::Display for NoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NoteError::ZeroAssetId => write!(f, "Note asset id must not be 0"),
+            NoteError::ZeroOwner => write!(f, "Note owner must not be the zero key"),
+            NoteError::ZeroMaturity => write!(f, "Note maturity date must not be 0"),
+            NoteError::Matured { maturity_date, now } => write!(
+                f,
+                "Cannot mint a note maturing at {} (already past, now {})",
+                maturity_date, now
+            ),
+        }
+    }
 }
 
+impl std::error::Error for NoteError {}
+
 impl CircuitNote {
-    /// Create a dummy note (value=0) for padding
-    pub fn dummy(owner: Fr, asset_id: u64, maturity_date: u64) -> Self {
+    /// Build a note, checking it is one the protocol can carry. Values and salts
+    /// are `u64`, which always fit the field, so only ids and keys are checked.
+    pub fn new(
+        value: u64,
+        salt: u64,
+        owner: Fr,
+        asset_id: u64,
+        maturity_date: u64,
+    ) -> Result<Self, NoteError> {
+        if asset_id == 0 {
+            return Err(NoteError::ZeroAssetId);
+        }
+        if owner.is_zero() {
+            return Err(NoteError::ZeroOwner);
+        }
+        if maturity_date == 0 {
+            return Err(NoteError::ZeroMaturity);
+        }
+        Ok(Self::new_unchecked(value, salt, owner, asset_id, maturity_date))
+    }
+
+    /// Build a note to be minted at `now`: as `new`, and not yet matured
+    pub fn for_mint(
+        value: u64,
+        salt: u64,
+        owner: Fr,
+        asset_id: u64,
+        maturity_date: u64,
+        now: u64,
+    ) -> Result<Self, NoteError> {
+        if maturity_date <= now {
+            return Err(NoteError::Matured { maturity_date, now });
+        }
+        Self::new(value, salt, owner, asset_id, maturity_date)
+    }
+
+    /// Build a note without checks, for padding notes (e.g. owner 0)
+    pub fn new_unchecked(value: u64, salt: u64, owner: Fr, asset_id: u64, maturity_date: u64) -> Self {
         CircuitNote {
-            value: 0,
-            salt: 0,
+            value,
+            salt,
             owner,
             asset_id,
             maturity_date,
+            memo_hash: None,
+        }
+    }
+
+    /// The note as committed with the memo hash recorded for it, e.g. in its bond file
+    pub fn with_memo_hash(self, memo_hash: Option<Fr>) -> Self {
+        CircuitNote { memo_hash, ..self }
+    }
+
+    /// Under `config::COMMITMENT_SCHEME`, bind the note to the memo `sender` seals
+    /// for the holder of `recipient`, if the scheme commits to memos. Sealing is
+    /// deterministic, so the memo saved later for the note is this same ciphertext.
+    pub fn bound_to_memo(self, sender: &ShieldedKeys, recipient: &[u8; 32]) -> Result<Self, String> {
+        self.bound_to_memo_under(crate::scheme::active(), sender, recipient)
+    }
+
+    /// `bound_to_memo` under `scheme`
+    pub fn bound_to_memo_under(
+        self,
+        scheme: &dyn CommitmentScheme,
+        sender: &ShieldedKeys,
+        recipient: &[u8; 32],
+    ) -> Result<Self, String> {
+        if !scheme.binds_memo() {
+            return Ok(self);
         }
+        let memo = Note::encrypt(sender, recipient, &Note::describing(&self))?;
+        Ok(self.with_memo_hash(Some(memo.hash())))
+    }
+
+    /// Create a dummy note (value=0, salt=`DUMMY_SALT`) for padding
+    pub fn dummy(owner: Fr, asset_id: u64, maturity_date: u64) -> Self {
+        Self::new_unchecked(0, DUMMY_SALT, owner, asset_id, maturity_date)
+    }
+
+    /// The dummy that pads a spend of this note: same owner, asset and maturity.
+    /// onboard mints it next to the real note and buy/redeem look it up, so both
+    /// sides must derive it here. There is no single zero commitment: it depends
+    /// on all three fields.
+    pub fn padding(&self) -> Self {
+        Self::dummy(self.owner, self.asset_id, self.maturity_date)
+    }
+
+    /// Whether this is a dummy (value=0, salt=`DUMMY_SALT`) rather than a real note
+    pub fn is_dummy(&self) -> bool {
+        self.value == 0 && self.salt == DUMMY_SALT
     }
     
-    /// Compute the note commitment (matches circuit's note_commit function)
+    /// Compute the note commitment (matches circuit's note_commit function) under
+    /// `config::COMMITMENT_SCHEME`; for V1,
     /// commitment = poseidon::hash_5([value, salt, owner, asset_id, maturity_date])
+    /// Field order must stay in sync with `note_commit` in circuits/src/main.nr;
+    /// `test_commitment_matches_circuit_vector` fails if either side is reordered.
     pub fn commitment(&self) -> Fr {
-        use poseidon_rs::Poseidon;
-        let hasher = Poseidon::new();
-        hasher.hash(vec![
-            Fr::from_str(&self.value.to_string()).unwrap(),
-            Fr::from_str(&self.salt.to_string()).unwrap(),
-            self.owner.clone(),
-            Fr::from_str(&self.asset_id.to_string()).unwrap(),
-            Fr::from_str(&self.maturity_date.to_string()).unwrap(),
-        ]).unwrap()
+        crate::scheme::active().commit(self)
     }
 }
 
 /// Re-export MerklePath as alias for compatibility
 pub type MerklePath = CircuitMerklePath;
 
+/// Inputs of the circuit's `main`, by the names this wallet writes to Prover.toml
+pub const PROVER_FIELDS: [&str; 16] = [
+    "root",
+    "nullifiers",
+    "commitments_out",
+    "input_values",
+    "input_salts",
+    "input_owner",
+    "input_asset_id",
+    "input_maturity_date",
+    "path_indices",
+    "path_elements",
+    "output_values",
+    "output_salts",
+    "output_owners",
+    "output_asset_ids",
+    "output_maturity_date",
+    "private_key",
+];
+
+/// Extra inputs of a circuit whose `note_commit` binds memos (`scheme::MemoBound`):
+/// the memo hash of each input and output note, zero for notes without a memo
+pub const MEMO_PROVER_FIELDS: [&str; 2] = ["input_memo_hashes", "output_memo_hashes"];
+
+/// Field-name mapping read from the circuit directory: a JSON object from
+/// `PROVER_FIELDS` names to the circuit's own, e.g. `{"root": "merkle_root"}`
+pub const PROVER_FIELDS_FILE: &str = "prover_fields.json";
+
+/// Prover.toml key for each circuit input, for circuits that rename `main`'s
+/// parameters. Inputs without an entry keep their `PROVER_FIELDS` name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProverFields {
+    renames: BTreeMap<String, String>,
+}
+
+impl ProverFields {
+    /// Parse and validate a mapping: every key must be a known input, every name
+    /// a bare TOML key, and no two inputs may end up with the same name
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let renames: BTreeMap<String, String> = serde_json::from_str(json)
+            .map_err(|e| format!("Invalid field mapping: {}", e))?;
+        for (field, name) in &renames {
+            if !PROVER_FIELDS.iter().chain(&MEMO_PROVER_FIELDS).any(|f| f == field) {
+                return Err(format!(
+                    "Unknown circuit input '{}'; expected one of {}",
+                    field,
+                    PROVER_FIELDS.join(", ")
+                ));
+            }
+            let bare = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if name.is_empty() || !bare {
+                return Err(format!("Invalid Prover.toml key '{}' for input '{}'", name, field));
+            }
+        }
+
+        let fields = ProverFields { renames };
+        let mut seen: BTreeMap<&str, &str> = BTreeMap::new();
+        for field in PROVER_FIELDS.iter().chain(&MEMO_PROVER_FIELDS) {
+            if let Some(other) = seen.insert(fields.name(field), field) {
+                return Err(format!(
+                    "Inputs '{}' and '{}' both map to '{}'",
+                    other,
+                    field,
+                    fields.name(field)
+                ));
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Load `PROVER_FIELDS_FILE` from `circuit_dir`; the default names if there is none
+    pub fn load(circuit_dir: &str) -> Result<Self, String> {
+        let path = Path::new(circuit_dir).join(PROVER_FIELDS_FILE);
+        match fs::read_to_string(&path) {
+            Ok(json) => Self::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Prover.toml key for a `PROVER_FIELDS` input
+    pub fn name<'a>(&'a self, field: &'a str) -> &'a str {
+        self.renames.get(field).map(String::as_str).unwrap_or(field)
+    }
+}
+
 /// Witness data for generating a ZK proof
+#[serde_as]
+#[derive(Serialize, Deserialize)]
 pub struct WitnessBuilder {
     // Public inputs
+    #[serde_as(as = "FrHex")]
     pub root: Fr,
-    pub nullifiers: [Fr; 2],
+    #[serde_as(as = "Vec<FrHex>")]
+    pub nullifiers: Vec<Fr>,
+    #[serde_as(as = "[FrHex; 2]")]
     pub commitments_out: [Fr; 2],
 
-    // Input notes
-    pub input_notes: [CircuitNote; 2],
-    pub merkle_paths: [MerklePath; 2],
+    // Input notes (one entry per circuit input slot)
+    pub input_notes: Vec<CircuitNote>,
+    pub merkle_paths: Vec<MerklePath>,
 
     // Output notes
     pub output_notes: [CircuitNote; 2],
 
-    // Private key
+    // Private key: only ever written to Prover.toml, never to the saved witness JSON.
+    // A witness read back with `load_json` carries zero here and cannot be re-proven.
+    #[serde(skip)]
     pub private_key: Fr,
 }
 
 impl WitnessBuilder {
     /// Create a new witness builder with all required data
-    pub fn new(
+    pub fn new<const N: usize>(
         root: Fr,
-        nullifiers: [Fr; 2],
+        nullifiers: [Fr; N],
         commitments_out: [Fr; 2],
-        input_notes: [CircuitNote; 2],
-        merkle_paths: [MerklePath; 2],
+        input_notes: [CircuitNote; N],
+        merkle_paths: [MerklePath; N],
         output_notes: [CircuitNote; 2],
         private_key: Fr,
     ) -> Self {
-        WitnessBuilder {
+        let mut witness = WitnessBuilder {
             root,
-            nullifiers,
+            nullifiers: nullifiers.to_vec(),
             commitments_out,
-            input_notes,
-            merkle_paths,
+            input_notes: input_notes.to_vec(),
+            merkle_paths: merkle_paths.to_vec(),
             output_notes,
             private_key,
+        };
+        witness.canonicalize_outputs();
+        witness
+    }
+
+    /// Put outputs in canonical order: ascending by commitment, compared as the
+    /// big-endian bytes32 submitted on-chain. Each note moves with its commitment.
+    ///
+    /// The circuit checks `commitments_out[i]` against `output_*[i]` positionally, so
+    /// callers must submit `commitments_out` in this order rather than their own.
+    /// Inputs keep their given order (real note first, then the dummy).
+    pub fn canonicalize_outputs(&mut self) {
+        if fr_to_bytes32(&self.commitments_out[0]) > fr_to_bytes32(&self.commitments_out[1]) {
+            self.commitments_out.swap(0, 1);
+            self.output_notes.swap(0, 1);
+        }
+    }
+
+    /// Number of input slots this witness fills
+    pub fn input_count(&self) -> usize {
+        self.input_notes.len()
+    }
+
+    /// Value of a public input by its `TRANSFER_PUBLIC_INPUTS` name. A missing
+    /// second nullifier (1-input circuit) is zero; such a witness is never submitted
+    /// (`check_submittable`).
+    pub fn public_input(&self, name: &str) -> Option<Fr> {
+        match name {
+            "root" => Some(self.root),
+            "nullifiers[0]" => self.nullifiers.first().copied(),
+            "nullifiers[1]" => Some(self.nullifiers.get(1).copied().unwrap_or(Fr::zero())),
+            "commitments_out[0]" => Some(self.commitments_out[0]),
+            "commitments_out[1]" => Some(self.commitments_out[1]),
+            _ => None,
         }
     }
 
-    /// Convert Fr to hex string format for Prover.toml
-    fn fr_to_hex(fr: &Fr) -> String {
+    /// Render a list as a Prover.toml array
+    pub(crate) fn toml_array<T>(items: &[T], render: impl Fn(&T) -> String) -> String {
+        format!(
+            "[{}]",
+            items.iter().map(render).collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    /// A field element as a Prover.toml integer literal: minimal 0x hex, leading
+    /// zeros stripped. Everything stored on disk uses `utils::fr_to_hex` instead
+    pub(crate) fn toml_integer(fr: &Fr) -> String {
         // Fr's repr is [u64; 4] in little-endian limb order
         let repr = fr.into_repr();
         let limbs: &[u64] = repr.as_ref();
@@ -113,131 +376,153 @@ impl WitnessBuilder {
         }
     }
 
-    /// Convert Fr to plain string (for private_key which doesn't use hex format)
-    fn fr_to_string(fr: &Fr) -> String {
-        format!("{}", fr)
+    /// Generate Prover.toml content with the circuit's default input names
+    pub fn to_prover_toml(&self) -> String {
+        self.to_prover_toml_with(&ProverFields::default())
+    }
+
+    /// Generate Prover.toml content, naming each input as `fields` maps it
+    pub fn to_prover_toml_with(&self, fields: &ProverFields) -> String {
+        self.prover_toml(fields, crate::scheme::active())
     }
 
-    /// Generate Prover.toml content
-    pub fn to_prover_toml(&self) -> String {
+    /// Prover.toml for a circuit hashing notes under `scheme`
+    fn prover_toml(&self, fields: &ProverFields, scheme: &dyn CommitmentScheme) -> String {
         let mut toml = String::new();
+        let line = |toml: &mut String, field: &str, value: String| {
+            toml.push_str(&format!("{} = {}\n", fields.name(field), value));
+        };
+        let quoted = |fr: &Fr| format!("\"{}\"", Self::toml_integer(fr));
 
         // Public inputs
         toml.push_str("# Public inputs (witnesses to be proven on-chain)\n");
-        toml.push_str(&format!("root = \"{}\"\n", Self::fr_to_hex(&self.root)));
-        toml.push_str(&format!(
-            "nullifiers = [\"{}\", \"{}\"]\n",
-            Self::fr_to_hex(&self.nullifiers[0]),
-            Self::fr_to_hex(&self.nullifiers[1])
-        ));
-        toml.push_str(&format!(
-            "commitments_out = [\"{}\", \"{}\"]\n",
-            Self::fr_to_hex(&self.commitments_out[0]),
-            Self::fr_to_hex(&self.commitments_out[1])
-        ));
-        toml.push('\n');
+        line(&mut toml, "root", quoted(&self.root));
+        line(&mut toml, "nullifiers", Self::toml_array(&self.nullifiers, quoted));
+        line(&mut toml, "commitments_out", Self::toml_array(&self.commitments_out, quoted));
 
         // Input notes - use hex strings for salts (large u64 values overflow Noir's parser)
-        toml.push_str("# Input notes (private)\n");
-        toml.push_str(&format!(
-            "input_values = [{}, {}]\n",
-            self.input_notes[0].value,
-            self.input_notes[1].value
-        ));
-        toml.push_str(&format!(
-            "input_salts = [\"0x{:x}\", \"0x{:x}\"]\n",
-            self.input_notes[0].salt,
-            self.input_notes[1].salt
-        ));
-        toml.push_str(&format!(
-            "input_owner = \"{}\"\n",
-            Self::fr_to_hex(&self.input_notes[0].owner)
-        ));
-        toml.push_str(&format!(
-            "input_asset_id = {}\n",
-            self.input_notes[0].asset_id
-        ));
-        toml.push_str(&format!(
-            "input_maturity_date = {}\n",
-            self.input_notes[0].maturity_date
-        ));
-        toml.push('\n');
+        toml.push_str("\n# Input notes (private)\n");
+        line(
+            &mut toml,
+            "input_values",
+            Self::toml_array(&self.input_notes, |n| n.value.to_string()),
+        );
+        line(
+            &mut toml,
+            "input_salts",
+            Self::toml_array(&self.input_notes, |n| format!("\"0x{:x}\"", n.salt)),
+        );
+        line(&mut toml, "input_owner", quoted(&self.input_notes[0].owner));
+        line(&mut toml, "input_asset_id", self.input_notes[0].asset_id.to_string());
+        line(&mut toml, "input_maturity_date", self.input_notes[0].maturity_date.to_string());
 
         // Merkle paths
-        toml.push_str("# Merkle proof paths for each input note\n");
-        toml.push_str(&format!(
-            "path_indices = [[{}], [{}]]\n",
-            self.merkle_paths[0]
-                .indices
-                .iter()
-                .map(|i| i.to_string())
-                .collect::<Vec<_>>()
-                .join(", "),
-            self.merkle_paths[1]
-                .indices
-                .iter()
-                .map(|i| i.to_string())
-                .collect::<Vec<_>>()
-                .join(", ")
-        ));
-        toml.push_str(&format!(
-            "path_elements = [[{}], [{}]]\n",
-            self.merkle_paths[0]
-                .elements
-                .iter()
-                .map(|e| format!("\"{}\"", Self::fr_to_hex(e)))
-                .collect::<Vec<_>>()
-                .join(", "),
-            self.merkle_paths[1]
-                .elements
-                .iter()
-                .map(|e| format!("\"{}\"", Self::fr_to_hex(e)))
-                .collect::<Vec<_>>()
-                .join(", ")
-        ));
-        toml.push('\n');
+        toml.push_str("\n# Merkle proof paths for each input note\n");
+        line(
+            &mut toml,
+            "path_indices",
+            Self::toml_array(&self.merkle_paths, MerklePath::indices_toml),
+        );
+        line(
+            &mut toml,
+            "path_elements",
+            Self::toml_array(&self.merkle_paths, MerklePath::elements_toml),
+        );
 
         // Output notes - use hex strings for salts (large u64 values overflow Noir's parser)
-        toml.push_str("# Output notes (private)\n");
-        toml.push_str(&format!(
-            "output_values = [{}, {}]\n",
-            self.output_notes[0].value,
-            self.output_notes[1].value
-        ));
-        toml.push_str(&format!(
-            "output_salts = [\"0x{:x}\", \"0x{:x}\"]\n",
-            self.output_notes[0].salt,
-            self.output_notes[1].salt
-        ));
-        toml.push_str(&format!(
-            "output_owners = [\"{}\", \"{}\"]\n",
-            Self::fr_to_hex(&self.output_notes[0].owner),
-            Self::fr_to_hex(&self.output_notes[1].owner)
-        ));
-        toml.push_str(&format!(
-            "output_asset_ids = [{}, {}]\n",
-            self.output_notes[0].asset_id,
-            self.output_notes[1].asset_id
-        ));
-        toml.push_str(&format!(
-            "output_maturity_date = {}\n",
-            self.output_notes[0].maturity_date
-        ));
-        toml.push('\n');
+        toml.push_str("\n# Output notes (private)\n");
+        line(
+            &mut toml,
+            "output_values",
+            Self::toml_array(&self.output_notes, |n| n.value.to_string()),
+        );
+        line(
+            &mut toml,
+            "output_salts",
+            Self::toml_array(&self.output_notes, |n| format!("\"0x{:x}\"", n.salt)),
+        );
+        line(
+            &mut toml,
+            "output_owners",
+            Self::toml_array(&self.output_notes, |n| quoted(&n.owner)),
+        );
+        line(
+            &mut toml,
+            "output_asset_ids",
+            Self::toml_array(&self.output_notes, |n| n.asset_id.to_string()),
+        );
+        line(&mut toml, "output_maturity_date", self.output_notes[0].maturity_date.to_string());
 
-        // Private key - use decimal format like the working example
-        toml.push_str("# Private key\n");
-        toml.push_str(&format!(
-            "private_key = \"{}\"\n",
-            Self::fr_to_hex(&self.private_key)
-        ));
+        // Memo hashes, for circuits whose note_commit takes them
+        if scheme.binds_memo() {
+            let memo_hash = |n: &CircuitNote| quoted(&n.memo_hash.unwrap_or_else(|| u64_to_fr(0)));
+            toml.push_str("\n# Memo hashes bound into each note's commitment\n");
+            line(&mut toml, "input_memo_hashes", Self::toml_array(&self.input_notes, memo_hash));
+            line(&mut toml, "output_memo_hashes", Self::toml_array(&self.output_notes, memo_hash));
+        }
+
+        // Private key
+        toml.push_str("\n# Private key\n");
+        line(&mut toml, "private_key", quoted(&self.private_key));
 
         toml
     }
 
+    /// Save the structured witness as JSON (field elements as canonical bytes32 hex)
+    pub fn save_json(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize witness: {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Failed to write witness {}: {}", path, e))
+    }
+
+    /// Load a witness previously written by `save_json`
+    pub fn load_json(path: &str) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read witness {}: {}", path, e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Failed to parse witness {}: {}", path, e))
+    }
+
+    /// The circuit takes one `input_maturity_date` and one `output_maturity_date` and
+    /// asserts they are equal, so every note in a witness must share a maturity.
+    /// Splitting a tranche into staggered (serial) maturities is not provable.
+    pub fn check_single_maturity(&self) -> Result<(), String> {
+        let maturity = self
+            .input_notes
+            .first()
+            .ok_or("Witness has no input notes")?
+            .maturity_date;
+        let notes = self.input_notes.iter().chain(self.output_notes.iter());
+        match notes.map(|n| n.maturity_date).find(|m| *m != maturity) {
+            Some(other) => Err(format!(
+                "Notes must share one maturity: input matures {} but a note has {}; \
+                 the circuit cannot change or split maturities",
+                maturity, other
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The circuit recomputes each output's `note_commit` and asserts it equals
+    /// `commitments_out[i]`. Recompute them here first, so a witness whose
+    /// commitments drifted from its notes fails before nargo or bb run.
+    pub fn check_output_commitments(&self) -> Result<(), String> {
+        for (i, (note, submitted)) in self.output_notes.iter().zip(&self.commitments_out).enumerate() {
+            let computed = note.commitment();
+            if computed != *submitted {
+                return Err(format!(
+                    "wallet/circuit commitment mismatch on output {}: its note hashes to {} but commitments_out[{}] is {}",
+                    i, computed, i, submitted
+                ));
+            }
+        }
+        Ok(())
+    }
+
     /// Write Prover.toml to the circuit directory
-    pub fn write_prover_toml(&self, circuit_dir: &str) -> Result<(), String> {
-        let content = self.to_prover_toml();
+    pub fn write_prover_toml(&self, circuit_dir: &str, fields: &ProverFields) -> Result<(), String> {
+        self.check_single_maturity()?;
+        self.check_output_commitments()?;
+        let content = self.to_prover_toml_with(fields);
         let path = format!("{}/Prover.toml", circuit_dir);
 
         fs::write(&path, content).map_err(|e| format!("Failed to write Prover.toml: {}", e))?;
@@ -247,8 +532,20 @@ impl WitnessBuilder {
     }
 }
 
-/// Generate a proof for a bond using nargo and bb
-pub async fn generate_proof(circuit_dir: &str, witness_name: &str) -> Result<String, String> {
+/// Generate a proof for a bond using nargo and bb. A tool that cannot be started is
+/// `ToolingMissing`; one that runs and rejects the witness is `ProofFailed`.
+pub async fn generate_proof(
+    circuit_dir: &str,
+    witness_name: &str,
+    out_dir: &str,
+) -> Result<String, WalletError> {
+    // bb runs inside the circuit directory, so hand it an absolute output path
+    fs::create_dir_all(out_dir).map_err(|e| format!("Failed to create {}: {}", out_dir, e))?;
+    let out_dir = fs::canonicalize(out_dir)
+        .map_err(|e| format!("Failed to resolve {}: {}", out_dir, e))?
+        .to_string_lossy()
+        .to_string();
+
     println!("   🔄 Generating witness...");
 
     // Step 1: nargo execute to generate witness
@@ -256,45 +553,481 @@ pub async fn generate_proof(circuit_dir: &str, witness_name: &str) -> Result<Str
         .arg("execute")
         .arg(witness_name)
         .current_dir(circuit_dir)
+        .kill_on_drop(true)
         .output()
-        .map_err(|e| format!("Failed to run nargo: {}", e))?;
+        .await
+        .map_err(|e| WalletError::ToolingMissing(format!("Failed to run nargo: {}", e)))?;
 
     if !output.status.success() {
-        return Err(format!(
-            "nargo execute failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
+        return Err(WalletError::ProofFailed(nargo_execute_error(&String::from_utf8_lossy(
+            &output.stderr,
+        ))));
     }
 
     println!("   ✅ Witness generated");
     println!("   🔄 Generating proof with bb...");
 
     // Step 2: bb prove to generate actual proof
+    let started = std::time::Instant::now();
     let bb_output = Command::new("bb")
         .arg("prove")
         .arg("-b")
-        .arg(&format!("./target/{}.json", witness_name))
+        .arg(format!("./target/{}.json", witness_name))
         .arg("-w")
-        .arg(&format!("./target/{}", witness_name))
+        .arg(format!("./target/{}", witness_name))
         .arg("-o")
-        .arg("./target")
+        .arg(&out_dir)
         .arg("--oracle_hash")
         .arg("keccak")
         .current_dir(circuit_dir)
+        .kill_on_drop(true)
         .output()
-        .map_err(|e| format!("Failed to run bb prove: {}", e))?;
+        .await
+        .map_err(|e| WalletError::ToolingMissing(format!("Failed to run bb prove: {}", e)))?;
 
     if !bb_output.status.success() {
-        return Err(format!(
+        return Err(WalletError::ProofFailed(format!(
             "bb prove failed: {}",
             String::from_utf8_lossy(&bb_output.stderr)
-        ));
+        )));
     }
 
+    let gen_millis = started.elapsed().as_millis() as u64;
     println!("   ✅ Proof generated!");
 
-    // Return path to proof
-    Ok(format!("{}/target/proof", circuit_dir))
+    // Return path to proof, checking bb actually wrote it where we expect
+    let proof_path = locate_proof(&out_dir).map_err(|e| e.to_string())?;
+    let version = match Command::new("bb").arg("--version").output().await {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).to_string(),
+        _ => String::new(),
+    };
+    match ProofMeta::from_bb_output(&proof_path, &version, gen_millis).and_then(|meta| {
+        meta.save(&proof_path)?;
+        Ok(meta)
+    }) {
+        Ok(meta) => println!("   📏 {}", meta),
+        Err(e) => println!("   ⚠️  No proof metadata recorded: {}", e),
+    }
+    Ok(proof_path)
+}
+
+/// Explain a failed `nargo execute`. The circuit exposes no computed commitments,
+/// only `assert(output_commitments == commitments_out[i])`, so a failure on that
+/// assert is how a drift between the wallet's and the circuit's `note_commit`
+/// (e.g. a `config::COMMITMENT_SCHEME` for another circuit version) surfaces.
+pub fn nargo_execute_error(stderr: &str) -> String {
+    if stderr.contains("== commitments_out") {
+        format!(
+            "wallet/circuit commitment mismatch: the circuit's note_commit disagrees with the \
+             commitments the wallet computed; check config::COMMITMENT_SCHEME matches the circuit\n{}",
+            stderr
+        )
+    } else {
+        format!("nargo execute failed: {}", stderr)
+    }
+}
+
+/// What bb produced for one proof, kept next to it as `<proof>.meta.json` so the
+/// public-input count can be checked against the contract before submitting
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProofMeta {
+    pub size_bytes: u64,
+    /// Field elements in the `public_inputs` file bb writes next to the proof
+    pub num_public_inputs: usize,
+    pub gen_millis: u64,
+    pub backend_version: String,
+    /// Root the proof was built against (bytes32 hex, its first public input);
+    /// absent from metadata recorded before roots were
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+}
+
+/// The `public_inputs` file bb writes next to `proof_path`, one bytes32 per input
+/// in `TRANSFER_PUBLIC_INPUTS` order
+pub fn read_public_inputs(proof_path: &str) -> Result<Vec<FixedBytes<32>>, String> {
+    let path = Path::new(proof_path).with_file_name("public_inputs");
+    let bytes = fs::read(&path).map_err(|_| format!("bb wrote no public_inputs next to {}", proof_path))?;
+    if bytes.len() % 32 != 0 {
+        return Err(format!(
+            "{} is {} bytes, not a whole number of field elements",
+            path.display(),
+            bytes.len()
+        ));
+    }
+    Ok(bytes.chunks(32).map(FixedBytes::from_slice).collect())
+}
+
+/// Sidecar file holding a proof's `ProofMeta`
+pub fn proof_meta_path(proof_path: &str) -> String {
+    format!("{}.meta.json", proof_path)
+}
+
+impl ProofMeta {
+    /// Read bb's output: the proof at `proof_path`, the `public_inputs` file beside
+    /// it (one 32-byte field element each) and what `bb --version` printed
+    pub fn from_bb_output(proof_path: &str, bb_version: &str, gen_millis: u64) -> Result<Self, String> {
+        let size_bytes = fs::metadata(proof_path)
+            .map_err(|e| format!("Failed to read {}: {}", proof_path, e))?
+            .len();
+        let inputs = read_public_inputs(proof_path)?;
+        // `bb --version` prints e.g. "0.87.0" or "v0.87.0", sometimes after a name
+        let backend_version = bb_version
+            .split_whitespace()
+            .last()
+            .map(|v| v.trim_start_matches('v').to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        Ok(ProofMeta {
+            size_bytes,
+            num_public_inputs: inputs.len(),
+            gen_millis,
+            backend_version,
+            root: inputs.first().map(|root| format!("{}", root)),
+        })
+    }
+
+    pub fn save(&self, proof_path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize proof metadata: {}", e))?;
+        fs::write(proof_meta_path(proof_path), json)
+            .map_err(|e| format!("Failed to write proof metadata: {}", e))
+    }
+
+    /// The proof's metadata, or `None` for a proof made without it (e.g. by hand)
+    pub fn load(proof_path: &str) -> Option<Result<Self, String>> {
+        let path = proof_meta_path(proof_path);
+        let json = fs::read_to_string(&path).ok()?;
+        Some(serde_json::from_str(&json).map_err(|e| format!("Invalid proof metadata {}: {}", path, e)))
+    }
+
+    /// A proof over a different number of public inputs than the verifier takes
+    /// would only fail on-chain
+    pub fn check_public_inputs(&self, expected: usize) -> Result<(), String> {
+        if self.num_public_inputs != expected {
+            return Err(format!(
+                "Proof has {} public inputs but the contract's verifier expects {}; was the circuit changed without redeploying?",
+                self.num_public_inputs, expected
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ProofMeta {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Proof: {} bytes, {} public inputs, {} ms (bb {})",
+            self.size_bytes, self.num_public_inputs, self.gen_millis, self.backend_version
+        )
+    }
+}
+
+/// Why a proof could not be picked up after `bb prove`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProofError {
+    /// No proof file at `expected`, and no single other `proof*` file in the output directory
+    OutputNotFound {
+        expected: String,
+        candidates: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for ProofError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ProofError::OutputNotFound {
+                expected,
+                candidates,
+            } if candidates.is_empty() => {
+                write!(f, "bb reported success but no proof was written to {}", expected)
+            }
+            ProofError::OutputNotFound {
+                expected,
+                candidates,
+            } => write!(
+                f,
+                "No proof at {}; found several candidates, pick one: {}",
+                expected,
+                candidates.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+/// Find the proof bb wrote under `target_dir`: `proof` itself, or else the one
+/// `proof*` file (e.g. a versioned name) directly in it or in a subfolder
+pub fn locate_proof(target_dir: &str) -> Result<String, ProofError> {
+    let expected = format!("{}/proof", target_dir);
+    if Path::new(&expected).is_file() {
+        return Ok(expected);
+    }
+
+    let mut candidates: Vec<String> = Vec::new();
+    let mut dirs = vec![PathBuf::from(target_dir)];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                // One level of subfolders only
+                if dir == Path::new(target_dir) {
+                    dirs.push(path);
+                }
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with("proof") && !name.ends_with(".json") {
+                candidates.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+    candidates.sort();
+
+    match candidates.as_slice() {
+        [only] => Ok(only.clone()),
+        _ => Err(ProofError::OutputNotFound {
+            expected,
+            candidates,
+        }),
+    }
+}
+
+/// Public inputs in the order `PrivateBond.transfer`/`burn` hand them to the verifier
+pub const TRANSFER_PUBLIC_INPUTS: [&str; 5] = [
+    "root",
+    "nullifiers[0]",
+    "nullifiers[1]",
+    "commitments_out[0]",
+    "commitments_out[1]",
+];
+
+/// Refuse a witness shape the deployed contract cannot take. `PrivateBond.transfer`
+/// and `burn` mark both `nullifiersIn` spent and verify against
+/// `TRANSFER_PUBLIC_INPUTS`, so a 1-input circuit has nothing to put in the second
+/// slot: sent as zero, the first spend marks it spent and every later one reverts.
+/// It stays refused until a contract and verifier that take one nullifier exist.
+pub fn check_submittable(circuit_inputs: usize) -> Result<(), String> {
+    if circuit_inputs == 2 {
+        return Ok(());
+    }
+    Err(format!(
+        "A {}-input circuit cannot be submitted: PrivateBond takes 2 nullifiers and its verifier {} public inputs",
+        circuit_inputs,
+        TRANSFER_PUBLIC_INPUTS.len()
+    ))
+}
+
+/// JSON description of the verifier's public inputs, for integrators deploying their own
+pub fn public_input_layout() -> serde_json::Value {
+    let inputs: Vec<serde_json::Value> = TRANSFER_PUBLIC_INPUTS
+        .iter()
+        .enumerate()
+        .map(|(index, name)| serde_json::json!({ "index": index, "name": name, "type": "bytes32" }))
+        .collect();
+    serde_json::json!({
+        "function": "transfer(bytes proof, bytes32 root, bytes32[2] nullifiersIn, bytes32[2] commitmentsOut)",
+        "encoding": "big-endian field elements as bytes32",
+        "public_inputs": inputs,
+    })
+}
+
+/// Write the verification key with bb and export it with the public-input layout.
+/// Returns the paths of the VK and layout files.
+pub async fn export_vk(circuit_dir: &str, out_dir: &str) -> Result<(String, String), WalletError> {
+    let output = Command::new("bb")
+        .arg("write_vk")
+        .arg("-b")
+        .arg("./target/circuits.json")
+        .arg("-o")
+        .arg("./target")
+        .arg("--oracle_hash")
+        .arg("keccak")
+        .current_dir(circuit_dir)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| WalletError::ToolingMissing(format!("Failed to run bb write_vk: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(WalletError::Other(format!(
+            "bb write_vk failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    fs::create_dir_all(out_dir).map_err(|e| format!("Failed to create {}: {}", out_dir, e))?;
+    let vk_path = format!("{}/vk", out_dir);
+    fs::copy(format!("{}/target/vk", circuit_dir), &vk_path)
+        .map_err(|e| format!("Failed to copy vk: {}", e))?;
+
+    let layout_path = format!("{}/public_inputs.json", out_dir);
+    let layout = serde_json::to_string_pretty(&public_input_layout())
+        .map_err(|e| format!("Failed to serialize layout: {}", e))?;
+    fs::write(&layout_path, layout).map_err(|e| format!("Failed to write layout: {}", e))?;
+
+    Ok((vk_path, layout_path))
+}
+
+/// Proving backend: turns a witness into a proof file and returns its path
+pub trait Prover {
+    fn prove(&self, witness: &WitnessBuilder)
+        -> impl Future<Output = Result<String, WalletError>> + Send;
+
+    /// Prove one of several witnesses proven concurrently (trade's two legs). Each
+    /// `leg` writes to its own files, so legs never clobber each other's output.
+    fn prove_leg(&self, witness: &WitnessBuilder, leg: &str)
+        -> impl Future<Output = Result<String, WalletError>> + Send;
+}
+
+/// Real backend: writes Prover.toml and shells out to nargo + bb
+pub struct BbProver {
+    pub circuit_dir: String,
+    pub witness_name: String,
+    /// Prover.toml names of the circuit's inputs
+    pub fields: ProverFields,
+    /// Where bb writes proofs, one subfolder per witness; `None` keeps them in
+    /// the circuit's own `target/`
+    pub proof_out_dir: Option<String>,
+}
+
+impl BbProver {
+    pub fn new(circuit_dir: &str) -> Self {
+        BbProver {
+            circuit_dir: circuit_dir.to_string(),
+            witness_name: "circuits".to_string(),
+            fields: ProverFields::default(),
+            proof_out_dir: None,
+        }
+    }
+
+    /// Write proofs under `dir` instead of the circuit's `target/`
+    pub fn with_proof_out_dir(mut self, dir: &str) -> Self {
+        self.proof_out_dir = Some(dir.to_string());
+        self
+    }
+
+    /// Output directory for proving `witness` from `circuit_dir`. Under
+    /// `proof_out_dir` it is named after the witness, so concurrent or
+    /// cached proofs of different witnesses never share a folder.
+    pub fn proof_dir(&self, circuit_dir: &str, witness: &WitnessBuilder) -> String {
+        match &self.proof_out_dir {
+            Some(base) => format!("{}/{}", base, witness_id(witness)),
+            None => format!("{}/target", circuit_dir),
+        }
+    }
+
+    /// Use the circuit directory's field-name mapping, failing if it is invalid
+    pub fn with_circuit_fields(mut self) -> Result<Self, String> {
+        self.fields = ProverFields::load(&self.circuit_dir)?;
+        Ok(self)
+    }
+}
+
+impl Prover for BbProver {
+    async fn prove(&self, witness: &WitnessBuilder) -> Result<String, WalletError> {
+        witness
+            .write_prover_toml(&self.circuit_dir, &self.fields)
+            .map_err(WalletError::ProofFailed)?;
+        let out_dir = self.proof_dir(&self.circuit_dir, witness);
+        generate_proof(&self.circuit_dir, &self.witness_name, &out_dir).await
+    }
+
+    async fn prove_leg(&self, witness: &WitnessBuilder, leg: &str) -> Result<String, WalletError> {
+        let dir = leg_circuit_dir(&self.circuit_dir, leg)?;
+        witness
+            .write_prover_toml(&dir, &self.fields)
+            .map_err(WalletError::ProofFailed)?;
+        let out_dir = self.proof_dir(&dir, witness);
+        generate_proof(&dir, &self.witness_name, &out_dir).await
+    }
+}
+
+/// Short stable identifier of a witness: the first 8 bytes of the keccak256 of
+/// its Prover.toml, as hex
+pub fn witness_id(witness: &WitnessBuilder) -> String {
+    hex::encode(&keccak256(witness.to_prover_toml())[..8])
+}
+
+/// Working copy of the circuit project for one proving leg, under
+/// `target/legs/{leg}`: its own Prover.toml and `target/`, same sources
+pub fn leg_circuit_dir(circuit_dir: &str, leg: &str) -> Result<String, String> {
+    let dir = format!("{}/target/legs/{}", circuit_dir, leg);
+    copy_dir(&format!("{}/src", circuit_dir), &format!("{}/src", dir))?;
+    fs::copy(format!("{}/Nargo.toml", circuit_dir), format!("{}/Nargo.toml", dir))
+        .map_err(|e| format!("Failed to copy Nargo.toml for leg {}: {}", leg, e))?;
+    Ok(dir)
+}
+
+fn copy_dir(from: &str, to: &str) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| format!("Failed to create {}: {}", to, e))?;
+    for entry in fs::read_dir(from).map_err(|e| format!("Failed to read {}: {}", from, e))? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let source = entry.path().to_string_lossy().to_string();
+        let target = format!("{}/{}", to, entry.file_name().to_string_lossy());
+        if entry.path().is_dir() {
+            copy_dir(&source, &target)?;
+        } else {
+            fs::copy(&source, &target).map_err(|e| format!("Failed to copy {}: {}", source, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Test backend: writes a dummy proof and the public inputs it would commit to,
+/// without running nargo or bb
+pub struct MockProver {
+    pub out_dir: String,
+    /// Public inputs to report; defaults to root, nullifiers and commitments of the witness
+    pub public_inputs: Option<Vec<Fr>>,
+}
+
+impl MockProver {
+    pub fn new(out_dir: &str) -> Self {
+        MockProver {
+            out_dir: out_dir.to_string(),
+            public_inputs: None,
+        }
+    }
+
+    /// Public inputs file written next to the proof, as concatenated bytes32
+    pub fn public_inputs_path(&self) -> String {
+        format!("{}/public_inputs", self.out_dir)
+    }
+}
+
+impl Prover for MockProver {
+    async fn prove(&self, witness: &WitnessBuilder) -> Result<String, WalletError> {
+        // Reject what the circuit would
+        witness.check_single_maturity().map_err(WalletError::ProofFailed)?;
+        witness.check_output_commitments().map_err(WalletError::ProofFailed)?;
+        fs::create_dir_all(&self.out_dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.out_dir, e))?;
+
+        let inputs = self.public_inputs.clone().unwrap_or_else(|| {
+            let mut inputs = vec![witness.root];
+            inputs.extend(&witness.nullifiers);
+            inputs.extend(witness.commitments_out);
+            inputs
+        });
+        let bytes: Vec<u8> = inputs.iter().flat_map(|fr| fr_to_bytes32(fr).0).collect();
+        fs::write(self.public_inputs_path(), bytes)
+            .map_err(|e| format!("Failed to write public inputs: {}", e))?;
+
+        let proof_path = format!("{}/proof", self.out_dir);
+        fs::write(&proof_path, [0u8; 32]).map_err(|e| format!("Failed to write proof: {}", e))?;
+        ProofMeta::from_bb_output(&proof_path, "mock", 0)?.save(&proof_path)?;
+        Ok(proof_path)
+    }
+
+    async fn prove_leg(&self, witness: &WitnessBuilder, leg: &str) -> Result<String, WalletError> {
+        MockProver {
+            out_dir: format!("{}/{}", self.out_dir, leg),
+            public_inputs: self.public_inputs.clone(),
+        }
+        .prove(witness)
+        .await
+    }
 }
 
 /// Helper: Build a witness for a single-input operation (buy, redeem)
@@ -310,42 +1043,22 @@ pub fn build_single_note_witness(
 ) -> WitnessBuilder {
     // Create dummy for second input (same owner, zero value)
     let dummy_input = CircuitNote::dummy(
-        input_note.owner.clone(),
+        input_note.owner,
         input_note.asset_id,
         input_note.maturity_date,
     );
     let dummy_path = MerklePath::dummy();
 
-    // Compute dummy nullifier (Poseidon(0, private_key))
-    let dummy_nullifier = {
-        use poseidon_rs::Poseidon;
-        let hasher = Poseidon::new();
-        hasher
-            .hash(vec![Fr::from_str("0").unwrap(), private_key.clone()])
-            .unwrap()
-    };
+    let dummy_nullifier = crate::scheme::active().nullify(DUMMY_SALT, &private_key);
 
     // Create dummy for second output
     let dummy_output = CircuitNote::dummy(
-        output_note.owner.clone(),
+        output_note.owner,
         output_note.asset_id,
         output_note.maturity_date,
     );
 
-    // Compute dummy output commitment
-    let dummy_commitment = {
-        use poseidon_rs::Poseidon;
-        let hasher = Poseidon::new();
-        hasher
-            .hash(vec![
-                Fr::from_str("0").unwrap(), // value
-                Fr::from_str("0").unwrap(), // salt
-                dummy_output.owner.clone(),
-                Fr::from_str(&dummy_output.asset_id.to_string()).unwrap(),
-                Fr::from_str(&dummy_output.maturity_date.to_string()).unwrap(),
-            ])
-            .unwrap()
-    };
+    let dummy_commitment = dummy_output.commitment();
 
     WitnessBuilder::new(
         root,
@@ -360,6 +1073,7 @@ pub fn build_single_note_witness(
 
 /// Helper: Build a witness for a JoinSplit (buy) operation
 /// 1 real input with merkle proof, 1 dummy input (value=0) also in tree, 2 real outputs
+#[allow(clippy::too_many_arguments)]
 pub fn build_joinsplit_witness(
     root: Fr,
     input_note: CircuitNote,
@@ -371,13 +1085,7 @@ pub fn build_joinsplit_witness(
     output_commitments: [Fr; 2],        // [buyer_commitment, change_commitment]
     private_key: Fr,
 ) -> WitnessBuilder {
-    // Compute dummy nullifier (Poseidon(salt=0, private_key))
-    let dummy_nullifier = {
-        use poseidon_rs::Poseidon;
-        let hasher = Poseidon::new();
-        let salt_zero = Fr::from_str("0").unwrap();
-        hasher.hash(vec![salt_zero, private_key.clone()]).unwrap()
-    };
+    let dummy_nullifier = crate::scheme::active().nullify(DUMMY_SALT, &private_key);
 
     WitnessBuilder::new(
         root,
@@ -390,6 +1098,94 @@ pub fn build_joinsplit_witness(
     )
 }
 
+/// Helper: Build a witness spending one whole note, shaped for the configured circuit.
+/// A 1-input circuit takes the real note alone; a 2-input circuit needs the dummy note
+/// (value=0, salt=0, already in the tree) and its merkle path for the second slot.
+pub fn build_spend_witness(
+    circuit_inputs: usize,
+    root: Fr,
+    input: (CircuitNote, MerklePath, Fr),
+    dummy: Option<(CircuitNote, MerklePath)>,
+    output_notes: [CircuitNote; 2],
+    output_commitments: [Fr; 2],
+    private_key: Fr,
+) -> Result<WitnessBuilder, String> {
+    let (input_note, input_merkle_path, input_nullifier) = input;
+    match (circuit_inputs, dummy) {
+        (1, None) => Ok(WitnessBuilder::new(
+            root,
+            [input_nullifier],
+            output_commitments,
+            [input_note],
+            [input_merkle_path],
+            output_notes,
+            private_key,
+        )),
+        (1, Some(_)) => Err("1-input circuit does not take a dummy input note".to_string()),
+        (2, Some((dummy_note, dummy_path))) => Ok(build_joinsplit_witness(
+            root,
+            input_note,
+            input_merkle_path,
+            input_nullifier,
+            dummy_note,
+            dummy_path,
+            output_notes,
+            output_commitments,
+            private_key,
+        )),
+        (2, None) => Err("2-input circuit requires a dummy input note".to_string()),
+        (n, _) => Err(format!("Unsupported circuit input count: {} (expected 1 or 2)", n)),
+    }
+}
+
+/// Helper: Build a witness spending several owned notes in one JoinSplit, one per
+/// input slot, for a circuit that takes a real note in every slot (`multi_input`).
+/// The deployed circuit and contract take one real note plus the dummy, so no
+/// command builds one yet; spends go through `build_spend_witness`. The inputs
+/// share the circuit's single `input_owner`, and value is conserved across all of them.
+pub fn build_multi_input_witness(
+    circuit_inputs: usize,
+    multi_input: bool,
+    root: Fr,
+    inputs: Vec<(CircuitNote, MerklePath, Fr)>,
+    output_notes: [CircuitNote; 2],
+    output_commitments: [Fr; 2],
+    private_key: Fr,
+) -> Result<WitnessBuilder, String> {
+    if inputs.len() > 1 && !multi_input {
+        return Err(format!(
+            "Circuit takes one real input note plus the dummy, not {} notes",
+            inputs.len()
+        ));
+    }
+    if inputs.len() != circuit_inputs {
+        return Err(format!(
+            "{} input notes for a {}-input circuit",
+            inputs.len(),
+            circuit_inputs
+        ));
+    }
+    let mut input_notes = Vec::with_capacity(inputs.len());
+    let mut merkle_paths = Vec::with_capacity(inputs.len());
+    let mut nullifiers = Vec::with_capacity(inputs.len());
+    for (note, path, nullifier) in inputs {
+        input_notes.push(note);
+        merkle_paths.push(path);
+        nullifiers.push(nullifier);
+    }
+    JoinSplitInputs {
+        root,
+        input_notes,
+        merkle_paths,
+        nullifiers,
+        output_notes,
+        commitments_out: output_commitments,
+        private_key,
+        burn: false,
+    }
+    .build()
+}
+
 /// Helper: Build a witness for a trade operation (two inputs, two outputs)
 pub fn build_trade_witness(
     root: Fr,
@@ -420,71 +1216,33 @@ pub fn build_redeem_witness(
     private_key: Fr,
 ) -> WitnessBuilder {
     // For redemption, outputs have value=0
-    let zero_output = CircuitNote {
-        value: 0,
-        salt: 0,
-        owner: input_note.owner.clone(),
-        asset_id: input_note.asset_id,
-        maturity_date: input_note.maturity_date,
-    };
+    let zero_output = input_note.padding();
 
-    // Compute zero output commitment
-    let zero_commitment = {
-        use poseidon_rs::Poseidon;
-        let hasher = Poseidon::new();
-        hasher
-            .hash(vec![
-                Fr::from_str("0").unwrap(), // value
-                Fr::from_str("0").unwrap(), // salt
-                zero_output.owner.clone(),
-                Fr::from_str(&zero_output.asset_id.to_string()).unwrap(),
-                Fr::from_str(&zero_output.maturity_date.to_string()).unwrap(),
-            ])
-            .unwrap()
-    };
+    let zero_commitment = zero_output.commitment();
 
     // Create dummy for second input
     let dummy_input = CircuitNote::dummy(
-        input_note.owner.clone(),
+        input_note.owner,
         input_note.asset_id,
         input_note.maturity_date,
     );
     let dummy_path = MerklePath::dummy();
 
-    // Compute dummy nullifier
-    let dummy_nullifier = {
-        use poseidon_rs::Poseidon;
-        let hasher = Poseidon::new();
-        hasher
-            .hash(vec![Fr::from_str("0").unwrap(), private_key.clone()])
-            .unwrap()
-    };
+    let dummy_nullifier = crate::scheme::active().nullify(DUMMY_SALT, &private_key);
 
     // Second dummy output
     let dummy_output = CircuitNote::dummy(
-        input_note.owner.clone(),
+        input_note.owner,
         input_note.asset_id,
         input_note.maturity_date,
     );
 
-    let dummy_commitment = {
-        use poseidon_rs::Poseidon;
-        let hasher = Poseidon::new();
-        hasher
-            .hash(vec![
-                Fr::from_str("0").unwrap(),
-                Fr::from_str("0").unwrap(),
-                dummy_output.owner.clone(),
-                Fr::from_str(&dummy_output.asset_id.to_string()).unwrap(),
-                Fr::from_str(&dummy_output.maturity_date.to_string()).unwrap(),
-            ])
-            .unwrap()
-    };
+    let dummy_commitment = dummy_output.commitment();
 
     WitnessBuilder::new(
         root,
         [input_nullifier, dummy_nullifier],
-        [zero_commitment.clone(), dummy_commitment],
+        [zero_commitment, dummy_commitment],
         [input_note, dummy_input],
         [input_merkle_path, dummy_path],
         [zero_output, dummy_output],
@@ -492,16 +1250,350 @@ pub fn build_redeem_witness(
     )
 }
 
+/// Value conservation as the circuit checks it: `total_input_value ==
+/// total_output_value` over the field, where totals that differ by a multiple of
+/// the modulus look equal. Requires the integer sums of the values to match and
+/// stay below the modulus, so native and field arithmetic agree.
+pub fn check_field_conservation(inputs: &[Fr], outputs: &[Fr]) -> Result<(), String> {
+    let modulus = field::modulus();
+    let total = |values: &[Fr]| values.iter().map(fr_to_biguint).sum::<num_bigint::BigUint>();
+    let (total_in, total_out) = (total(inputs), total(outputs));
+    if total_in >= modulus || total_out >= modulus {
+        return Err(format!(
+            "Value totals {} in / {} out wrap the field modulus; the circuit would compare them mod p",
+            total_in, total_out
+        ));
+    }
+    if total_in != total_out {
+        return Err(format!(
+            "Value not conserved: inputs total {} but outputs total {}",
+            total_in, total_out
+        ));
+    }
+    Ok(())
+}
+
+/// Named inputs for a JoinSplit witness. Unlike the `build_*_witness` helpers,
+/// `build()` checks the transaction against the circuit's rules before any
+/// proving time is spent.
+pub struct JoinSplitInputs {
+    pub root: Fr,
+    /// One entry per circuit input slot; a 1 real + dummy spend takes the dummy note second
+    pub input_notes: Vec<CircuitNote>,
+    pub merkle_paths: Vec<MerklePath>,
+    pub nullifiers: Vec<Fr>,
+    pub output_notes: [CircuitNote; 2],
+    pub commitments_out: [Fr; 2],
+    pub private_key: Fr,
+    /// Redemption: every output must be zero-valued instead of carrying the input total
+    pub burn: bool,
+}
+
+impl JoinSplitInputs {
+    /// Validate slot counts, value conservation, that no real recipient is paid zero,
+    /// asset and maturity consistency and that each output commitment matches its
+    /// note, then produce the witness
+    pub fn build(self) -> Result<WitnessBuilder, String> {
+        let slots = self.input_notes.len();
+        if slots == 0 {
+            return Err("Unsupported input count: 0 (expected at least 1)".to_string());
+        }
+        if self.merkle_paths.len() != slots || self.nullifiers.len() != slots {
+            return Err(format!(
+                "{} input notes need as many merkle paths and nullifiers (got {} and {})",
+                slots,
+                self.merkle_paths.len(),
+                self.nullifiers.len()
+            ));
+        }
+
+        // Natively the totals must fit a u64 like the values; the field check then
+        // confirms the circuit's mod-p sums say the same
+        let values = |notes: &[CircuitNote]| notes.iter().map(|n| u64_to_fr(n.value)).collect::<Vec<_>>();
+        let total = |notes: &[CircuitNote], side: &str| {
+            notes
+                .iter()
+                .try_fold(0u64, |sum, n| sum.checked_add(n.value))
+                .ok_or_else(|| format!("{} values overflow u64", side))
+        };
+        total(&self.input_notes, "Input")?;
+        let total_out = total(&self.output_notes, "Output")?;
+        if self.burn {
+            if total_out != 0 {
+                return Err(format!("Burn outputs must be zero-valued, got {}", total_out));
+            }
+        } else {
+            check_field_conservation(&values(&self.input_notes), &values(&self.output_notes))?;
+        }
+
+        // The circuit has one `input_owner` and one key authorizing every input
+        let spender = self.input_notes[0].owner;
+        if self.input_notes.iter().any(|note| note.owner != spender) {
+            return Err("Input notes must share one owner".to_string());
+        }
+
+        // A zero-value note for someone else spends the input for nothing; only
+        // change back to the spender and the dummy may be worth zero
+        if !self.burn {
+            if let Some(i) = self
+                .output_notes
+                .iter()
+                .position(|n| n.value == 0 && n.owner != spender && !n.is_dummy())
+            {
+                return Err(format!(
+                    "Output {} would give its recipient a zero-value note; only change and the dummy may be zero-valued",
+                    i
+                ));
+            }
+        }
+
+        let asset_id = self.input_notes[0].asset_id;
+        let notes = self.input_notes.iter().chain(self.output_notes.iter());
+        if let Some(other) = notes.map(|n| n.asset_id).find(|a| *a != asset_id) {
+            return Err(format!(
+                "Notes must share one asset: input is asset {} but a note has asset {}",
+                asset_id, other
+            ));
+        }
+
+        for (i, (note, commitment)) in self.output_notes.iter().zip(&self.commitments_out).enumerate() {
+            if note.commitment() != *commitment {
+                return Err(format!(
+                    "Output {} commitment {} does not match its note",
+                    i, commitment
+                ));
+            }
+        }
+
+        let mut witness = WitnessBuilder {
+            root: self.root,
+            nullifiers: self.nullifiers,
+            commitments_out: self.commitments_out,
+            input_notes: self.input_notes,
+            merkle_paths: self.merkle_paths,
+            output_notes: self.output_notes,
+            private_key: self.private_key,
+        };
+        witness.check_single_maturity()?;
+        witness.canonicalize_outputs();
+        Ok(witness)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::fr_from_bytes32;
+
+    /// Parse a Prover.toml field (0x hex, possibly without leading zeros)
+    fn fr_hex(s: &str) -> Fr {
+        let padded = format!("0x{:0>64}", s.trim_start_matches("0x"));
+        fr_from_bytes32(&padded.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_locate_proof_reports_missing_output() {
+        let target =
+            std::env::temp_dir().join(format!("wallet_locate_proof_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&target);
+        fs::create_dir_all(&target).unwrap();
+        let target_dir = target.to_string_lossy().to_string();
+
+        // Stub bb run: succeeded but left no proof, only other artifacts
+        fs::write(target.join("circuits.json"), "{}").unwrap();
+        fs::write(target.join("vk"), [0u8; 4]).unwrap();
+        let err = locate_proof(&target_dir).unwrap_err();
+        assert_eq!(
+            err,
+            ProofError::OutputNotFound {
+                expected: format!("{}/proof", target_dir),
+                candidates: vec![],
+            }
+        );
+        assert!(err.to_string().contains(&format!("{}/proof", target_dir)));
+
+        // A versioned name in a subfolder is picked up
+        fs::create_dir_all(target.join("v1")).unwrap();
+        fs::write(target.join("v1/proof.bin"), [1u8; 4]).unwrap();
+        assert!(locate_proof(&target_dir).unwrap().ends_with("v1/proof.bin"));
+
+        // Two candidates are ambiguous
+        fs::write(target.join("proof_v2"), [2u8; 4]).unwrap();
+        assert!(matches!(
+            locate_proof(&target_dir),
+            Err(ProofError::OutputNotFound { candidates, .. }) if candidates.len() == 2
+        ));
+
+        // The expected path always wins
+        fs::write(target.join("proof"), [3u8; 4]).unwrap();
+        assert_eq!(locate_proof(&target_dir).unwrap(), format!("{}/proof", target_dir));
+
+        fs::remove_dir_all(&target).unwrap();
+    }
+
+    #[test]
+    fn test_proof_meta_from_bb_output() {
+        let target = std::env::temp_dir().join(format!("wallet_proof_meta_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&target);
+        fs::create_dir_all(&target).unwrap();
+        let proof_path = target.join("proof").to_string_lossy().to_string();
+
+        // What `bb prove -o` leaves: the proof and the five public inputs beside it
+        fs::write(&proof_path, vec![7u8; 14_080]).unwrap();
+        fs::write(target.join("public_inputs"), vec![0u8; 5 * 32]).unwrap();
+        let meta = ProofMeta::from_bb_output(&proof_path, "v0.87.0\n", 1_234).unwrap();
+        assert_eq!(
+            meta,
+            ProofMeta {
+                size_bytes: 14_080,
+                num_public_inputs: 5,
+                gen_millis: 1_234,
+                backend_version: "0.87.0".to_string(),
+                root: Some(format!("{}", FixedBytes::<32>::ZERO)),
+            }
+        );
+        meta.check_public_inputs(TRANSFER_PUBLIC_INPUTS.len()).unwrap();
+        assert!(meta.check_public_inputs(4).unwrap_err().contains("5 public inputs"));
+
+        // Kept as a sidecar, which locate_proof does not mistake for a proof
+        assert!(ProofMeta::load(&proof_path).is_none());
+        meta.save(&proof_path).unwrap();
+        assert_eq!(ProofMeta::load(&proof_path).unwrap().unwrap(), meta);
+        assert_eq!(locate_proof(&target.to_string_lossy()).unwrap(), proof_path);
+        assert_eq!(
+            ProofMeta::from_bb_output(&proof_path, "bb 0.84.0", 0).unwrap().backend_version,
+            "0.84.0"
+        );
+
+        // A truncated or missing public_inputs file is not guessed at
+        fs::write(target.join("public_inputs"), vec![0u8; 33]).unwrap();
+        assert!(ProofMeta::from_bb_output(&proof_path, "0.87.0", 0).is_err());
+        fs::remove_file(target.join("public_inputs")).unwrap();
+        assert!(ProofMeta::from_bb_output(&proof_path, "0.87.0", 0).is_err());
+
+        fs::remove_dir_all(&target).unwrap();
+    }
+
+    #[test]
+    fn test_proof_out_dirs_do_not_collide() {
+        let (input, outputs, commitments) = spend_fixture();
+        let private_key = Fr::from_str("999").unwrap();
+        let witness_for = |root: &str| {
+            build_spend_witness(
+                1,
+                Fr::from_str(root).unwrap(),
+                input.clone(),
+                None,
+                outputs.clone(),
+                commitments,
+                private_key,
+            )
+            .unwrap()
+        };
+        let (first, second) = (witness_for("1"), witness_for("2"));
+
+        let base = std::env::temp_dir().join(format!("wallet_proof_out_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let prover = BbProver::new("circuits").with_proof_out_dir(&base.to_string_lossy());
+
+        // Same witness, same folder; different witnesses, different folders
+        let dir_first = prover.proof_dir("circuits", &first);
+        let dir_second = prover.proof_dir("circuits", &second);
+        assert_eq!(dir_first, prover.proof_dir("circuits/target/legs/a", &witness_for("1")));
+        assert_ne!(dir_first, dir_second);
+        assert!(dir_first.starts_with(&*base.to_string_lossy()));
+        assert_eq!(BbProver::new("circuits").proof_dir("circuits", &first), "circuits/target");
+
+        // Stub bb output in each: both proofs survive and are found where written
+        for (dir, byte) in [(&dir_first, 1u8), (&dir_second, 2u8)] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(format!("{}/proof", dir), [byte; 4]).unwrap();
+        }
+        assert_eq!(fs::read(locate_proof(&dir_first).unwrap()).unwrap(), [1u8; 4]);
+        assert_eq!(fs::read(locate_proof(&dir_second).unwrap()).unwrap(), [2u8; 4]);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_circuit_note_validation() {
+        let owner = Fr::from_str("42").unwrap();
+        let zero = Fr::from_str("0").unwrap();
+
+        let note = CircuitNote::new(100, 7, owner, 1, 1893456000).unwrap();
+        assert_eq!(note.commitment(), CircuitNote::new_unchecked(100, 7, owner, 1, 1893456000).commitment());
+        // Zero-value notes (change, burn outputs) are fine
+        assert!(CircuitNote::new(0, 7, owner, 1, 1893456000).is_ok());
+
+        assert_eq!(CircuitNote::new(100, 7, owner, 0, 1893456000).unwrap_err(), NoteError::ZeroAssetId);
+        assert_eq!(CircuitNote::new(100, 7, zero, 1, 1893456000).unwrap_err(), NoteError::ZeroOwner);
+        assert_eq!(CircuitNote::new(100, 7, owner, 1, 0).unwrap_err(), NoteError::ZeroMaturity);
+        assert_eq!(
+            CircuitNote::for_mint(100, 7, owner, 1, 1893456000, 1893456000).unwrap_err(),
+            NoteError::Matured { maturity_date: 1893456000, now: 1893456000 }
+        );
+        assert!(CircuitNote::for_mint(100, 7, owner, 1, 1893456000, 1700000000).is_ok());
+        assert_eq!(
+            CircuitNote::for_mint(100, 7, owner, 0, 1893456000, 1700000000).unwrap_err(),
+            NoteError::ZeroAssetId
+        );
+
+        // Padding notes skip the checks
+        assert_eq!(CircuitNote::dummy(zero, 1, 1893456000).owner, zero);
+    }
+
+    #[test]
+    fn test_commitment_at_u64_max() {
+        let note = CircuitNote {
+            value: u64::MAX,
+            salt: u64::MAX,
+            owner: Fr::from_str("1").unwrap(),
+            asset_id: u64::MAX,
+            maturity_date: u64::MAX,
+            memo_hash: None,
+        };
+        let max = Fr::from_str(&u64::MAX.to_string()).unwrap();
+        let expected = crate::field::poseidon()
+            .hash(vec![max, max, note.owner, max, max])
+            .unwrap();
+        assert_eq!(note.commitment(), expected);
+    }
+
+    #[test]
+    fn test_commitment_matches_circuit_vector() {
+        // Output notes and commitments_out from the proven circuits/Prover.toml vector;
+        // the circuit asserts note_commit(outputs[i]) == commitments_out[i]
+        let buyer = CircuitNote {
+            value: 1000000,
+            salt: 0x229d76a291abdd01,
+            owner: fr_hex("0x9224288ae909c4f3dd9f174dba6919f7109b44baf09fd95a6e463d386a6e98f"),
+            asset_id: 1,
+            maturity_date: 1893456000,
+            memo_hash: None,
+        };
+        let change = CircuitNote {
+            value: 99000000,
+            salt: 0x399fe7dbd0da2fdb,
+            owner: fr_hex("0x14690c253b7392ec967c8d43d0c84fd6e2f3349c99ef96fb716e638034a2ea1"),
+            asset_id: 1,
+            maturity_date: 1893456000,
+            memo_hash: None,
+        };
+
+        assert_eq!(
+            buyer.commitment(),
+            fr_hex("0x1b2a41e40670db69490b7db2e79850284159edfa946a8e4ebd9109787c4e1f47")
+        );
+        assert_eq!(
+            change.commitment(),
+            fr_hex("0x8421fccbfb32c22899ac2d7ed5db508d6b3a99134c2313eff9a0fa8c9dd3116")
+        );
+    }
 
     #[test]
-    fn test_fr_to_hex() {
-        let fr = Fr::from_str("999").unwrap();
-        let hex = WitnessBuilder::fr_to_hex(&fr);
-        // Should produce a valid hex string
-        assert!(hex.starts_with("0x"));
+    fn test_toml_integer() {
+        assert_eq!(WitnessBuilder::toml_integer(&Fr::from_str("999").unwrap()), "0x3e7");
+        assert_eq!(WitnessBuilder::toml_integer(&Fr::zero()), "0x0");
     }
 
     #[test]
@@ -514,17 +1606,19 @@ mod tests {
         let input_note = CircuitNote {
             value: 100,
             salt: 123,
-            owner: owner.clone(),
+            owner,
             asset_id: 1,
             maturity_date: 1893456000,
+            memo_hash: None,
         };
 
         let output_note = CircuitNote {
             value: 100,
             salt: 456,
-            owner: owner.clone(),
+            owner,
             asset_id: 1,
             maturity_date: 1893456000,
+            memo_hash: None,
         };
 
         let path = MerklePath::dummy();
@@ -551,4 +1645,578 @@ mod tests {
 
         println!("Generated TOML:\n{}", toml);
     }
+
+    /// Key -> raw value of each `key = value` line of a Prover.toml
+    fn parse_toml_fields(toml: &str) -> BTreeMap<String, String> {
+        toml.lines()
+            .filter(|l| !l.starts_with('#'))
+            .filter_map(|l| l.split_once(" = "))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_prover_toml_with_custom_field_names() {
+        let (input, outputs, commitments) = spend_fixture();
+        let witness = build_spend_witness(
+            1,
+            Fr::from_str("1").unwrap(),
+            input,
+            None,
+            outputs,
+            commitments,
+            Fr::from_str("999").unwrap(),
+        )
+        .unwrap();
+
+        let fields = ProverFields::from_json(
+            r#"{"root": "merkle_root", "path_indices": "index_bits", "private_key": "sk"}"#,
+        )
+        .unwrap();
+        let default = parse_toml_fields(&witness.to_prover_toml());
+        let renamed = parse_toml_fields(&witness.to_prover_toml_with(&fields));
+
+        // Every input is written once, under its mapped name, with the same value
+        assert_eq!(default.len(), PROVER_FIELDS.len());
+        assert_eq!(renamed.len(), PROVER_FIELDS.len());
+        for field in PROVER_FIELDS {
+            assert_eq!(renamed.get(fields.name(field)), default.get(field), "{}", field);
+        }
+        assert_eq!(renamed["index_bits"], "[[0, 0, 0]]");
+        assert!(!renamed.contains_key("root"));
+        assert_eq!(ProverFields::default().name("root"), "root");
+
+        // The circuit's main takes exactly these inputs
+        let main_nr = concat!(env!("CARGO_MANIFEST_DIR"), "/../circuits/src/main.nr");
+        let circuit = fs::read_to_string(main_nr).unwrap();
+        for field in PROVER_FIELDS {
+            assert!(circuit.contains(&format!("    {}: ", field)), "{}", field);
+        }
+    }
+
+    #[test]
+    fn test_memo_bound_prover_toml_writes_memo_hashes() {
+        let (input, outputs, commitments) = spend_fixture();
+        let mut witness = build_spend_witness(
+            1,
+            Fr::from_str("1").unwrap(),
+            input,
+            None,
+            outputs,
+            commitments,
+            Fr::from_str("999").unwrap(),
+        )
+        .unwrap();
+        witness.output_notes[0].memo_hash = Some(u64_to_fr(77));
+        let fields = ProverFields::from_json(r#"{"output_memo_hashes": "memos_out"}"#).unwrap();
+
+        // V1 circuits take no memo inputs; memo-bound ones get one per note, zero if unbound
+        let v1 = parse_toml_fields(&witness.prover_toml(&fields, &crate::scheme::V1));
+        assert!(!v1.contains_key("input_memo_hashes") && !v1.contains_key("memos_out"));
+        let bound = parse_toml_fields(&witness.prover_toml(&fields, &crate::scheme::MemoBound));
+        assert_eq!(bound.len(), PROVER_FIELDS.len() + MEMO_PROVER_FIELDS.len());
+        let hex = |n: u64| WitnessBuilder::toml_integer(&u64_to_fr(n));
+        assert_eq!(bound["memos_out"], format!("[\"{}\", \"{}\"]", hex(77), hex(0)));
+        assert_eq!(bound["input_memo_hashes"], format!("[\"{}\"]", hex(0)));
+    }
+
+    #[test]
+    fn test_prover_fields_validation() {
+        let err = |json: &str| ProverFields::from_json(json).unwrap_err();
+        assert!(err(r#"{"index_bits": "path_indices"}"#).contains("Unknown circuit input"));
+        assert!(err(r#"{"root": "merkle root"}"#).contains("Invalid Prover.toml key"));
+        assert!(err(r#"{"root": ""}"#).contains("Invalid Prover.toml key"));
+        // Renaming onto another input's default name collides with it
+        assert!(err(r#"{"root": "nullifiers"}"#).contains("both map to 'nullifiers'"));
+        // Swapping two names is fine
+        assert!(ProverFields::from_json(r#"{"root": "nullifiers", "nullifiers": "root"}"#).is_ok());
+        assert!(err("[]").contains("Invalid field mapping"));
+
+        // A missing file means the default names; a bad one fails to load
+        let dir = std::env::temp_dir().join(format!("wallet_prover_fields_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let dir_str = dir.to_string_lossy().to_string();
+        assert_eq!(ProverFields::load(&dir_str).unwrap(), ProverFields::default());
+        fs::write(dir.join(PROVER_FIELDS_FILE), r#"{"roots": "r"}"#).unwrap();
+        assert!(ProverFields::load(&dir_str).unwrap_err().contains(PROVER_FIELDS_FILE));
+        assert!(BbProver::new(&dir_str).with_circuit_fields().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn spend_fixture() -> ((CircuitNote, MerklePath, Fr), [CircuitNote; 2], [Fr; 2]) {
+        let owner = Fr::from_str("12345").unwrap();
+        let input_note = CircuitNote {
+            value: 100,
+            salt: 123,
+            owner,
+            asset_id: 1,
+            maturity_date: 1893456000,
+            memo_hash: None,
+        };
+        let outputs = [
+            CircuitNote::dummy(owner, 1, 1893456000),
+            CircuitNote::dummy(owner, 1, 1893456000),
+        ];
+        let commitments = [outputs[0].commitment(), outputs[1].commitment()];
+        (
+            (input_note, MerklePath::dummy(), Fr::from_str("111").unwrap()),
+            outputs,
+            commitments,
+        )
+    }
+
+    #[test]
+    fn test_witness_requires_single_maturity() {
+        let (input, outputs, commitments) = spend_fixture();
+        let private_key = Fr::from_str("999").unwrap();
+        let witness_with = |outputs: [CircuitNote; 2]| {
+            build_spend_witness(
+                1,
+                Fr::from_str("1").unwrap(),
+                input.clone(),
+                None,
+                outputs,
+                commitments,
+                private_key,
+            )
+            .unwrap()
+        };
+
+        // Same maturity throughout: permitted
+        assert!(witness_with(outputs.clone()).check_single_maturity().is_ok());
+
+        // Serial maturities (earlier or later than the input) are rejected
+        for maturity in [1861920000, 1924992000] {
+            let mut staggered = outputs.clone();
+            staggered[1].maturity_date = maturity;
+            let witness = witness_with(staggered);
+            assert!(witness.check_single_maturity().is_err());
+            assert!(witness.write_prover_toml("/nonexistent", &ProverFields::default()).unwrap_err().contains("maturity"));
+        }
+
+        // A witness read back without inputs is refused rather than indexed into
+        let mut empty = witness_with(outputs);
+        empty.input_notes.clear();
+        assert!(empty.check_single_maturity().unwrap_err().contains("no input notes"));
+    }
+
+    #[test]
+    fn test_mismatched_output_commitment_is_caught_before_proving() {
+        let (input, outputs, commitments) = spend_fixture();
+        let mut witness = build_spend_witness(
+            1,
+            Fr::from_str("1").unwrap(),
+            input,
+            None,
+            outputs,
+            commitments,
+            Fr::from_str("999").unwrap(),
+        )
+        .unwrap();
+        assert!(witness.check_output_commitments().is_ok());
+
+        // A commitment the circuit's note_commit would not reproduce
+        witness.commitments_out[0] = Fr::from_str("424242").unwrap();
+        let err = witness.check_output_commitments().unwrap_err();
+        assert!(err.contains("wallet/circuit commitment mismatch on output 0"), "{}", err);
+        let err = witness.write_prover_toml("/nonexistent", &ProverFields::default()).unwrap_err();
+        assert!(err.contains("wallet/circuit commitment mismatch"), "{}", err);
+
+        // nargo failing on the commitment assert is named for what it is
+        let stderr = "error: Failed constraint\n    ┌─ src/main.nr:97:16\n   │\n97 │         \
+                      assert(output_commitments == commitments_out[i]);\n";
+        assert!(nargo_execute_error(stderr).starts_with("wallet/circuit commitment mismatch"));
+        let other = "error: Failed constraint\n97 │     assert(total_input_value == total_output_value);\n";
+        assert!(nargo_execute_error(other).starts_with("nargo execute failed"));
+    }
+
+    #[test]
+    fn test_single_input_witness_shape() {
+        let (input, outputs, commitments) = spend_fixture();
+        let private_key = Fr::from_str("999").unwrap();
+
+        let witness = build_spend_witness(
+            1,
+            Fr::from_str("1").unwrap(),
+            input,
+            None,
+            outputs,
+            commitments,
+            private_key,
+        )
+        .unwrap();
+
+        assert_eq!(witness.input_count(), 1);
+        assert_eq!(witness.nullifiers.len(), 1);
+        let toml = witness.to_prover_toml();
+        assert!(toml.contains("input_values = [100]\n"));
+        assert!(toml.contains("path_indices = [[0, 0, 0]]\n"));
+        // The contract has no slot layout for it: a zero second nullifier would be
+        // marked spent by the first redeem and revert every later one
+        assert!(check_submittable(witness.input_count()).unwrap_err().contains("2 nullifiers"));
+    }
+
+    #[test]
+    fn test_two_input_witness_shape() {
+        let (input, outputs, commitments) = spend_fixture();
+        let owner = input.0.owner;
+        let private_key = Fr::from_str("999").unwrap();
+        let dummy = (CircuitNote::dummy(owner, 1, 1893456000), MerklePath::dummy());
+
+        let witness = build_spend_witness(
+            2,
+            Fr::from_str("1").unwrap(),
+            input,
+            Some(dummy),
+            outputs,
+            commitments,
+            private_key,
+        )
+        .unwrap();
+
+        assert_eq!(witness.input_count(), 2);
+        assert_eq!(witness.nullifiers.len(), 2);
+        let toml = witness.to_prover_toml();
+        assert!(toml.contains("input_values = [100, 0]\n"));
+        assert!(toml.contains("path_indices = [[0, 0, 0], [0, 0, 0]]\n"));
+        assert!(check_submittable(witness.input_count()).is_ok());
+    }
+
+    #[test]
+    fn test_spend_witness_rejects_wrong_shape() {
+        let (input, outputs, commitments) = spend_fixture();
+        let owner = input.0.owner;
+        let private_key = Fr::from_str("999").unwrap();
+        let root = Fr::from_str("1").unwrap();
+        let dummy = (CircuitNote::dummy(owner, 1, 1893456000), MerklePath::dummy());
+
+        assert!(build_spend_witness(
+            1,
+            root,
+            input.clone(),
+            Some(dummy),
+            outputs.clone(),
+            commitments,
+            private_key
+        )
+        .is_err());
+        assert!(build_spend_witness(2, root, input.clone(), None, outputs.clone(), commitments, private_key).is_err());
+        assert!(build_spend_witness(3, root, input, None, outputs, commitments, private_key).is_err());
+    }
+
+    /// `values.len()` owned notes of one owner, each with its own path and nullifier
+    fn multi_inputs(values: &[u64]) -> Vec<(CircuitNote, MerklePath, Fr)> {
+        let owner = Fr::from_str("12345").unwrap();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let note = CircuitNote::new_unchecked(*value, 100 + i as u64, owner, 1, 1893456000);
+                (note, MerklePath::dummy(), Fr::from_str(&(111 + i).to_string()).unwrap())
+            })
+            .collect()
+    }
+
+    fn multi_outputs(values: [u64; 2]) -> ([CircuitNote; 2], [Fr; 2]) {
+        let owner = Fr::from_str("12345").unwrap();
+        let outputs = values.map(|value| CircuitNote::new_unchecked(value, value + 7, owner, 1, 1893456000));
+        let commitments = [outputs[0].commitment(), outputs[1].commitment()];
+        (outputs, commitments)
+    }
+
+    #[test]
+    fn test_two_real_input_witness() {
+        let (outputs, commitments) = multi_outputs([90, 60]);
+        let root = Fr::from_str("1").unwrap();
+        let private_key = Fr::from_str("999").unwrap();
+
+        let witness =
+            build_multi_input_witness(2, true, root, multi_inputs(&[100, 50]), outputs, commitments, private_key)
+                .unwrap();
+        assert_eq!(witness.input_count(), 2);
+        assert_eq!(witness.nullifiers.len(), 2);
+        let toml = witness.to_prover_toml();
+        assert!(toml.contains("input_values = [100, 50]\n"), "{}", toml);
+        assert!(toml.contains("input_salts = [\"0x64\", \"0x65\"]\n"), "{}", toml);
+
+        // Without the capability only the 1 real + dummy shape is accepted
+        let (outputs, commitments) = multi_outputs([90, 60]);
+        let err =
+            build_multi_input_witness(2, false, root, multi_inputs(&[100, 50]), outputs, commitments, private_key)
+                .err().unwrap();
+        assert!(err.contains("one real input note"), "{}", err);
+    }
+
+    #[test]
+    fn test_three_real_input_witness() {
+        let root = Fr::from_str("1").unwrap();
+        let private_key = Fr::from_str("999").unwrap();
+
+        let (outputs, commitments) = multi_outputs([120, 30]);
+        let witness =
+            build_multi_input_witness(3, true, root, multi_inputs(&[100, 40, 10]), outputs, commitments, private_key)
+                .unwrap();
+        assert_eq!(witness.input_count(), 3);
+        let toml = witness.to_prover_toml();
+        assert!(toml.contains("input_values = [100, 40, 10]\n"), "{}", toml);
+        assert!(toml.contains("path_indices = [[0, 0, 0], [0, 0, 0], [0, 0, 0]]\n"), "{}", toml);
+
+        // Value is conserved across all three inputs, not just the first
+        let (outputs, commitments) = multi_outputs([100, 30]);
+        let err =
+            build_multi_input_witness(3, true, root, multi_inputs(&[100, 40, 10]), outputs, commitments, private_key)
+                .err().unwrap();
+        assert!(err.contains("Value not conserved"), "{}", err);
+
+        // One note per slot, and one owner for every input
+        let (outputs, commitments) = multi_outputs([120, 30]);
+        assert!(
+            build_multi_input_witness(2, true, root, multi_inputs(&[100, 40, 10]), outputs, commitments, private_key)
+                .err().unwrap()
+                .contains("2-input circuit")
+        );
+        let mut foreign = multi_inputs(&[100, 40, 10]);
+        foreign[2].0.owner = Fr::from_str("54321").unwrap();
+        let (outputs, commitments) = multi_outputs([120, 30]);
+        assert!(build_multi_input_witness(3, true, root, foreign, outputs, commitments, private_key)
+            .err().unwrap()
+            .contains("one owner"));
+    }
+
+    #[test]
+    fn test_witness_json_round_trip() {
+        let (input, outputs, commitments) = spend_fixture();
+        let owner = input.0.owner;
+        let mut tree = crate::merkle::FixedMerkleTree::new();
+        tree.insert(input.0.commitment());
+        tree.insert(CircuitNote::dummy(owner, 1, 1893456000).commitment());
+        let dummy = (CircuitNote::dummy(owner, 1, 1893456000), tree.generate_proof(1));
+        let input = (input.0, tree.generate_proof(0), input.2);
+
+        let witness = build_spend_witness(
+            2,
+            tree.root(),
+            input,
+            Some(dummy),
+            outputs,
+            commitments,
+            Fr::from_str("999").unwrap(),
+        )
+        .unwrap();
+
+        let path = std::env::temp_dir().join(format!("witness_roundtrip_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        witness.save_json(path).unwrap();
+        let mut loaded = WitnessBuilder::load_json(path).unwrap();
+        let _ = fs::remove_file(path);
+
+        // The key is never saved; with it restored the witness is the same
+        assert_eq!(loaded.private_key, Fr::from_str("0").unwrap());
+        loaded.private_key = witness.private_key;
+        assert_eq!(loaded.to_prover_toml(), witness.to_prover_toml());
+        assert_eq!(loaded.root, witness.root);
+        assert_eq!(loaded.nullifiers, witness.nullifiers);
+    }
+
+    #[test]
+    fn test_outputs_are_canonically_ordered() {
+        let owner = Fr::from_str("12345").unwrap();
+        let note_a = CircuitNote {
+            value: 40,
+            salt: 456,
+            owner,
+            asset_id: 1,
+            maturity_date: 1893456000,
+            memo_hash: None,
+        };
+        let note_b = CircuitNote {
+            value: 60,
+            salt: 789,
+            ..note_a.clone()
+        };
+        let (comm_a, comm_b) = (note_a.commitment(), note_b.commitment());
+
+        let build = |outputs: [CircuitNote; 2], commitments: [Fr; 2]| {
+            build_trade_witness(
+                Fr::from_str("1").unwrap(),
+                [note_a.clone(), CircuitNote::dummy(owner, 1, 1893456000)],
+                [MerklePath::dummy(), MerklePath::dummy()],
+                [Fr::from_str("11").unwrap(), Fr::from_str("22").unwrap()],
+                outputs,
+                commitments,
+                Fr::from_str("999").unwrap(),
+            )
+        };
+
+        let forward = build([note_a.clone(), note_b.clone()], [comm_a, comm_b]);
+        let reversed = build([note_b.clone(), note_a.clone()], [comm_b, comm_a]);
+
+        // Same canonical order regardless of the order the caller passed
+        assert_eq!(forward.commitments_out, reversed.commitments_out);
+        assert_eq!(forward.to_prover_toml(), reversed.to_prover_toml());
+        assert!(fr_to_bytes32(&forward.commitments_out[0]) < fr_to_bytes32(&forward.commitments_out[1]));
+
+        // Each commitment still belongs to the note in the same slot
+        for (note, commitment) in forward.output_notes.iter().zip(forward.commitments_out) {
+            assert_eq!(note.commitment(), commitment);
+        }
+
+        // Inputs are not reordered
+        assert_eq!(forward.input_notes[0].value, 40);
+        assert_eq!(forward.nullifiers[0], Fr::from_str("11").unwrap());
+    }
+
+    fn joinsplit_fixture() -> JoinSplitInputs {
+        let owner = Fr::from_str("12345").unwrap();
+        let input = CircuitNote {
+            value: 100,
+            salt: 123,
+            owner,
+            asset_id: 1,
+            maturity_date: 1893456000,
+            memo_hash: None,
+        };
+        let outputs = [
+            CircuitNote { value: 30, salt: 456, ..input.clone() },
+            CircuitNote { value: 70, salt: 789, ..input.clone() },
+        ];
+        JoinSplitInputs {
+            root: Fr::from_str("1").unwrap(),
+            input_notes: vec![input, CircuitNote::dummy(owner, 1, 1893456000)],
+            merkle_paths: vec![MerklePath::dummy(), MerklePath::dummy()],
+            nullifiers: vec![Fr::from_str("11").unwrap(), Fr::from_str("22").unwrap()],
+            commitments_out: [outputs[0].commitment(), outputs[1].commitment()],
+            output_notes: outputs,
+            private_key: Fr::from_str("999").unwrap(),
+            burn: false,
+        }
+    }
+
+    #[test]
+    fn test_joinsplit_inputs_build() {
+        let witness = joinsplit_fixture().build().unwrap();
+        assert_eq!(witness.input_count(), 2);
+        for (note, commitment) in witness.output_notes.iter().zip(witness.commitments_out) {
+            assert_eq!(note.commitment(), commitment);
+        }
+
+        // A burn zeroes every output
+        let mut burn = joinsplit_fixture();
+        burn.burn = true;
+        for note in burn.output_notes.iter_mut() {
+            note.value = 0;
+        }
+        burn.commitments_out = [burn.output_notes[0].commitment(), burn.output_notes[1].commitment()];
+        assert!(burn.build().is_ok());
+
+        // Every path through `build` refuses inputs of two owners, the dummy included
+        let mut foreign = joinsplit_fixture();
+        foreign.input_notes[1] = CircuitNote::dummy(Fr::from_str("54321").unwrap(), 1, 1893456000);
+        assert!(foreign.build().err().unwrap().contains("one owner"));
+    }
+
+    #[test]
+    fn test_field_conservation_rejects_mod_p_wrap() {
+        let fr = |n: &num_bigint::BigUint| Fr::from_str(&n.to_string()).unwrap();
+        let p = field::modulus();
+        let (one, two) = (num_bigint::BigUint::from(1u32), num_bigint::BigUint::from(2u32));
+
+        // (p - 1) + 2 == 1 mod p: balanced in the field, not as integers
+        let inputs = [fr(&(&p - &one)), fr(&two)];
+        let outputs = [fr(&one), Fr::zero()];
+        let mut field_in = inputs[0];
+        field_in.add_assign(&inputs[1]);
+        assert_eq!(field_in, outputs[0]);
+        let err = check_field_conservation(&inputs, &outputs).unwrap_err();
+        assert!(err.contains("wrap the field modulus"), "{}", err);
+
+        // Large values that stay below the modulus are compared exactly
+        let half = &p / &two;
+        assert!(check_field_conservation(&[fr(&half)], &[fr(&(&half - &one)), fr(&one)]).is_ok());
+        assert!(check_field_conservation(&[fr(&half)], &[fr(&half), fr(&one)]).is_err());
+        assert!(check_field_conservation(&[u64_to_fr(u64::MAX)], &[u64_to_fr(u64::MAX - 1), u64_to_fr(1)]).is_ok());
+    }
+
+    #[test]
+    fn test_joinsplit_inputs_allow_zero_value_dummy() {
+        // A zero-owner dummy output, and the spender's own zero change (what a trade leg pads with)
+        let mut trade = joinsplit_fixture();
+        trade.output_notes = [
+            CircuitNote { value: 100, owner: Fr::from_str("54321").unwrap(), ..trade.output_notes[0].clone() },
+            CircuitNote::dummy(Fr::from_str("0").unwrap(), 1, 1893456000),
+        ];
+        trade.commitments_out = [trade.output_notes[0].commitment(), trade.output_notes[1].commitment()];
+        assert!(trade.build().is_ok());
+
+        let mut whole = joinsplit_fixture();
+        whole.output_notes[0].value = 100;
+        whole.output_notes[1].value = 0;
+        whole.commitments_out = [whole.output_notes[0].commitment(), whole.output_notes[1].commitment()];
+        assert!(whole.build().is_ok());
+    }
+
+    #[test]
+    fn test_joinsplit_inputs_rejects_invalid() {
+        let rejects = |edit: fn(&mut JoinSplitInputs), expected: &str| {
+            let mut inputs = joinsplit_fixture();
+            edit(&mut inputs);
+            let err = inputs.build().err().expect("build should fail");
+            assert!(err.contains(expected), "{}", err);
+        };
+
+        // Value conservation (commitment kept in sync so only the sum is wrong)
+        rejects(
+            |i| {
+                i.output_notes[1].value = 71;
+                i.commitments_out[1] = i.output_notes[1].commitment();
+            },
+            "Value not conserved",
+        );
+        rejects(|i| i.burn = true, "zero-valued");
+
+        // Totals that no longer fit a u64
+        rejects(
+            |i| {
+                i.input_notes[1].value = u64::MAX;
+                i.output_notes[1].value = u64::MAX;
+                i.commitments_out[1] = i.output_notes[1].commitment();
+            },
+            "overflow u64",
+        );
+
+        // A real recipient paid nothing
+        rejects(
+            |i| {
+                i.output_notes[0].value = 0;
+                i.output_notes[0].owner = Fr::from_str("54321").unwrap();
+                i.output_notes[1].value = 100;
+                i.commitments_out = [i.output_notes[0].commitment(), i.output_notes[1].commitment()];
+            },
+            "zero-value note",
+        );
+
+        // Asset and maturity consistency
+        rejects(
+            |i| {
+                i.output_notes[0].asset_id = 2;
+                i.commitments_out[0] = i.output_notes[0].commitment();
+            },
+            "one asset",
+        );
+        rejects(
+            |i| {
+                i.output_notes[0].maturity_date = 1924992000;
+                i.commitments_out[0] = i.output_notes[0].commitment();
+            },
+            "one maturity",
+        );
+
+        // Commitment that belongs to a different note
+        rejects(|i| i.commitments_out.swap(0, 1), "does not match");
+
+        // Slot counts
+        rejects(|i| i.input_notes.clear(), "Unsupported input count");
+        rejects(|i| { i.nullifiers.pop(); }, "merkle paths and nullifiers");
+    }
 }
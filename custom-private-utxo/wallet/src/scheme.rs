@@ -0,0 +1,193 @@
+//! How note fields hash to a commitment and a spend to its nullifier. Each
+//! circuit version fixes both; `config::COMMITMENT_SCHEME` picks the one the
+//! deployed circuit uses, so a circuit upgrade is a new impl here rather than a
+//! fork of every call site.
+
+use crate::field::{poseidon, Fr};
+use crate::prover::CircuitNote;
+use crate::utils::{u64_to_fr, Salt, Value};
+
+pub trait CommitmentScheme: Sync {
+    /// Leaf the circuit's `note_commit` computes for `note`
+    fn commit(&self, note: &CircuitNote) -> Fr;
+
+    /// Nullifier the circuit's `note_nullifier` computes for a note with `salt`
+    /// spent under `private_key`
+    fn nullify(&self, salt: u64, private_key: &Fr) -> Fr;
+
+    /// Whether `commit` covers the note's `memo_hash`, so a note handed over with a
+    /// memo must be bound to it (`CircuitNote::bound_to_memo`) before it is committed
+    fn binds_memo(&self) -> bool {
+        false
+    }
+}
+
+/// Circuit versions the wallet can hash for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemeVersion {
+    V1,
+    MemoBound,
+}
+
+impl SchemeVersion {
+    pub fn scheme(self) -> &'static dyn CommitmentScheme {
+        match self {
+            SchemeVersion::V1 => &V1,
+            SchemeVersion::MemoBound => &MemoBound,
+        }
+    }
+}
+
+/// The scheme `config::COMMITMENT_SCHEME` selects
+pub fn active() -> &'static dyn CommitmentScheme {
+    crate::config::COMMITMENT_SCHEME.scheme()
+}
+
+/// circuits/src/main.nr as deployed:
+/// commitment = poseidon([value, salt, owner, asset_id, maturity_date]),
+/// nullifier = poseidon([salt, private_key])
+pub struct V1;
+
+impl CommitmentScheme for V1 {
+    fn commit(&self, note: &CircuitNote) -> Fr {
+        poseidon()
+            .hash(vec![
+                Value(note.value).to_fr(),
+                Salt(note.salt).to_fr(),
+                note.owner,
+                u64_to_fr(note.asset_id),
+                u64_to_fr(note.maturity_date),
+            ])
+            .expect("Failed to compute commitment")
+    }
+
+    fn nullify(&self, salt: u64, private_key: &Fr) -> Fr {
+        poseidon()
+            .hash(vec![Salt(salt).to_fr(), *private_key])
+            .expect("Failed to compute nullifier")
+    }
+}
+
+/// V1 with the note's memo hash (`Memo::hash`) as a sixth commitment field, zero
+/// for notes no memo was sent with (dummies, pads, change kept by its owner):
+/// commitment = poseidon([value, salt, owner, asset_id, maturity_date, memo_hash]).
+/// A sender can then not hand over a memo that misstates the note, since `scan`
+/// finds no commitment for it. The price: the memo is sealed before the note is
+/// committed and can never be re-sent under another ciphertext, the holder keeps
+/// its hash to spend the note, and `reconstruct` cannot rebuild a bound note from
+/// its fields alone. Needs a circuit whose `note_commit` takes the extra field.
+pub struct MemoBound;
+
+impl CommitmentScheme for MemoBound {
+    fn commit(&self, note: &CircuitNote) -> Fr {
+        poseidon()
+            .hash(vec![
+                Value(note.value).to_fr(),
+                Salt(note.salt).to_fr(),
+                note.owner,
+                u64_to_fr(note.asset_id),
+                u64_to_fr(note.maturity_date),
+                note.memo_hash.unwrap_or_else(|| u64_to_fr(0)),
+            ])
+            .expect("Failed to compute commitment")
+    }
+
+    fn nullify(&self, salt: u64, private_key: &Fr) -> Fr {
+        V1.nullify(salt, private_key)
+    }
+
+    fn binds_memo(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fr_from_hex;
+
+    /// A hypothetical circuit that drops maturity from the commitment and binds the
+    /// nullifier to the asset too; it hashes nothing like V1
+    struct V2;
+
+    impl CommitmentScheme for V2 {
+        fn commit(&self, note: &CircuitNote) -> Fr {
+            poseidon()
+                .hash(vec![
+                    note.owner,
+                    u64_to_fr(note.asset_id),
+                    Value(note.value).to_fr(),
+                    Salt(note.salt).to_fr(),
+                ])
+                .unwrap()
+        }
+
+        fn nullify(&self, salt: u64, private_key: &Fr) -> Fr {
+            poseidon()
+                .hash(vec![*private_key, Salt(salt).to_fr(), u64_to_fr(1)])
+                .unwrap()
+        }
+    }
+
+    fn hex(s: &str) -> Fr {
+        fr_from_hex(&format!("0x{:0>64}", s.trim_start_matches("0x"))).unwrap()
+    }
+
+    #[test]
+    fn test_v1_matches_circuit_vector() {
+        // circuits/Prover.toml: outputs[0] -> commitments_out[0] and
+        // (input_salts[i], private_key) -> nullifiers[i]
+        let buyer = CircuitNote::new_unchecked(
+            1000000,
+            0x229d76a291abdd01,
+            hex("0x9224288ae909c4f3dd9f174dba6919f7109b44baf09fd95a6e463d386a6e98f"),
+            1,
+            1893456000,
+        );
+        let key = hex("0x8f03e2d5802e0308");
+        assert_eq!(
+            V1.commit(&buyer),
+            hex("0x1b2a41e40670db69490b7db2e79850284159edfa946a8e4ebd9109787c4e1f47")
+        );
+        assert_eq!(
+            V1.nullify(0x94485c80fa244c27, &key),
+            hex("0xf4853301a6e130e81948bf0b9e3f3f1522cdf9ee388278857444904700cb153")
+        );
+        assert_eq!(
+            V1.nullify(0, &key),
+            hex("0x21d0b3d5278b9339f65968bde078108fdc71c82a864b1d1d0908a8378f355151")
+        );
+
+        // The wallet hashes with the configured scheme
+        assert_eq!(crate::config::COMMITMENT_SCHEME, SchemeVersion::V1);
+        assert_eq!(buyer.commitment(), V1.commit(&buyer));
+
+        // Another scheme plugs in behind the same trait
+        let schemes: [&dyn CommitmentScheme; 2] = [&V1, &V2];
+        let commitments: Vec<Fr> = schemes.iter().map(|s| s.commit(&buyer)).collect();
+        let nullifiers: Vec<Fr> = schemes.iter().map(|s| s.nullify(7, &key)).collect();
+        assert_ne!(commitments[0], commitments[1]);
+        assert_ne!(nullifiers[0], nullifiers[1]);
+    }
+
+    #[test]
+    fn test_memo_bound_commits_the_memo_hash() {
+        let note = CircuitNote::new_unchecked(300, 42, u64_to_fr(7), 1, 1893456000);
+        let bound = CircuitNote {
+            memo_hash: Some(u64_to_fr(99)),
+            ..note.clone()
+        };
+
+        // V1 ignores the memo; the memo-bound scheme commits to it, zero by default
+        assert_eq!(V1.commit(&bound), V1.commit(&note));
+        assert_ne!(MemoBound.commit(&bound), MemoBound.commit(&note));
+        assert_ne!(MemoBound.commit(&note), V1.commit(&note));
+        let zero = CircuitNote {
+            memo_hash: Some(u64_to_fr(0)),
+            ..note.clone()
+        };
+        assert_eq!(MemoBound.commit(&zero), MemoBound.commit(&note));
+        assert_eq!(MemoBound.nullify(42, &u64_to_fr(5)), V1.nullify(42, &u64_to_fr(5)));
+        assert!(MemoBound.binds_memo() && !V1.binds_memo());
+    }
+}
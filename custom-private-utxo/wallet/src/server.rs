@@ -0,0 +1,569 @@
+//! Minimal JSON-RPC 2.0 over HTTP, for driving the wallet from a web frontend.
+//! Responses carry public note data only: seeds, private keys and note salts
+//! never leave the process.
+//!
+//! Every request must carry `Authorization: Bearer <token>` with the token the
+//! server was started with, and a JSON `Content-Type`. A page in the browser can
+//! send neither cross-origin without a preflight, which this server never answers.
+//!
+//! Sealed wallets are opened with the passphrase `serve` takes once at startup
+//! (`passphrase::unlock`), and wallets registered here are sealed with it.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as Json};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+use crate::commands::{
+    buy, register, scan_memos, wallet_balance, Balance, NoteSummary, ScannedMemo, SourceNote,
+};
+use crate::config::ASSET_ID;
+use crate::contract::BondContract;
+use crate::events::EventSink;
+use crate::prover::Prover;
+use crate::utils::{bond_path, data_dir, load_bond, load_wallet, wallet_exists};
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Command-level failure (wallet missing, proof failed, ...)
+const COMMAND_FAILED: i64 = -32000;
+
+/// Largest request body accepted; bigger requests are refused before the body is read
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+/// Cap on the request line and headers together
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+/// How long a client may take to send its request, and to read the response
+pub const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Random bearer token for a server started without `--token`
+pub fn generate_token() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 32]>())
+}
+
+/// Compare a presented token without returning early on the first differing byte
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Resolve a bond named in a request (`info`, `buy`'s `source_note`) to a file
+/// directly inside the data directory; other paths (absolute, `..`,
+/// sub-directories) are refused
+fn bond_in_data_dir(bond: &str) -> Result<String, String> {
+    let prefix = format!("{}/", data_dir());
+    let name = bond.strip_prefix(&prefix).unwrap_or(bond);
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!(
+            "Bond '{}' is not a file in the data directory {}",
+            bond,
+            data_dir()
+        ));
+    }
+    Ok(bond_path(name))
+}
+
+/// Refuse a wallet name that `wallet_path` would resolve outside the data directory
+fn wallet_in_data_dir(wallet: &str) -> Result<&str, String> {
+    if wallet.is_empty() || wallet == "." || wallet == ".." || wallet.contains(['/', '\\']) {
+        return Err(format!(
+            "Wallet '{}' is not a wallet in the data directory {}",
+            wallet,
+            data_dir()
+        ));
+    }
+    Ok(wallet)
+}
+
+/// Structured result of one RPC call
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CommandResult {
+    Registered {
+        wallet: String,
+        public_spending_key: String,
+        public_viewing_key: String,
+    },
+    Balance(Balance),
+    Bought(NoteSummary),
+    Info(NoteSummary),
+    Scanned {
+        found: usize,
+        memos: Vec<ScannedMemo>,
+    },
+}
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    method: String,
+    #[serde(default)]
+    params: Json,
+    #[serde(default)]
+    id: Json,
+}
+
+#[derive(Deserialize)]
+struct WalletParams {
+    wallet: String,
+}
+
+#[derive(Deserialize)]
+struct BuyParams {
+    wallet: String,
+    value: u64,
+    issuer_wallet: String,
+    source_note: Option<String>,
+    source_commitment: Option<String>,
+    asset_id: Option<u64>,
+    change_to: Option<String>,
+    label: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct InfoParams {
+    bond: String,
+}
+
+#[derive(Deserialize)]
+struct ScanParams {
+    wallet: String,
+    sender: Option<String>,
+}
+
+/// A command's error as a JSON-RPC error
+fn failed(e: impl std::fmt::Display) -> (i64, String) {
+    (COMMAND_FAILED, e.to_string())
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Json) -> Result<T, (i64, String)> {
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))
+}
+
+/// Run one RPC method against the library API
+async fn dispatch(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    request: RpcRequest,
+) -> Result<CommandResult, (i64, String)> {
+    match request.method.as_str() {
+        "register" => {
+            let p: WalletParams = params(request.params)?;
+            wallet_in_data_dir(&p.wallet).map_err(failed)?;
+            if wallet_exists(&p.wallet) {
+                return Err(failed(format!("Wallet '{}' already exists", p.wallet)));
+            }
+            register(&p.wallet).map_err(failed)?;
+            let wallet = load_wallet(&p.wallet)
+                .ok_or_else(|| failed(format!("Failed to create wallet '{}'", p.wallet)))?;
+            Ok(CommandResult::Registered {
+                wallet: p.wallet,
+                public_spending_key: wallet.keys.public_spending_key_hex.clone(),
+                public_viewing_key: hex::encode(wallet.keys.public_viewing_key()),
+            })
+        }
+        "balance" => {
+            let p: WalletParams = params(request.params)?;
+            let wallet = wallet_in_data_dir(&p.wallet).map_err(failed)?;
+            let balance = wallet_balance(contract, wallet).await.map_err(failed)?;
+            Ok(CommandResult::Balance(balance))
+        }
+        "buy" => {
+            let p: BuyParams = params(request.params)?;
+            wallet_in_data_dir(&p.wallet).map_err(failed)?;
+            wallet_in_data_dir(&p.issuer_wallet).map_err(failed)?;
+            // change_to is a wallet name unless it is a `<spending key>:<viewing key>` pair
+            if let Some(change_to) = p.change_to.as_deref().filter(|who| !who.contains(':')) {
+                wallet_in_data_dir(change_to).map_err(failed)?;
+            }
+            let source = match (p.source_note, p.source_commitment) {
+                (Some(path), _) => SourceNote::Path(bond_in_data_dir(&path).map_err(failed)?),
+                (_, Some(commitment)) => SourceNote::Commitment(commitment),
+                _ => SourceNote::Auto {
+                    asset_id: p.asset_id.unwrap_or(ASSET_ID),
+                },
+            };
+            let (path, bond) = buy(
+                prover,
+                contract,
+                events,
+                &p.wallet,
+                p.value,
+                &source,
+                &p.issuer_wallet,
+                p.change_to.as_deref(),
+                p.label.as_deref(),
+            )
+            .await
+            .map_err(failed)?;
+            Ok(CommandResult::Bought(NoteSummary::new(&path, &bond)))
+        }
+        "info" => {
+            let p: InfoParams = params(request.params)?;
+            let path = bond_in_data_dir(&p.bond).map_err(failed)?;
+            let bond =
+                load_bond(&path).ok_or_else(|| failed(format!("Bond '{}' not found", p.bond)))?;
+            Ok(CommandResult::Info(NoteSummary::new(&p.bond, &bond)))
+        }
+        "scan" => {
+            let p: ScanParams = params(request.params)?;
+            let wallet = wallet_in_data_dir(&p.wallet).map_err(failed)?;
+            let (found, memos) = scan_memos(wallet, p.sender.as_deref()).map_err(failed)?;
+            Ok(CommandResult::Scanned { found, memos })
+        }
+        other => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", other))),
+    }
+}
+
+/// JSON-RPC response body for a raw request body
+async fn handle_body(
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    body: &[u8],
+) -> Json {
+    let request: RpcRequest = match serde_json::from_slice(body) {
+        Ok(r) => r,
+        Err(e) => {
+            return json!({
+                "jsonrpc": "2.0",
+                "error": { "code": PARSE_ERROR, "message": e.to_string() },
+                "id": null,
+            })
+        }
+    };
+    let id = request.id.clone();
+    match dispatch(prover, contract, events, request).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "error": { "code": code, "message": message },
+            "id": id,
+        }),
+    }
+}
+
+/// A request refused before dispatch: HTTP status and error message
+type Refusal = (&'static str, String);
+
+/// Read the request head and body from `reader`, checking method, token,
+/// content type and size before any of the body is read
+async fn read_request(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    token: &str,
+) -> std::io::Result<Result<Vec<u8>, Refusal>> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut content_length = None;
+    let mut authorized = false;
+    let mut json_body = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().ok();
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorized = value
+                    .strip_prefix("Bearer ")
+                    .is_some_and(|given| token_matches(given.trim(), token));
+            } else if name.eq_ignore_ascii_case("content-type") {
+                json_body = value
+                    .split(';')
+                    .next()
+                    .is_some_and(|t| t.trim().eq_ignore_ascii_case("application/json"));
+            }
+        }
+    }
+
+    if !request_line.starts_with("POST ") {
+        return Ok(Err(("405 Method Not Allowed", "use POST".to_string())));
+    }
+    if !authorized {
+        return Ok(Err((
+            "401 Unauthorized",
+            "missing or wrong bearer token".to_string(),
+        )));
+    }
+    if !json_body {
+        return Ok(Err((
+            "415 Unsupported Media Type",
+            "Content-Type must be application/json".to_string(),
+        )));
+    }
+    let content_length = match content_length {
+        Some(n) if n > MAX_BODY_BYTES => {
+            return Ok(Err((
+                "413 Payload Too Large",
+                format!("body is limited to {} bytes", MAX_BODY_BYTES),
+            )))
+        }
+        Some(n) => n,
+        None => return Ok(Err(("411 Length Required", "Content-Length required".to_string()))),
+    };
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).await?;
+    Ok(Ok(body))
+}
+
+/// Read one HTTP request and answer it; only an authorized `POST` with a JSON body
+/// is dispatched. A client that stalls for `IO_TIMEOUT` is dropped.
+async fn handle_connection(
+    token: &str,
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+    stream: TcpStream,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream).take((MAX_HEAD_BYTES + MAX_BODY_BYTES) as u64);
+
+    let (status, body) = match timeout(IO_TIMEOUT, read_request(&mut reader, token)).await {
+        Err(_) => (
+            "408 Request Timeout",
+            json!({ "error": "request not received in time" }).to_string(),
+        ),
+        Ok(request) => match request? {
+            Ok(body) => {
+                let response = handle_body(prover, contract, events, &body).await;
+                ("200 OK", response.to_string())
+            }
+            Err((status, message)) => (status, json!({ "error": message }).to_string()),
+        },
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let stream = reader.get_mut().get_mut();
+    timeout(IO_TIMEOUT, stream.write_all(response.as_bytes()))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "client stopped reading"))?
+}
+
+/// Serve JSON-RPC requests on `listener`, one connection at a time, until the process exits.
+/// Requests must present `token` as a bearer token.
+pub async fn serve(
+    listener: TcpListener,
+    token: &str,
+    prover: &impl Prover,
+    contract: &impl BondContract,
+    events: &impl EventSink,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                if let Err(e) = handle_connection(token, prover, contract, events, stream).await {
+                    println!("⚠️  Connection failed: {}", e);
+                }
+            }
+            Err(e) => println!("⚠️  Accept failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::MockBondContract;
+    use crate::events::RecordingSink;
+    use crate::keys::ShieldedKeys;
+    use crate::prover::MockProver;
+    use crate::utils::{
+        ensure_data_dir, fr_to_bytes32, set_data_dir, temp_data_dir, wallet_path, Bond, Wallet,
+        BOND_SCHEMA_VERSION, WALLET_SCHEMA_VERSION,
+    };
+    use std::fs;
+
+    const TOKEN: &str = "test-token";
+
+    /// Send raw HTTP and return the status line and parsed response body
+    async fn send(addr: std::net::SocketAddr, http: String) -> (String, Json) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(http.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.lines().next().unwrap().to_string();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    /// POST a JSON-RPC request with the server's token and return the parsed response body
+    async fn post(addr: std::net::SocketAddr, request: Json) -> Json {
+        let body = request.to_string();
+        let http = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            TOKEN,
+            body.len(),
+            body
+        );
+        send(addr, http).await.1
+    }
+
+    #[test]
+    fn test_balance_endpoint() {
+        let (_guard, dir) = temp_data_dir("server_balance");
+        ensure_data_dir().unwrap();
+
+        let keys = ShieldedKeys::from_seed([4u8; 32]);
+        let wallet = Wallet {
+            schema_version: WALLET_SCHEMA_VERSION,
+            keys: keys.clone(),
+            created_at: String::new(),
+        };
+        fs::write(
+            wallet_path("holder"),
+            serde_json::to_string(&wallet).unwrap(),
+        )
+        .unwrap();
+        for (salt, value) in [(1u64, 300u64), (2, 700), (3, 50)] {
+            let bond = Bond {
+                schema_version: BOND_SCHEMA_VERSION,
+                commitment: format!("0x{:064x}", salt),
+                nullifier: String::new(),
+                value,
+                salt,
+                owner: keys.public_spending_key_hex.clone(),
+                asset_id: 1,
+                maturity_date: 1893456000,
+                created_at: String::new(),
+                label: None,
+                memo_hash: None,
+            };
+            let path = format!("{}/bond_holder_{}.json", dir, salt);
+            fs::write(path, serde_json::to_string(&bond).unwrap()).unwrap();
+        }
+
+        // The 50-unit note is already spent on-chain
+        let contract = MockBondContract::new();
+        contract
+            .nullifiers
+            .lock()
+            .unwrap()
+            .insert(fr_to_bytes32(&keys.sign_nullifier(3)));
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let events = RecordingSink::new();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let response = rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let request = json!({
+                "jsonrpc": "2.0",
+                "method": "balance",
+                "params": { "wallet": "holder" },
+                "id": 7,
+            });
+            tokio::select! {
+                _ = serve(listener, TOKEN, &prover, &contract, &events) => unreachable!(),
+                response = post(addr, request) => response,
+            }
+        });
+
+        assert_eq!(response["id"], 7);
+        let result = &response["result"];
+        assert_eq!(result["type"], "balance");
+        assert_eq!(result["total"], 1000);
+        assert_eq!(result["notes"].as_array().unwrap().len(), 2);
+
+        // Nothing secret on the wire
+        let text = response.to_string();
+        assert!(!text.contains("salt") && !text.contains("seed") && !text.contains("private"));
+
+        set_data_dir(None);
+    }
+
+    #[test]
+    fn test_server_refuses_unauthenticated_oversized_and_outside_requests() {
+        let (_guard, dir) = temp_data_dir("server_refusals");
+        ensure_data_dir().unwrap();
+        let outside = format!("{}_outside.json", dir);
+        fs::write(&outside, "{}").unwrap();
+        let escape = format!(
+            "../{}",
+            std::path::Path::new(&outside).file_name().unwrap().to_string_lossy()
+        );
+
+        let contract = MockBondContract::new();
+        let prover = MockProver::new(&format!("{}/proof", dir));
+        let events = RecordingSink::new();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::select! {
+                _ = serve(listener, TOKEN, &prover, &contract, &events) => unreachable!(),
+                _ = async {
+                    let body = json!({ "jsonrpc": "2.0", "method": "info", "params": { "bond": "x" }, "id": 1 }).to_string();
+
+                    // No token, then the wrong token: a cross-site form post looks like this
+                    for auth in ["", "Authorization: Bearer wrong-token\r\n"] {
+                        let http = format!(
+                            "POST / HTTP/1.1\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                            auth, body.len(), body
+                        );
+                        assert!(send(addr, http).await.0.contains("401"));
+                    }
+
+                    // A form content type is refused even with the token
+                    let http = format!(
+                        "POST / HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+                        TOKEN, body.len(), body
+                    );
+                    assert!(send(addr, http).await.0.contains("415"));
+
+                    // A huge Content-Length is refused before anything is allocated
+                    let http = format!(
+                        "POST / HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+                        TOKEN, usize::MAX
+                    );
+                    assert!(send(addr, http).await.0.contains("413"));
+
+                    // info only reads bond files inside the data directory
+                    for bond in [outside.as_str(), escape.as_str()] {
+                        let response = post(addr, json!({
+                            "jsonrpc": "2.0", "method": "info", "params": { "bond": bond }, "id": 2,
+                        })).await;
+                        assert_eq!(response["error"]["code"], COMMAND_FAILED);
+                        assert!(response["error"]["message"].as_str().unwrap().contains("not a file in the data directory"));
+                    }
+
+                    // Wallet names cannot reach outside it either
+                    let escape_wallet = escape.trim_end_matches(".json");
+                    for (method, params) in [
+                        ("register", json!({ "wallet": escape_wallet })),
+                        ("balance", json!({ "wallet": escape_wallet })),
+                        ("scan", json!({ "wallet": escape_wallet })),
+                        ("buy", json!({ "wallet": "buyer", "value": 1, "issuer_wallet": escape_wallet })),
+                        ("buy", json!({ "wallet": "buyer", "value": 1, "issuer_wallet": "issuer", "change_to": escape_wallet })),
+                    ] {
+                        let response = post(addr, json!({
+                            "jsonrpc": "2.0", "method": method, "params": params, "id": 3,
+                        })).await;
+                        assert_eq!(response["error"]["code"], COMMAND_FAILED, "{}", method);
+                        assert!(response["error"]["message"].as_str().unwrap().contains("not a wallet in the data directory"));
+                    }
+                    assert_eq!(fs::read_to_string(&outside).unwrap(), "{}");
+                } => {}
+            }
+        });
+
+        set_data_dir(None);
+        let _ = fs::remove_file(&outside);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
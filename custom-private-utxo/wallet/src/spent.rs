@@ -0,0 +1,54 @@
+//! Every nullifier the contract has marked spent, rebuilt from its
+//! `NullifierSpent` events by `sync-nullifiers`. The history log only holds this
+//! wallet's own calls; this set comes from the chain, so it covers spends made
+//! elsewhere and can be rebuilt after the data directory is lost.
+
+use alloy::primitives::FixedBytes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+
+use crate::utils::{ensure_data_dir, spent_nullifiers_path, write_atomic};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpentNullifiers {
+    /// Block the event scan started from
+    pub from_block: u64,
+    /// Highest block an event was seen in; a later `--from-block` resumes after it
+    pub last_block: u64,
+    /// Nullifiers (bytes32 hex, lowercase)
+    pub nullifiers: BTreeSet<String>,
+}
+
+impl SpentNullifiers {
+    /// The saved set, or an empty one if `sync-nullifiers` never ran
+    pub fn load() -> Result<SpentNullifiers, String> {
+        let path = spent_nullifiers_path();
+        match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Invalid {}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SpentNullifiers::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path, e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        ensure_data_dir().map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize spent set: {}", e))?;
+        write_atomic(&spent_nullifiers_path(), json)
+            .map_err(|e| format!("Failed to write {}: {}", spent_nullifiers_path(), e))
+    }
+
+    /// Add the events of one scan, returning how many nullifiers were new
+    pub fn extend(&mut self, events: &[(u64, FixedBytes<32>)]) -> usize {
+        let before = self.nullifiers.len();
+        for (block, nullifier) in events {
+            self.last_block = self.last_block.max(*block);
+            self.nullifiers.insert(format!("{}", nullifier).to_lowercase());
+        }
+        self.nullifiers.len() - before
+    }
+
+    pub fn contains(&self, nullifier: &FixedBytes<32>) -> bool {
+        self.nullifiers.contains(&format!("{}", nullifier).to_lowercase())
+    }
+}
@@ -1,63 +1,347 @@
 //! Utility functions and data paths for the wallet
 
 use alloy::primitives::FixedBytes;
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
 use num_bigint::BigUint;
-use poseidon_rs::Fr;
 use ff::PrimeField;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::{serde_as, DeserializeAs, SerializeAs};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
 
+use crate::field::{Fr, FrRepr};
+use crate::config::{FIELD_ENDIANNESS, ROOT_HISTORY_SIZE, TREE_BACKEND};
 use crate::keys::ShieldedKeys;
+use crate::passphrase;
 
 /// Data directory for all wallet files
 pub const DATA_DIR: &str = "data";
 
+static DATA_DIR_OVERRIDE: RwLock<Option<String>> = RwLock::new(None);
+
+/// Current data directory (`DATA_DIR` unless overridden with `set_data_dir`)
+pub fn data_dir() -> String {
+    DATA_DIR_OVERRIDE
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| DATA_DIR.to_string())
+}
+
+/// Redirect all wallet files to another directory (used by tests)
+pub fn set_data_dir(dir: Option<&str>) {
+    *DATA_DIR_OVERRIDE.write().unwrap() = dir.map(str::to_string);
+}
+
+/// Serializes tests that redirect the process-wide data directory
+#[cfg(test)]
+static DATA_DIR_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Point the data directory at a fresh temp dir for the duration of the guard
+#[cfg(test)]
+pub(crate) fn temp_data_dir(name: &str) -> (std::sync::MutexGuard<'static, ()>, String) {
+    let guard = DATA_DIR_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = std::env::temp_dir().join(format!("wallet_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let dir = dir.to_string_lossy().to_string();
+    set_data_dir(Some(&dir));
+    (guard, dir)
+}
+
 /// Get path for wallet file
 pub fn wallet_path(wallet_name: &str) -> String {
-    format!("{}/{}.json", DATA_DIR, wallet_name)
+    format!("{}/{}.json", data_dir(), wallet_name)
+}
+
+/// Hex private key that signs and pays for `wallet_name`'s contract calls, if the
+/// wallet has its own (see `contract::resolve_signer`)
+pub fn signer_key_path(wallet_name: &str) -> String {
+    format!("{}/{}.signer", data_dir(), wallet_name)
 }
 
 /// Get path for bond file  
 pub fn bond_path(filename: &str) -> String {
-    if filename.starts_with(DATA_DIR) || filename.starts_with("./") {
+    let dir = data_dir();
+    if filename.starts_with(&dir) || filename.starts_with("./") {
         filename.to_string()
     } else {
-        format!("{}/{}", DATA_DIR, filename)
+        format!("{}/{}", dir, filename)
     }
 }
 
 /// Get path for tree state file
 pub fn tree_state_path() -> String {
-    format!("{}/tree_state.json", DATA_DIR)
+    format!("{}/tree_state.json", data_dir())
+}
+
+/// Path of the `PagedTree` file kept under `TreeBackend::Paged`
+pub fn paged_tree_path() -> String {
+    format!("{}/tree_pages.bin", data_dir())
 }
 
 /// Get path for global note tranche file
 pub fn global_note_path() -> String {
-    format!("{}/global_note_tranche.json", DATA_DIR)
+    format!("{}/global_note_tranche.json", data_dir())
 }
 
-/// Ensure data directory exists
-pub fn ensure_data_dir() {
-    let _ = fs::create_dir_all(DATA_DIR);
+/// Get path for a saved witness, keyed by the transaction hash it was submitted in
+pub fn witness_path(tx_hash: &str) -> String {
+    format!("{}/witness_{}.json", data_dir(), tx_hash)
 }
 
-/// Convert Fr field element to bytes32 for contract calls
+/// Get path for a redemption receipt, keyed by the burned nullifier (bytes32 hex)
+pub fn redemption_receipt_path(nullifier: &str) -> String {
+    format!(
+        "{}/redemption_{}.json",
+        data_dir(),
+        short(nullifier.trim_start_matches("0x"), 16)
+    )
+}
+
+/// Get path for the append-only log of confirmed transactions
+pub fn history_path() -> String {
+    format!("{}/history.jsonl", data_dir())
+}
+
+/// Get path for the spent set rebuilt from the contract's events (`spent::SpentNullifiers`)
+pub fn spent_nullifiers_path() -> String {
+    format!("{}/spent_nullifiers.json", data_dir())
+}
+
+/// Directory of spends recorded before submission (`attempt::Attempt`)
+pub fn attempts_dir() -> String {
+    format!("{}/attempts", data_dir())
+}
+
+/// Directory `prune` moves spent bond files into
+pub fn archive_dir() -> String {
+    format!("{}/archive", data_dir())
+}
+
+/// Bond files directly in `dir`, sorted by path
+pub fn bonds_in(dir: &str) -> Vec<(String, Bond)> {
+    let mut bonds: Vec<(String, Bond)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path().to_string_lossy().to_string())
+        .filter(|p| p.ends_with(".json"))
+        .filter_map(|path| {
+            let bond = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+            Some((path, bond))
+        })
+        .collect();
+    bonds.sort_by(|a, b| a.0.cmp(&b.0));
+    bonds
+}
+
+/// Get path for asset metadata registry
+pub fn assets_path() -> String {
+    format!("{}/assets.json", data_dir())
+}
+
+/// Ensure data directory exists, returning its path; the error names the
+/// directory that could not be created
+pub fn ensure_data_dir() -> std::io::Result<PathBuf> {
+    let dir = PathBuf::from(data_dir());
+    fs::create_dir_all(&dir).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Cannot create data directory {}: {}", dir.display(), e),
+        )
+    })?;
+    Ok(dir)
+}
+
+/// Replace `path` with what `write` produces, via a temporary file renamed into
+/// place: an interrupted or failed write leaves the previous file untouched
+pub fn write_atomic_with(
+    path: &str,
+    write: impl FnOnce(&mut fs::File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let tmp = format!("{}.tmp", path);
+    let written = fs::File::create(&tmp).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    match written.and_then(|_| fs::rename(&tmp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// `fs::write`, atomically: see `write_atomic_with`
+pub fn write_atomic(path: &str, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    write_atomic_with(path, |file| file.write_all(contents.as_ref()))
+}
+
+/// Byte order of a field element's 32-byte encoding
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    /// Most significant byte first, as Solidity reads a `bytes32`
+    Big,
+    /// Least significant byte first, as some non-EVM verifiers expect
+    Little,
+}
+
+/// Convert Fr field element to bytes32 for contract calls, in `FIELD_ENDIANNESS`
 pub fn fr_to_bytes32(fr: &Fr) -> FixedBytes<32> {
+    fr_to_bytes32_with(fr, FIELD_ENDIANNESS)
+}
+
+/// Convert an Fr to 32 bytes in the given byte order
+pub fn fr_to_bytes32_with(fr: &Fr, endianness: Endianness) -> FixedBytes<32> {
     let repr = fr.into_repr();
     let limbs: &[u64] = repr.as_ref();
-    
-    // Convert 4 u64 limbs to 32 bytes (little-endian limbs to big-endian bytes)
+
+    // Limbs are least significant first, so their little-endian bytes concatenate
+    // to the little-endian encoding
     let mut bytes = [0u8; 32];
     for (i, limb) in limbs.iter().enumerate() {
-        let limb_bytes = limb.to_le_bytes();
-        bytes[i * 8..(i + 1) * 8].copy_from_slice(&limb_bytes);
+        bytes[i * 8..(i + 1) * 8].copy_from_slice(&limb.to_le_bytes());
+    }
+    if endianness == Endianness::Big {
+        bytes.reverse();
     }
-    // Reverse for big-endian (contract expects big-endian)
-    bytes.reverse();
     FixedBytes::from(bytes)
 }
 
+/// Convert bytes32 in `FIELD_ENDIANNESS` back to Fr (None if not a canonical field
+/// element); the inverse of `fr_to_bytes32`
+pub fn fr_from_bytes32(bytes: &FixedBytes<32>) -> Option<Fr> {
+    fr_from_bytes32_with(bytes, FIELD_ENDIANNESS)
+}
+
+/// Convert 32 bytes in the given byte order back to Fr (None if not canonical)
+pub fn fr_from_bytes32_with(bytes: &FixedBytes<32>, endianness: Endianness) -> Option<Fr> {
+    let mut le = bytes.0;
+    if endianness == Endianness::Big {
+        le.reverse();
+    }
+    let mut repr = FrRepr::default();
+    for (i, limb) in repr.as_mut().iter_mut().enumerate() {
+        let mut limb_bytes = [0u8; 8];
+        limb_bytes.copy_from_slice(&le[i * 8..(i + 1) * 8]);
+        *limb = u64::from_le_bytes(limb_bytes);
+    }
+    Fr::from_repr(repr).ok()
+}
+
+/// Canonical string form of a field element, used for everything stored on disk:
+/// 0x-prefixed 32-byte big-endian hex, the same encoding as the on-chain bytes32
+pub fn fr_to_hex(fr: &Fr) -> String {
+    format!("{}", fr_to_bytes32_with(fr, Endianness::Big))
+}
+
+/// Parse the `fr_to_hex` form (None if malformed or not a canonical field element).
+/// Use `parse_commitment` for strings that may predate it.
+pub fn fr_from_hex(s: &str) -> Option<Fr> {
+    fr_from_bytes32_with(&s.parse().ok()?, Endianness::Big)
+}
+
+/// Field element from a u64, built from limbs rather than a decimal string.
+/// Any u64 is far below the BN254 modulus, so this never reduces or fails.
+pub fn u64_to_fr(n: u64) -> Fr {
+    let mut repr = FrRepr::default();
+    repr.as_mut()[0] = n;
+    Fr::from_repr(repr).expect("u64 is below the field modulus")
+}
+
+/// Note salt. Bounded to u64 so it always maps to a unique field element;
+/// displayed as 16 hex digits, as in note file names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Salt(pub u64);
+
+/// Note value in smallest units, bounded to u64 like `Salt`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Value(pub u64);
+
+impl Salt {
+    pub fn to_fr(self) -> Fr {
+        u64_to_fr(self.0)
+    }
+}
+
+impl Value {
+    pub fn to_fr(self) -> Fr {
+        u64_to_fr(self.0)
+    }
+}
+
+impl TryFrom<u128> for Salt {
+    type Error = String;
+
+    fn try_from(n: u128) -> Result<Self, String> {
+        u64::try_from(n)
+            .map(Salt)
+            .map_err(|_| format!("Salt {} exceeds the field-safe bound {}", n, u64::MAX))
+    }
+}
+
+impl TryFrom<u128> for Value {
+    type Error = String;
+
+    fn try_from(n: u128) -> Result<Self, String> {
+        u64::try_from(n)
+            .map(Value)
+            .map_err(|_| format!("Value {} exceeds the field-safe bound {}", n, u64::MAX))
+    }
+}
+
+impl std::fmt::Display for Salt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Serde adapter storing an Fr canonically as 0x-prefixed 32-byte big-endian hex.
+/// Use directly as a newtype or via `#[serde_as(as = "FrHex")]` on `Fr` fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrHex(pub Fr);
+
+impl SerializeAs<Fr> for FrHex {
+    fn serialize_as<S: Serializer>(fr: &Fr, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&fr_to_hex(fr))
+    }
+}
+
+impl<'de> DeserializeAs<'de, Fr> for FrHex {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<Fr, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let bytes: FixedBytes<32> = s
+            .parse()
+            .map_err(|e| serde::de::Error::custom(format!("invalid bytes32 '{}': {}", s, e)))?;
+        fr_from_bytes32_with(&bytes, Endianness::Big)
+            .ok_or_else(|| serde::de::Error::custom(format!("'{}' is not a field element", s)))
+    }
+}
+
+impl Serialize for FrHex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FrHex::serialize_as(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FrHex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        FrHex::deserialize_as(deserializer).map(FrHex)
+    }
+}
+
 /// Format timestamp as human-readable date
 pub fn format_date(ts: u64) -> String {
     match DateTime::from_timestamp(ts as i64, 0) {
@@ -66,6 +350,38 @@ pub fn format_date(ts: u64) -> String {
     }
 }
 
+/// Where a note stands relative to its maturity at `now`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaturityStatus {
+    /// `now` is at or past the maturity date
+    Matured,
+    /// Whole days left, rounded up so the last day before maturity shows as 1
+    Pending { days_left: u64 },
+}
+
+/// Compare a maturity date with `now` (both unix seconds) without underflowing
+pub fn maturity_status(maturity_date: u64, now: u64) -> MaturityStatus {
+    match maturity_date.checked_sub(now) {
+        Some(secs) if secs > 0 => MaturityStatus::Pending {
+            days_left: secs.div_ceil(86400),
+        },
+        _ => MaturityStatus::Matured,
+    }
+}
+
+/// Current unix time in seconds (0 if the clock is before the epoch)
+pub fn unix_now() -> u64 {
+    u64::try_from(Utc::now().timestamp()).unwrap_or(0)
+}
+
+/// Truncate a string to at most `n` characters for display (never panics)
+pub fn short(s: &str, n: usize) -> &str {
+    match s.char_indices().nth(n) {
+        Some((idx, _)) => &s[..idx],
+        None => s,
+    }
+}
+
 /// Parse commitment string (Fr(0x...) format) to Fr
 pub fn parse_commitment(s: &str) -> Option<Fr> {
     // Strip "Fr(0x" prefix and ")" suffix
@@ -74,9 +390,14 @@ pub fn parse_commitment(s: &str) -> Option<Fr> {
         .trim_start_matches("Fr(")
         .trim_start_matches("0x")
         .trim_end_matches(')');
-    
-    // Try parsing as hex
-    if let Ok(bytes) = hex::decode(clean) {
+
+    // Try parsing as hex (left-pad odd lengths, e.g. Prover.toml's trimmed values)
+    let padded = if clean.len() % 2 == 1 {
+        format!("0{}", clean)
+    } else {
+        clean.to_string()
+    };
+    if let Ok(bytes) = hex::decode(&padded) {
         if !bytes.is_empty() {
             // Convert hex bytes to a big number, then to Fr
             let mut num_bytes = [0u8; 32];
@@ -98,14 +419,30 @@ pub fn parse_commitment(s: &str) -> Option<Fr> {
 
 // === Wallet and Bond types ===
 
+/// Layout of wallet files. v1 files predate `schema_version` and may lack `kdf_version`.
+pub const WALLET_SCHEMA_VERSION: u32 = 2;
+/// Layout of bond files. v1 files predate `schema_version` and may hold commitments
+/// and nullifiers in the poseidon-rs `Fr(0x..)` display form or as decimal.
+pub const BOND_SCHEMA_VERSION: u32 = 2;
+
+/// Files written before versioning have no `schema_version`
+fn legacy_schema_version() -> u32 {
+    1
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Wallet {
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
     pub keys: ShieldedKeys,
     pub created_at: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Bond {
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
     pub commitment: String,
     pub nullifier: String,
     pub value: u64,
@@ -114,33 +451,250 @@ pub struct Bond {
     pub asset_id: u64,
     pub maturity_date: u64,
     pub created_at: String,
+    /// Local name for the bond; never part of the commitment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Memo hash the commitment binds under `scheme::MemoBound`, needed to spend
+    /// the note; `None` for notes committed without one
+    #[serde_as(as = "Option<FrHex>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo_hash: Option<Fr>,
+}
+
+impl Bond {
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        write_atomic(path, serde_json::to_string_pretty(self).unwrap())
+            .map_err(|e| format!("Error saving bond to {}: {}", path, e))
+    }
 }
 
-/// Load wallet from data directory
+/// Record linking a burned nullifier to its off-chain settlement.
+/// With `private`, value and owner are left out so the receipt can be shared
+/// for reconciliation without revealing the note.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RedemptionReceipt {
+    /// Burned nullifier as the bytes32 submitted to `burn()`
+    pub nullifier: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settlement_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settlement_account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    pub asset_id: u64,
+    pub maturity_date: u64,
+    pub redeemed_at: String,
+}
+
+impl RedemptionReceipt {
+    pub fn new(
+        bond: &Bond,
+        nullifier: &Fr,
+        settlement_ref: Option<String>,
+        settlement_account: Option<String>,
+        private: bool,
+    ) -> Self {
+        RedemptionReceipt {
+            nullifier: fr_to_hex(nullifier),
+            tx_hash: None,
+            settlement_ref,
+            settlement_account,
+            value: (!private).then_some(bond.value),
+            owner: (!private).then(|| bond.owner.clone()),
+            asset_id: bond.asset_id,
+            maturity_date: bond.maturity_date,
+            redeemed_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Save to `data/redemption_<nullifier prefix>.json`, returning the path
+    pub fn save(&self) -> std::io::Result<String> {
+        ensure_data_dir()?;
+        let path = redemption_receipt_path(&self.nullifier);
+        write_atomic(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+/// Name of sub-account `account` of a wallet: the wallet itself for account 0,
+/// else `name@account`, which `load_wallet` derives from the wallet's seed
+pub fn account_wallet_name(wallet_name: &str, account: u32) -> String {
+    match account {
+        0 => wallet_name.to_string(),
+        n => format!("{}@{}", wallet_name, n),
+    }
+}
+
+/// A field element as v1 files wrote it: `Fr(0x..)`, `0x..` hex or decimal
+fn legacy_fr(s: &str) -> Option<Fr> {
+    if s.starts_with("Fr(") || s.starts_with("0x") {
+        parse_commitment(s)
+    } else if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        Fr::from_str(s)
+    } else {
+        None
+    }
+}
+
+/// Upgrade a wallet file's JSON from `version` to the next version
+fn migrate_wallet(version: u32, json: &mut serde_json::Value) -> Result<(), String> {
+    match version {
+        // v1 -> v2: spell out the key derivation wallets used before it was recorded, and
+        // write the public key in `fr_to_hex` form so it compares equal to bond owners
+        1 => {
+            let keys = json
+                .get_mut("keys")
+                .and_then(|k| k.as_object_mut())
+                .ok_or("wallet has no keys")?;
+            keys.entry("kdf_version").or_insert(crate::keys::KDF_V1.into());
+            if let Some(value) = keys.get_mut("public_spending_key_hex") {
+                if let Some(fr) = value.as_str().and_then(legacy_fr) {
+                    *value = fr_to_hex(&fr).into();
+                }
+            }
+            Ok(())
+        }
+        v => Err(format!("no migration from wallet schema v{}", v)),
+    }
+}
+
+/// Upgrade a bond file's JSON from `version` to the next version
+fn migrate_bond(version: u32, json: &mut serde_json::Value) -> Result<(), String> {
+    match version {
+        // v1 -> v2: commitment, nullifier and owner in `fr_to_hex` form. The issuer's
+        // tranche note has a placeholder nullifier, which is left as it is.
+        1 => {
+            for field in ["commitment", "nullifier", "owner"] {
+                let Some(value) = json.get_mut(field) else {
+                    continue;
+                };
+                if let Some(fr) = value.as_str().and_then(legacy_fr) {
+                    *value = fr_to_hex(&fr).into();
+                }
+            }
+            Ok(())
+        }
+        v => Err(format!("no migration from bond schema v{}", v)),
+    }
+}
+
+/// Parse a versioned JSON file, running `migrate` one version at a time up to
+/// `current`. Returns the value and whether it was upgraded (and so should be written back).
+fn load_versioned<T: serde::de::DeserializeOwned>(
+    content: &str,
+    current: u32,
+    migrate: fn(u32, &mut serde_json::Value) -> Result<(), String>,
+) -> Result<(T, bool), String> {
+    let mut json: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let found = match json.get("schema_version") {
+        None => legacy_schema_version(),
+        Some(v) => v.as_u64().ok_or("schema_version is not a number")? as u32,
+    };
+    if found > current {
+        return Err(format!(
+            "schema v{} is newer than this wallet understands (v{})",
+            found, current
+        ));
+    }
+    for version in found..current {
+        migrate(version, &mut json)?;
+    }
+    if found < current {
+        json["schema_version"] = current.into();
+    }
+    let value = serde_json::from_value(json).map_err(|e| e.to_string())?;
+    Ok((value, found < current))
+}
+
+/// Write back a file `load_versioned` upgraded; a failure only costs redoing the migration
+fn write_migrated(path: &str, json: Result<String, String>, from: &str) {
+    let written = json.and_then(|json| write_atomic(path, json).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => println!("ℹ️  Upgraded {} from {}", path, from),
+        Err(e) => println!("⚠️  Could not write upgraded {}: {}", path, e),
+    }
+}
+
+/// Load wallet from data directory; `name@N` loads account N of wallet `name`
 pub fn load_wallet(wallet_name: &str) -> Option<Wallet> {
+    if let Some((base, account)) = wallet_name.rsplit_once('@') {
+        let account: u32 = account.parse().ok()?;
+        let wallet = load_wallet(base)?;
+        return Some(Wallet {
+            keys: wallet.keys.derive_account(account),
+            ..wallet
+        });
+    }
     let path = wallet_path(wallet_name);
-    match fs::read_to_string(&path) {
-        Ok(content) => serde_json::from_str(&content).ok(),
-        Err(_) => None,
+    let content = fs::read_to_string(&path).ok()?;
+    let loaded = passphrase::open_wallet_file(&content)
+        .and_then(|content| load_versioned::<Wallet>(&content, WALLET_SCHEMA_VERSION, migrate_wallet));
+    match loaded {
+        Ok((wallet, migrated)) => {
+            if migrated {
+                write_migrated(&path, wallet_file_json(&wallet), "an older wallet format");
+            }
+            Some(wallet)
+        }
+        Err(e) => {
+            println!("❌ Error reading wallet {}: {}", path, e);
+            None
+        }
     }
 }
 
-/// Save wallet to data directory
+/// Whether `wallet_name` has a wallet file, even one that does not load (sealed
+/// without the passphrase at hand, or unreadable): creating a wallet must not replace it
+pub fn wallet_exists(wallet_name: &str) -> bool {
+    std::path::Path::new(&wallet_path(wallet_name)).exists()
+}
+
+/// Save wallet to data directory. Sub-accounts (`name@N`) are derived, never saved.
 pub fn save_wallet(wallet_name: &str, wallet: &Wallet) -> std::io::Result<()> {
-    ensure_data_dir();
+    if wallet_name.contains('@') {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{}' is a derived account; only the base wallet is stored", wallet_name),
+        ));
+    }
+    ensure_data_dir()?;
     let path = wallet_path(wallet_name);
-    fs::write(&path, serde_json::to_string_pretty(wallet)?)
+    write_atomic(&path, wallet_file_json(wallet).map_err(std::io::Error::other)?)
+}
+
+/// A wallet as its file stores it: keys sealed if a passphrase is set (`passphrase`)
+pub fn wallet_file_json(wallet: &Wallet) -> Result<String, String> {
+    let mut json = serde_json::to_value(wallet).map_err(|e| e.to_string())?;
+    passphrase::seal_wallet_json(&mut json)?;
+    serde_json::to_string_pretty(&json).map_err(|e| e.to_string())
 }
 
 /// Load bond from path (handles both absolute and relative paths)
+/// The file `load_bond` would read for `path`: as given, else under the data directory
+pub fn resolve_bond_path(path: &str) -> Option<String> {
+    [path.to_string(), bond_path(path)]
+        .into_iter()
+        .find(|p| std::path::Path::new(p).is_file())
+}
+
 pub fn load_bond(path: &str) -> Option<Bond> {
     // Try path as-is first, then with data/ prefix
     let paths_to_try = [path.to_string(), bond_path(path)];
     
     for p in &paths_to_try {
         if let Ok(content) = fs::read_to_string(p) {
-            match serde_json::from_str(&content) {
-                Ok(bond) => return Some(bond),
+            match load_versioned::<Bond>(&content, BOND_SCHEMA_VERSION, migrate_bond) {
+                Ok((bond, migrated)) => {
+                    if migrated {
+                        let json = serde_json::to_string_pretty(&bond).map_err(|e| e.to_string());
+                        write_migrated(p, json, "an older bond format");
+                    }
+                    return Some(bond);
+                }
                 Err(e) => {
                     println!("❌ Error parsing bond from {}: {}", p, e);
                     return None;
@@ -154,57 +708,966 @@ pub fn load_bond(path: &str) -> Option<Bond> {
 
 /// Save bond to data directory
 pub fn save_bond(filename: &str, bond: &Bond) -> std::io::Result<String> {
-    ensure_data_dir();
+    ensure_data_dir()?;
     let path = bond_path(filename);
     fs::write(&path, serde_json::to_string_pretty(bond)?)?;
+    index_bond(&mut TreeState::load(), &path, bond);
     Ok(path)
 }
 
+/// Add a freshly written bond file to the tree state's bond index. Flows holding
+/// a loaded `TreeState` pass it in, so their later saves keep the entry.
+pub fn index_bond(tree_state: &mut TreeState, path: &str, bond: &Bond) {
+    if let Err(e) = tree_state.record_bond(&bond.commitment, path) {
+        println!("⚠️  Bond {} not indexed: {}", path, e);
+    }
+}
+
 // === Tree State for merkle commitments ===
 
-use crate::merkle::FixedMerkleTree;
+use crate::merkle::{BuildProgress, CircuitMerklePath, FixedMerkleTree, MAX_LEAVES, TREE_HEIGHT};
+use crate::paged_tree::PagedTree;
+
+/// Where the tree that roots and merkle paths are read from lives
+/// (`config::TREE_BACKEND`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeBackend {
+    /// Rebuilt in memory from the state file's leaves by each command
+    Memory,
+    /// A `PagedTree` file in the data directory, caught up with the state file's
+    /// leaves and read one path at a time; rebuilt when its root is not the
+    /// state's latest
+    Paged,
+}
+
+/// The tree a spend takes its root and merkle paths from (`TreeState::proof_tree`)
+pub enum ProofTree {
+    Memory(FixedMerkleTree),
+    Paged { tree: PagedTree, root: Fr },
+}
+
+impl ProofTree {
+    pub fn root(&self) -> Fr {
+        match self {
+            ProofTree::Memory(tree) => tree.root(),
+            ProofTree::Paged { root, .. } => *root,
+        }
+    }
+
+    /// Merkle path of the leaf at `leaf_index`, in the circuit's format
+    pub fn generate_proof(&self, leaf_index: usize) -> Result<CircuitMerklePath, String> {
+        match self {
+            ProofTree::Memory(tree) => Ok(tree.generate_proof(leaf_index)),
+            ProofTree::Paged { tree, .. } => tree
+                .generate_proof(leaf_index)
+                .map(CircuitMerklePath::from)
+                .map_err(|e| format!("Cannot read {}: {}", paged_tree_path(), e)),
+        }
+    }
+}
+
+/// Saved contents of a `TreeState`, from `TreeState::snapshot`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    commitments: Vec<String>,
+    bond_files: Option<BTreeMap<String, Vec<String>>>,
+    root_history: VecDeque<String>,
+}
+
+/// A leaf to insert, with its index in the contract's tree when known
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Leaf {
+    /// Position the contract gave it (`commitment_at`); `None` for a leaf only
+    /// known locally, e.g. from a memo or another machine's export
+    pub index: Option<u64>,
+    pub commitment: Fr,
+}
+
+/// Canonical insertion order: leaves with an on-chain index by that index (the
+/// contract's own order, so the root matches `currentRoot`), then the rest by
+/// commitment as the bytes32 submitted on-chain. Repeats of a leaf collapse.
+/// Any machine ordering the same leaves this way builds the same tree.
+pub fn canonical_order(mut leaves: Vec<Leaf>) -> Vec<Leaf> {
+    leaves.sort_by(|a, b| match (a.index, b.index) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => fr_to_bytes32(&a.commitment).cmp(&fr_to_bytes32(&b.commitment)),
+    });
+    leaves.dedup();
+    leaves
+}
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct TreeState {
-    /// List of commitment strings in insertion order (stored as Fr debug format)
-    pub commitments: Vec<String>,
+    /// List of commitment strings in insertion order (`fr_to_hex` form; older
+    /// entries may be in the poseidon-rs `Fr(0x..)` display form)
+    commitments: Vec<String>,
+    /// Bond files saved in this data directory, keyed by `fr_to_hex` commitment
+    /// (copies of a note share a key). `None` until first used, when the existing
+    /// bond files are indexed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bond_files: Option<BTreeMap<String, Vec<String>>>,
+    /// `fr_to_hex` commitment -> first leaf index, rebuilt from `commitments` on load
+    #[serde(skip)]
+    positions: HashMap<String, usize>,
+    /// `fr_to_hex` roots after each of the last `ROOT_HISTORY_SIZE` inserts, oldest
+    /// first; rebuilt from `commitments` when missing
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    root_history: VecDeque<String>,
+    /// Batched mode: inserts from this leaf index on are not yet hashed into
+    /// `root_history` or written
+    #[serde(skip)]
+    pending_from: Option<usize>,
+    /// Pending inserts that trigger a `flush` in batched mode
+    #[serde(skip)]
+    flush_every: usize,
+    /// State file writes made through this instance
+    #[serde(skip)]
+    writes: usize,
 }
 
 impl TreeState {
     pub fn load() -> Self {
         let path = tree_state_path();
-        match fs::read_to_string(&path) {
+        let mut state: TreeState = match fs::read_to_string(&path) {
             Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
             Err(_) => TreeState::default(),
+        };
+        state.reindex();
+        state
+    }
+
+    /// In-memory tree over `commitments`, without a bond index
+    pub fn from_commitments(commitments: Vec<String>) -> Self {
+        let mut state = TreeState {
+            commitments,
+            ..TreeState::default()
+        };
+        state.reindex();
+        state
+    }
+
+    /// In-memory tree over `leaves` in `canonical_order`, without a bond index
+    pub fn from_leaves(leaves: Vec<Leaf>) -> Self {
+        Self::from_commitments(canonical_order(leaves).iter().map(|l| fr_to_hex(&l.commitment)).collect())
+    }
+
+    /// Replace every leaf with `leaves` in `canonical_order` and save. The bond
+    /// index is kept; the root history restarts from the new leaves.
+    pub fn replace_leaves(&mut self, leaves: Vec<Leaf>) {
+        self.commitments = canonical_order(leaves).iter().map(|l| fr_to_hex(&l.commitment)).collect();
+        self.root_history.clear();
+        self.pending_from = None;
+        self.reindex();
+        self.save();
+    }
+
+    fn reindex(&mut self) {
+        self.positions.clear();
+        for (index, c) in self.commitments.iter().enumerate() {
+            if let Some(fr) = parse_commitment(c) {
+                self.positions.entry(fr_to_hex(&fr)).or_insert(index);
+            }
+        }
+        if self.root_history.is_empty() {
+            let leaves: Vec<Fr> = self.commitments.iter().take(MAX_LEAVES).filter_map(|c| parse_commitment(c)).collect();
+            let mut tree = FixedMerkleTree::new();
+            for leaf in leaves {
+                tree.insert(leaf);
+                self.push_root(tree.root());
+            }
         }
     }
     
-    pub fn save(&self) {
-        ensure_data_dir();
+    pub fn save(&mut self) {
+        if ensure_data_dir().is_err() {
+            return;
+        }
         let path = tree_state_path();
-        let _ = fs::write(&path, serde_json::to_string_pretty(self).unwrap());
+        if write_atomic(&path, serde_json::to_string_pretty(self).unwrap()).is_ok() {
+            self.writes += 1;
+        }
+    }
+
+    /// Times this instance has written the state file
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+
+    /// Buffer `add_commitment`s in memory until `commit` (or every `flush_every`
+    /// inserts), then hash and write them at once. Until then the state file keeps
+    /// its last complete version: a crash loses the buffered inserts, nothing more.
+    pub fn begin_batch(&mut self, flush_every: usize) {
+        if self.pending_from.is_none() {
+            self.pending_from = Some(self.commitments.len());
+        }
+        self.flush_every = flush_every.max(1);
+    }
+
+    /// Record the roots of the buffered inserts and write the state file, staying
+    /// in batched mode
+    pub fn flush(&mut self) {
+        let Some(from) = self.pending_from else {
+            return;
+        };
+        let end = self.commitments.len().min(MAX_LEAVES);
+        if from < end {
+            let leaves: Vec<Fr> = self.commitments[..end].iter().filter_map(|c| parse_commitment(c)).collect();
+            let mut tree = FixedMerkleTree::from_leaves(&leaves[..from.min(leaves.len())]);
+            for leaf in &leaves[from.min(leaves.len())..] {
+                tree.insert(*leaf);
+                self.push_root(tree.root());
+            }
+        }
+        self.pending_from = Some(self.commitments.len());
+        self.save();
+    }
+
+    /// `flush` and leave batched mode
+    pub fn commit(&mut self) {
+        self.flush();
+        self.pending_from = None;
+    }
+
+    /// Checkpoint taken before a command changes the tree, for `restore` if the
+    /// transaction it prepares fails
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            commitments: self.commitments.clone(),
+            bond_files: self.bond_files.clone(),
+            root_history: self.root_history.clone(),
+        }
+    }
+
+    /// Roll back to `snapshot` and save. Bond files written since stay on disk,
+    /// unindexed, until `reindex`.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.commitments = snapshot.commitments;
+        self.bond_files = snapshot.bond_files;
+        self.root_history = snapshot.root_history;
+        self.pending_from = None;
+        self.reindex();
+        self.save();
+    }
+
+    /// Leaves in insertion order
+    pub fn commitments(&self) -> &[String] {
+        &self.commitments
     }
     
     pub fn add_commitment(&mut self, commitment_fr: Fr) -> usize {
         let index = self.commitments.len();
-        // Store as the Fr debug format for consistency
-        self.commitments.push(format!("{}", commitment_fr));
+        let hex = fr_to_hex(&commitment_fr);
+        self.positions.entry(hex.clone()).or_insert(index);
+        self.commitments.push(hex);
+        if let Some(from) = self.pending_from {
+            if self.commitments.len() - from >= self.flush_every {
+                self.flush();
+            }
+            return index;
+        }
+        if self.commitments.len() <= MAX_LEAVES {
+            self.push_root(self.build_tree().root());
+        }
         self.save();
         index
     }
+
+    pub(crate) fn push_root(&mut self, root: Fr) {
+        if self.root_history.len() == ROOT_HISTORY_SIZE {
+            self.root_history.pop_front();
+        }
+        self.root_history.push_back(fr_to_hex(&root));
+    }
+
+    /// Roots after each of the last `ROOT_HISTORY_SIZE` inserts, oldest first
+    pub fn recent_roots(&self) -> Vec<Fr> {
+        self.root_history.iter().filter_map(|r| fr_from_hex(r)).collect()
+    }
+
+    /// Whether `root` is one of the `recent_roots`, so a proof against it still verifies
+    pub fn is_recent_root(&self, root: &Fr) -> bool {
+        self.root_history.contains(&fr_to_hex(root))
+    }
     
-    pub fn find_commitment(&self, commitment_str: &str) -> Option<usize> {
-        self.commitments.iter().position(|c| c == commitment_str)
+    /// Index of a commitment, comparing field elements so the stored formatting doesn't matter
+    pub fn find_commitment(&self, commitment: &Fr) -> Option<usize> {
+        self.positions.get(&fr_to_hex(commitment)).copied()
+    }
+
+    /// The bond index, seeded from the bond files already in the data directory
+    /// the first time it is needed
+    fn bond_index(&mut self) -> &mut BTreeMap<String, Vec<String>> {
+        self.bond_files.get_or_insert_with(|| {
+            let mut index: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            let mut paths: Vec<String> = fs::read_dir(data_dir())
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|e| e.path().to_string_lossy().to_string())
+                .filter(|p| p.ends_with(".json") && !p.ends_with("_REDEEMED.json"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                let bond: Option<Bond> = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|content| serde_json::from_str(&content).ok());
+                if let Some(commitment) = bond.and_then(|b| parse_commitment(&b.commitment)) {
+                    index.entry(fr_to_hex(&commitment)).or_default().push(path);
+                }
+            }
+            index
+        })
+    }
+
+    /// Re-index the bond files in the data directory, e.g. after copying some in
+    /// by hand. Returns the number of files indexed.
+    pub fn reindex_bonds(&mut self) -> usize {
+        self.bond_files = None;
+        let count = self.bond_index().values().map(Vec::len).sum();
+        self.save();
+        count
+    }
+
+    /// Remember that `path` holds the note with `commitment`, and save
+    pub fn record_bond(&mut self, commitment: &str, path: &str) -> Result<(), String> {
+        let commitment = parse_commitment(commitment)
+            .ok_or_else(|| format!("Invalid commitment '{}'", commitment))?;
+        let files = self.bond_index().entry(fr_to_hex(&commitment)).or_default();
+        if !files.iter().any(|f| f == path) {
+            files.push(path.to_string());
+        }
+        self.save();
+        Ok(())
+    }
+
+    /// Drop `path` from the bond index, e.g. once it is archived, and save
+    pub fn forget_bond(&mut self, path: &str) {
+        let index = self.bond_index();
+        for files in index.values_mut() {
+            files.retain(|f| f != path);
+        }
+        index.retain(|_, files| !files.is_empty());
+        self.save();
+    }
+
+    /// Files recorded for the note with `commitment`
+    pub fn bond_files(&mut self, commitment: &Fr) -> Vec<String> {
+        self.bond_index()
+            .get(&fr_to_hex(commitment))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Every indexed bond whose file is still there, sorted by path. Redeemed
+    /// bonds drop out once their file is renamed to `*_REDEEMED.json`.
+    pub fn indexed_bonds(&mut self) -> Vec<(String, Bond)> {
+        let mut bonds: Vec<(String, Bond)> = self
+            .bond_index()
+            .values()
+            .flatten()
+            .filter_map(|path| {
+                let bond = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+                Some((path.clone(), bond))
+            })
+            .collect();
+        bonds.sort_by(|a, b| a.0.cmp(&b.0));
+        bonds
     }
     
     /// Build a merkle tree from stored commitments
     pub fn build_tree(&self) -> FixedMerkleTree {
-        let mut tree = FixedMerkleTree::new();
-        for comm_str in &self.commitments {
-            if let Some(fr) = parse_commitment(comm_str) {
-                tree.insert(fr);
+        self.build_tree_with_progress(|_| {})
+    }
+
+    /// The tree for proofs under `config::TREE_BACKEND`
+    pub fn proof_tree(&self) -> Result<ProofTree, String> {
+        self.proof_tree_with(TREE_BACKEND)
+    }
+
+    /// The tree for proofs under `backend`
+    pub fn proof_tree_with(&self, backend: TreeBackend) -> Result<ProofTree, String> {
+        match backend {
+            TreeBackend::Memory => Ok(ProofTree::Memory(self.build_tree())),
+            TreeBackend::Paged => {
+                let path = paged_tree_path();
+                let tree = self
+                    .paged_tree(&path)
+                    .map_err(|e| format!("Cannot update {}: {}", path, e))?;
+                let root = tree.root().map_err(|e| format!("Cannot read {}: {}", path, e))?;
+                Ok(ProofTree::Paged { tree, root })
             }
         }
-        tree
+    }
+
+    /// Open the paged tree at `path` and insert the leaves added since it was last
+    /// used. One with more leaves than the state, another shape, or a root other
+    /// than the latest in `root_history` (the state was restored or repaired) is
+    /// rebuilt from the leaves instead.
+    fn paged_tree(&self, path: &str) -> std::io::Result<PagedTree> {
+        let leaves: Vec<Fr> = self.commitments.iter().take(MAX_LEAVES).filter_map(|c| parse_commitment(c)).collect();
+        let latest = self.root_history.back().and_then(|r| fr_from_hex(r));
+        if let (Ok(mut tree), Some(latest)) = (PagedTree::open(path), latest) {
+            if (tree.arity(), tree.depth()) == (2, TREE_HEIGHT) && tree.len() <= leaves.len() {
+                for leaf in &leaves[tree.len()..] {
+                    tree.insert(*leaf)?;
+                }
+                if tree.root()? == latest {
+                    return Ok(tree);
+                }
+            }
+        }
+        ensure_data_dir()?;
+        PagedTree::build(path, 2, TREE_HEIGHT, leaves)
+    }
+
+    /// Build the merkle tree, calling `on_level` as each level is hashed
+    pub fn build_tree_with_progress(&self, on_level: impl FnMut(BuildProgress)) -> FixedMerkleTree {
+        let leaves: Vec<Fr> = self.commitments.iter().filter_map(|c| parse_commitment(c)).collect();
+        FixedMerkleTree::from_leaves_with_progress(&leaves, on_level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TREE_BATCH_FLUSH;
+
+    fn sample_bond() -> Bond {
+        Bond {
+            schema_version: BOND_SCHEMA_VERSION,
+            commitment: "Fr(0x01)".to_string(),
+            nullifier: "Fr(0x02)".to_string(),
+            value: 4242,
+            salt: 7,
+            owner: "Fr(0xabcdef)".to_string(),
+            asset_id: 1,
+            maturity_date: 1893456000,
+            created_at: "2030-01-01T00:00:00Z".to_string(),
+            label: None,
+            memo_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_interrupted_write_leaves_original_intact() {
+        let (_guard, dir) = temp_data_dir("atomic_write");
+        fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/tree_state.json", dir);
+        write_atomic(&path, "original").unwrap();
+
+        // Half the new contents land, then the write is cut short
+        let err = write_atomic_with(&path, |file| {
+            file.write_all(b"{\"commitm")?;
+            Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "interrupted"))
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Interrupted);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+
+        write_atomic(&path, "replaced").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "replaced");
+    }
+
+    #[test]
+    fn test_ensure_data_dir_reports_unwritable_parent() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_guard, dir) = temp_data_dir("ensure_data_dir");
+        fs::create_dir_all(&dir).unwrap();
+
+        // Read-only parent; root ignores permission bits, so only assert when they apply
+        let parent = format!("{}/readonly", dir);
+        fs::create_dir_all(&parent).unwrap();
+        fs::set_permissions(&parent, fs::Permissions::from_mode(0o555)).unwrap();
+        let enforced = fs::write(format!("{}/probe", parent), b"").is_err();
+        set_data_dir(Some(&format!("{}/data", parent)));
+        let result = ensure_data_dir();
+        if enforced {
+            let err = result.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+            assert!(err.to_string().contains(&format!("{}/data", parent)));
+        }
+        fs::set_permissions(&parent, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // A file in the way fails for everyone
+        let blocker = format!("{}/blocker", dir);
+        fs::write(&blocker, b"").unwrap();
+        set_data_dir(Some(&format!("{}/data", blocker)));
+        let err = ensure_data_dir().unwrap_err();
+        assert!(err.to_string().contains(&format!("Cannot create data directory {}/data", blocker)));
+        assert!(save_wallet("alice", &Wallet {
+            schema_version: WALLET_SCHEMA_VERSION,
+            keys: ShieldedKeys::from_seed([1u8; 32]),
+            created_at: String::new(),
+        })
+        .is_err());
+
+        set_data_dir(Some(&dir));
+        assert_eq!(ensure_data_dir().unwrap(), PathBuf::from(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bond_label_round_trip() {
+        let (_guard, dir) = temp_data_dir("bond_label");
+        ensure_data_dir().unwrap();
+        let path = format!("{}/bond.json", dir);
+
+        let unlabeled = sample_bond();
+        unlabeled.save(&path).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("label"));
+        assert_eq!(load_bond(&path).unwrap().label, None);
+
+        let labeled = Bond {
+            label: Some("2030 serial A".to_string()),
+            ..sample_bond()
+        };
+        labeled.save(&path).unwrap();
+        let loaded = load_bond(&path).unwrap();
+        assert_eq!(loaded.label.as_deref(), Some("2030 serial A"));
+
+        // Same note, same or different labels: the commitment is untouched
+        let relabeled = Bond {
+            label: Some("other".to_string()),
+            ..sample_bond()
+        };
+        assert_eq!(loaded.commitment, unlabeled.commitment);
+        assert_eq!(relabeled.commitment, labeled.commitment);
+
+        set_data_dir(None);
+    }
+
+    #[test]
+    fn test_v1_files_migrate_on_load() {
+        let (_guard, dir) = temp_data_dir("schema_v1");
+        ensure_data_dir().unwrap();
+
+        // Before versioning: poseidon-rs display form, a decimal nullifier, no label
+        let path = format!("{}/bond_old.json", dir);
+        fs::write(
+            &path,
+            r#"{"commitment": "Fr(0x00000000000000000000000000000000000000000000000000000000000000ff)",
+                "nullifier": "4660", "value": 500, "salt": 9, "owner": "0xabc",
+                "asset_id": 1, "maturity_date": 1893456000, "created_at": ""}"#,
+        )
+        .unwrap();
+        let bond = load_bond(&path).unwrap();
+        assert_eq!(bond.schema_version, BOND_SCHEMA_VERSION);
+        assert_eq!(bond.commitment, fr_to_hex(&Fr::from_str("255").unwrap()));
+        assert_eq!(bond.nullifier, fr_to_hex(&Fr::from_str("4660").unwrap()));
+        assert_eq!(bond.owner, fr_to_hex(&Fr::from_str("2748").unwrap()));
+        assert_eq!((bond.value, bond.label), (500, None));
+        let rewritten: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(rewritten["schema_version"], BOND_SCHEMA_VERSION);
+        assert_eq!(rewritten["nullifier"], bond.nullifier.as_str());
+
+        // The tranche note's placeholder nullifier is not a field element
+        let mut tranche: serde_json::Value = rewritten.clone();
+        tranche.as_object_mut().unwrap().remove("schema_version");
+        tranche["nullifier"] = "N/A (Global Note)".into();
+        fs::write(&path, tranche.to_string()).unwrap();
+        assert_eq!(load_bond(&path).unwrap().nullifier, "N/A (Global Note)");
+
+        // A wallet from before kdf_version was recorded keeps the v1 derivation
+        let keys = ShieldedKeys::from_seed_versioned([3u8; 32], crate::keys::KDF_V1).unwrap();
+        let mut wallet = serde_json::json!({ "keys": keys, "created_at": "2029-12-31" });
+        wallet["keys"].as_object_mut().unwrap().remove("kdf_version");
+        let legacy_key = format!("{}", keys.public_spending_key());
+        wallet["keys"]["public_spending_key_hex"] = legacy_key.into();
+        fs::write(wallet_path("old"), wallet.to_string()).unwrap();
+        let loaded = load_wallet("old").unwrap();
+        assert_eq!(loaded.schema_version, WALLET_SCHEMA_VERSION);
+        assert_eq!(loaded.keys.kdf_version(), crate::keys::KDF_V1);
+        assert_eq!(loaded.keys.public_spending_key(), keys.public_spending_key());
+        assert_eq!(loaded.keys.public_spending_key_hex, keys.public_spending_key_hex);
+        let rewritten = fs::read_to_string(wallet_path("old")).unwrap();
+        assert!(rewritten.contains("\"kdf_version\": 1"), "{}", rewritten);
+
+        // Files from a newer wallet are refused rather than misread
+        wallet["schema_version"] = (WALLET_SCHEMA_VERSION + 1).into();
+        fs::write(wallet_path("new"), wallet.to_string()).unwrap();
+        assert!(load_wallet("new").is_none());
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_private_redemption_receipt_hides_note() {
+        let nullifier = Fr::from_str("123456789").unwrap();
+        let receipt = RedemptionReceipt::new(
+            &sample_bond(),
+            &nullifier,
+            Some("WIRE-2030-001".to_string()),
+            None,
+            true,
+        );
+        let json = serde_json::to_string(&receipt).unwrap();
+
+        assert!(json.contains(&format!("{}", fr_to_bytes32(&nullifier))));
+        assert!(json.contains("WIRE-2030-001"));
+        assert!(!json.contains("4242"));
+        assert!(!json.contains("abcdef"));
+        assert!(!json.contains("\"value\""));
+        assert!(!json.contains("\"owner\""));
+    }
+
+    #[test]
+    fn test_redemption_receipt_includes_note_by_default() {
+        let nullifier = Fr::from_str("1").unwrap();
+        let receipt = RedemptionReceipt::new(&sample_bond(), &nullifier, None, None, false);
+        assert_eq!(receipt.value, Some(4242));
+        assert_eq!(receipt.owner.as_deref(), Some("Fr(0xabcdef)"));
+    }
+
+    #[test]
+    fn test_find_commitment_ignores_formatting() {
+        let commitment = Fr::from_str("1234567890123456789").unwrap();
+        let state = TreeState::from_commitments(vec![
+            "Fr(0x01)".to_string(),
+            format!("{}", commitment),
+        ]);
+
+        // Same value as bytes32 hex and as trimmed hex without leading zeros
+        let bytes32 = format!("{}", fr_to_bytes32(&commitment));
+        let trimmed = format!("0x{}", bytes32.trim_start_matches("0x").trim_start_matches('0'));
+        assert_eq!(state.find_commitment(&parse_commitment(&bytes32).unwrap()), Some(1));
+        assert_eq!(state.find_commitment(&parse_commitment(&trimmed).unwrap()), Some(1));
+        assert_eq!(state.find_commitment(&Fr::from_str("7").unwrap()), None);
+    }
+
+    #[test]
+    fn test_account_wallet_names() {
+        let (_guard, dir) = temp_data_dir("accounts");
+        let keys = ShieldedKeys::from_seed([8u8; 32]);
+        let wallet = Wallet {
+            schema_version: WALLET_SCHEMA_VERSION,
+            keys: keys.clone(),
+            created_at: String::new(),
+        };
+        save_wallet("desk", &wallet).unwrap();
+
+        assert_eq!(account_wallet_name("desk", 0), "desk");
+        assert_eq!(account_wallet_name("desk", 2), "desk@2");
+        let account = load_wallet("desk@2").unwrap();
+        assert_eq!(account.keys.public_spending_key(), keys.derive_account(2).public_spending_key());
+        assert_ne!(account.keys.public_spending_key(), keys.public_spending_key());
+        assert!(load_wallet("desk@x").is_none());
+        assert!(load_wallet("nobody@1").is_none());
+
+        // Accounts are always derived from the stored base wallet
+        assert!(save_wallet("desk@2", &account).is_err());
+        assert!(!std::path::Path::new(&wallet_path("desk@2")).exists());
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_recent_roots_ring_buffer() {
+        let (_guard, dir) = temp_data_dir("root_history");
+        let mut state = TreeState::from_commitments(Vec::new());
+        let mut roots = Vec::new();
+        for i in 1..=5 {
+            state.add_commitment(u64_to_fr(i));
+            roots.push(state.build_tree().root());
+        }
+        assert_eq!(state.recent_roots(), roots);
+        assert!(roots.iter().all(|root| state.is_recent_root(root)));
+        assert!(!state.is_recent_root(&u64_to_fr(99)));
+
+        // Persisted, and rebuilt from the leaves for a state saved without history
+        assert_eq!(TreeState::load().recent_roots(), roots);
+        let legacy = TreeState::from_commitments(state.commitments().to_vec());
+        assert_eq!(legacy.recent_roots(), roots);
+
+        // Only the last ROOT_HISTORY_SIZE roots are kept
+        for i in 0..ROOT_HISTORY_SIZE as u64 - 2 {
+            state.push_root(u64_to_fr(1_000 + i));
+        }
+        let recent = state.recent_roots();
+        assert_eq!(recent.len(), ROOT_HISTORY_SIZE);
+        assert!(!state.is_recent_root(&roots[0]));
+        assert!(!state.is_recent_root(&roots[2]));
+        assert_eq!(recent[..2], roots[3..]);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_canonical_order_gives_one_root() {
+        let fr = |n: u64| u64_to_fr(n);
+        let leaves: Vec<Leaf> = [(Some(0), 50), (Some(1), 10), (Some(2), 40), (None, 30), (None, 20)]
+            .into_iter()
+            .map(|(index, n)| Leaf { index, commitment: fr(n) })
+            .collect();
+
+        // Fed in any order, repeats included, the tree comes out the same
+        let mut shuffled = leaves.clone();
+        shuffled.reverse();
+        shuffled.swap(0, 2);
+        shuffled.push(leaves[3]);
+        let a = TreeState::from_leaves(leaves.clone());
+        let b = TreeState::from_leaves(shuffled);
+        assert_eq!(a.commitments(), b.commitments());
+        assert_eq!(a.build_tree().root(), b.build_tree().root());
+
+        // On-chain indices first, in the contract's order rather than by value;
+        // the rest after them by value
+        let order: Vec<Fr> = canonical_order(leaves.clone()).iter().map(|l| l.commitment).collect();
+        assert_eq!(order, [fr(50), fr(10), fr(40), fr(20), fr(30)]);
+        let mut chain = FixedMerkleTree::new();
+        for n in [50, 10, 40] {
+            chain.insert(fr(n));
+        }
+        let onchain = TreeState::from_leaves(leaves[..3].to_vec());
+        assert_eq!(onchain.build_tree().root(), chain.root());
+    }
+
+    #[test]
+    fn test_paged_backend_gives_the_memory_tree_proofs() {
+        let (_guard, dir) = temp_data_dir("paged_backend");
+        let same = |state: &TreeState| {
+            let memory = state.proof_tree_with(TreeBackend::Memory).unwrap();
+            let paged = state.proof_tree_with(TreeBackend::Paged).unwrap();
+            assert!(matches!(paged, ProofTree::Paged { .. }));
+            assert_eq!(paged.root(), memory.root());
+            for index in 0..state.commitments().len() {
+                assert_eq!(paged.generate_proof(index).unwrap(), memory.generate_proof(index).unwrap());
+            }
+        };
+        let mut state = TreeState::from_commitments((1..=3).map(|n| fr_to_hex(&u64_to_fr(n))).collect());
+        same(&state);
+        let before = state.snapshot();
+
+        // New leaves are inserted into the existing file
+        state.add_commitment(u64_to_fr(4));
+        same(&state);
+        assert_eq!(PagedTree::open(&paged_tree_path()).unwrap().len(), 4);
+
+        // A file ahead of the state, or over other leaves of the same count, is rebuilt
+        state.restore(before);
+        same(&state);
+        state.replace_leaves((7..=9).map(|n| Leaf { index: None, commitment: u64_to_fr(n) }).collect());
+        same(&state);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_batched_inserts_write_once() {
+        let (_guard, dir) = temp_data_dir("tree_batch");
+        let leaves: Vec<Fr> = (1..=1000).map(u64_to_fr).collect();
+        let mut state = TreeState::from_commitments(Vec::new());
+        state.begin_batch(TREE_BATCH_FLUSH);
+        for leaf in &leaves {
+            state.add_commitment(*leaf);
+        }
+        assert_eq!(state.writes(), 0);
+        assert!(!std::path::Path::new(&tree_state_path()).exists());
+        state.commit();
+        assert_eq!(state.writes(), 1);
+
+        // Same leaves and roots as inserting one at a time
+        let hexes: Vec<String> = leaves.iter().map(fr_to_hex).collect();
+        let expected = TreeState::from_commitments(hexes.clone());
+        let saved = TreeState::load();
+        assert_eq!(saved.commitments(), &hexes[..]);
+        assert_eq!(saved.recent_roots(), expected.recent_roots());
+        assert_eq!(
+            saved.recent_roots().last(),
+            Some(&FixedMerkleTree::from_leaves(&leaves[..MAX_LEAVES]).root())
+        );
+        assert_eq!(saved.find_commitment(&u64_to_fr(1000)), Some(999));
+
+        // A batch larger than the threshold flushes along the way; unflushed
+        // inserts never reach the file
+        let mut state = TreeState::from_commitments(Vec::new());
+        state.begin_batch(300);
+        for leaf in &leaves {
+            state.add_commitment(*leaf);
+        }
+        assert_eq!(state.writes(), 3);
+        assert_eq!(TreeState::load().commitments().len(), 900);
+        state.commit();
+        assert_eq!(state.writes(), 4);
+        assert_eq!(TreeState::load().recent_roots(), expected.recent_roots());
+
+        // Out of batched mode every insert writes again
+        state.add_commitment(u64_to_fr(1001));
+        assert_eq!(state.writes(), 5);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_commitment_index_matches_linear_scan() {
+        let (_guard, dir) = temp_data_dir("tree_index");
+        let linear = |state: &TreeState, c: &Fr| {
+            state
+                .commitments()
+                .iter()
+                .position(|s| parse_commitment(s).as_ref() == Some(c))
+        };
+
+        // Legacy display-form entries, then inserts including a duplicate leaf
+        let mut state = TreeState::from_commitments(vec![
+            "Fr(0x05)".to_string(),
+            format!("{}", u64_to_fr(9)),
+        ]);
+        let leaves: Vec<Fr> = [3, 5, 7, 3, 11].into_iter().map(u64_to_fr).collect();
+        for leaf in &leaves {
+            state.add_commitment(*leaf);
+        }
+        for probe in (0..14).map(u64_to_fr) {
+            assert_eq!(state.find_commitment(&probe), linear(&state, &probe));
+        }
+        assert_eq!(state.find_commitment(&u64_to_fr(5)), Some(0));
+        assert_eq!(state.find_commitment(&u64_to_fr(3)), Some(2));
+
+        // The index is rebuilt on load rather than stored
+        let reloaded = TreeState::load();
+        assert!(!fs::read_to_string(tree_state_path()).unwrap().contains("positions"));
+        for probe in (0..14).map(u64_to_fr) {
+            assert_eq!(reloaded.find_commitment(&probe), linear(&reloaded, &probe));
+        }
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_bond_index_tracks_saved_bonds() {
+        let (_guard, dir) = temp_data_dir("bond_index");
+        ensure_data_dir().unwrap();
+        let bond = |n: u64| Bond {
+            commitment: fr_to_hex(&u64_to_fr(n)),
+            ..sample_bond()
+        };
+
+        // Written before any index existed: picked up when the index is first used
+        let legacy = format!("{}/bond_legacy.json", dir);
+        bond(1).save(&legacy).unwrap();
+        bond(9).save(&format!("{}/bond_old_REDEEMED.json", dir)).unwrap();
+        let saved = save_bond("bond_new.json", &bond(2)).unwrap();
+
+        let mut state = TreeState::load();
+        assert_eq!(state.bond_files(&u64_to_fr(1)), vec![legacy.clone()]);
+        assert_eq!(state.bond_files(&u64_to_fr(2)), vec![saved.clone()]);
+        assert!(state.bond_files(&u64_to_fr(9)).is_empty());
+        let paths: Vec<String> = state.indexed_bonds().into_iter().map(|(p, _)| p).collect();
+        assert_eq!(paths, vec![legacy.clone(), saved.clone()]);
+
+        // Adding leaves keeps the index; a renamed file drops out
+        state.add_commitment(u64_to_fr(2));
+        fs::rename(&saved, saved.replace(".json", "_REDEEMED.json")).unwrap();
+        let mut reloaded = TreeState::load();
+        assert_eq!(reloaded.find_commitment(&u64_to_fr(2)), Some(0));
+        let paths: Vec<String> = reloaded.indexed_bonds().into_iter().map(|(p, _)| p).collect();
+        assert_eq!(paths, vec![legacy.clone()]);
+
+        // A copy dropped in by hand shows up after a re-index, under the same key
+        let copy = format!("{}/bond_copy.json", dir);
+        bond(1).save(&copy).unwrap();
+        assert_eq!(reloaded.reindex_bonds(), 2);
+        assert_eq!(reloaded.bond_files(&u64_to_fr(1)), vec![copy, legacy]);
+
+        set_data_dir(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_fr_bytes32_endianness() {
+        use ff::Field;
+
+        // modulus - 1, the largest field element
+        let mut max = Fr::one();
+        max.negate();
+        let big_hex = "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000000";
+        let big = fr_to_bytes32_with(&max, Endianness::Big);
+        let little = fr_to_bytes32_with(&max, Endianness::Little);
+        assert_eq!(format!("{}", big), big_hex);
+        let mut reversed = big.0;
+        reversed.reverse();
+        assert_eq!(little.0, reversed);
+
+        for endianness in [Endianness::Big, Endianness::Little] {
+            for fr in [Fr::zero(), Fr::one(), u64_to_fr(0x0102_0304_0506_0708), max] {
+                let bytes = fr_to_bytes32_with(&fr, endianness);
+                assert_eq!(fr_from_bytes32_with(&bytes, endianness), Some(fr));
+            }
+        }
+
+        // One is the last byte big-endian and the first little-endian
+        assert_eq!(fr_to_bytes32_with(&Fr::one(), Endianness::Big)[31], 1);
+        assert_eq!(fr_to_bytes32_with(&Fr::one(), Endianness::Little)[0], 1);
+        // Read in the wrong order, 0xff becomes 0xff << 248, far above the modulus
+        let low_byte = fr_to_bytes32_with(&u64_to_fr(0xff), Endianness::Big);
+        assert_eq!(fr_from_bytes32_with(&low_byte, Endianness::Little), None);
+
+        // The default follows the deployment, which reads Solidity bytes32
+        assert_eq!(FIELD_ENDIANNESS, Endianness::Big);
+        assert_eq!(fr_to_bytes32(&max), big);
+        assert_eq!(fr_from_bytes32(&big), Some(max));
+    }
+
+    #[test]
+    fn test_fr_hex_round_trip() {
+        use ff::Field;
+        use rand::Rng;
+
+        let mut max = Fr::one();
+        max.negate(); // modulus - 1
+        let mut values = vec![Fr::zero(), Fr::one(), u64_to_fr(u64::MAX), max];
+        let mut rng = rand::thread_rng();
+        for _ in 0..32 {
+            // Clearing the top bits keeps random bytes below the modulus
+            let mut bytes: [u8; 32] = rng.gen();
+            bytes[0] &= 0x1f;
+            values.push(fr_from_bytes32(&FixedBytes::from(bytes)).unwrap());
+        }
+
+        for fr in values {
+            let hex = fr_to_hex(&fr);
+            assert_eq!(hex.len(), 66);
+            assert_eq!(fr_from_hex(&hex), Some(fr));
+        }
+        assert_eq!(fr_to_hex(&Fr::one()), format!("0x{}1", "0".repeat(63)));
+        assert!(fr_from_hex("Fr(0x01)").is_none());
+    }
+
+    #[test]
+    fn test_salt_and_value_at_u64_max() {
+        let max = Fr::from_str(&u64::MAX.to_string()).unwrap();
+        assert_eq!(Salt(u64::MAX).to_fr(), max);
+        assert_eq!(Value(u64::MAX).to_fr(), max);
+        assert_eq!(Salt(0).to_fr(), Fr::from_str("0").unwrap());
+        assert_eq!(format!("{}", Salt(u64::MAX)), "ffffffffffffffff");
+
+        assert_eq!(Salt::try_from(u64::MAX as u128), Ok(Salt(u64::MAX)));
+        assert!(Salt::try_from(u64::MAX as u128 + 1).is_err());
+        assert!(Value::try_from(u128::MAX).is_err());
+    }
+
+    #[test]
+    fn test_short_truncates_long_strings() {
+        assert_eq!(short("Fr(0x0123456789abcdef)", 12), "Fr(0x0123456");
+    }
+
+    #[test]
+    fn test_short_handles_short_strings() {
+        // A small field element can format to fewer than 12 characters
+        let commitment = "Fr(5";
+        assert_eq!(commitment.len(), 4);
+        assert_eq!(short("12345", 12), "12345");
+        assert_eq!(short(commitment, 12), commitment);
+        assert_eq!(short("", 12), "");
     }
 }
@@ -0,0 +1,94 @@
+//! Pinned test vectors for key derivation, note hashing and the merkle tree.
+//! Values were captured once from this implementation; a change to Poseidon
+//! parameters, field encoding or hashing order makes these fail.
+
+use wallet::field::Fr;
+use wallet::keys::{ShieldedKeys, KDF_V1};
+use wallet::merkle::FixedMerkleTree;
+use wallet::notes::Note;
+use wallet::prover::CircuitNote;
+use wallet::utils::{fr_from_hex, fr_to_hex, u64_to_fr};
+
+fn fr(hex: &str) -> Fr {
+    fr_from_hex(hex).expect("vector is a canonical field element")
+}
+
+/// Keys of wallets created before `kdf_version`; new wallets derive v2
+#[test]
+fn test_seed_to_keys() {
+    let keys = ShieldedKeys::from_seed_versioned([1u8; 32], KDF_V1).unwrap();
+
+    // Spending key is a small field element: catches zero-padding/formatting bugs
+    assert_eq!(
+        fr_to_hex(&keys.get_private_spending_key()),
+        "0x00000000000000000000000000000000000000000000000034e9b1f31b9e8b5b"
+    );
+    assert_eq!(
+        keys.public_spending_key(),
+        fr("0x1b97e65f405263a1155fb2b4281d5d0b7188531ceb041c2cc7e1b64401bb2e48")
+    );
+    assert_eq!(
+        hex::encode(keys.public_viewing_key()),
+        "a4e09292b651c278b9772c569f5fa9bb13d906b46ab68c9df9dc2b4409f8a209"
+    );
+}
+
+#[test]
+fn test_note_commitment() {
+    let expected = fr("0x0dab9449e4a1398a15224c0b15a49d598b2174d305a316c918125f8feeb123c0");
+
+    let circuit_note = CircuitNote {
+        value: 1,
+        salt: 2,
+        owner: u64_to_fr(3),
+        asset_id: 4,
+        maturity_date: 5,
+        memo_hash: None,
+    };
+    assert_eq!(circuit_note.commitment(), expected);
+
+    // The memo note type hashes the same fields in the same order
+    let note = Note {
+        value: 1,
+        salt: 2,
+        owner: 3,
+        asset_id: 4,
+        maturity_date: 5,
+    };
+    assert_eq!(note.commit(), expected);
+}
+
+#[test]
+fn test_nullifier() {
+    let keys = ShieldedKeys::from_seed_versioned([1u8; 32], KDF_V1).unwrap();
+    assert_eq!(
+        keys.sign_nullifier(42),
+        fr("0x24caafab229df5e146ee10494910b61d1c511fb4a22e376f4ece83e6d05c9e5e")
+    );
+
+    let note = Note {
+        value: 1,
+        salt: 2,
+        owner: 3,
+        asset_id: 4,
+        maturity_date: 5,
+    };
+    assert_eq!(
+        note.nullifer(u64_to_fr(7)),
+        fr("0x2fc9d02c3fe93dc85378457e98760c28c83c1189a84316d65e956edf2f9baee7")
+    );
+}
+
+#[test]
+fn test_merkle_root() {
+    assert_eq!(
+        FixedMerkleTree::new().root(),
+        fr("0x18f43331537ee2af2e3d758d50f72106467c6eea50371dd528d57eb2b856d238")
+    );
+
+    let tree = FixedMerkleTree::from_leaves(&[u64_to_fr(1), u64_to_fr(2), u64_to_fr(3)]);
+    assert_eq!(
+        tree.root(),
+        fr("0x05c1e52b41a571293b30efacd2afdb7173b20cfaf1f646c4ac9f96eb75848270")
+    );
+}